@@ -45,7 +45,7 @@ fn node_config() -> NodeConfig {
         service_public_key,
         service_secret_key,
         consensus_public_key,
-        consensus_secret_key,
+        consensus_secret_key: Some(consensus_secret_key),
         genesis,
         external_address: None,
         network: Default::default(),
@@ -54,6 +54,13 @@ fn node_config() -> NodeConfig {
         mempool: Default::default(),
         services_configs: Default::default(),
         database: Default::default(),
+        state_sync: Default::default(),
+        signer: Default::default(),
+        service_signer: Default::default(),
+        reputation: Default::default(),
+        log_level: None,
+        shutdown_timeout: None,
+        replica: None,
     }
 }
 
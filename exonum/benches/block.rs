@@ -17,12 +17,14 @@
 
 #[macro_use]
 extern crate exonum;
+extern crate chrono;
 extern crate futures;
 extern crate tempdir;
 extern crate test;
 
 #[cfg(test)]
 mod tests {
+    use chrono::Utc;
     use tempdir::TempDir;
     use futures::sync::mpsc;
     use test::Bencher;
@@ -51,7 +53,7 @@ mod tests {
 
     fn execute_block(blockchain: &Blockchain, height: u64, txs: &[Hash]) -> Patch {
         blockchain
-            .create_patch(ValidatorId::zero(), Height(height), txs)
+            .create_patch(ValidatorId::zero(), Height(height), Utc::now(), txs)
             .1
     }
 
@@ -0,0 +1,106 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reference external signer for the Exonum node.
+//!
+//! The signer reads the consensus keys from the node configuration (either the full node
+//! config or the secret config generated by `generate-config`), so the node config itself
+//! may be stripped of the consensus secret key afterwards. Run it with
+//!
+//! ```text
+//! cargo run --example signer -- --config node.toml --state signer.json --listen 127.0.0.1:6400
+//! ```
+//!
+//! and add the following section to the node configuration:
+//!
+//! ```toml
+//! [signer]
+//! type = "Remote"
+//! address = "127.0.0.1:6400"
+//! timeout = 1000
+//! ```
+//!
+//! Use `--listen unix:/path/to/socket` and `address = "unix:/path/to/socket"` for a Unix
+//! domain socket.
+
+extern crate clap;
+extern crate exonum;
+#[macro_use]
+extern crate serde_derive;
+
+use clap::{App, Arg};
+
+use std::net::TcpListener;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+
+use exonum::crypto::{PublicKey, SecretKey};
+use exonum::helpers::{self, config::ConfigFile};
+use exonum::node::signer::{DoubleSignGuard, LocalSigner, SignerAddress, SignerServer};
+
+/// Consensus keys from the node configuration.
+#[derive(Debug, Deserialize)]
+struct ConsensusKeys {
+    consensus_public_key: PublicKey,
+    consensus_secret_key: SecretKey,
+}
+
+fn main() {
+    helpers::init_logger().unwrap();
+
+    let matches = App::new("Reference Exonum signer")
+        .arg(
+            Arg::with_name("CONFIG")
+                .long("config")
+                .help("Path to the node configuration with the consensus keys")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("STATE")
+                .long("state")
+                .help("Path to the file with the double signing protection state")
+                .takes_value(true)
+                .required(true),
+        )
+        .arg(
+            Arg::with_name("LISTEN")
+                .long("listen")
+                .help("TCP address or `unix:path` to listen for the node requests")
+                .takes_value(true)
+                .required(true),
+        )
+        .get_matches();
+
+    let keys: ConsensusKeys = ConfigFile::load(matches.value_of("CONFIG").unwrap())
+        .expect("Unable to load the consensus keys");
+    let guard = DoubleSignGuard::open(matches.value_of("STATE").unwrap())
+        .expect("Unable to open the signer state");
+    let signer = LocalSigner::new(keys.consensus_public_key, keys.consensus_secret_key);
+    let mut server = SignerServer::new(signer, guard);
+
+    match SignerAddress::parse(matches.value_of("LISTEN").unwrap()) {
+        SignerAddress::Tcp(address) => {
+            let listener = TcpListener::bind(&address).expect("Unable to bind the address");
+            server.run_tcp(&listener).unwrap();
+        }
+        #[cfg(unix)]
+        SignerAddress::Unix(path) => {
+            let listener = UnixListener::bind(&path).expect("Unable to bind the socket");
+            server.run_unix(&listener).unwrap();
+        }
+        #[cfg(not(unix))]
+        SignerAddress::Unix(_) => panic!("Unix sockets are not supported on this platform"),
+    }
+}
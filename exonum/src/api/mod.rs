@@ -72,6 +72,10 @@ pub enum ApiError {
     /// Unauthorized error.
     #[fail(display = "Unauthorized")]
     Unauthorized,
+
+    /// The request cannot be served at the moment.
+    #[fail(display = "Service unavailable: {}", _0)]
+    ServiceUnavailable(String),
 }
 
 impl From<io::Error> for ApiError {
@@ -99,6 +103,8 @@ impl From<ApiError> for IronError {
 
             ApiError::BadRequest(..) => status::BadRequest,
 
+            ApiError::ServiceUnavailable(..) => status::ServiceUnavailable,
+
             ApiError::Storage(..) |
             ApiError::Io(..) |
             ApiError::InternalError(..) => status::InternalServerError,
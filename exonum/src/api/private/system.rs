@@ -17,13 +17,18 @@ use router::Router;
 use iron::prelude::*;
 
 use std::net::SocketAddr;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::time::SystemTime;
 
-use crypto::PublicKey;
+use chrono::{DateTime, Utc};
+use futures::Future;
+use futures::sync::oneshot;
+
+use crypto::{Hash, PublicKey};
 use node::{ExternalMessage, ApiSender};
-use blockchain::{Service, Blockchain, SharedNodeState};
+use blockchain::{Service, Blockchain, Schema, SharedNodeState};
 use api::{Api, ApiError};
-use messages::{TEST_NETWORK_ID, PROTOCOL_MAJOR_VERSION};
+use messages::{MessageClass, PROTOCOL_MAJOR_VERSION, TEST_NETWORK_ID};
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 struct ServiceInfo {
@@ -59,6 +64,12 @@ impl NodeInfo {
                 .collect(),
         }
     }
+
+    /// Sets the identifier of the network stamped into the messages of the node.
+    pub fn with_network_id(mut self, network_id: u8) -> NodeInfo {
+        self.network_id = network_id;
+        self
+    }
 }
 
 #[derive(Serialize, Default)]
@@ -85,10 +96,38 @@ struct IncomingConnection {
     state: IncomingConnectionState,
 }
 
+#[derive(Serialize)]
+struct PeerReputationInfo {
+    public_key: PublicKey,
+    score: i64,
+    banned_until: Option<DateTime<Utc>>,
+}
+
 #[derive(Serialize)]
 struct PeersInfo {
     incoming_connections: Vec<SocketAddr>,
     outgoing_connections: HashMap<SocketAddr, IncomingConnection>,
+    reputation: Vec<PeerReputationInfo>,
+}
+
+#[derive(Serialize)]
+struct DroppedMessagesInfo {
+    consensus: u64,
+    requests: u64,
+    transactions: u64,
+}
+
+#[derive(Serialize)]
+struct SchemaVersionInfo {
+    name: String,
+    id: u16,
+    applied: u16,
+    supported: u16,
+}
+
+#[derive(Serialize)]
+struct MigrationDryRunInfo {
+    state_hash: Hash,
 }
 
 /// Private system API.
@@ -137,9 +176,28 @@ impl SystemApi {
                 .public_key = Some(p);
         }
 
+        let now = SystemTime::now();
+        let reputation = self.shared_api_state
+            .peers_reputation()
+            .into_iter()
+            .map(|(public_key, reputation)| {
+                let banned_until = if reputation.is_banned(now) {
+                    reputation.banned_until().map(DateTime::from)
+                } else {
+                    None
+                };
+                PeerReputationInfo {
+                    public_key,
+                    score: reputation.score(),
+                    banned_until,
+                }
+            })
+            .collect();
+
         PeersInfo {
             incoming_connections: self.shared_api_state.incoming_connections(),
             outgoing_connections,
+            reputation,
         }
     }
 
@@ -167,6 +225,54 @@ impl SystemApi {
         router.post("/v1/peers", peer_add, "peer_add");
     }
 
+    fn handle_peer_disconnect(self, router: &mut Router) {
+        let peer_disconnect = move |request: &mut Request| -> IronResult<Response> {
+            let public_key: PublicKey = self.url_fragment(request, "public_key")?;
+            let message = ExternalMessage::PeerDisconnect(public_key);
+            self.node_channel.send_external_message(message).map_err(
+                ApiError::from,
+            )?;
+            self.ok_response(&serde_json::to_value("Ok").unwrap())
+        };
+
+        router.delete("/v1/peers/:public_key", peer_disconnect, "peer_disconnect");
+    }
+
+    fn handle_whitelist_add(self, router: &mut Router) {
+        let whitelist_add = move |request: &mut Request| -> IronResult<Response> {
+            #[derive(Serialize, Deserialize, Clone, Debug)]
+            struct WhitelistPeerInfo {
+                public_key: PublicKey,
+            }
+
+            let WhitelistPeerInfo { public_key } = self.parse_body(request)?;
+            let message = ExternalMessage::WhitelistAdd(public_key);
+            self.node_channel.send_external_message(message).map_err(
+                ApiError::from,
+            )?;
+            self.ok_response(&serde_json::to_value("Ok").unwrap())
+        };
+
+        router.post("/v1/whitelist", whitelist_add, "whitelist_add");
+    }
+
+    fn handle_whitelist_remove(self, router: &mut Router) {
+        let whitelist_remove = move |request: &mut Request| -> IronResult<Response> {
+            let public_key: PublicKey = self.url_fragment(request, "public_key")?;
+            let message = ExternalMessage::WhitelistRemove(public_key);
+            self.node_channel.send_external_message(message).map_err(
+                ApiError::from,
+            )?;
+            self.ok_response(&serde_json::to_value("Ok").unwrap())
+        };
+
+        router.delete(
+            "/v1/whitelist/:public_key",
+            whitelist_remove,
+            "whitelist_remove",
+        );
+    }
+
     fn handle_network(self, router: &mut Router) {
         let network = move |_: &mut Request| -> IronResult<Response> {
             let info = self.info.clone();
@@ -176,6 +282,23 @@ impl SystemApi {
         router.get("/v1/network", network, "network_info");
     }
 
+    fn handle_dropped_messages(self, router: &mut Router) {
+        let dropped_messages = move |_: &mut Request| -> IronResult<Response> {
+            let info = DroppedMessagesInfo {
+                consensus: self.shared_api_state.dropped_messages(MessageClass::Consensus),
+                requests: self.shared_api_state.dropped_messages(MessageClass::Requests),
+                transactions: self.shared_api_state.dropped_messages(MessageClass::Transactions),
+            };
+            self.ok_response(&serde_json::to_value(info).unwrap())
+        };
+
+        router.get(
+            "/v1/network/dropped_messages",
+            dropped_messages,
+            "dropped_messages",
+        );
+    }
+
     fn handle_is_consensus_enabled(self, router: &mut Router) {
         let consensus_enabled_info = move |_: &mut Request| -> IronResult<Response> {
             let info = self.shared_api_state.is_enabled();
@@ -211,6 +334,68 @@ impl SystemApi {
         );
     }
 
+    fn handle_schema_versions(self, router: &mut Router) {
+        let schema_versions = move |_: &mut Request| -> IronResult<Response> {
+            let snapshot = self.blockchain.snapshot();
+            let schema = Schema::new(&snapshot);
+            let info: Vec<_> = self.blockchain
+                .service_map()
+                .values()
+                .map(|service| {
+                    SchemaVersionInfo {
+                        name: service.service_name().to_owned(),
+                        id: service.service_id(),
+                        applied: schema.service_schema_version(service.service_id()),
+                        supported: service.schema_version(),
+                    }
+                })
+                .collect();
+            self.ok_response(&serde_json::to_value(info).unwrap())
+        };
+
+        router.get("/v1/schema_versions", schema_versions, "schema_versions");
+    }
+
+    fn handle_migrations_dry_run(self, router: &mut Router) {
+        let dry_run = move |request: &mut Request| -> IronResult<Response> {
+            #[derive(Serialize, Deserialize, Clone, Debug)]
+            struct MigrationTarget {
+                service_versions: BTreeMap<String, u16>,
+            }
+
+            let MigrationTarget { service_versions } = self.parse_body(request)?;
+            let state_hash = self.blockchain
+                .dry_run_migrations(&service_versions)
+                .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+            let info = MigrationDryRunInfo { state_hash };
+            self.ok_response(&serde_json::to_value(info).unwrap())
+        };
+
+        router.post(
+            "/v1/schema_versions/dry_run",
+            dry_run,
+            "schema_versions_dry_run",
+        );
+    }
+
+    fn handle_config_reload(self, router: &mut Router) {
+        let config_reload = move |_: &mut Request| -> IronResult<Response> {
+            let (result_tx, result_rx) = oneshot::channel();
+            self.node_channel
+                .send_external_message(ExternalMessage::ReloadConfig(result_tx))
+                .map_err(ApiError::from)?;
+            match result_rx.wait() {
+                Ok(Ok(())) => self.ok_response(&serde_json::to_value("Ok").unwrap()),
+                Ok(Err(e)) => Err(ApiError::BadRequest(e.to_string()).into()),
+                Err(_) => Err(
+                    ApiError::InternalError("The node has not responded".into()).into(),
+                ),
+            }
+        };
+
+        router.post("/v1/config/reload", config_reload, "config_reload");
+    }
+
     fn handle_shutdown(self, router: &mut Router) {
         let shutdown = move |_: &mut Request| -> IronResult<Response> {
             self.node_channel
@@ -227,9 +412,16 @@ impl Api for SystemApi {
     fn wire(&self, router: &mut Router) {
         self.clone().handle_peers_info(router);
         self.clone().handle_peer_add(router);
+        self.clone().handle_peer_disconnect(router);
+        self.clone().handle_whitelist_add(router);
+        self.clone().handle_whitelist_remove(router);
         self.clone().handle_network(router);
+        self.clone().handle_dropped_messages(router);
         self.clone().handle_is_consensus_enabled(router);
         self.clone().handle_set_consensus_enabled(router);
+        self.clone().handle_schema_versions(router);
+        self.clone().handle_migrations_dry_run(router);
+        self.clone().handle_config_reload(router);
         self.clone().handle_shutdown(router);
     }
 }
@@ -13,23 +13,37 @@
 // limitations under the License.
 
 use router::Router;
-use serde_json::Value as JsonValue;
+use serde_json::{self, Value as JsonValue};
 use iron::prelude::*;
+use iron::status;
+use iron::response::WriteBody;
+use iron::headers::CacheControl;
+use iron::headers::CacheDirective;
 
+use std::io::{self, Write};
 use std::ops::Range;
 use std::cmp;
 use std::error::Error;
+use std::time::Duration;
 
 use api::{Api, ApiError};
-use blockchain::{Transaction, Block, Blockchain, TxLocation, Schema, TransactionErrorType,
-                 TransactionResult};
+use blockchain::{Transaction, Block, Blockchain, CompactBlockProof, Evidence, TxLocation, Schema,
+                 TransactionErrorType, TransactionEvent, TransactionResult, Notification,
+                 Subscription, SubscriptionFilter, DEFAULT_SUBSCRIPTION_BUFFER, EVIDENCE_TABLE,
+                 TRANSACTION_EVENTS_TABLE};
 use crypto::Hash;
 use helpers::Height;
-use messages::Precommit;
-use storage::{ListProof, Snapshot};
+use messages::{Precommit, CONSENSUS as CORE_SERVICE};
+use storage::{ListProof, MapProof, Snapshot};
 
 const MAX_BLOCKS_PER_REQUEST: u64 = 1000;
 
+/// Default maximum number of simultaneous event stream subscribers. The public api server
+/// of the node reserves a thread for each of them.
+pub const DEFAULT_MAX_SUBSCRIPTIONS: usize = 16;
+/// Interval in milliseconds between the keep-alive comments of an idle event stream.
+const KEEP_ALIVE_INTERVAL: u64 = 15_000;
+
 /// Block information.
 #[derive(Debug, Serialize, Deserialize, PartialEq)]
 pub struct BlockInfo {
@@ -52,6 +66,36 @@ pub struct TxInfo {
     pub location_proof: ListProof<Hash>,
     /// Status of the transaction execution.
     pub status: TxStatus,
+    /// Events emitted by the transaction.
+    pub events: TxEvents,
+}
+
+/// Events emitted by a transaction together with the proofs of their inclusion
+/// into the `state_hash` of the latest block.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct TxEvents {
+    /// Events in the order of their emission.
+    pub events: Vec<TransactionEvent>,
+    /// Proof of the events against the root hash of the `transaction_events` list,
+    /// or `None` if the transaction emitted no events.
+    pub list_proof: Option<ListProof<TransactionEvent>>,
+    /// Proof of the list root hash in the `transaction_events_roots` table,
+    /// or of its absence if the transaction emitted no events.
+    pub root_proof: MapProof<Hash, Hash>,
+    /// Proof of the `transaction_events_roots` table in the `state_hash` of the latest block.
+    pub table_proof: MapProof<Hash, Hash>,
+}
+
+/// Evidence of a validator misbehavior together with the proofs of its inclusion
+/// into the `state_hash` of the latest block.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+pub struct EvidenceInfo {
+    /// The evidence or `None` if it is unknown.
+    pub evidence: Option<Evidence>,
+    /// Proof of the evidence presence or absence in the `evidence` table.
+    pub proof: MapProof<Hash, Evidence>,
+    /// Proof of the `evidence` table in the `state_hash` of the latest block.
+    pub table_proof: MapProof<Hash, Hash>,
 }
 
 /// Transaction execution status. Simplified version of `TransactionResult`.
@@ -72,6 +116,112 @@ pub enum TxStatus {
         /// Error description.
         description: String,
     },
+    /// A service call made by the transaction cannot be dispatched.
+    ServiceCall {
+        /// Error description.
+        description: String,
+    },
+}
+
+impl<'a> From<&'a TransactionResult> for TxStatus {
+    fn from(result: &'a TransactionResult) -> TxStatus {
+        match *result {
+            Ok(()) => TxStatus::Success,
+            Err(ref e) => {
+                let description = e.description().unwrap_or_default().to_owned();
+                match e.error_type() {
+                    TransactionErrorType::Panic => TxStatus::Panic { description },
+                    TransactionErrorType::Code(code) => TxStatus::Error { code, description },
+                    TransactionErrorType::ServiceCall => TxStatus::ServiceCall { description },
+                }
+            }
+        }
+    }
+}
+
+/// Change of the blockchain sent to the event stream subscribers.
+#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+pub enum NotificationInfo {
+    /// A block has been committed.
+    Block {
+        /// Header of the block.
+        block: Block,
+    },
+    /// A transaction has been committed.
+    Transaction {
+        /// Hash of the transaction.
+        tx_hash: Hash,
+        /// Height of the block containing the transaction.
+        height: Height,
+        /// Status of the transaction execution.
+        status: TxStatus,
+    },
+    /// A committed transaction has emitted an event.
+    Event {
+        /// Hash of the transaction.
+        tx_hash: Hash,
+        /// The emitted event.
+        event: TransactionEvent,
+    },
+    /// The stream is closed because the subscriber has not kept up with the blockchain.
+    Lagged,
+}
+
+impl From<Notification> for NotificationInfo {
+    fn from(notification: Notification) -> NotificationInfo {
+        match notification {
+            Notification::Block(block) => NotificationInfo::Block { block },
+            Notification::Transaction {
+                tx_hash,
+                height,
+                result,
+            } => NotificationInfo::Transaction {
+                tx_hash,
+                height,
+                status: TxStatus::from(&result),
+            },
+            Notification::Event { tx_hash, event } => NotificationInfo::Event { tx_hash, event },
+        }
+    }
+}
+
+/// Body of the `text/event-stream` response, written as long as the client is connected.
+struct EventStream {
+    subscription: Subscription,
+}
+
+impl EventStream {
+    fn write_notification(res: &mut Write, info: &NotificationInfo) -> io::Result<()> {
+        let data = serde_json::to_string(info).unwrap();
+        write!(res, "data: {}\n\n", data)
+    }
+}
+
+impl WriteBody for EventStream {
+    fn write_body(&mut self, res: &mut Write) -> io::Result<()> {
+        let interval = Duration::from_millis(KEEP_ALIVE_INTERVAL);
+        loop {
+            match self.subscription.recv_timeout(interval) {
+                Ok(Some(notification)) => {
+                    Self::write_notification(res, &notification.into())?;
+                    // Writes the rest of the committed block at once.
+                    while let Some(notification) = self.subscription.try_recv() {
+                        Self::write_notification(res, &notification.into())?;
+                    }
+                }
+                // Writing to a closed connection fails, which cancels the subscription.
+                Ok(None) => res.write_all(b": keep-alive\n\n")?,
+                Err(_) => {
+                    if self.subscription.is_lagged() {
+                        Self::write_notification(res, &NotificationInfo::Lagged)?;
+                    }
+                    return res.flush();
+                }
+            }
+            res.flush()?;
+        }
+    }
 }
 
 /// Information on blocks coupled with the corresponding range in the blockchain.
@@ -102,12 +252,22 @@ pub enum TransactionInfo {
 #[derive(Clone, Debug)]
 pub struct ExplorerApi {
     blockchain: Blockchain,
+    max_subscriptions: usize,
 }
 
 impl ExplorerApi {
     /// Creates a new `ExplorerApi` instance.
     pub fn new(blockchain: Blockchain) -> Self {
-        ExplorerApi { blockchain }
+        ExplorerApi {
+            blockchain,
+            max_subscriptions: DEFAULT_MAX_SUBSCRIPTIONS,
+        }
+    }
+
+    /// Sets the maximum number of simultaneous event stream subscribers.
+    pub fn with_max_subscriptions(mut self, max_subscriptions: usize) -> Self {
+        self.max_subscriptions = max_subscriptions;
+        self
     }
 
     fn explorer(&self) -> BlockchainExplorer {
@@ -162,6 +322,33 @@ impl ExplorerApi {
         }
     }
 
+    fn subscribe(&self, services: Option<String>) -> Result<Subscription, ApiError> {
+        let services = match services {
+            Some(services) => services
+                .split(',')
+                .map(|id| {
+                    id.trim().parse::<u16>().map_err(|e| {
+                        ApiError::BadRequest(format!("Invalid service id '{}': {}", id, e))
+                    })
+                })
+                .collect::<Result<Vec<_>, _>>()?,
+            None => Vec::new(),
+        };
+        self.blockchain
+            .subscriptions()
+            .subscribe(
+                SubscriptionFilter::new(services),
+                DEFAULT_SUBSCRIPTION_BUFFER,
+                self.max_subscriptions,
+            )
+            .ok_or_else(|| {
+                ApiError::ServiceUnavailable(format!(
+                    "Max event stream subscribers count exceeded ({})",
+                    self.max_subscriptions
+                ))
+            })
+    }
+
     fn set_blocks_response(self, router: &mut Router) {
         let blocks = move |req: &mut Request| -> IronResult<Response> {
             let count: u64 = self.required_param(req, "count")?;
@@ -185,6 +372,20 @@ impl ExplorerApi {
         router.get("/v1/blocks/:height", block, "height");
     }
 
+    fn set_block_certificate_response(self, router: &mut Router) {
+        let certificate = move |req: &mut Request| -> IronResult<Response> {
+            let height: Height = self.url_fragment(req, "height")?;
+            let proof = self.explorer().block_certificate(height)?;
+            let result = match proof {
+                None => Self::not_found_response,
+                Some(_) => Self::ok_response,
+            };
+            result(&self, &::serde_json::to_value(proof).unwrap())
+        };
+
+        router.get("/v1/blocks/:height/certificate", certificate, "certificate");
+    }
+
     fn set_transaction_info_response(self, router: &mut Router) {
         let transaction = move |req: &mut Request| -> IronResult<Response> {
             let hash: Hash = self.url_fragment(req, "hash")?;
@@ -198,13 +399,54 @@ impl ExplorerApi {
 
         router.get("/v1/transactions/:hash", transaction, "hash");
     }
+
+    fn set_evidence_list_response(self, router: &mut Router) {
+        let evidence_list = move |_: &mut Request| -> IronResult<Response> {
+            let list = self.explorer().evidence_list();
+            self.ok_response(&::serde_json::to_value(list).unwrap())
+        };
+
+        router.get("/v1/evidence", evidence_list, "evidence_list");
+    }
+
+    fn set_evidence_response(self, router: &mut Router) {
+        let evidence = move |req: &mut Request| -> IronResult<Response> {
+            let hash: Hash = self.url_fragment(req, "hash")?;
+            let info = self.explorer().evidence_info(&hash);
+            let result = match info.evidence {
+                None => Self::not_found_response,
+                Some(_) => Self::ok_response,
+            };
+            result(&self, &::serde_json::to_value(info).unwrap())
+        };
+
+        router.get("/v1/evidence/:hash", evidence, "evidence");
+    }
+
+    fn set_events_response(self, router: &mut Router) {
+        let events = move |req: &mut Request| -> IronResult<Response> {
+            let services: Option<String> = self.optional_param(req, "services")?;
+            let subscription = self.subscribe(services)?;
+            let body: Box<WriteBody> = Box::new(EventStream { subscription });
+            let mut resp = Response::with((status::Ok, body));
+            resp.headers.set_raw("Content-Type", vec![b"text/event-stream".to_vec()]);
+            resp.headers.set(CacheControl(vec![CacheDirective::NoCache]));
+            Ok(resp)
+        };
+
+        router.get("/v1/events", events, "events");
+    }
 }
 
 impl Api for ExplorerApi {
     fn wire(&self, router: &mut Router) {
         self.clone().set_blocks_response(router);
         self.clone().set_block_response(router);
+        self.clone().set_block_certificate_response(router);
         self.clone().set_transaction_info_response(router);
+        self.clone().set_evidence_list_response(router);
+        self.clone().set_evidence_response(router);
+        self.clone().set_events_response(router);
     }
 }
 
@@ -254,14 +496,23 @@ impl<'a> BlockchainExplorer<'a> {
             .get_proof(location.position_in_block());
 
         // Unwrap is OK here, because we already know that transaction is committed.
-        let status = match schema.transaction_results().get(tx_hash).unwrap() {
-            Ok(()) => TxStatus::Success,
-            Err(e) => {
-                let description = e.description().unwrap_or_default().to_owned();
-                match e.error_type() {
-                    TransactionErrorType::Panic => TxStatus::Panic { description },
-                    TransactionErrorType::Code(code) => TxStatus::Error { code, description },
-                }
+        let status = TxStatus::from(&schema.transaction_results().get(tx_hash).unwrap());
+
+        let events = {
+            let tx_events = schema.transaction_events(tx_hash);
+            let list_proof = if tx_events.is_empty() {
+                None
+            } else {
+                Some(tx_events.get_range_proof(0, tx_events.len()))
+            };
+            TxEvents {
+                events: tx_events.iter().collect(),
+                list_proof,
+                root_proof: schema.transaction_events_roots().get_proof(*tx_hash),
+                table_proof: schema.get_proof_to_service_table(
+                    CORE_SERVICE,
+                    TRANSACTION_EVENTS_TABLE,
+                ),
             }
         };
 
@@ -270,6 +521,7 @@ impl<'a> BlockchainExplorer<'a> {
             location,
             location_proof,
             status,
+            events,
         }))
     }
 
@@ -291,6 +543,17 @@ impl<'a> BlockchainExplorer<'a> {
         }
     }
 
+    /// Returns the block for the specified height with the compact certificate of its commit
+    /// or `None` if there is no such block or it is the genesis block.
+    pub fn block_certificate(&self, height: Height) -> Result<Option<CompactBlockProof>, ApiError> {
+        let schema = Schema::new(self.blockchain.snapshot());
+        schema.block_and_certificate(height).map_err(|e| {
+            ApiError::InternalError(
+                format!("Invalid precommits of the block at height {}: {}", height, e).into(),
+            )
+        })
+    }
+
     /// Returns the list of blocks in the given range.
     pub fn blocks_range(
         &self,
@@ -352,4 +615,22 @@ impl<'a> BlockchainExplorer<'a> {
         let schema = Schema::new(self.blockchain.snapshot());
         schema.transaction_results().get(hash)
     }
+
+    /// Returns all the evidence of validators misbehavior stored in the blockchain.
+    pub fn evidence_list(&self) -> Vec<Evidence> {
+        let schema = Schema::new(self.blockchain.snapshot());
+        let evidence = schema.evidence();
+        evidence.values().collect()
+    }
+
+    /// Returns the evidence with the given hash together with the proofs.
+    pub fn evidence_info(&self, hash: &Hash) -> EvidenceInfo {
+        let schema = Schema::new(self.blockchain.snapshot());
+        let evidence = schema.evidence();
+        EvidenceInfo {
+            evidence: evidence.get(hash),
+            proof: evidence.get_proof(*hash),
+            table_proof: schema.get_proof_to_service_table(CORE_SERVICE, EVIDENCE_TABLE),
+        }
+    }
 }
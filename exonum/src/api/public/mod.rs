@@ -16,7 +16,8 @@
 
 pub use self::system::{HealthCheckInfo, SystemApi};
 pub use self::blockchain_explorer::{BlockInfo, BlockchainExplorer, BlocksRange, ExplorerApi,
-                                    TransactionInfo, TxInfo, TxStatus};
+                                    NotificationInfo, TransactionInfo, TxInfo, TxStatus,
+                                    DEFAULT_MAX_SUBSCRIPTIONS};
 
 mod system;
 mod blockchain_explorer;
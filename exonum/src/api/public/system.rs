@@ -23,6 +23,8 @@ use helpers::user_agent;
 #[derive(Serialize, Deserialize, PartialEq)]
 struct MemPoolInfo {
     pub size: usize,
+    pub rejected: u64,
+    pub evicted: u64,
 }
 
 #[doc(hidden)]
@@ -51,7 +53,11 @@ impl SystemApi {
         let mempool = move |_: &mut Request| -> IronResult<Response> {
             let snapshot = self.blockchain.snapshot();
             let schema = Schema::new(&snapshot);
-            let info = MemPoolInfo { size: schema.transactions_pool_len() };
+            let info = MemPoolInfo {
+                size: schema.transactions_pool_len(),
+                rejected: self.shared_api_state.rejected_txs(),
+                evicted: self.shared_api_state.evicted_txs(),
+            };
             self.ok_response(&serde_json::to_value(info).unwrap())
         };
         router.get("/v1/mempool", mempool, "mempool");
@@ -14,6 +14,7 @@
 
 use router::Router;
 use serde_json;
+use chrono::Utc;
 
 use blockchain::{Block, SCHEMA_MAJOR_VERSION};
 use crypto::Hash;
@@ -31,6 +32,7 @@ fn test_json_response_for_complex_val() {
         SCHEMA_MAJOR_VERSION,
         ValidatorId::zero(),
         Height(24),
+        Utc::now(),
         tx_count,
         &Hash::new([24; 32]),
         &Hash::new([34; 32]),
@@ -12,12 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::cell::Cell;
+
+use chrono::{DateTime, Utc};
+
 use crypto::Hash;
 use messages::Precommit;
 use helpers::{Height, ValidatorId};
 
 /// Current core information schema version.
-pub const SCHEMA_MAJOR_VERSION: u16 = 0;
+pub const SCHEMA_MAJOR_VERSION: u16 = 1;
 
 encoding_struct!(
     /// Exonum block header data structure.
@@ -35,6 +39,8 @@ encoding_struct!(
         proposer_id: ValidatorId,
         /// Height of the committed block
         height: Height,
+        /// Time of the block proposed by the block proposer and agreed by the validators.
+        time: DateTime<Utc>,
         /// Number of transactions in block.
         tx_count: u32,
         /// Hash link to the previous block in blockchain.
@@ -56,8 +62,38 @@ pub struct BlockProof {
     pub precommits: Vec<Precommit>,
 }
 
+thread_local! {
+    static BLOCK_TIME: Cell<Option<DateTime<Utc>>> = Cell::new(None);
+}
+
+/// Returns the time of the block which transactions are currently executed.
+///
+/// The time is the same on all the nodes, so transactions may use it instead of the local
+/// time of the node. Returns `None` outside of the block execution.
+pub fn block_time() -> Option<DateTime<Utc>> {
+    BLOCK_TIME.with(|time| time.get())
+}
+
+/// Makes the block time available to transactions until the scope is dropped.
+pub(crate) struct BlockTimeScope;
+
+impl BlockTimeScope {
+    pub(crate) fn enter(time: DateTime<Utc>) -> BlockTimeScope {
+        BLOCK_TIME.with(|block_time| block_time.set(Some(time)));
+        BlockTimeScope
+    }
+}
+
+impl Drop for BlockTimeScope {
+    fn drop(&mut self) {
+        BLOCK_TIME.with(|block_time| block_time.set(None));
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use chrono::TimeZone;
+
     use crypto::hash;
     use super::*;
 
@@ -66,6 +102,7 @@ mod tests {
         let proposer_id = ValidatorId(1024);
         let txs = [4, 5, 6];
         let height = Height(123_345);
+        let time = Utc.timestamp(1_486_720_340, 0);
         let prev_hash = hash(&[1, 2, 3]);
         let tx_hash = hash(&txs);
         let tx_count = txs.len() as u32;
@@ -74,6 +111,7 @@ mod tests {
             SCHEMA_MAJOR_VERSION,
             proposer_id,
             height,
+            time,
             tx_count,
             &prev_hash,
             &tx_hash,
@@ -83,6 +121,7 @@ mod tests {
         assert_eq!(block.schema_version(), SCHEMA_MAJOR_VERSION);
         assert_eq!(block.proposer_id(), proposer_id);
         assert_eq!(block.height(), height);
+        assert_eq!(block.time(), time);
         assert_eq!(block.tx_count(), tx_count);
         assert_eq!(block.prev_hash(), &prev_hash);
         assert_eq!(block.tx_hash(), &tx_hash);
@@ -91,4 +130,15 @@ mod tests {
         let block1: Block = ::serde_json::from_str(&json_str).unwrap();
         assert_eq!(block1, block);
     }
+
+    #[test]
+    fn test_block_time_scope() {
+        let time = Utc.timestamp(1_486_720_340, 0);
+        assert_eq!(block_time(), None);
+        {
+            let _scope = BlockTimeScope::enter(time);
+            assert_eq!(block_time(), Some(time));
+        }
+        assert_eq!(block_time(), None);
+    }
 }
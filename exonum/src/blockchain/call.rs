@@ -0,0 +1,255 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Calls of service interfaces made by transactions of other services.
+//!
+//! A service may declare methods of its interface as types implementing the
+//! [`ServiceMethod`] trait and handle them in [`Service::handle_call`]. A transaction
+//! of another service invokes such a method with [`call_service`]. The call is executed
+//! within the same `Fork` and the same checkpoint as the calling transaction, so changes
+//! made by the callee are committed or rolled back together with the transaction.
+//!
+//! If a call cannot be dispatched, the framework returns an error which fails
+//! the transaction with `TransactionErrorType::ServiceCall`; errors returned by the callee
+//! keep their codes.
+//!
+//! [`ServiceMethod`]: trait.ServiceMethod.html
+//! [`Service::handle_call`]: trait.Service.html#method.handle_call
+//! [`call_service`]: fn.call_service.html
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use vec_map::VecMap;
+
+use storage::{Fork, StorageValue};
+use super::{ExecutionError, Service};
+use super::event::{enter_service, leave_service};
+
+/// Maximum depth of nested service calls within a single transaction.
+pub const MAX_CALL_DEPTH: usize = 8;
+
+/// Result of a service call with the method output encoded as bytes.
+pub type CallResult = Result<Vec<u8>, ExecutionError>;
+
+/// A method of a service interface that can be invoked by other services.
+///
+/// The type itself holds the method arguments; arguments and the output are encoded
+/// with `StorageValue`, so `encoding_struct!` types and primitive types can be used.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate exonum;
+/// use exonum::blockchain::ServiceMethod;
+/// use exonum::crypto::PublicKey;
+///
+/// encoding_struct! {
+///     /// Debits the wallet of the given owner.
+///     struct Debit {
+///         owner: &PublicKey,
+///         amount: u64,
+///     }
+/// }
+///
+/// impl ServiceMethod for Debit {
+///     const SERVICE_ID: u16 = 1;
+///     const METHOD_ID: u16 = 0;
+///     /// Remaining balance of the wallet.
+///     type Output = u64;
+/// }
+/// # fn main() {}
+/// ```
+pub trait ServiceMethod: StorageValue {
+    /// Identifier of the service that implements the method.
+    const SERVICE_ID: u16;
+    /// Identifier of the method within the service interface.
+    const METHOD_ID: u16;
+    /// Output of the method.
+    type Output: StorageValue;
+}
+
+/// A call of a service interface method passed to `Service::handle_call`.
+#[derive(Debug)]
+pub struct ServiceCall {
+    service_id: u16,
+    method_id: u16,
+    args: Vec<u8>,
+}
+
+impl ServiceCall {
+    /// Returns the identifier of the called service.
+    pub fn service_id(&self) -> u16 {
+        self.service_id
+    }
+
+    /// Returns the identifier of the called method.
+    pub fn method_id(&self) -> u16 {
+        self.method_id
+    }
+
+    /// Decodes the call arguments if this is a call of the method `M`.
+    /// Returns `None` otherwise.
+    pub fn args<M: ServiceMethod>(&self) -> Option<M> {
+        if self.service_id == M::SERVICE_ID && self.method_id == M::METHOD_ID {
+            Some(M::from_bytes(Cow::Borrowed(&self.args)))
+        } else {
+            None
+        }
+    }
+
+    /// Returns the error signalling that the method is not supported by the service.
+    pub fn unsupported(&self) -> ExecutionError {
+        ExecutionError::service_call(format!(
+            "Method {} is not supported by service {}",
+            self.method_id,
+            self.service_id
+        ))
+    }
+}
+
+/// Invokes the method `M` of another service within the currently executed transaction.
+///
+/// The callee works with the same `fork` as the caller. If the call fails, the whole
+/// transaction fails with the same error and all its changes are rolled back, even if
+/// the caller ignores the returned error.
+///
+/// Calls may be nested up to [`MAX_CALL_DEPTH`] levels.
+///
+/// [`MAX_CALL_DEPTH`]: constant.MAX_CALL_DEPTH.html
+pub fn call_service<M: ServiceMethod>(
+    fork: &mut Fork,
+    method: M,
+) -> Result<M::Output, ExecutionError> {
+    let call = ServiceCall {
+        service_id: M::SERVICE_ID,
+        method_id: M::METHOD_ID,
+        args: method.into_bytes(),
+    };
+
+    let result = match enter_call(call.service_id) {
+        Ok(services) => {
+            enter_service(call.service_id);
+            let result = match services.get(call.service_id as usize) {
+                Some(service) => service.handle_call(fork, &call),
+                None => Err(call_error(format!("Unknown service {}", call.service_id))),
+            };
+            leave_service();
+            leave_call();
+            result
+        }
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(output) => Ok(M::Output::from_bytes(Cow::Owned(output))),
+        Err(e) => {
+            record_error(&e);
+            Err(e)
+        }
+    }
+}
+
+fn call_error(description: String) -> ExecutionError {
+    ExecutionError::service_call(description)
+}
+
+struct CallContext {
+    services: Arc<VecMap<Box<Service>>>,
+    active_services: HashSet<u16>,
+    depth: usize,
+    error: Option<ExecutionError>,
+}
+
+thread_local! {
+    static CALL_CONTEXT: RefCell<Option<CallContext>> = RefCell::new(None);
+}
+
+/// Makes services available for calls while transactions of the block are executed.
+/// Calls are disabled again when the scope is dropped.
+pub(crate) struct CallScope;
+
+impl CallScope {
+    pub(crate) fn enter(
+        services: Arc<VecMap<Box<Service>>>,
+        active_services: HashSet<u16>,
+    ) -> CallScope {
+        CALL_CONTEXT.with(|context| {
+            *context.borrow_mut() = Some(CallContext {
+                services,
+                active_services,
+                depth: 0,
+                error: None,
+            });
+        });
+        CallScope
+    }
+}
+
+impl Drop for CallScope {
+    fn drop(&mut self) {
+        CALL_CONTEXT.with(|context| *context.borrow_mut() = None);
+    }
+}
+
+/// Resets the call state before the execution of a transaction and returns the error
+/// of the first failed call made by the previous transaction, if any.
+pub(crate) fn take_call_error() -> Option<ExecutionError> {
+    CALL_CONTEXT.with(|context| {
+        context.borrow_mut().as_mut().and_then(|context| {
+            context.depth = 0;
+            context.error.take()
+        })
+    })
+}
+
+fn enter_call(service_id: u16) -> Result<Arc<VecMap<Box<Service>>>, ExecutionError> {
+    CALL_CONTEXT.with(|context| {
+        let mut context = context.borrow_mut();
+        let context = match context.as_mut() {
+            Some(context) => context,
+            None => {
+                return Err(call_error(
+                    "Services can be called only during transaction execution".to_owned(),
+                ))
+            }
+        };
+        if context.depth >= MAX_CALL_DEPTH {
+            return Err(call_error(
+                format!("Maximum call depth {} is exceeded", MAX_CALL_DEPTH),
+            ));
+        }
+        if !context.active_services.contains(&service_id) {
+            return Err(call_error(format!("Service {} is not active", service_id)));
+        }
+        context.depth += 1;
+        Ok(Arc::clone(&context.services))
+    })
+}
+
+fn leave_call() {
+    CALL_CONTEXT.with(|context| if let Some(ref mut context) = *context.borrow_mut() {
+        context.depth -= 1;
+    });
+}
+
+fn record_error(error: &ExecutionError) {
+    CALL_CONTEXT.with(|context| if let Some(ref mut context) = *context.borrow_mut() {
+        if context.error.is_none() {
+            context.error = Some(error.clone());
+        }
+    });
+}
@@ -0,0 +1,216 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Compact certificates of the block commits.
+//!
+//! The precommits of a block differ only in the validator, time and signature, so
+//! a [`CommitCertificate`] stores the shared fields once together with a bitmap of the
+//! signers. The certificate is about half the size of the list of precommits and is
+//! verified against the `validator_keys` of the `StoredConfiguration` the same way.
+//!
+//! [`CommitCertificate`]: struct.CommitCertificate.html
+
+use std::collections::BTreeMap;
+
+use bit_vec::BitVec;
+use chrono::{DateTime, Utc};
+
+use crypto::{CryptoHash, Hash, Signature};
+use helpers::{Height, Round, ValidatorId};
+use messages::{Message, Precommit};
+use super::{Block, ValidatorKeys};
+
+encoding_struct! {
+    /// Time and signature of the precommit of a validator in a `CommitCertificate`.
+    struct CertificateVote {
+        /// Time of the `Precommit`.
+        time: DateTime<Utc>,
+        /// Signature of the `Precommit`.
+        signature: &Signature,
+    }
+}
+
+encoding_struct! {
+    /// Compact replacement of the list of precommits for a block.
+    struct CommitCertificate {
+        /// Height of the block.
+        height: Height,
+        /// Round in which the block has been committed.
+        round: Round,
+        /// Hash of the corresponding `Propose`.
+        propose_hash: &Hash,
+        /// Hash of the block.
+        block_hash: &Hash,
+        /// Bitmap of the validators whose precommits are included. The bit with index `i`
+        /// corresponds to the validator with the `ValidatorId(i)`.
+        signers: BitVec,
+        /// Times and signatures of the precommits in the ascending order of the validators.
+        votes: Vec<CertificateVote>,
+    }
+}
+
+/// Reason why a commit certificate cannot be created or is invalid.
+#[derive(Debug, Clone, PartialEq, Eq, Fail)]
+pub enum CertificateError {
+    /// There are no precommits to create the certificate from.
+    #[fail(display = "No precommits for the certificate")]
+    Empty,
+    /// The precommits are given for different blocks, heights or rounds.
+    #[fail(display = "Precommits are given for different blocks")]
+    Inconsistent,
+    /// The validator has more than one precommit.
+    #[fail(display = "Duplicate precommit of the validator {:?}", _0)]
+    Duplicate(ValidatorId),
+    /// The number of signers does not match the number of votes.
+    #[fail(display = "Signers bitmap does not match the votes")]
+    Malformed,
+    /// The signer is not listed in the configuration.
+    #[fail(display = "Unknown validator {:?}", _0)]
+    UnknownValidator(ValidatorId),
+    /// There are not enough votes to commit the block.
+    #[fail(display = "Not enough votes: {}, required: {}", votes, required)]
+    NotEnoughVotes {
+        /// Number of votes in the certificate.
+        votes: usize,
+        /// Number of votes required by the configuration.
+        required: usize,
+    },
+    /// The signature of the precommit is invalid.
+    #[fail(display = "Invalid signature of the validator {:?}", _0)]
+    InvalidSignature(ValidatorId),
+    /// The certificate is given for another block.
+    #[fail(display = "Certificate does not match the block")]
+    BlockMismatch,
+}
+
+impl CommitCertificate {
+    /// Creates the certificate from the precommits of a block.
+    pub fn from_precommits(precommits: &[Precommit]) -> Result<Self, CertificateError> {
+        let first = precommits.first().ok_or(CertificateError::Empty)?;
+        let mut by_validator = BTreeMap::new();
+        for precommit in precommits {
+            if precommit.height() != first.height() || precommit.round() != first.round() ||
+                precommit.propose_hash() != first.propose_hash() ||
+                precommit.block_hash() != first.block_hash()
+            {
+                return Err(CertificateError::Inconsistent);
+            }
+            let validator = precommit.validator();
+            if by_validator.insert(validator, precommit).is_some() {
+                return Err(CertificateError::Duplicate(validator));
+            }
+        }
+
+        // Unwrap is OK here, because there is at least one precommit.
+        let max_validator = *by_validator.keys().next_back().unwrap();
+        let mut signers = BitVec::from_elem(max_validator.0 as usize + 1, false);
+        let mut votes = Vec::with_capacity(by_validator.len());
+        for (validator, precommit) in by_validator {
+            signers.set(validator.0 as usize, true);
+            votes.push(CertificateVote::new(
+                precommit.time(),
+                precommit.raw().signature(),
+            ));
+        }
+        Ok(CommitCertificate::new(
+            first.height(),
+            first.round(),
+            first.propose_hash(),
+            first.block_hash(),
+            signers,
+            votes,
+        ))
+    }
+
+    /// Restores the signed precommits contained in the certificate.
+    pub fn precommits(&self) -> Vec<Precommit> {
+        self.signed_validators()
+            .into_iter()
+            .zip(self.votes())
+            .map(|(validator, vote)| {
+                Precommit::new_with_signature(
+                    validator,
+                    self.height(),
+                    self.round(),
+                    self.propose_hash(),
+                    self.block_hash(),
+                    vote.time(),
+                    vote.signature(),
+                )
+            })
+            .collect()
+    }
+
+    /// Checks that the certificate contains the valid precommits of the byzantine majority
+    /// of the given validators.
+    pub fn verify(&self, validator_keys: &[ValidatorKeys]) -> Result<(), CertificateError> {
+        let signed_validators = self.signed_validators();
+        if signed_validators.len() != self.votes().len() {
+            return Err(CertificateError::Malformed);
+        }
+        if let Some(&validator) = signed_validators.iter().find(|validator| {
+            validator.0 as usize >= validator_keys.len()
+        })
+        {
+            return Err(CertificateError::UnknownValidator(validator));
+        }
+
+        let required = validator_keys.len() * 2 / 3 + 1;
+        let votes = self.votes().len();
+        if votes < required {
+            return Err(CertificateError::NotEnoughVotes { votes, required });
+        }
+
+        for precommit in self.precommits() {
+            let validator = precommit.validator();
+            let key = &validator_keys[validator.0 as usize].consensus_key;
+            if !precommit.verify_signature(key) {
+                return Err(CertificateError::InvalidSignature(validator));
+            }
+        }
+        Ok(())
+    }
+
+    // Returns the validators whose precommits are included.
+    fn signed_validators(&self) -> Vec<ValidatorId> {
+        self.signers()
+            .iter()
+            .enumerate()
+            .filter(|&(_, signed)| signed)
+            .map(|(index, _)| ValidatorId(index as u16))
+            .collect()
+    }
+}
+
+/// Block with the compact certificate of its commit.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CompactBlockProof {
+    /// Block.
+    pub block: Block,
+    /// Certificate of the block commit.
+    pub certificate: CommitCertificate,
+}
+
+impl CompactBlockProof {
+    /// Checks that the certificate is given for the block and is valid for the given
+    /// validators.
+    pub fn verify(&self, validator_keys: &[ValidatorKeys]) -> Result<(), CertificateError> {
+        if self.certificate.block_hash() != &self.block.hash() ||
+            self.certificate.height() != self.block.height()
+        {
+            return Err(CertificateError::BlockMismatch);
+        }
+        self.certificate.verify(validator_keys)
+    }
+}
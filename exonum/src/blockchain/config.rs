@@ -53,6 +53,12 @@ pub struct StoredConfiguration {
     /// Keys are `service_name` from `Service` trait and values are the serialized json.
     #[serde(default)]
     pub services: BTreeMap<String, serde_json::Value>,
+    /// Target data schema versions of services.
+    /// Keys are `service_name` from `Service` trait and values are the schema versions.
+    /// Once the configuration becomes actual, data of the listed services is migrated
+    /// up to the specified versions, see `Service::migrate`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub service_versions: BTreeMap<String, u16>,
 }
 
 /// Consensus algorithm parameters.
@@ -70,11 +76,37 @@ pub struct ConsensusConfig {
     pub max_message_len: u32,
     /// `TimeoutAdjuster` configuration.
     pub timeout_adjuster: TimeoutAdjusterConfig,
+    /// `LeaderElection` configuration.
+    /// It is omitted from the serialized configuration if the default round-robin election
+    /// is used, so hashes of existing configurations are not affected.
+    #[serde(default, skip_serializing_if = "LeaderElectionConfig::is_round_robin")]
+    pub leader_election: LeaderElectionConfig,
+    /// Maximum interval between blocks (in milliseconds) if empty blocks are skipped.
+    /// If set, leaders do not propose a block while the transactions pool is empty,
+    /// unless the interval has passed since the time of the last block. Otherwise a block
+    /// is proposed every round, even if it is empty.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_idle_interval: Option<Milliseconds>,
+    /// Maximum total length of the transactions per block (in bytes).
+    /// If set, leaders do not include more transactions into a `Propose`, and validators
+    /// do not vote for proposes exceeding the limit.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_block_size: Option<u32>,
+    /// Maximum time (in milliseconds) by which the time of a proposed block may be ahead
+    /// of the time of a validator. Validators do not vote for proposes exceeding it.
+    #[serde(default = "ConsensusConfig::default_max_propose_time_drift")]
+    pub max_propose_time_drift: Milliseconds,
 }
 
 impl ConsensusConfig {
     /// Default value for max_message_len.
     pub const DEFAULT_MAX_MESSAGE_LEN: u32 = 1024 * 1024; // 1 MB
+    /// Default value for max_propose_time_drift.
+    pub const DEFAULT_MAX_PROPOSE_TIME_DRIFT: Milliseconds = 10_000;
+
+    fn default_max_propose_time_drift() -> Milliseconds {
+        Self::DEFAULT_MAX_PROPOSE_TIME_DRIFT
+    }
 
     /// Checks if propose timeout is less than round timeout. Warns if fails.
     #[doc(hidden)]
@@ -105,6 +137,10 @@ impl Default for ConsensusConfig {
             txs_block_limit: 1000,
             max_message_len: Self::DEFAULT_MAX_MESSAGE_LEN,
             timeout_adjuster: TimeoutAdjusterConfig::Constant { timeout: 500 },
+            leader_election: LeaderElectionConfig::RoundRobin,
+            max_idle_interval: None,
+            max_block_size: None,
+            max_propose_time_drift: Self::DEFAULT_MAX_PROPOSE_TIME_DRIFT,
         }
     }
 }
@@ -186,6 +222,48 @@ impl StoredConfiguration {
             )));
         }
 
+        if config.consensus.max_idle_interval == Some(0) {
+            return Err(JsonError::custom("max_idle_interval must be positive"));
+        }
+
+        if config.consensus.max_block_size == Some(0) {
+            return Err(JsonError::custom("max_block_size must be positive"));
+        }
+
+        // Check leader election.
+        let validators_count = config.validator_keys.len();
+        match config.consensus.leader_election {
+            LeaderElectionConfig::RoundRobin => {}
+            LeaderElectionConfig::ReputationWeighted { window } => {
+                if window <= validators_count as u64 {
+                    return Err(JsonError::custom(format!(
+                        "ReputationWeighted leader election: window ({}) must be greater than \
+                        the number of validators ({})",
+                        window,
+                        validators_count
+                    )));
+                }
+            }
+            LeaderElectionConfig::Weighted { ref weights } => {
+                if weights.len() != validators_count {
+                    return Err(JsonError::custom(format!(
+                        "Weighted leader election: number of weights ({}) must be equal to \
+                        the number of validators ({})",
+                        weights.len(),
+                        validators_count
+                    )));
+                }
+                let total = weights.iter().fold(0u64, |total, w| total.saturating_add(*w));
+                if total == 0 || total > LeaderElectionConfig::MAX_TOTAL_WEIGHT {
+                    return Err(JsonError::custom(format!(
+                        "Weighted leader election: total weight must be in the (0..{}] range: {}",
+                        LeaderElectionConfig::MAX_TOTAL_WEIGHT,
+                        total
+                    )));
+                }
+            }
+        }
+
         Ok(config)
     }
 }
@@ -238,6 +316,44 @@ pub enum TimeoutAdjusterConfig {
     },
 }
 
+/// `LeaderElection` config.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "type")]
+pub enum LeaderElectionConfig {
+    /// Validators lead the rounds in turn: the leader of a round is
+    /// `(height + round) % validators_count`.
+    RoundRobin,
+    /// Validators that have not proposed any of the last `window` committed blocks lead
+    /// the rounds after the other validators. Every `window` heights all validators lead
+    /// the rounds in turn for `validators_count` heights, so that recovered validators
+    /// may restore their reputation.
+    ReputationWeighted {
+        /// Number of the last blocks taken into account.
+        window: u64,
+    },
+    /// Validators lead the first round of a height proportionally to their weights.
+    Weighted {
+        /// Weights of the validators in the order of `validator_keys`.
+        weights: Vec<u64>,
+    },
+}
+
+impl LeaderElectionConfig {
+    /// Maximal total weight of the validators for the `Weighted` leader election.
+    pub const MAX_TOTAL_WEIGHT: u64 = 65_536;
+
+    /// Returns `true` if this is the `RoundRobin` leader election.
+    pub fn is_round_robin(&self) -> bool {
+        *self == LeaderElectionConfig::RoundRobin
+    }
+}
+
+impl Default for LeaderElectionConfig {
+    fn default() -> Self {
+        LeaderElectionConfig::RoundRobin
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use toml;
@@ -461,6 +577,88 @@ mod tests {
         serialize_deserialize(&configuration);
     }
 
+    #[test]
+    fn leader_election_config_toml() {
+        check_toml_roundtrip(&LeaderElectionConfig::ReputationWeighted { window: 10 });
+        check_toml_roundtrip(&LeaderElectionConfig::Weighted { weights: vec![1, 2, 3] });
+    }
+
+    #[test]
+    fn round_robin_leader_election_is_not_serialized() {
+        let mut configuration = create_test_configuration();
+        let serialized = String::from_utf8(configuration.try_serialize().unwrap()).unwrap();
+        assert!(!serialized.contains("leader_election"));
+
+        configuration.consensus.leader_election =
+            LeaderElectionConfig::ReputationWeighted { window: 10 };
+        assert_eq!(configuration, serialize_deserialize(&configuration));
+    }
+
+    #[test]
+    #[should_panic(expected = "ReputationWeighted leader election: window (3) must be greater")]
+    fn reputation_leader_election_small_window() {
+        let mut configuration = create_test_configuration();
+        configuration.consensus.leader_election =
+            LeaderElectionConfig::ReputationWeighted { window: 3 };
+        serialize_deserialize(&configuration);
+    }
+
+    #[test]
+    #[should_panic(expected = "Weighted leader election: number of weights (2) must be equal")]
+    fn weighted_leader_election_invalid_weights_count() {
+        let mut configuration = create_test_configuration();
+        configuration.consensus.leader_election = LeaderElectionConfig::Weighted {
+            weights: vec![1, 2],
+        };
+        serialize_deserialize(&configuration);
+    }
+
+    #[test]
+    #[should_panic(expected = "Weighted leader election: total weight must be in the")]
+    fn weighted_leader_election_zero_weights() {
+        let mut configuration = create_test_configuration();
+        configuration.consensus.leader_election = LeaderElectionConfig::Weighted {
+            weights: vec![0, 0, 0],
+        };
+        serialize_deserialize(&configuration);
+    }
+
+    #[test]
+    fn max_idle_interval() {
+        let mut configuration = create_test_configuration();
+        let serialized = String::from_utf8(configuration.try_serialize().unwrap()).unwrap();
+        assert!(!serialized.contains("max_idle_interval"));
+
+        configuration.consensus.max_idle_interval = Some(60_000);
+        assert_eq!(configuration, serialize_deserialize(&configuration));
+    }
+
+    #[test]
+    #[should_panic(expected = "max_idle_interval must be positive")]
+    fn zero_max_idle_interval() {
+        let mut configuration = create_test_configuration();
+        configuration.consensus.max_idle_interval = Some(0);
+        serialize_deserialize(&configuration);
+    }
+
+    #[test]
+    fn max_block_size() {
+        let mut configuration = create_test_configuration();
+        let serialized = String::from_utf8(configuration.try_serialize().unwrap()).unwrap();
+        assert!(!serialized.contains("max_block_size"));
+
+        configuration.consensus.max_block_size = Some(64 * 1024);
+        assert_eq!(configuration, serialize_deserialize(&configuration));
+    }
+
+    #[test]
+    #[should_panic(expected = "max_block_size must be positive")]
+    fn zero_max_block_size() {
+        let mut configuration = create_test_configuration();
+        configuration.consensus.max_block_size = Some(0);
+        serialize_deserialize(&configuration);
+    }
+
     fn create_test_configuration() -> StoredConfiguration {
         let validator_keys = (1..4)
             .map(|i| {
@@ -478,6 +676,7 @@ mod tests {
             consensus: ConsensusConfig::default(),
             services: BTreeMap::new(),
             majority_count: None,
+            service_versions: BTreeMap::new(),
         }
     }
 
@@ -0,0 +1,163 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Structured events emitted by transactions.
+//!
+//! During `Transaction::execute` a service may emit events declared as types implementing
+//! the [`ServiceEvent`] trait with [`emit_event`]. Events of a successfully executed
+//! transaction are stored in the [`transaction_events`] list, which is covered by the
+//! block's `state_hash`. Events of failed or panicked transactions are discarded together
+//! with the other changes of the transaction.
+//!
+//! A service may emit only its own events: the `SERVICE_ID` of the event must be the one
+//! of the executed transaction or of the service called with `call_service`.
+//!
+//! [`ServiceEvent`]: trait.ServiceEvent.html
+//! [`emit_event`]: fn.emit_event.html
+//! [`transaction_events`]: struct.Schema.html#method.transaction_events
+
+use std::borrow::Cow;
+use std::cell::RefCell;
+
+use storage::StorageValue;
+
+/// An event that can be emitted by transactions of a service.
+///
+/// # Examples
+///
+/// ```
+/// # #[macro_use] extern crate exonum;
+/// use exonum::blockchain::ServiceEvent;
+/// use exonum::crypto::PublicKey;
+///
+/// encoding_struct! {
+///     /// Funds were transferred between two wallets.
+///     struct Transferred {
+///         from: &PublicKey,
+///         to: &PublicKey,
+///         amount: u64,
+///     }
+/// }
+///
+/// impl ServiceEvent for Transferred {
+///     const SERVICE_ID: u16 = 1;
+///     const EVENT_ID: u16 = 0;
+/// }
+/// # fn main() {}
+/// ```
+pub trait ServiceEvent: StorageValue {
+    /// Identifier of the service that emits the event.
+    const SERVICE_ID: u16;
+    /// Identifier of the event within the service.
+    const EVENT_ID: u16;
+}
+
+encoding_struct! {
+    /// Event emitted by a transaction as it is stored in the blockchain.
+    struct TransactionEvent {
+        /// Identifier of the service that emitted the event.
+        service_id: u16,
+        /// Identifier of the event within the service.
+        event_id: u16,
+        /// Event encoded as bytes.
+        data: &[u8],
+    }
+}
+
+impl TransactionEvent {
+    /// Decodes the event if it is the event `E`. Returns `None` otherwise.
+    pub fn event<E: ServiceEvent>(&self) -> Option<E> {
+        if self.service_id() == E::SERVICE_ID && self.event_id() == E::EVENT_ID {
+            Some(E::from_bytes(Cow::Borrowed(self.data())))
+        } else {
+            None
+        }
+    }
+}
+
+struct EventsContext {
+    // Services executing the transaction: the service of the transaction followed by
+    // the services called by it.
+    services: Vec<u16>,
+    events: Vec<TransactionEvent>,
+}
+
+thread_local! {
+    static EMITTED_EVENTS: RefCell<Option<EventsContext>> = RefCell::new(None);
+}
+
+/// Emits the event on behalf of the currently executed transaction.
+///
+/// Events emitted outside of `Transaction::execute` are ignored.
+///
+/// # Panics
+///
+/// If the event belongs to a service other than the one currently executing the transaction,
+/// so the transaction fails.
+pub fn emit_event<E: ServiceEvent>(event: E) {
+    let service_id = EMITTED_EVENTS.with(|context| {
+        context.borrow().as_ref().and_then(
+            |context| context.services.last().cloned(),
+        )
+    });
+    match service_id {
+        None => return,
+        Some(service_id) if service_id != E::SERVICE_ID => panic!(
+            "Service {} cannot emit events of service {}",
+            service_id,
+            E::SERVICE_ID
+        ),
+        Some(_) => {}
+    }
+
+    let event = TransactionEvent::new(E::SERVICE_ID, E::EVENT_ID, &event.into_bytes());
+    EMITTED_EVENTS.with(|context| if let Some(ref mut context) = *context.borrow_mut() {
+        context.events.push(event);
+    });
+}
+
+/// Starts collecting events before the execution of a transaction of the given service.
+pub(crate) fn start_events(service_id: u16) {
+    EMITTED_EVENTS.with(|context| {
+        *context.borrow_mut() = Some(EventsContext {
+            services: vec![service_id],
+            events: Vec::new(),
+        })
+    });
+}
+
+/// Marks the given service as the one emitting the events until `leave_service` is called.
+pub(crate) fn enter_service(service_id: u16) {
+    EMITTED_EVENTS.with(|context| if let Some(ref mut context) = *context.borrow_mut() {
+        context.services.push(service_id);
+    });
+}
+
+/// Returns the emission of the events to the caller of the service.
+pub(crate) fn leave_service() {
+    EMITTED_EVENTS.with(|context| if let Some(ref mut context) = *context.borrow_mut() {
+        context.services.pop();
+    });
+}
+
+/// Stops collecting events and returns the events emitted since `start_events`.
+pub(crate) fn take_events() -> Vec<TransactionEvent> {
+    EMITTED_EVENTS.with(|context| {
+        context
+            .borrow_mut()
+            .take()
+            .map(|context| context.events)
+            .unwrap_or_default()
+    })
+}
@@ -0,0 +1,175 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Evidence of Byzantine behavior of validators.
+//!
+//! A validator equivocates if it signs two conflicting consensus messages for the same
+//! height and round: two different proposes, prevotes for different proposes or precommits
+//! for different blocks. Nodes detect such messages during the consensus and report them
+//! with the [`ReportEvidence`] core transaction. Valid evidence is stored in the
+//! [`evidence`] index, which is covered by the block's `state_hash`, so services can punish
+//! the offending validators, for example, by removing them from the validator set with
+//! the configuration service.
+//!
+//! [`ReportEvidence`]: ../messages/struct.ReportEvidence.html
+//! [`evidence`]: struct.Schema.html#method.evidence
+
+use crypto::{CryptoHash, PublicKey};
+use helpers::{Height, Round, ValidatorId};
+use messages::{Any, ConsensusMessage, Message, RawMessage, ReportEvidence};
+use storage::Fork;
+use super::{ExecutionError, ExecutionResult, Schema, Transaction};
+
+/// Error code of the `ExecutionError`s returned by the `ReportEvidence` transaction if
+/// the evidence is already known, belongs to a future height or the offender is not
+/// a validator at the evidence height.
+pub const EVIDENCE_ERROR_CODE: u8 = 0;
+
+encoding_struct! {
+    /// Two conflicting consensus messages signed by the same validator.
+    struct Evidence {
+        /// Consensus public key of the validator.
+        validator: &PublicKey,
+        /// Height of the messages.
+        height: Height,
+        /// Round of the messages.
+        round: Round,
+        /// The conflicting messages ordered by their hashes.
+        messages: Vec<RawMessage>,
+    }
+}
+
+impl Evidence {
+    /// Creates evidence from two messages of the validator with the given consensus key.
+    /// Returns `None` if the messages do not conflict.
+    pub fn from_messages(
+        validator: &PublicKey,
+        first: &ConsensusMessage,
+        second: &ConsensusMessage,
+    ) -> Option<Evidence> {
+        if !is_conflicting(first, second) {
+            return None;
+        }
+        let mut messages = vec![first.raw().clone(), second.raw().clone()];
+        messages.sort_by_key(|msg| msg.hash());
+        Some(Evidence::new(
+            validator,
+            first.height(),
+            first.round(),
+            messages,
+        ))
+    }
+
+    /// Returns the conflicting messages or `None` if the evidence does not contain two
+    /// consensus messages.
+    pub fn consensus_messages(&self) -> Option<(ConsensusMessage, ConsensusMessage)> {
+        let mut messages = Vec::new();
+        for raw in self.messages() {
+            match Any::from_raw(raw) {
+                Ok(Any::Consensus(msg)) => messages.push(msg),
+                _ => return None,
+            }
+        }
+        if messages.len() != 2 {
+            return None;
+        }
+        let second = messages.pop().unwrap();
+        let first = messages.pop().unwrap();
+        Some((first, second))
+    }
+
+    /// Returns the id of the offending validator in the validator set of the evidence height.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the evidence does not contain consensus messages.
+    pub fn validator_id(&self) -> ValidatorId {
+        self.consensus_messages()
+            .expect("Evidence without consensus messages")
+            .0
+            .validator()
+    }
+
+    /// Verifies the internal consistency of the evidence: it should contain two conflicting
+    /// messages of the evidence height and round ordered by their hashes and signed with
+    /// the `validator` key.
+    pub fn verify(&self) -> bool {
+        let (first, second) = match self.consensus_messages() {
+            Some(messages) => messages,
+            None => return false,
+        };
+        first.raw().hash() < second.raw().hash() && first.height() == self.height() &&
+            first.round() == self.round() && is_conflicting(&first, &second) &&
+            first.verify(self.validator()) && second.verify(self.validator())
+    }
+}
+
+/// Returns `true` if the messages are different messages of the same kind signed by the same
+/// validator for the same height and round.
+pub(crate) fn is_conflicting(first: &ConsensusMessage, second: &ConsensusMessage) -> bool {
+    if first.validator() != second.validator() || first.height() != second.height() ||
+        first.round() != second.round()
+    {
+        return false;
+    }
+    match (first, second) {
+        (&ConsensusMessage::Propose(ref first), &ConsensusMessage::Propose(ref second)) => {
+            first.hash() != second.hash()
+        }
+        (&ConsensusMessage::Prevote(ref first), &ConsensusMessage::Prevote(ref second)) => {
+            first.propose_hash() != second.propose_hash()
+        }
+        (&ConsensusMessage::Precommit(ref first), &ConsensusMessage::Precommit(ref second)) => {
+            first.propose_hash() != second.propose_hash() ||
+                first.block_hash() != second.block_hash()
+        }
+        _ => false,
+    }
+}
+
+impl Transaction for ReportEvidence {
+    fn verify(&self) -> bool {
+        self.verify_signature(self.from()) && self.evidence().verify()
+    }
+
+    fn execute(&self, fork: &mut Fork) -> ExecutionResult {
+        let evidence = self.evidence();
+        let evidence_hash = evidence.hash();
+        let mut schema = Schema::new(fork);
+
+        if schema.evidence().contains(&evidence_hash) {
+            return Err(evidence_error("Evidence is already reported"));
+        }
+        if evidence.height() > schema.height().next() {
+            return Err(evidence_error("Evidence of a future height"));
+        }
+        let config = schema.configuration_by_height(evidence.height());
+        let is_validator = config
+            .validator_keys
+            .get(evidence.validator_id().0 as usize)
+            .map_or(false, |keys| keys.consensus_key == *evidence.validator());
+        if !is_validator {
+            return Err(evidence_error(
+                "Offender is not a validator at the evidence height",
+            ));
+        }
+
+        schema.evidence_mut().put(&evidence_hash, evidence);
+        Ok(())
+    }
+}
+
+fn evidence_error(description: &str) -> ExecutionError {
+    ExecutionError::with_description(EVIDENCE_ERROR_CODE, description.to_owned())
+}
@@ -34,7 +34,7 @@
 
 use std::sync::Arc;
 use std::collections::BTreeMap;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::mem;
 use std::fmt;
 use std::iter;
@@ -43,29 +43,53 @@ use std::net::SocketAddr;
 use std::error::Error as StdError;
 use failure;
 
+use chrono::{DateTime, TimeZone, Utc};
 use vec_map::VecMap;
 use byteorder::{ByteOrder, LittleEndian};
 use mount::Mount;
 
 use crypto::{self, CryptoHash, Hash, PublicKey, SecretKey};
-use messages::{CONSENSUS as CORE_SERVICE, Connect, Precommit, RawMessage};
+use messages::{CONSENSUS as CORE_SERVICE, Connect, Message, Precommit, RawMessage,
+               ReportEvidence, REPORT_EVIDENCE_MESSAGE_ID};
 use storage::{Database, Error, Fork, Patch, Snapshot};
 use helpers::{Height, Round, ValidatorId};
 use node::ApiSender;
+use node::signer::{LocalSigner, Signer};
 use encoding::Error as MessageError;
-
-pub use self::block::{Block, BlockProof, SCHEMA_MAJOR_VERSION};
-pub use self::schema::{Schema, TxLocation};
+use self::block::BlockTimeScope;
+use self::call::{take_call_error, CallScope};
+use self::event::{start_events, take_events};
+
+pub use self::block::{block_time, Block, BlockProof, SCHEMA_MAJOR_VERSION};
+pub use self::certificate::{CertificateError, CertificateVote, CommitCertificate,
+                            CompactBlockProof};
+pub use self::schema::{ConsensusLock, Schema, TxLocation, TxPoolError, EVIDENCE_TABLE,
+                       TRANSACTION_EVENTS_TABLE};
 pub use self::genesis::GenesisConfig;
-pub use self::config::{ConsensusConfig, StoredConfiguration, TimeoutAdjusterConfig, ValidatorKeys};
+pub use self::config::{ConsensusConfig, LeaderElectionConfig, StoredConfiguration,
+                       TimeoutAdjusterConfig, ValidatorKeys};
 pub use self::service::{ApiContext, Service, ServiceContext, SharedNodeState};
+pub use self::event::{emit_event, ServiceEvent, TransactionEvent};
+pub use self::evidence::{Evidence, EVIDENCE_ERROR_CODE};
+pub use self::restore::{StateSyncError, StateSyncUnsupported};
+pub(crate) use self::schema::HASHED_INDEXES;
+pub use self::subscription::{Notification, Subscription, SubscriptionFilter, Subscriptions,
+                             DEFAULT_SUBSCRIPTION_BUFFER};
+pub(crate) use self::evidence::is_conflicting;
+pub use self::call::{call_service, CallResult, ServiceCall, ServiceMethod, MAX_CALL_DEPTH};
 pub use self::transaction::{ExecutionError, ExecutionResult, Transaction, TransactionError,
                             TransactionErrorType, TransactionResult, TransactionSet};
 
 mod block;
+mod call;
+mod certificate;
+mod event;
+mod evidence;
+mod restore;
 mod schema;
 mod genesis;
 mod service;
+mod subscription;
 #[macro_use]
 mod transaction;
 #[cfg(test)]
@@ -80,7 +104,9 @@ pub struct Blockchain {
     db: Arc<Database>,
     service_map: Arc<VecMap<Box<Service>>>,
     service_keypair: (PublicKey, SecretKey),
+    service_signer: Arc<Signer>,
     api_sender: ApiSender,
+    subscriptions: Subscriptions,
 }
 
 impl Blockchain {
@@ -104,11 +130,17 @@ impl Blockchain {
             service_map.insert(id, service);
         }
 
+        let service_signer = Arc::new(LocalSigner::new(
+            service_public_key,
+            service_secret_key.clone(),
+        ));
         Blockchain {
             db: storage.into(),
             service_map: Arc::new(service_map),
             service_keypair: (service_public_key, service_secret_key),
+            service_signer,
             api_sender,
+            subscriptions: Subscriptions::default(),
         }
     }
 
@@ -121,11 +153,52 @@ impl Blockchain {
         }
     }
 
+    /// Returns the signer of the messages with the service key of the node.
+    pub fn service_signer(&self) -> &Arc<Signer> {
+        &self.service_signer
+    }
+
+    /// Replaces the signer of the messages with the service key, e.g. with a remote one.
+    /// The signer must use the service public key of the node.
+    pub fn set_service_signer(&mut self, signer: Arc<Signer>) {
+        assert_eq!(
+            signer.public_key(),
+            &self.service_keypair.0,
+            "Service signer uses a different public key"
+        );
+        self.service_signer = signer;
+    }
+
+    /// Returns the registry of the subscribers notified about the committed blocks.
+    pub fn subscriptions(&self) -> &Subscriptions {
+        &self.subscriptions
+    }
+
     /// Returns service `VecMap` for all our services.
     pub fn service_map(&self) -> &Arc<VecMap<Box<Service>>> {
         &self.service_map
     }
 
+    /// Returns `true` if the service with the given identifier is registered in this blockchain
+    /// and is active in the given configuration.
+    ///
+    /// A service is active if its `service_name` is present among the `services` of the
+    /// configuration. Services registered after the genesis block stay dormant until
+    /// a configuration listing them becomes actual; at that height their `initialize`
+    /// method is invoked as a part of the block. Dormant services do not participate
+    /// in the `state_hash` of blocks, do not receive `handle_commit` events and their
+    /// transactions are not accepted by the node. Core transactions are always active.
+    pub fn is_service_active(&self, service_id: u16, config: &StoredConfiguration) -> bool {
+        if service_id == CORE_SERVICE {
+            return true;
+        }
+        self.service_map
+            .get(service_id as usize)
+            .map_or(false, |service| {
+                config.services.contains_key(service.service_name())
+            })
+    }
+
     /// Creates a readonly snapshot of the current storage state.
     pub fn snapshot(&self) -> Box<Snapshot> {
         self.db.snapshot()
@@ -143,7 +216,15 @@ impl Blockchain {
     ///
     /// - Blockchain has service with the `service_id` of given raw message.
     /// - Service can deserialize given raw message.
+    ///
+    /// Raw messages of the core service are converted into core transactions.
     pub fn tx_from_raw(&self, raw: RawMessage) -> Result<Box<Transaction>, MessageError> {
+        if raw.service_id() == CORE_SERVICE {
+            return match raw.message_type() {
+                REPORT_EVIDENCE_MESSAGE_ID => Ok(Box::new(ReportEvidence::from_raw(raw)?)),
+                message_type => Err(MessageError::IncorrectMessageType { message_type }),
+            };
+        }
         let id = raw.service_id() as usize;
         let service = self.service_map.get(id).ok_or_else(|| {
             MessageError::from("Service not found.")
@@ -157,6 +238,13 @@ impl Blockchain {
         self.db.merge(patch)
     }
 
+    /// Commits changes from the patch to the blockchain storage and flushes them to the disk.
+    /// See [`Database::merge_sync`](../storage/trait.Database.html#tymethod.merge_sync)
+    /// for details.
+    pub fn merge_sync(&mut self, patch: Patch) -> Result<(), Error> {
+        self.db.merge_sync(patch)
+    }
+
     /// Returns the hash of latest committed block.
     ///
     /// # Panics
@@ -180,6 +268,9 @@ impl Blockchain {
 
     /// Creates and commits the genesis block for the given genesis configuration
     /// if the blockchain was not initialized.
+    ///
+    /// Returns an error if the blockchain was created with a different version
+    /// of the core information schema.
     pub fn initialize(&mut self, cfg: GenesisConfig) -> Result<(), Error> {
         let has_genesis_block = !Schema::new(&self.snapshot())
             .block_hashes_by_height()
@@ -187,7 +278,27 @@ impl Blockchain {
         if !has_genesis_block {
             self.create_genesis_block(cfg)?;
         }
-        Ok(())
+        // The schema version occupies the first bytes of the block in all the versions.
+        let schema_version = self.last_block().schema_version();
+        if schema_version != SCHEMA_MAJOR_VERSION {
+            return Err(Error::new(format!(
+                "Blockchain was created with the core schema version {}, \
+                 while the supported version is {}. Blocks of the previous versions \
+                 cannot be migrated, so the node should be started with a new database \
+                 and synchronized with the network",
+                schema_version,
+                SCHEMA_MAJOR_VERSION
+            )));
+        }
+
+        let patch = {
+            let mut fork = self.fork();
+            let mut schema = Schema::new(&mut fork);
+            schema.initialize_transactions_pool_len();
+            schema.migrate_transactions_pool();
+            fork.into_patch()
+        };
+        self.merge(patch)
     }
 
     /// Creates and commits the genesis block for the given genesis configuration.
@@ -199,6 +310,7 @@ impl Blockchain {
             consensus: cfg.consensus,
             services: BTreeMap::new(),
             majority_count: None,
+            service_versions: BTreeMap::new(),
         };
 
         let patch = {
@@ -206,6 +318,9 @@ impl Blockchain {
             // Update service tables
             for (_, service) in self.service_map.iter() {
                 let cfg = service.initialize(&mut fork);
+                Schema::new(&mut fork)
+                    .service_schema_versions_mut()
+                    .put(&service.service_id(), service.schema_version());
                 let name = service.service_name();
                 if config_propose.services.contains_key(name) {
                     panic!(
@@ -225,7 +340,7 @@ impl Blockchain {
                 schema.commit_configuration(config_propose);
             };
             self.merge(fork.into_patch())?;
-            self.create_patch(ValidatorId::zero(), Height::zero(), &[])
+            self.create_patch(ValidatorId::zero(), Height::zero(), Utc.timestamp(0, 0), &[])
                 .1
         };
         self.merge(patch)?;
@@ -255,10 +370,20 @@ impl Blockchain {
     /// Executes the given transactions from pool.
     /// Then it collects the resulting changes from the current storage state and returns them
     /// with the hash of resulting block.
+    ///
+    /// The `time` of the block is available to the transactions through [`block_time`].
+    ///
+    /// # Panics
+    ///
+    /// - If the data of a service cannot be migrated to the schema version required
+    ///   by the configuration which becomes actual at the given height.
+    ///
+    /// [`block_time`]: fn.block_time.html
     pub fn create_patch(
         &self,
         proposer_id: ValidatorId,
         height: Height,
+        time: DateTime<Utc>,
         tx_hashes: &[Hash],
     ) -> (Hash, Patch) {
         // Create fork
@@ -267,6 +392,39 @@ impl Blockchain {
         let block_hash = {
             // Get last hash.
             let last_hash = self.last_hash();
+            // Get the configuration the block is created under. It may be absent only
+            // if the blockchain has not been initialized.
+            let config = {
+                let schema = Schema::new(&fork);
+                if schema.configs_actual_from().is_empty() {
+                    None
+                } else {
+                    Some(schema.configuration_by_height(height))
+                }
+            };
+            // Initialize services that become active at this height and migrate
+            // the data of services with updated schema versions.
+            if let Some(ref config) = config {
+                self.activate_services(height, config, &mut fork);
+                if config.actual_from == height {
+                    // The node cannot build the block with the same state as the other nodes
+                    // without the migrated data, so it must not continue.
+                    if let Err(e) = self.migrate_services(&config.service_versions, &mut fork) {
+                        panic!("{}. Update the node software to continue.", e);
+                    }
+                }
+            }
+            // Make active services available for calls from transactions.
+            let active_services: HashSet<u16> = self.service_map
+                .values()
+                .map(|service| service.service_id())
+                .filter(|id| match config {
+                    Some(ref config) => self.is_service_active(*id, config),
+                    None => true,
+                })
+                .collect();
+            let _call_scope = CallScope::enter(Arc::clone(&self.service_map), active_services);
+            let _time_scope = BlockTimeScope::enter(time);
             // Save & execute transactions.
             for (index, hash) in tx_hashes.iter().enumerate() {
                 self.execute_transaction(*hash, height, index, &mut fork)
@@ -276,50 +434,15 @@ impl Blockchain {
             }
 
             // Get tx & state hash.
-            let (tx_hash, state_hash) = {
-                let state_hashes = {
-                    let schema = Schema::new(&fork);
-
-                    let vec_core_state = schema.core_state_hash();
-                    let mut state_hashes = Vec::new();
-
-                    for (idx, core_table_hash) in vec_core_state.into_iter().enumerate() {
-                        let key = Blockchain::service_table_unique_key(CORE_SERVICE, idx);
-                        state_hashes.push((key, core_table_hash));
-                    }
-
-                    for service in self.service_map.values() {
-                        let service_id = service.service_id();
-                        let vec_service_state = service.state_hash(&fork);
-                        for (idx, service_table_hash) in vec_service_state.into_iter().enumerate() {
-                            let key = Blockchain::service_table_unique_key(service_id, idx);
-                            state_hashes.push((key, service_table_hash));
-                        }
-                    }
-
-                    state_hashes
-                };
-
-                let mut schema = Schema::new(&mut fork);
-
-                let state_hash = {
-                    let mut sum_table = schema.state_hash_aggregator_mut();
-                    for (key, hash) in state_hashes {
-                        sum_table.put(&key, hash)
-                    }
-                    sum_table.merkle_root()
-                };
-
-                let tx_hash = schema.block_transactions(height).merkle_root();
-
-                (tx_hash, state_hash)
-            };
+            let state_hash = self.update_state_hash(config.as_ref(), &mut fork);
+            let tx_hash = Schema::new(&fork).block_transactions(height).merkle_root();
 
             // Create block.
             let block = Block::new(
                 SCHEMA_MAJOR_VERSION,
                 proposer_id,
                 height,
+                time,
                 tx_hashes.len() as u32,
                 &last_hash,
                 &tx_hash,
@@ -340,6 +463,181 @@ impl Blockchain {
         (block_hash, fork.into_patch())
     }
 
+    /// Aggregates the state hashes of the core and the active services into
+    /// the `state_hash_aggregator` index and returns its root hash.
+    ///
+    /// If `config` is `None`, all services are considered active.
+    fn update_state_hash(&self, config: Option<&StoredConfiguration>, fork: &mut Fork) -> Hash {
+        let state_hashes = {
+            let schema = Schema::new(&*fork);
+
+            let vec_core_state = schema.core_state_hash();
+            let mut state_hashes = Vec::new();
+
+            for (idx, core_table_hash) in vec_core_state.into_iter().enumerate() {
+                let key = Blockchain::service_table_unique_key(CORE_SERVICE, idx);
+                state_hashes.push((key, core_table_hash));
+            }
+
+            for service in self.service_map.values() {
+                let service_id = service.service_id();
+                if let Some(config) = config {
+                    if !self.is_service_active(service_id, config) {
+                        continue;
+                    }
+                }
+                let vec_service_state = service.state_hash(&*fork);
+                for (idx, service_table_hash) in vec_service_state.into_iter().enumerate() {
+                    let key = Blockchain::service_table_unique_key(service_id, idx);
+                    state_hashes.push((key, service_table_hash));
+                }
+            }
+
+            state_hashes
+        };
+
+        let mut schema = Schema::new(fork);
+        let mut sum_table = schema.state_hash_aggregator_mut();
+        for (key, hash) in state_hashes {
+            sum_table.put(&key, hash)
+        }
+        sum_table.merkle_root()
+    }
+
+    /// Checks that the services of the node support the given target schema versions,
+    /// keyed by `service_name`.
+    ///
+    /// A configuration with `service_versions` that do not pass this check should not be
+    /// proposed or voted for, since the node cannot migrate the data of the services
+    /// once the configuration becomes actual.
+    pub fn check_service_versions(
+        &self,
+        service_versions: &BTreeMap<String, u16>,
+    ) -> Result<(), failure::Error> {
+        for service in self.service_map.values() {
+            if let Some(target) = service_versions.get(service.service_name()) {
+                if *target > service.schema_version() {
+                    bail!(
+                        "Service '{}' supports schema versions up to {}, but the version {} \
+                         is required",
+                        service.service_name(),
+                        service.schema_version(),
+                        target
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Migrates the data of the services to the given target schema versions, keyed by
+    /// `service_name`. Services that already have the target or a greater version
+    /// are skipped.
+    ///
+    /// Returns an error if a target version is not supported by the service implementation
+    /// or the migration of the service data fails.
+    fn migrate_services(
+        &self,
+        service_versions: &BTreeMap<String, u16>,
+        fork: &mut Fork,
+    ) -> Result<(), failure::Error> {
+        for service in self.service_map.values() {
+            if let Some(target) = service_versions.get(service.service_name()) {
+                Self::migrate_service(service.as_ref(), *target, fork).map_err(|e| {
+                    failure::err_msg(format!(
+                        "Cannot migrate service '{}' to schema version {}: {}",
+                        service.service_name(),
+                        target,
+                        e
+                    ))
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the migrations of the service data up to the `target` schema version
+    /// and records the applied version in the core schema.
+    fn migrate_service(
+        service: &Service,
+        target: u16,
+        fork: &mut Fork,
+    ) -> Result<(), failure::Error> {
+        let service_id = service.service_id();
+        let applied = Schema::new(&*fork).service_schema_version(service_id);
+        if target <= applied {
+            return Ok(());
+        }
+        if target > service.schema_version() {
+            bail!(
+                "the service supports schema versions up to {}",
+                service.schema_version()
+            );
+        }
+
+        for version in applied + 1..target + 1 {
+            info!(
+                "Migrating service '{}' to schema version {}",
+                service.service_name(),
+                version
+            );
+            service.migrate(fork, version)?;
+        }
+        Schema::new(&mut *fork)
+            .service_schema_versions_mut()
+            .put(&service_id, target);
+        Ok(())
+    }
+
+    /// Applies migrations of the services to the given target schema versions, keyed by
+    /// `service_name`, to the current blockchain state without committing the changes.
+    /// Returns the resulting state hash, which can be compared between nodes before
+    /// the configuration with these versions is proposed.
+    ///
+    /// Returns an error if a target version is not supported by the service implementation
+    /// or the migration of the service data fails.
+    pub fn dry_run_migrations(
+        &self,
+        service_versions: &BTreeMap<String, u16>,
+    ) -> Result<Hash, failure::Error> {
+        self.check_service_versions(service_versions)?;
+
+        let mut fork = self.fork();
+        self.migrate_services(service_versions, &mut fork)?;
+        let config = Schema::new(&fork).actual_configuration();
+        Ok(self.update_state_hash(Some(&config), &mut fork))
+    }
+
+    /// Invokes `initialize` for the services that are listed in the configuration which
+    /// becomes actual at the given height but have not been listed in the previous one.
+    /// Configurations returned by `initialize` are ignored, since the configurations of
+    /// such services are already defined by `config`.
+    fn activate_services(&self, height: Height, config: &StoredConfiguration, fork: &mut Fork) {
+        // Services of the genesis configuration are initialized in `create_genesis_block`.
+        if config.actual_from != height || height == Height::zero() {
+            return;
+        }
+        let previous = match Schema::new(&*fork).configuration_by_hash(&config.previous_cfg_hash) {
+            Some(previous) => previous,
+            None => return,
+        };
+
+        for service in self.service_map.values() {
+            let id = service.service_id();
+            if self.is_service_active(id, config) && !self.is_service_active(id, &previous) {
+                info!(
+                    "Activating service '{}' at height {}",
+                    service.service_name(),
+                    config.actual_from
+                );
+                service.initialize(fork);
+                Schema::new(&mut *fork)
+                    .service_schema_versions_mut()
+                    .put(&id, service.schema_version());
+            }
+        }
+    }
+
 
     fn execute_transaction(
         &self,
@@ -364,10 +662,18 @@ impl Blockchain {
 
         fork.checkpoint();
 
+        take_call_error();
+        start_events(tx.raw().service_id());
         let catch_result = panic::catch_unwind(panic::AssertUnwindSafe(|| tx.execute(fork)));
+        let events = take_events();
 
         let tx_result = match catch_result {
             Ok(execution_result) => {
+                // A failed service call fails the whole transaction.
+                let execution_result = match (execution_result, take_call_error()) {
+                    (Ok(()), Some(call_error)) => Err(call_error),
+                    (execution_result, _) => execution_result,
+                };
                 match execution_result {
                     Ok(()) => {
                         fork.commit();
@@ -393,6 +699,15 @@ impl Blockchain {
         };
 
         let mut schema = Schema::new(fork);
+        // Events of a failed transaction are discarded together with its other changes.
+        if tx_result.is_ok() && !events.is_empty() {
+            let events_root = {
+                let mut tx_events = schema.transaction_events_mut(&tx_hash);
+                tx_events.extend(events);
+                tx_events.merkle_root()
+            };
+            schema.transaction_events_roots_mut().put(&tx_hash, events_root);
+        }
         schema.transaction_results_mut().put(&tx_hash, tx_result);
         schema.commit_transaction(&tx_hash);
         schema.block_transactions_mut(height).push(tx_hash);
@@ -427,27 +742,69 @@ impl Blockchain {
                     schema.precommits_mut(&block_hash).push(precommit.clone());
                 }
 
-                // Consensus messages cache is useful only during one height, so it should be
-                // cleared when a new height is achieved.
+                // Consensus messages cache and write-ahead log are useful only during one height,
+                // so they should be cleared when a new height is achieved.
                 schema.consensus_messages_cache_mut().clear();
+                schema.consensus_votes_mut().clear();
+                schema.remove_consensus_lock();
             }
             fork.into_patch()
         };
         self.merge(patch)?;
+        self.notify_subscribers();
         // Initializes the context after merge.
-        let context = ServiceContext::new(
+        let mut context = ServiceContext::new(
             self.service_keypair.0,
             self.service_keypair.1.clone(),
             self.api_sender.clone(),
             self.fork(),
         );
-        // Invokes `handle_commit` for each service in order of their identifiers
+        context.set_signer(Arc::clone(&self.service_signer));
+        // Configuration under which the committed block has been created.
+        let config = {
+            let snapshot = self.snapshot();
+            let schema = Schema::new(&snapshot);
+            schema.configuration_by_height(schema.height())
+        };
+        // Invokes `handle_commit` for each active service in order of their identifiers
         for service in self.service_map.values() {
-            service.handle_commit(&context);
+            if self.is_service_active(service.service_id(), &config) {
+                service.handle_commit(&context);
+            }
         }
         Ok(())
     }
 
+    // Publishes the latest committed block, its transactions and their events.
+    fn notify_subscribers(&self) {
+        if self.subscriptions.is_empty() {
+            return;
+        }
+        let notifications = {
+            let snapshot = self.snapshot();
+            let schema = Schema::new(&snapshot);
+            let block = schema.last_block();
+            let height = block.height();
+            let mut notifications = vec![Notification::Block(block)];
+            let results = schema.transaction_results();
+            for tx_hash in schema.block_transactions(height).iter() {
+                let result = results.get(&tx_hash).expect(
+                    "Result of the committed transaction not found",
+                );
+                notifications.push(Notification::Transaction {
+                    tx_hash,
+                    height,
+                    result,
+                });
+                for event in schema.transaction_events(&tx_hash).iter() {
+                    notifications.push(Notification::Event { tx_hash, event });
+                }
+            }
+            notifications
+        };
+        self.subscriptions.publish(&notifications);
+    }
+
     /// Returns `Mount` object that aggregates public api handlers.
     pub fn mount_public_api(&self) -> Mount {
         let context = self.api_context();
@@ -514,11 +871,28 @@ impl Blockchain {
     }
 
     /// Recover cached peers if any.
-    pub fn get_saved_peers(&self) -> HashMap<PublicKey, Connect> {
-        let schema = Schema::new(self.snapshot());
-        let peers_cache = schema.peers_cache();
-        let it = peers_cache.iter().map(|(k, v)| (k, v.clone()));
-        it.collect()
+    ///
+    /// `Connect` messages of the earlier protocol versions are removed from the cache.
+    pub fn get_saved_peers(&mut self) -> HashMap<PublicKey, Connect> {
+        let mut fork = self.fork();
+
+        let peers = {
+            let mut schema = Schema::new(&mut fork);
+            let mut peers_cache = schema.peers_cache_mut();
+            let (peers, outdated): (HashMap<_, _>, HashMap<_, _>) = peers_cache
+                .iter()
+                .partition(|&(_, ref connect)| Connect::from_raw(connect.raw().clone()).is_ok());
+            for pubkey in outdated.keys() {
+                warn!("Removed outdated Connect message of peer {:?} from the cache", pubkey);
+                peers_cache.remove(pubkey);
+            }
+            peers
+        };
+
+        self.merge(fork.into_patch()).expect(
+            "Unable to remove outdated peers from the peers cache",
+        );
+        peers
     }
 
     /// Saves the given raw message to the consensus messages cache.
@@ -544,6 +918,62 @@ impl Blockchain {
             "Unable to save messages to the consensus cache",
         );
     }
+
+    /// Saves the propose made by the node to the consensus messages cache. The changes are
+    /// flushed to the disk, so the propose must be saved before it is broadcast; otherwise,
+    /// the node could make a conflicting propose in the same round after a restart.
+    pub fn save_propose(&mut self, round: Round, raw: &RawMessage) {
+        let mut fork = self.fork();
+
+        {
+            let mut schema = Schema::new(&mut fork);
+            schema.consensus_messages_cache_mut().push(raw.clone());
+            schema.set_consensus_round(round);
+        }
+
+        self.merge_sync(fork.into_patch()).expect(
+            "Unable to save the propose to the consensus messages cache",
+        );
+    }
+
+    /// Saves a vote signed by the node to the consensus write-ahead log and the consensus
+    /// messages cache. The changes are flushed to the disk, so the vote must be saved before
+    /// it is broadcast.
+    pub fn save_vote(&mut self, round: Round, raw: &RawMessage) {
+        let mut fork = self.fork();
+
+        {
+            let mut schema = Schema::new(&mut fork);
+            schema.consensus_messages_cache_mut().push(raw.clone());
+            schema.consensus_votes_mut().push(raw.clone());
+            schema.set_consensus_round(round);
+        }
+
+        self.merge_sync(fork.into_patch()).expect(
+            "Unable to save the vote to the consensus write-ahead log",
+        );
+    }
+
+    /// Saves the lock of the node to the consensus write-ahead log together with the messages
+    /// justifying the lock, which are put to the consensus messages cache. The changes are
+    /// flushed to the disk.
+    pub fn save_lock<I>(&mut self, lock: ConsensusLock, messages: I)
+    where
+        I: IntoIterator<Item = RawMessage>,
+    {
+        let mut fork = self.fork();
+
+        {
+            let mut schema = Schema::new(&mut fork);
+            schema.consensus_messages_cache_mut().extend(messages);
+            schema.set_consensus_round(lock.round());
+            schema.set_consensus_lock(lock);
+        }
+
+        self.merge_sync(fork.into_patch()).expect(
+            "Unable to save the lock to the consensus write-ahead log",
+        );
+    }
 }
 
 impl fmt::Debug for Blockchain {
@@ -559,6 +989,8 @@ impl Clone for Blockchain {
             service_map: Arc::clone(&self.service_map),
             api_sender: self.api_sender.clone(),
             service_keypair: self.service_keypair.clone(),
+            service_signer: Arc::clone(&self.service_signer),
+            subscriptions: self.subscriptions.clone(),
         }
     }
 }
@@ -0,0 +1,431 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Restoring of the blockchain state received from peers during the state sync.
+//!
+//! Peers send the raw contents of the indexes, including the internal nodes of
+//! the Merkelized indexes, so nothing received is written to the storage as is. The history
+//! of blocks is verified starting from the local genesis block: each block must be linked
+//! to the previous one and committed by the validators of the configuration that is actual
+//! at its height. The Merkelized indexes are rebuilt from their elements, and the resulting
+//! state hash must match the `state_hash` of the snapshot block.
+
+use std::panic::{self, AssertUnwindSafe};
+
+use crypto::{CryptoHash, Hash};
+use helpers::Height;
+use messages::Precommit;
+use storage::{Fork, Patch, Snapshot};
+use super::{Block, Blockchain, CertificateError, CommitCertificate, CompactBlockProof, Schema,
+            StoredConfiguration, TxLocation};
+use super::schema::{ConfigReference, SYNCED_INDEXES};
+
+/// Reason why the state received during the state sync is rejected.
+#[derive(Debug, Clone, PartialEq, Fail)]
+pub enum StateSyncError {
+    /// The received indexes cannot be decoded.
+    #[fail(display = "Received state is malformed")]
+    Malformed,
+    /// The configurations do not form a chain starting from the genesis configuration.
+    #[fail(display = "Invalid configurations: {}", _0)]
+    InvalidConfigs(String),
+    /// The block is absent, does not match its hash or is not linked to the previous block.
+    #[fail(display = "Invalid block at height {}: {}", height, reason)]
+    InvalidBlock {
+        /// Height of the block.
+        height: Height,
+        /// Description of the error.
+        reason: String,
+    },
+    /// The block is not committed by the validators.
+    #[fail(display = "Invalid precommits of the block at height {}: {}", height, error)]
+    InvalidPrecommits {
+        /// Height of the block.
+        height: Height,
+        /// Reason why the precommits are invalid.
+        error: CertificateError,
+    },
+    /// The events of the transaction do not match their root hash.
+    #[fail(display = "Events of the transaction {:?} do not match their root hash", _0)]
+    InvalidEvents(Hash),
+    /// The data of the service cannot be restored.
+    #[fail(display = "Invalid data of the service '{}': {}", service, reason)]
+    InvalidServiceData {
+        /// Name of the service.
+        service: String,
+        /// Description of the error.
+        reason: String,
+    },
+    /// The state hash of the restored state does not match the block.
+    #[fail(display = "State hash {:?} does not match the block, expected {:?}", actual, expected)]
+    StateHashMismatch {
+        /// State hash of the restored state.
+        actual: Hash,
+        /// State hash of the block.
+        expected: Hash,
+    },
+    /// The node cannot restore the state by itself, for example, a service does not support
+    /// the state sync or the node software is outdated.
+    #[fail(display = "State cannot be restored by the node: {}", _0)]
+    Unsupported(String),
+}
+
+impl StateSyncError {
+    /// Returns `true` if the error is caused by the state received from the peer rather than
+    /// by the node itself.
+    pub fn is_peer_fault(&self) -> bool {
+        match *self {
+            StateSyncError::Unsupported(_) => false,
+            _ => true,
+        }
+    }
+}
+
+/// Error returned by the default implementation of `Service::restore_state`.
+#[derive(Debug, Fail)]
+#[fail(display = "Service '{}' does not support the state sync", _0)]
+pub struct StateSyncUnsupported(pub String);
+
+impl Blockchain {
+    /// Returns `true` if the index with the given name is transferred during the state sync.
+    ///
+    /// These are the core indexes making up the blockchain state and the indexes of
+    /// the registered services, whose names start with `service_name` followed by a dot.
+    pub fn is_synced_index(&self, name: &str) -> bool {
+        if SYNCED_INDEXES.iter().any(|index| *index == name) {
+            return true;
+        }
+        self.service_map.values().any(|service| {
+            let prefix = service.service_name();
+            name.len() > prefix.len() && name.starts_with(prefix) &&
+                name.as_bytes()[prefix.len()] == b'.'
+        })
+    }
+
+    /// Verifies the blockchain state up to the given block received from peers during
+    /// the state sync and returns the patch that replaces the local state with it.
+    ///
+    /// The block itself is verified as a part of the history, so it does not have to be
+    /// trusted. The services active at the height of the block restore their data with
+    /// [`Service::restore_state`]. Only the peers cache of the local state survives
+    /// the patch.
+    ///
+    /// [`Service::restore_state`]: trait.Service.html#method.restore_state
+    pub fn restore_state(
+        &self,
+        received: &Snapshot,
+        block: &Block,
+    ) -> Result<Patch, StateSyncError> {
+        // Peers may send anything, including data that panics on decoding.
+        panic::catch_unwind(AssertUnwindSafe(|| self.do_restore_state(received, block)))
+            .unwrap_or(Err(StateSyncError::Malformed))
+    }
+
+    fn do_restore_state(
+        &self,
+        received: &Snapshot,
+        block: &Block,
+    ) -> Result<Patch, StateSyncError> {
+        let (genesis_block, genesis_config_hash) = {
+            let snapshot = self.snapshot();
+            let schema = Schema::new(&snapshot);
+            let genesis_block = schema.block_and_precommits(Height::zero()).unwrap().block;
+            let genesis_config_hash = *schema.configs_actual_from().get(0).unwrap().cfg_hash();
+            (genesis_block, genesis_config_hash)
+        };
+
+        let mut fork = self.fork();
+        Schema::new(&mut fork).clear_for_state_sync();
+        let configs = restore_configs(received, &mut fork, &genesis_config_hash)?;
+        restore_blocks(received, &mut fork, block, genesis_block, &configs)?;
+        restore_transaction_data(received, &mut fork)?;
+        let config = config_by_height(&configs, block.height()).clone();
+        self.restore_services(received, &mut fork, &configs, &config)?;
+
+        let state_hash = self.update_state_hash(Some(&config), &mut fork);
+        if state_hash != *block.state_hash() {
+            return Err(StateSyncError::StateHashMismatch {
+                actual: state_hash,
+                expected: *block.state_hash(),
+            });
+        }
+        Ok(fork.into_patch())
+    }
+
+    /// Restores the schema versions and the data of the services active at the height
+    /// of the received state.
+    fn restore_services(
+        &self,
+        received: &Snapshot,
+        fork: &mut Fork,
+        configs: &[StoredConfiguration],
+        config: &StoredConfiguration,
+    ) -> Result<(), StateSyncError> {
+        for service in self.service_map.values() {
+            let service_id = service.service_id();
+            if !self.is_service_active(service_id, config) {
+                continue;
+            }
+            let name = service.service_name();
+            let invalid_data = |reason: String| {
+                StateSyncError::InvalidServiceData {
+                    service: name.to_owned(),
+                    reason,
+                }
+            };
+
+            // The version is recorded on the activation of the service and is raised by
+            // the configurations listing a greater version.
+            let version = Schema::new(received)
+                .service_schema_versions()
+                .get(&service_id)
+                .ok_or_else(|| invalid_data("schema version is absent".to_owned()))?;
+            let required = configs
+                .iter()
+                .filter(|cfg| cfg.actual_from <= config.actual_from)
+                .filter_map(|cfg| cfg.service_versions.get(name).cloned())
+                .max()
+                .unwrap_or(0);
+            if version < required {
+                return Err(invalid_data(format!(
+                    "schema version {} is less than the version {} required by the configuration",
+                    version,
+                    required
+                )));
+            }
+            if version > service.schema_version() {
+                return Err(StateSyncError::Unsupported(format!(
+                    "schema version {} of the service '{}' is not supported, the node software \
+                     should be updated",
+                    version,
+                    name
+                )));
+            }
+            Schema::new(&mut *fork)
+                .service_schema_versions_mut()
+                .put(&service_id, version);
+
+            service.restore_state(received, fork).map_err(|e| {
+                if e.downcast_ref::<StateSyncUnsupported>().is_some() {
+                    StateSyncError::Unsupported(e.to_string())
+                } else {
+                    invalid_data(e.to_string())
+                }
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Restores the configurations, which must form a chain starting from the local genesis
+/// configuration, and returns them in the ascending order of `actual_from`.
+fn restore_configs(
+    received: &Snapshot,
+    fork: &mut Fork,
+    genesis_config_hash: &Hash,
+) -> Result<Vec<StoredConfiguration>, StateSyncError> {
+    let mut configs: Vec<(Hash, StoredConfiguration)> =
+        Schema::new(received).configs().iter().collect();
+    configs.sort_by_key(|&(_, ref config)| config.actual_from);
+
+    match configs.first() {
+        Some(&(ref hash, _)) if hash == genesis_config_hash => {}
+        _ => {
+            return Err(StateSyncError::InvalidConfigs(
+                "genesis configuration does not match the local one".to_owned(),
+            ))
+        }
+    }
+    for (i, &(ref hash, ref config)) in configs.iter().enumerate() {
+        if *hash != config.hash() {
+            return Err(StateSyncError::InvalidConfigs(
+                format!("configuration {:?} does not match its hash", hash),
+            ));
+        }
+        if i == 0 {
+            continue;
+        }
+        let (ref previous_hash, ref previous) = configs[i - 1];
+        if config.previous_cfg_hash != *previous_hash ||
+            config.actual_from <= previous.actual_from
+        {
+            return Err(StateSyncError::InvalidConfigs(format!(
+                "configuration {:?} does not follow the previous one",
+                hash
+            )));
+        }
+    }
+
+    let mut schema = Schema::new(fork);
+    for &(ref hash, ref config) in &configs {
+        schema.configs_mut().put(hash, config.clone());
+        schema.configs_actual_from_mut().push(
+            ConfigReference::new(config.actual_from, hash),
+        );
+    }
+    Ok(configs.into_iter().map(|(_, config)| config).collect())
+}
+
+/// Restores the blocks up to the given one together with their precommits and transactions.
+fn restore_blocks(
+    received: &Snapshot,
+    fork: &mut Fork,
+    last_block: &Block,
+    genesis_block: Block,
+    configs: &[StoredConfiguration],
+) -> Result<(), StateSyncError> {
+    let received = Schema::new(received);
+    let block_hashes: Vec<Hash> = received.block_hashes_by_height().iter().collect();
+    let last_height = last_block.height();
+    if block_hashes.len() as u64 != last_height.next().0 {
+        return Err(invalid_block(
+            last_height,
+            "number of blocks does not match the height",
+        ));
+    }
+    if block_hashes[0] != genesis_block.hash() {
+        return Err(invalid_block(
+            Height::zero(),
+            "genesis block does not match the local one",
+        ));
+    }
+    if block_hashes[last_height.0 as usize] != last_block.hash() {
+        return Err(invalid_block(
+            last_height,
+            "block of the state snapshot is absent",
+        ));
+    }
+
+    let mut schema = Schema::new(fork);
+    schema.block_hashes_by_height_mut().extend(
+        block_hashes.iter().cloned(),
+    );
+    schema.blocks_mut().put(&block_hashes[0], genesis_block);
+    for (i, block_hash) in block_hashes.iter().enumerate().skip(1) {
+        let height = Height(i as u64);
+        let block = received.blocks().get(block_hash).ok_or_else(|| {
+            invalid_block(height, "block is absent")
+        })?;
+        if block.hash() != *block_hash || block.height() != height {
+            return Err(invalid_block(height, "block does not match its hash"));
+        }
+        if *block.prev_hash() != block_hashes[i - 1] {
+            return Err(invalid_block(
+                height,
+                "block is not linked to the previous block",
+            ));
+        }
+
+        let precommits: Vec<Precommit> = received.precommits(block_hash).iter().collect();
+        let proof = CommitCertificate::from_precommits(&precommits)
+            .and_then(|certificate| {
+                let proof = CompactBlockProof { block, certificate };
+                let config = config_by_height(configs, height);
+                proof.verify(&config.validator_keys).map(|()| proof)
+            })
+            .map_err(|error| StateSyncError::InvalidPrecommits { height, error })?;
+
+        restore_block_transactions(&received, &mut schema, &proof.block)?;
+        // Only the signed contents of the precommits are stored.
+        schema.precommits_mut(block_hash).extend(
+            proof.certificate.precommits(),
+        );
+        schema.blocks_mut().put(block_hash, proof.block);
+    }
+    Ok(())
+}
+
+/// Restores the transactions of the block and their locations.
+fn restore_block_transactions(
+    received: &Schema<&Snapshot>,
+    schema: &mut Schema<&mut Fork>,
+    block: &Block,
+) -> Result<(), StateSyncError> {
+    let height = block.height();
+    let tx_hashes: Vec<Hash> = received.block_transactions(height).iter().collect();
+    {
+        let mut block_transactions = schema.block_transactions_mut(height);
+        block_transactions.extend(tx_hashes.iter().cloned());
+        if block_transactions.merkle_root() != *block.tx_hash() ||
+            block_transactions.len() != u64::from(block.tx_count())
+        {
+            return Err(invalid_block(
+                height,
+                "transactions do not match the block",
+            ));
+        }
+    }
+
+    for (position, tx_hash) in tx_hashes.iter().enumerate() {
+        let tx = received.transactions().get(tx_hash).ok_or_else(|| {
+            invalid_block(height, format!("transaction {:?} is absent", tx_hash))
+        })?;
+        if tx.hash() != *tx_hash {
+            return Err(invalid_block(
+                height,
+                format!("transaction {:?} does not match its hash", tx_hash),
+            ));
+        }
+        schema.transactions_mut().put(tx_hash, tx);
+        schema.transactions_locations_mut().put(
+            tx_hash,
+            TxLocation::new(height, position as u64),
+        );
+    }
+    Ok(())
+}
+
+/// Restores the Merkelized indexes of the transaction results, events and evidence,
+/// which are verified by the state hash.
+fn restore_transaction_data(received: &Snapshot, fork: &mut Fork) -> Result<(), StateSyncError> {
+    let received = Schema::new(received);
+    let mut schema = Schema::new(fork);
+    for (tx_hash, result) in received.transaction_results().iter() {
+        schema.transaction_results_mut().put(&tx_hash, result);
+    }
+    for (tx_hash, events_root) in received.transaction_events_roots().iter() {
+        {
+            let mut events = schema.transaction_events_mut(&tx_hash);
+            events.extend(received.transaction_events(&tx_hash).iter());
+            if events.merkle_root() != events_root {
+                return Err(StateSyncError::InvalidEvents(tx_hash));
+            }
+        }
+        schema.transaction_events_roots_mut().put(
+            &tx_hash,
+            events_root,
+        );
+    }
+    for (hash, evidence) in received.evidence().iter() {
+        schema.evidence_mut().put(&hash, evidence);
+    }
+    Ok(())
+}
+
+/// Returns the configuration actual at the given height. `configs` are sorted by
+/// `actual_from` and start with the genesis configuration.
+fn config_by_height(configs: &[StoredConfiguration], height: Height) -> &StoredConfiguration {
+    configs
+        .iter()
+        .rev()
+        .find(|config| config.actual_from <= height)
+        .expect("Genesis configuration is absent")
+}
+
+fn invalid_block<S: Into<String>>(height: Height, reason: S) -> StateSyncError {
+    StateSyncError::InvalidBlock {
+        height,
+        reason: reason.into(),
+    }
+}
@@ -12,12 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use byteorder::{BigEndian, ByteOrder};
+
 use crypto::{PublicKey, Hash, CryptoHash};
 use messages::{Precommit, RawMessage, Connect};
-use storage::{Entry, Fork, ListIndex, MapIndex, MapProof, ProofListIndex, ProofMapIndex, Snapshot,
-              KeySetIndex};
+use storage::{index_names, Entry, Fork, ListIndex, MapIndex, MapProof, ProofListIndex,
+              ProofMapIndex, Snapshot, KeySetIndex, StorageKey, INDEXES_METADATA_TABLE_NAME};
 use helpers::{Height, Round};
-use super::{Block, BlockProof, Blockchain, TransactionResult};
+use node::TxPoolEvictionPolicy;
+use super::{Block, BlockProof, Blockchain, CertificateError, CommitCertificate,
+            CompactBlockProof, Evidence, Transaction, TransactionEvent, TransactionResult};
 use super::config::StoredConfiguration;
 
 /// Defines `&str` constants with given name and value.
@@ -34,7 +38,15 @@ macro_rules! define_names {
 define_names!(
     TRANSACTIONS => "transactions";
     TRANSACTION_RESULTS => "transaction_results";
+    TRANSACTION_EVENTS => "transaction_events";
+    TRANSACTION_EVENTS_ROOTS => "transaction_events_roots";
     TRANSACTIONS_POOL => "transactions_pool";
+    TRANSACTIONS_POOL_LEN => "transactions_pool_len";
+    TRANSACTIONS_POOL_SEQUENCE => "transactions_pool_sequence";
+    TRANSACTIONS_POOL_ENTRIES => "transactions_pool_entries";
+    TRANSACTIONS_POOL_BY_AGE => "transactions_pool_by_age";
+    TRANSACTIONS_POOL_BY_PRIORITY => "transactions_pool_by_priority";
+    TRANSACTIONS_POOL_SENDERS => "transactions_pool_senders";
     TRANSACTIONS_LOCATIONS => "transactions_locations";
     BLOCKS => "blocks";
     BLOCK_HASHES_BY_HEIGHT => "block_hashes_by_height";
@@ -46,8 +58,34 @@ define_names!(
     PEERS_CACHE => "peers_cache";
     CONSENSUS_MESSAGES_CACHE => "consensus_messages_cache";
     CONSENSUS_ROUND => "consensus_round";
+    CONSENSUS_VOTES => "consensus_votes";
+    CONSENSUS_LOCK => "consensus_lock";
+    SERVICE_SCHEMA_VERSIONS => "service_schema_versions";
+    EVIDENCE => "evidence";
 );
 
+/// Core indexes that make up the blockchain state transferred during the state sync.
+/// The other core indexes belong to the local state of the node or are recomputed.
+pub(crate) const SYNCED_INDEXES: &[&str] = &[
+    TRANSACTIONS,
+    TRANSACTION_RESULTS,
+    TRANSACTION_EVENTS,
+    TRANSACTION_EVENTS_ROOTS,
+    TRANSACTIONS_LOCATIONS,
+    BLOCKS,
+    BLOCK_HASHES_BY_HEIGHT,
+    BLOCK_TRANSACTIONS,
+    PRECOMMITS,
+    CONFIGS,
+    CONFIGS_ACTUAL_FROM,
+    SERVICE_SCHEMA_VERSIONS,
+    EVIDENCE,
+];
+
+/// Synced core indexes keyed by the hashes of their values, so that their entries
+/// can be verified as soon as they are received during the state sync.
+pub(crate) const HASHED_INDEXES: &[&str] = &[TRANSACTIONS, BLOCKS];
+
 encoding_struct! (
     /// Configuration index.
     struct ConfigReference {
@@ -68,6 +106,78 @@ encoding_struct! (
     }
 );
 
+encoding_struct! (
+    /// Lock of the node recorded in the consensus write-ahead log.
+    struct ConsensusLock {
+        /// Height of the lock.
+        height: Height,
+        /// Round in which the node has locked.
+        round: Round,
+        /// Hash of the propose the node has locked on.
+        propose_hash: &Hash,
+    }
+);
+
+encoding_struct! (
+    /// Eviction metadata of the uncommitted transaction.
+    struct PoolEntry {
+        /// Sequence number of the transaction in the order of its arrival into the pool.
+        sequence: u64,
+        /// Priority of the transaction, see `Transaction::pool_priority`.
+        priority: u64,
+        /// Sender of the transaction, see `Transaction::pool_sender`. Zero key if the
+        /// transaction has no sender.
+        sender: &PublicKey,
+    }
+);
+
+/// Key of the pool index ordering transactions by priority and then by arrival.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PoolOrderKey {
+    priority: u64,
+    sequence: u64,
+}
+
+impl StorageKey for PoolOrderKey {
+    fn size(&self) -> usize {
+        16
+    }
+
+    fn write(&self, buffer: &mut [u8]) {
+        BigEndian::write_u64(&mut buffer[0..8], self.priority);
+        BigEndian::write_u64(&mut buffer[8..16], self.sequence);
+    }
+
+    fn read(buffer: &[u8]) -> Self {
+        PoolOrderKey {
+            priority: BigEndian::read_u64(&buffer[0..8]),
+            sequence: BigEndian::read_u64(&buffer[8..16]),
+        }
+    }
+}
+
+/// Reason why a transaction has not been admitted into the memory pool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Fail)]
+pub enum TxPoolError {
+    /// The pool is full and every transaction in it is protected from eviction.
+    #[fail(display = "Transaction pool is full")]
+    PoolFull,
+    /// The pool is full and the transaction priority is not greater than the lowest one.
+    #[fail(display = "Transaction priority is too low to enter the full pool")]
+    LowPriority,
+    /// The sender of the transaction has reached its quota of uncommitted transactions.
+    #[fail(display = "Sender has too many transactions in the pool")]
+    SenderQuotaExceeded,
+    /// The transaction is already in the pool or committed.
+    #[fail(display = "Transaction is already known")]
+    Duplicate,
+}
+
+/// Position of the `transaction_events_roots` table in `Schema::core_state_hash`.
+pub const TRANSACTION_EVENTS_TABLE: usize = 2;
+/// Position of the `evidence` table in `Schema::core_state_hash`.
+pub const EVIDENCE_TABLE: usize = 3;
+
 /// Information schema for `exonum-core`.
 #[derive(Debug)]
 pub struct Schema<T> {
@@ -93,18 +203,57 @@ where
         ProofMapIndex::new(TRANSACTION_RESULTS, &self.view)
     }
 
+    /// Returns table that keeps the list of events emitted by the transaction
+    /// with the given hash.
+    pub fn transaction_events(&self, tx_hash: &Hash) -> ProofListIndex<&T, TransactionEvent> {
+        ProofListIndex::new_in_family(TRANSACTION_EVENTS, tx_hash, &self.view)
+    }
+
+    /// Returns table that represents a map from transaction hash into the root hash of
+    /// its [`transaction_events`][1] list. Only transactions that emitted events are present.
+    ///
+    /// [1]: struct.Schema.html#method.transaction_events
+    pub fn transaction_events_roots(&self) -> ProofMapIndex<&T, Hash, Hash> {
+        ProofMapIndex::new(TRANSACTION_EVENTS_ROOTS, &self.view)
+    }
+
+    /// Returns table that represents a map from evidence hash into the [`Evidence`][1] of
+    /// a validator that signed conflicting consensus messages.
+    ///
+    /// [1]: struct.Evidence.html
+    pub fn evidence(&self) -> ProofMapIndex<&T, Hash, Evidence> {
+        ProofMapIndex::new(EVIDENCE, &self.view)
+    }
+
     /// Returns table that represents a set of uncommitted transactions hashes.
     pub fn transactions_pool(&self) -> KeySetIndex<&T, Hash> {
         KeySetIndex::new(TRANSACTIONS_POOL, &self.view)
     }
 
     /// Returns number of transactions in the pool
-    #[cfg_attr(feature = "cargo-clippy", allow(let_and_return))]
     pub fn transactions_pool_len(&self) -> usize {
-        let pool = self.transactions_pool();
-        // TODO: Change count to other method with O(1) complexity. (ECR-977)
-        let count = pool.iter().count();
-        count
+        let len: Entry<&T, u64> = Entry::new(TRANSACTIONS_POOL_LEN, &self.view);
+        len.get().unwrap_or(0) as usize
+    }
+
+    /// Returns table that keeps the eviction metadata for every transaction in the pool.
+    fn transactions_pool_entries(&self) -> MapIndex<&T, Hash, PoolEntry> {
+        MapIndex::new(TRANSACTIONS_POOL_ENTRIES, &self.view)
+    }
+
+    /// Returns table that orders transactions in the pool by their arrival.
+    fn transactions_pool_by_age(&self) -> MapIndex<&T, u64, Hash> {
+        MapIndex::new(TRANSACTIONS_POOL_BY_AGE, &self.view)
+    }
+
+    /// Returns table that orders transactions in the pool by priority and then by arrival.
+    fn transactions_pool_by_priority(&self) -> MapIndex<&T, PoolOrderKey, Hash> {
+        MapIndex::new(TRANSACTIONS_POOL_BY_PRIORITY, &self.view)
+    }
+
+    /// Returns table that keeps the number of transactions in the pool for every sender.
+    fn transactions_pool_senders(&self) -> MapIndex<&T, PublicKey, u64> {
+        MapIndex::new(TRANSACTIONS_POOL_SENDERS, &self.view)
     }
 
     /// Returns table that keeps the block height and tx position inside block for every
@@ -184,6 +333,31 @@ where
             .unwrap_or_else(Round::first)
     }
 
+    /// Returns the prevotes and precommits signed by the node at the current height.
+    /// Together with the [`consensus_lock`][1] they make up the consensus write-ahead log,
+    /// which is flushed to the disk before the votes are broadcast.
+    ///
+    /// [1]: struct.Schema.html#method.consensus_lock
+    pub(crate) fn consensus_votes(&self) -> ListIndex<&T, RawMessage> {
+        ListIndex::new(CONSENSUS_VOTES, &self.view)
+    }
+
+    /// Returns the last lock of the node recorded in the consensus write-ahead log.
+    pub(crate) fn consensus_lock(&self) -> Option<ConsensusLock> {
+        Entry::new(CONSENSUS_LOCK, &self.view).get()
+    }
+
+    /// Returns table that keeps the applied data schema version for every service identifier.
+    pub fn service_schema_versions(&self) -> MapIndex<&T, u16, u16> {
+        MapIndex::new(SERVICE_SCHEMA_VERSIONS, &self.view)
+    }
+
+    /// Returns the applied data schema version of the service with the given identifier.
+    /// Services without a recorded version are considered to have the version `0`.
+    pub fn service_schema_version(&self, service_id: u16) -> u16 {
+        self.service_schema_versions().get(&service_id).unwrap_or(0)
+    }
+
     /// Returns block hash for the given height.
     pub fn block_hash_by_height(&self, height: Height) -> Option<Hash> {
         self.block_hashes_by_height().get(height.into())
@@ -202,6 +376,26 @@ where
         Some(res)
     }
 
+    /// Returns the block for the given height with the compact certificate of its commit.
+    ///
+    /// Returns `Ok(None)` if there is no such block or the block has no precommits, which
+    /// is the case for the genesis block, and an error if the stored precommits cannot
+    /// be compacted into a certificate.
+    pub fn block_and_certificate(
+        &self,
+        height: Height,
+    ) -> Result<Option<CompactBlockProof>, CertificateError> {
+        let BlockProof { block, precommits } = match self.block_and_precommits(height) {
+            None => return Ok(None),
+            Some(proof) => proof,
+        };
+        match CommitCertificate::from_precommits(&precommits) {
+            Ok(certificate) => Ok(Some(CompactBlockProof { block, certificate })),
+            Err(CertificateError::Empty) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
     /// Returns latest committed block.
     ///
     /// # Panics
@@ -296,9 +490,17 @@ where
         self.configs().get(hash)
     }
 
-    /// Returns the `state_hash` table for core tables.
+    /// Returns the `state_hash` table for core tables. The positions of the tables are
+    /// used to build the proofs to them with `get_proof_to_service_table`, e.g.
+    /// [`TRANSACTION_EVENTS_TABLE`](constant.TRANSACTION_EVENTS_TABLE.html) and
+    /// [`EVIDENCE_TABLE`](constant.EVIDENCE_TABLE.html).
     pub fn core_state_hash(&self) -> Vec<Hash> {
-        vec![self.configs().merkle_root(), self.transaction_results().merkle_root()]
+        vec![
+            self.configs().merkle_root(),
+            self.transaction_results().merkle_root(),
+            self.transaction_events_roots().merkle_root(),
+            self.evidence().merkle_root(),
+        ]
     }
 
     /// Constructs a proof of inclusion of root hash of a specific service
@@ -370,6 +572,30 @@ impl<'a> Schema<&'a mut Fork> {
         ProofMapIndex::new(TRANSACTION_RESULTS, self.view)
     }
 
+    /// Mutable reference to the [`transaction_events`][1] index.
+    ///
+    /// [1]: struct.Schema.html#method.transaction_events
+    pub(crate) fn transaction_events_mut(
+        &mut self,
+        tx_hash: &Hash,
+    ) -> ProofListIndex<&mut Fork, TransactionEvent> {
+        ProofListIndex::new_in_family(TRANSACTION_EVENTS, tx_hash, self.view)
+    }
+
+    /// Mutable reference to the [`transaction_events_roots`][1] index.
+    ///
+    /// [1]: struct.Schema.html#method.transaction_events_roots
+    pub(crate) fn transaction_events_roots_mut(&mut self) -> ProofMapIndex<&mut Fork, Hash, Hash> {
+        ProofMapIndex::new(TRANSACTION_EVENTS_ROOTS, self.view)
+    }
+
+    /// Mutable reference to the [`evidence`][1] index.
+    ///
+    /// [1]: struct.Schema.html#method.evidence
+    pub(crate) fn evidence_mut(&mut self) -> ProofMapIndex<&mut Fork, Hash, Evidence> {
+        ProofMapIndex::new(EVIDENCE, self.view)
+    }
+
     /// Mutable reference to the [`transactions_pool`][1] index.
     ///
     /// [1]: struct.Schema.html#method.transactions_pool
@@ -377,6 +603,34 @@ impl<'a> Schema<&'a mut Fork> {
         KeySetIndex::new(TRANSACTIONS_POOL, self.view)
     }
 
+    /// Mutable reference to the [`transactions_pool_entries`][1] index.
+    ///
+    /// [1]: struct.Schema.html#method.transactions_pool_entries
+    fn transactions_pool_entries_mut(&mut self) -> MapIndex<&mut Fork, Hash, PoolEntry> {
+        MapIndex::new(TRANSACTIONS_POOL_ENTRIES, self.view)
+    }
+
+    /// Mutable reference to the [`transactions_pool_by_age`][1] index.
+    ///
+    /// [1]: struct.Schema.html#method.transactions_pool_by_age
+    fn transactions_pool_by_age_mut(&mut self) -> MapIndex<&mut Fork, u64, Hash> {
+        MapIndex::new(TRANSACTIONS_POOL_BY_AGE, self.view)
+    }
+
+    /// Mutable reference to the [`transactions_pool_by_priority`][1] index.
+    ///
+    /// [1]: struct.Schema.html#method.transactions_pool_by_priority
+    fn transactions_pool_by_priority_mut(&mut self) -> MapIndex<&mut Fork, PoolOrderKey, Hash> {
+        MapIndex::new(TRANSACTIONS_POOL_BY_PRIORITY, self.view)
+    }
+
+    /// Mutable reference to the [`transactions_pool_senders`][1] index.
+    ///
+    /// [1]: struct.Schema.html#method.transactions_pool_senders
+    fn transactions_pool_senders_mut(&mut self) -> MapIndex<&mut Fork, PublicKey, u64> {
+        MapIndex::new(TRANSACTIONS_POOL_SENDERS, self.view)
+    }
+
     /// Mutable reference to the [`transactions_locations`][1] index.
     ///
     /// [1]: struct.Schema.html#method.transactions_locations
@@ -451,6 +705,44 @@ impl<'a> Schema<&'a mut Fork> {
         ListIndex::new(CONSENSUS_MESSAGES_CACHE, self.view)
     }
 
+    /// Mutable reference to the [`consensus_votes`][1] index.
+    ///
+    /// [1]: struct.Schema.html#method.consensus_votes
+    pub(crate) fn consensus_votes_mut(&mut self) -> ListIndex<&mut Fork, RawMessage> {
+        ListIndex::new(CONSENSUS_VOTES, self.view)
+    }
+
+    /// Saves the lock of the node into the consensus write-ahead log.
+    pub(crate) fn set_consensus_lock(&mut self, lock: ConsensusLock) {
+        let mut entry: Entry<&mut Fork, _> = Entry::new(CONSENSUS_LOCK, self.view);
+        entry.set(lock);
+    }
+
+    /// Removes the lock of the node from the consensus write-ahead log.
+    pub(crate) fn remove_consensus_lock(&mut self) {
+        let mut entry: Entry<&mut Fork, ConsensusLock> = Entry::new(CONSENSUS_LOCK, self.view);
+        entry.remove();
+    }
+
+    /// Mutable reference to the [`service_schema_versions`][1] index.
+    ///
+    /// [1]: struct.Schema.html#method.service_schema_versions
+    pub(crate) fn service_schema_versions_mut(&mut self) -> MapIndex<&mut Fork, u16, u16> {
+        MapIndex::new(SERVICE_SCHEMA_VERSIONS, self.view)
+    }
+
+    /// Removes the contents of all the indexes except for the peers cache, so that the state
+    /// received during the state sync is written from scratch. This drops the stale entries
+    /// of the local state along with the memory pool and the consensus write-ahead log,
+    /// which are not valid at the height of the received state.
+    pub(crate) fn clear_for_state_sync(&mut self) {
+        for name in index_names(&*self.view) {
+            if name != INDEXES_METADATA_TABLE_NAME && name != PEERS_CACHE {
+                self.view.remove_by_prefix(&name, None);
+            }
+        }
+    }
+
     /// Saves the given consensus round value into storage.
     pub(crate) fn set_consensus_round(&mut self, round: Round) {
         let mut entry: Entry<&mut Fork, _> = Entry::new(CONSENSUS_ROUND, self.view);
@@ -498,21 +790,198 @@ impl<'a> Schema<&'a mut Fork> {
     /// Adds transaction into persistent pool.
     #[doc(hidden)]
     pub fn add_transaction_into_pool(&mut self, tx: RawMessage) {
-        self.transactions_pool_mut().insert(tx.hash());
-        self.transactions_mut().put(&tx.hash(), tx);
+        let hash = tx.hash();
+        if self.transactions_pool().contains(&hash) {
+            return;
+        }
+        self.insert_into_pool(&hash, 0, None);
+        self.transactions_mut().put(&hash, tx);
+    }
+
+    /// Adds transaction into persistent pool that holds at most `capacity` transactions.
+    ///
+    /// If the pool is full, transactions are evicted according to `policy`; transactions
+    /// for which `is_protected` returns `true` are never evicted. Evicted transactions are
+    /// removed from the [`transactions`][1] index as well. Returns hashes of the evicted
+    /// transactions.
+    ///
+    /// [1]: struct.Schema.html#method.transactions
+    #[doc(hidden)]
+    pub fn add_transaction_into_limited_pool<F>(
+        &mut self,
+        tx: &Transaction,
+        capacity: usize,
+        policy: TxPoolEvictionPolicy,
+        is_protected: F,
+    ) -> Result<Vec<Hash>, TxPoolError>
+    where
+        F: Fn(&Hash) -> bool,
+    {
+        let hash = tx.raw().hash();
+        if self.transactions().contains(&hash) {
+            return Err(TxPoolError::Duplicate);
+        }
+
+        let priority = tx.pool_priority();
+        let sender = tx.pool_sender();
+        if let TxPoolEvictionPolicy::PerSenderQuota { max_txs_per_sender } = policy {
+            if let Some(ref sender) = sender {
+                let count = self.transactions_pool_senders().get(sender).unwrap_or(0);
+                if count >= max_txs_per_sender {
+                    return Err(TxPoolError::SenderQuotaExceeded);
+                }
+            }
+        }
+
+        let excess = (self.transactions_pool_len() + 1).saturating_sub(capacity);
+        let victims = if excess == 0 {
+            Vec::new()
+        } else {
+            let victims: Vec<(u64, Hash)> = match policy {
+                TxPoolEvictionPolicy::LowestPriorityFirst => self.transactions_pool_by_priority()
+                    .iter()
+                    .filter(|&(_, ref hash)| !is_protected(hash))
+                    .take(excess)
+                    .map(|(key, hash)| (key.priority, hash))
+                    .collect(),
+                _ => self.transactions_pool_by_age()
+                    .values()
+                    .filter(|hash| !is_protected(hash))
+                    .take(excess)
+                    .map(|hash| (0, hash))
+                    .collect(),
+            };
+            if victims.len() < excess {
+                return Err(TxPoolError::PoolFull);
+            }
+            if policy == TxPoolEvictionPolicy::LowestPriorityFirst
+                && victims.iter().any(|&(victim_priority, _)| victim_priority >= priority)
+            {
+                return Err(TxPoolError::LowPriority);
+            }
+            victims
+        };
+
+        let evicted = victims
+            .into_iter()
+            .map(|(_, victim)| {
+                self.remove_from_pool(&victim);
+                self.transactions_mut().remove(&victim);
+                victim
+            })
+            .collect();
+
+        self.insert_into_pool(&hash, priority, sender);
+        self.transactions_mut().put(&hash, tx.raw().clone());
+        Ok(evicted)
     }
 
     /// Changes transaction status from `in_pool`, to `committed`.
     pub(crate) fn commit_transaction(&mut self, hash: &Hash) {
-        self.transactions_pool_mut().remove(hash)
+        self.remove_from_pool(hash);
     }
 
     /// Remove transaction from persistent pool.
     #[doc(hidden)]
     pub fn reject_transaction(&mut self, hash: &Hash) -> Result<(), ()> {
-        let contains = self.transactions_pool_mut().contains(hash);
-        self.transactions_pool_mut().remove(hash);
+        let contains = self.remove_from_pool(hash);
         self.transactions_mut().remove(hash);
         if contains { Ok(()) } else { Err(()) }
     }
+
+    /// Inserts transaction hash into the pool along with its eviction metadata.
+    fn insert_into_pool(&mut self, hash: &Hash, priority: u64, sender: Option<PublicKey>) {
+        self.insert_pool_entry(hash, priority, sender);
+        self.transactions_pool_mut().insert(*hash);
+        let len = self.transactions_pool_len() as u64;
+        self.set_transactions_pool_len(len + 1);
+    }
+
+    /// Records the eviction metadata of the transaction in the pool.
+    fn insert_pool_entry(&mut self, hash: &Hash, priority: u64, sender: Option<PublicKey>) {
+        let sequence = {
+            let mut entry: Entry<&mut Fork, u64> =
+                Entry::new(TRANSACTIONS_POOL_SEQUENCE, self.view);
+            let sequence = entry.get().unwrap_or(0);
+            entry.set(sequence + 1);
+            sequence
+        };
+
+        if let Some(ref sender) = sender {
+            let count = self.transactions_pool_senders().get(sender).unwrap_or(0);
+            self.transactions_pool_senders_mut().put(sender, count + 1);
+        }
+        let sender = sender.unwrap_or_else(PublicKey::zero);
+        let entry = PoolEntry::new(sequence, priority, &sender);
+        self.transactions_pool_entries_mut().put(hash, entry);
+        self.transactions_pool_by_age_mut().put(&sequence, *hash);
+        self.transactions_pool_by_priority_mut()
+            .put(&PoolOrderKey { priority, sequence }, *hash);
+    }
+
+    /// Removes transaction hash and its eviction metadata from the pool. Returns `true`
+    /// if the transaction has been in the pool.
+    fn remove_from_pool(&mut self, hash: &Hash) -> bool {
+        if !self.transactions_pool().contains(hash) {
+            return false;
+        }
+
+        if let Some(entry) = self.transactions_pool_entries().get(hash) {
+            let sender = entry.sender();
+            if sender != &PublicKey::zero() {
+                let count = self.transactions_pool_senders().get(sender).unwrap_or(0);
+                if count > 1 {
+                    self.transactions_pool_senders_mut().put(sender, count - 1);
+                } else {
+                    self.transactions_pool_senders_mut().remove(sender);
+                }
+            }
+            self.transactions_pool_by_age_mut().remove(&entry.sequence());
+            self.transactions_pool_by_priority_mut().remove(&PoolOrderKey {
+                priority: entry.priority(),
+                sequence: entry.sequence(),
+            });
+            self.transactions_pool_entries_mut().remove(hash);
+        }
+
+        self.transactions_pool_mut().remove(hash);
+        let len = self.transactions_pool_len() as u64;
+        self.set_transactions_pool_len(len.saturating_sub(1));
+        true
+    }
+
+    /// Initializes the number of transactions in the pool from the pool itself if
+    /// the number is not stored, which is the case for databases created by the previous
+    /// versions of Exonum.
+    pub(crate) fn initialize_transactions_pool_len(&mut self) {
+        let is_stored = {
+            let len: Entry<&Fork, u64> = Entry::new(TRANSACTIONS_POOL_LEN, &*self.view);
+            len.exists()
+        };
+        if !is_stored {
+            let len = self.transactions_pool().iter().count() as u64;
+            self.set_transactions_pool_len(len);
+        }
+    }
+
+    /// Records the eviction metadata of the transactions put into the pool by the previous
+    /// versions of Exonum, which are otherwise never evicted. Such transactions get
+    /// the lowest priority, and they are older than any transaction added after the migration.
+    pub(crate) fn migrate_transactions_pool(&mut self) {
+        let legacy = {
+            let entries = self.transactions_pool_entries();
+            self.transactions_pool()
+                .iter()
+                .filter(|hash| !entries.contains(hash))
+                .collect::<Vec<_>>()
+        };
+        for hash in legacy {
+            self.insert_pool_entry(&hash, 0, None);
+        }
+    }
+
+    fn set_transactions_pool_len(&mut self, len: u64) {
+        let mut entry: Entry<&mut Fork, u64> = Entry::new(TRANSACTIONS_POOL_LEN, self.view);
+        entry.set(len);
+    }
 }
@@ -22,15 +22,19 @@ use std::net::SocketAddr;
 
 use serde_json::Value;
 use iron::Handler;
+use failure;
 
 use crypto::{Hash, PublicKey, SecretKey};
 use storage::{Fork, Snapshot};
-use messages::RawTransaction;
+use messages::{MessageClass, RawTransaction};
 use encoding::Error as MessageError;
-use node::{ApiSender, Node, State, TransactionSend};
-use blockchain::{Blockchain, ConsensusConfig, Schema, StoredConfiguration, ValidatorKeys};
+use node::{AllowOrigin, ApiSender, Node, PeerReputation, State, TransactionSend};
+use node::signer::{LocalSigner, Signer};
+use blockchain::{Blockchain, ConsensusConfig, Schema, StateSyncUnsupported, StoredConfiguration,
+                 ValidatorKeys};
 use helpers::{Height, Milliseconds, ValidatorId};
 use super::transaction::Transaction;
+use super::call::{CallResult, ServiceCall};
 
 
 /// A trait that describes business logic of a concrete service.
@@ -169,10 +173,85 @@ pub trait Service: Send + Sync + 'static {
     /// Initializes the information schema of the service
     /// and generates an initial service configuration.
     /// Called on genesis block creation.
+    ///
+    /// If the service is added to an already running blockchain, it stays dormant until
+    /// a configuration that lists its `service_name` in `services` becomes actual. This method
+    /// is called as a part of the block at that height; the returned configuration
+    /// is ignored in this case.
     fn initialize(&self, fork: &mut Fork) -> Value {
         Value::Null
     }
 
+    /// Returns the version of the data schema used by this service implementation.
+    ///
+    /// The version should be increased each time the layout of the service data is changed
+    /// in an incompatible way, for example, when a field is added into an `encoding_struct!`
+    /// stored in an index. Data is migrated to a new version by the [`migrate`] method.
+    ///
+    /// [`migrate`]: #method.migrate
+    fn schema_version(&self) -> u16 {
+        0
+    }
+
+    /// Migrates the service data from version `to_version - 1` to version `to_version`.
+    ///
+    /// Migrations are invoked as a part of the block at the height where a configuration
+    /// listing a greater version of the service in `service_versions` becomes actual.
+    /// If the service data should be migrated by several versions, this method is invoked
+    /// for each intermediate version in the ascending order. The applied version is recorded
+    /// in the core schema and can be obtained by `Schema::service_schema_version`.
+    ///
+    /// Migrations must be deterministic. If a migration returns an error, the node stops,
+    /// since it cannot build the block with the same state as the other nodes.
+    ///
+    /// The default implementation returns an error, since a service with the only schema
+    /// version has nothing to migrate.
+    fn migrate(&self, fork: &mut Fork, to_version: u16) -> Result<(), failure::Error> {
+        bail!(
+            "Service '{}' has no migration to schema version {}",
+            self.service_name(),
+            to_version
+        )
+    }
+
+    /// Restores the service data received from other nodes during the state sync.
+    ///
+    /// `received` contains the indexes of the service as they have been sent by a peer,
+    /// including the internal nodes of the Merkelized indexes, so none of them can be trusted.
+    /// The implementation should read the elements of its indexes from `received` and write
+    /// them into `fork` anew, so that the root hashes returned by [`state_hash`] are
+    /// recomputed. The core compares the resulting state hash with the `state_hash` of
+    /// the snapshot block. The data that does not contribute to [`state_hash`] should be
+    /// either checked against the Merkelized data or rebuilt from it; an error aborts
+    /// the state sync and bans the peer.
+    ///
+    /// Only the indexes whose names start with `service_name` followed by a dot are
+    /// transferred during the state sync.
+    ///
+    /// The default implementation returns [`StateSyncUnsupported`], so a node falls back
+    /// to the block sync in a blockchain with the services that do not support the state sync.
+    ///
+    /// [`state_hash`]: #tymethod.state_hash
+    /// [`StateSyncUnsupported`]: struct.StateSyncUnsupported.html
+    fn restore_state(&self, received: &Snapshot, fork: &mut Fork) -> Result<(), failure::Error> {
+        Err(StateSyncUnsupported(self.service_name().to_owned()).into())
+    }
+
+    /// Handles a call of a method of the service interface made by a transaction of another
+    /// service with [`call_service`]. The call is executed within the same `fork` and
+    /// checkpoint as the calling transaction.
+    ///
+    /// Use [`ServiceCall::args`] to decode arguments of the declared methods and return
+    /// the method output encoded with `StorageValue::into_bytes`.
+    ///
+    /// The default implementation does not support any methods.
+    ///
+    /// [`call_service`]: fn.call_service.html
+    /// [`ServiceCall::args`]: struct.ServiceCall.html#method.args
+    fn handle_call(&self, fork: &mut Fork, call: &ServiceCall) -> CallResult {
+        Err(call.unsupported())
+    }
+
     /// Handles block commit. This handler is invoked for each service after commit of the block.
     /// For example, a service can create one or more transactions if a specific condition
     /// has occurred.
@@ -180,6 +259,14 @@ pub trait Service: Send + Sync + 'static {
     /// *Try not to perform long operations in this handler*.
     fn handle_commit(&self, context: &ServiceContext) {}
 
+    /// Handles shutdown of the node. This handler is invoked for each service after the node
+    /// has stopped accepting transactions and disconnected from its peers, before
+    /// the storage is flushed to the disk. A service can release its resources or finish
+    /// its background activity here.
+    ///
+    /// *Try not to perform long operations in this handler*.
+    fn shutdown(&self) {}
+
     /// Returns an API handler for public requests. The handler is mounted on
     /// the `/api/services/{service_name}` path at [the public listen address][pub-addr]
     /// of all full nodes in the blockchain network.
@@ -205,6 +292,7 @@ pub trait Service: Send + Sync + 'static {
 pub struct ServiceContext {
     validator_id: Option<ValidatorId>,
     service_keypair: (PublicKey, SecretKey),
+    signer: Arc<Signer>,
     api_sender: ApiSender,
     fork: Fork,
     stored_configuration: StoredConfiguration,
@@ -235,9 +323,14 @@ impl ServiceContext {
             .position(|validator| service_public_key == validator.service_key)
             .map(|id| ValidatorId(id as u16));
 
+        let signer = Arc::new(LocalSigner::new(
+            service_public_key,
+            service_secret_key.clone(),
+        ));
         ServiceContext {
             validator_id,
             service_keypair: (service_public_key, service_secret_key),
+            signer,
             api_sender,
             fork,
             stored_configuration,
@@ -272,10 +365,23 @@ impl ServiceContext {
     }
 
     /// Returns current node's secret key.
+    ///
+    /// The key is not used if the node signs the messages with the service key by a remote
+    /// signer, so the messages should be signed with [`signer`](#method.signer) instead.
     pub fn secret_key(&self) -> &SecretKey {
         &self.service_keypair.1
     }
 
+    /// Returns the signer of the messages with the service key of the node. The remote signer
+    /// blocks the caller until the message is signed.
+    pub fn signer(&self) -> &Signer {
+        &*self.signer
+    }
+
+    pub(crate) fn set_signer(&mut self, signer: Arc<Signer>) {
+        self.signer = signer;
+    }
+
     /// Returns the actual consensus configuration.
     pub fn actual_consensus_config(&self) -> &ConsensusConfig {
         &self.stored_configuration.consensus
@@ -304,7 +410,14 @@ pub struct ApiNodeState {
     reconnects_timeout: HashMap<SocketAddr, Milliseconds>,
     //TODO: update on event?
     peers_info: HashMap<SocketAddr, PublicKey>,
+    peers_reputation: HashMap<PublicKey, PeerReputation>,
     is_enabled: bool,
+    rejected_txs: u64,
+    evicted_txs: u64,
+    dropped_messages: HashMap<MessageClass, u64>,
+    allow_origin: Option<AllowOrigin>,
+    is_shutting_down: bool,
+    is_stopped: bool,
 }
 
 impl ApiNodeState {
@@ -370,6 +483,16 @@ impl SharedNodeState {
             .map(|(c, e)| (*c, *e))
             .collect()
     }
+    /// Return reputation of the peers
+    pub fn peers_reputation(&self) -> Vec<(PublicKey, PeerReputation)> {
+        self.state
+            .read()
+            .expect("Expected read lock.")
+            .peers_reputation
+            .iter()
+            .map(|(p, r)| (*p, *r))
+            .collect()
+    }
     /// Update internal state, from `Node` State`
     pub fn update_node_state(&self, state: &State) {
         for (p, c) in state.peers().iter() {
@@ -379,6 +502,10 @@ impl SharedNodeState {
                 .peers_info
                 .insert(c.addr(), *p);
         }
+        self.state
+            .write()
+            .expect("Expected write lock.")
+            .peers_reputation = state.reputation().peers().clone();
     }
 
     /// Is the node enabled?
@@ -393,6 +520,43 @@ impl SharedNodeState {
         state.is_enabled = is_enabled;
     }
 
+    /// Returns `true` if the node is shutting down and does not accept transactions.
+    pub fn is_shutting_down(&self) -> bool {
+        let state = self.state.read().expect("Expected read lock.");
+        state.is_shutting_down
+    }
+
+    /// Informs internal state that the node is shutting down.
+    pub fn set_shutting_down(&self) {
+        let mut state = self.state.write().expect("Expected write lock.");
+        state.is_shutting_down = true;
+    }
+
+    /// Returns `true` if the node has stopped and the api no longer serves requests.
+    pub fn is_stopped(&self) -> bool {
+        let state = self.state.read().expect("Expected read lock.");
+        state.is_stopped
+    }
+
+    /// Informs internal state that the node has stopped.
+    pub fn set_stopped(&self) {
+        let mut state = self.state.write().expect("Expected write lock.");
+        state.is_stopped = true;
+    }
+
+    /// Returns CORS options of the public api.
+    pub fn allow_origin(&self) -> Option<AllowOrigin> {
+        let state = self.state.read().expect("Expected read lock.");
+        state.allow_origin.clone()
+    }
+
+    /// Replaces CORS options of the public api. The new options are applied
+    /// to the subsequent requests.
+    pub fn set_allow_origin(&self, allow_origin: Option<AllowOrigin>) {
+        let mut state = self.state.write().expect("Expected write lock.");
+        state.allow_origin = allow_origin;
+    }
+
     /// Returns value of the `state_update_timeout`.
     pub fn state_update_timeout(&self) -> Milliseconds {
         self.state_update_timeout
@@ -454,6 +618,47 @@ impl SharedNodeState {
             .reconnects_timeout
             .remove(addr)
     }
+
+    /// Returns number of transactions that have not been admitted into the memory pool.
+    pub fn rejected_txs(&self) -> u64 {
+        self.state.read().expect("Expected read lock.").rejected_txs
+    }
+
+    /// Returns number of transactions that have been evicted from the memory pool.
+    pub fn evicted_txs(&self) -> u64 {
+        self.state.read().expect("Expected read lock.").evicted_txs
+    }
+
+    /// Increments the counter of transactions not admitted into the memory pool.
+    pub fn add_rejected_txs(&self, count: u64) {
+        self.state.write().expect("Expected write lock").rejected_txs += count;
+    }
+
+    /// Increments the counter of transactions evicted from the memory pool.
+    pub fn add_evicted_txs(&self, count: u64) {
+        self.state.write().expect("Expected write lock").evicted_txs += count;
+    }
+
+    /// Returns number of messages of the given class dropped because of the peers rate limits.
+    pub fn dropped_messages(&self, class: MessageClass) -> u64 {
+        self.state
+            .read()
+            .expect("Expected read lock.")
+            .dropped_messages
+            .get(&class)
+            .cloned()
+            .unwrap_or(0)
+    }
+
+    /// Increments the counter of dropped messages of the given class.
+    pub fn add_dropped_message(&self, class: MessageClass) {
+        *self.state
+            .write()
+            .expect("Expected write lock")
+            .dropped_messages
+            .entry(class)
+            .or_insert(0) += 1;
+    }
 }
 
 /// Provides the current node state to api handlers.
@@ -462,6 +667,7 @@ pub struct ApiContext {
     node_channel: ApiSender,
     public_key: PublicKey,
     secret_key: SecretKey,
+    signer: Arc<Signer>,
 }
 
 /// Provides the current node state to api handlers.
@@ -474,6 +680,7 @@ impl ApiContext {
             node_channel: node.channel(),
             public_key: *node.state().service_public_key(),
             secret_key: node.state().service_secret_key().clone(),
+            signer: Arc::clone(handler.blockchain.service_signer()),
         }
     }
 
@@ -489,6 +696,7 @@ impl ApiContext {
             node_channel,
             public_key: *public_key,
             secret_key: secret_key.clone(),
+            signer: Arc::clone(blockchain.service_signer()),
         }
     }
 
@@ -508,9 +716,17 @@ impl ApiContext {
     }
 
     /// Returns the secret key of current node.
+    ///
+    /// The key is not used if the node signs the messages with the service key by a remote
+    /// signer, so the messages should be signed with [`signer`](#method.signer) instead.
     pub fn secret_key(&self) -> &SecretKey {
         &self.secret_key
     }
+
+    /// Returns the signer of the messages with the service key of the node.
+    pub fn signer(&self) -> &Arc<Signer> {
+        &self.signer
+    }
 }
 
 impl ::std::fmt::Debug for ApiContext {
@@ -0,0 +1,224 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Subscriptions to the changes of the blockchain.
+//!
+//! After a block is committed, [`Blockchain::commit`] publishes a [`Notification`] with the
+//! block header, one per committed transaction and one per event emitted by these
+//! transactions. The notifications of a block are sent to a subscriber as a single batch,
+//! and each subscriber has a bounded buffer of such batches; a subscriber which does not
+//! keep up with the blockchain and overflows its buffer is dropped and marked as lagged,
+//! so that a slow consumer never blocks the node.
+//!
+//! [`Blockchain::commit`]: ../struct.Blockchain.html#method.commit
+//! [`Notification`]: enum.Notification.html
+
+use std::collections::{HashSet, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError};
+use std::time::Duration;
+
+use crypto::Hash;
+use helpers::Height;
+use super::{Block, TransactionEvent, TransactionResult};
+
+/// Default number of committed blocks buffered for a subscriber.
+pub const DEFAULT_SUBSCRIPTION_BUFFER: usize = 64;
+
+/// Change of the blockchain sent to the subscribers.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Notification {
+    /// A block has been committed.
+    Block(Block),
+    /// A transaction has been committed.
+    Transaction {
+        /// Hash of the transaction.
+        tx_hash: Hash,
+        /// Height of the block containing the transaction.
+        height: Height,
+        /// Result of the transaction execution.
+        result: TransactionResult,
+    },
+    /// A committed transaction has emitted an event.
+    Event {
+        /// Hash of the transaction.
+        tx_hash: Hash,
+        /// The emitted event.
+        event: TransactionEvent,
+    },
+}
+
+/// Filter of the notifications received by a subscriber.
+///
+/// Blocks and transactions are always delivered, while the events are delivered only
+/// for the listed services.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SubscriptionFilter {
+    services: HashSet<u16>,
+}
+
+impl SubscriptionFilter {
+    /// Creates a filter delivering the events of the given services.
+    pub fn new<I: IntoIterator<Item = u16>>(services: I) -> Self {
+        SubscriptionFilter { services: services.into_iter().collect() }
+    }
+
+    /// Returns `true` if the notification passes the filter.
+    pub fn matches(&self, notification: &Notification) -> bool {
+        match *notification {
+            Notification::Event { ref event, .. } => self.services.contains(&event.service_id()),
+            _ => true,
+        }
+    }
+}
+
+struct Subscriber {
+    filter: SubscriptionFilter,
+    sender: SyncSender<Vec<Notification>>,
+    // Shared with the `Subscription`, so the subscriber is closed once this is the only
+    // reference left.
+    lagged: Arc<AtomicBool>,
+}
+
+impl Subscriber {
+    fn is_closed(&self) -> bool {
+        Arc::strong_count(&self.lagged) == 1
+    }
+}
+
+/// Registry of the blockchain subscribers, shared between the clones of the blockchain.
+#[derive(Clone, Default)]
+pub struct Subscriptions {
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+impl Subscriptions {
+    /// Registers a subscriber with the given filter and size of the buffer in blocks.
+    ///
+    /// Returns `None` if there are already `max_subscribers` active subscribers.
+    pub fn subscribe(
+        &self,
+        filter: SubscriptionFilter,
+        buffer: usize,
+        max_subscribers: usize,
+    ) -> Option<Subscription> {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| !subscriber.is_closed());
+        if subscribers.len() >= max_subscribers {
+            return None;
+        }
+
+        let (sender, receiver) = sync_channel(buffer);
+        let lagged = Arc::new(AtomicBool::new(false));
+        subscribers.push(Subscriber {
+            filter,
+            sender,
+            lagged: Arc::clone(&lagged),
+        });
+        Some(Subscription {
+            receiver,
+            pending: VecDeque::new(),
+            lagged,
+        })
+    }
+
+    /// Returns the number of active subscribers.
+    pub fn len(&self) -> usize {
+        let subscribers = self.subscribers.lock().unwrap();
+        subscribers.iter().filter(|subscriber| !subscriber.is_closed()).count()
+    }
+
+    /// Returns `true` if there are no active subscribers.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sends the notifications of a committed block to the subscribers, dropping
+    /// the subscribers which have been closed or whose buffers are full.
+    pub fn publish(&self, notifications: &[Notification]) {
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|subscriber| {
+            let batch = notifications
+                .iter()
+                .filter(|notification| subscriber.filter.matches(notification))
+                .cloned()
+                .collect::<Vec<_>>();
+            match subscriber.sender.try_send(batch) {
+                Ok(()) => true,
+                Err(TrySendError::Full(_)) => {
+                    warn!("Subscriber is lagging behind the blockchain, dropping it");
+                    subscriber.lagged.store(true, Ordering::SeqCst);
+                    false
+                }
+                Err(TrySendError::Disconnected(_)) => false,
+            }
+        });
+    }
+}
+
+impl ::std::fmt::Debug for Subscriptions {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        f.debug_struct("Subscriptions")
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+/// Receiving end of a subscription. The subscription is cancelled when it is dropped.
+#[derive(Debug)]
+pub struct Subscription {
+    receiver: Receiver<Vec<Notification>>,
+    pending: VecDeque<Notification>,
+    lagged: Arc<AtomicBool>,
+}
+
+impl Subscription {
+    /// Waits for the next notification for at most `timeout`.
+    ///
+    /// Returns `Ok(None)` if no notification has been published during the timeout
+    /// and `Err(RecvTimeoutError::Disconnected)` once the subscription is closed
+    /// and all the buffered notifications are received.
+    pub fn recv_timeout(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<Option<Notification>, RecvTimeoutError> {
+        if let Some(notification) = self.pending.pop_front() {
+            return Ok(Some(notification));
+        }
+        match self.receiver.recv_timeout(timeout) {
+            Ok(batch) => {
+                self.pending.extend(batch);
+                Ok(self.pending.pop_front())
+            }
+            Err(RecvTimeoutError::Timeout) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Returns the next buffered notification without blocking.
+    pub fn try_recv(&mut self) -> Option<Notification> {
+        if self.pending.is_empty() {
+            let batch = self.receiver.try_recv().ok()?;
+            self.pending.extend(batch);
+        }
+        self.pending.pop_front()
+    }
+
+    /// Returns `true` if the subscription has been closed because the subscriber
+    /// did not keep up with the blockchain.
+    pub fn is_lagged(&self) -> bool {
+        self.lagged.load(Ordering::SeqCst)
+    }
+}
@@ -213,6 +213,7 @@ fn handling_tx_panic(blockchain: &mut Blockchain) {
     let (_, patch) = blockchain.create_patch(
         ValidatorId::zero(),
         Height::zero(),
+        Utc::now(),
         &[tx_ok1.hash(), tx_failed.hash(), tx_ok2.hash()],
     );
 
@@ -265,6 +266,7 @@ fn handling_tx_panic_storage_error(blockchain: &mut Blockchain) {
     blockchain.create_patch(
         ValidatorId::zero(),
         Height::zero(),
+        Utc::now(),
         &[tx_ok1.hash(), tx_storage_error.hash(), tx_ok2.hash()],
     );
 }
@@ -363,6 +365,1166 @@ mod transactions_tests {
     }
 }
 
+mod pool_tests {
+    use std::sync::Arc;
+    use blockchain::{Schema, Transaction, ExecutionResult, TxPoolError};
+    use crypto::{gen_keypair, CryptoHash, PublicKey, SecretKey};
+    use messages::Message;
+    use node::TxPoolEvictionPolicy;
+    use storage::{Database, Entry, Fork, KeySetIndex, MapIndex, MemoryDB};
+    use super::test_helpers::{self, genesis};
+
+    transactions! {
+        PoolTransactions {
+            const SERVICE_ID = 93;
+
+            struct PoolTx {
+                from: &PublicKey,
+                priority: u64,
+                seed: u64,
+            }
+        }
+    }
+
+    impl Transaction for PoolTx {
+        fn verify(&self) -> bool {
+            true
+        }
+
+        fn execute(&self, _: &mut Fork) -> ExecutionResult {
+            Ok(())
+        }
+
+        fn pool_priority(&self) -> u64 {
+            self.priority()
+        }
+
+        fn pool_sender(&self) -> Option<PublicKey> {
+            Some(*self.from())
+        }
+    }
+
+    fn pool_tx(keys: &(PublicKey, SecretKey), priority: u64, seed: u64) -> PoolTx {
+        PoolTx::new(&keys.0, priority, seed, &keys.1)
+    }
+
+    #[test]
+    fn test_pool_oldest_first() {
+        let db = MemoryDB::new();
+        let mut fork = db.fork();
+        let mut schema = Schema::new(&mut fork);
+        let keys = gen_keypair();
+        let policy = TxPoolEvictionPolicy::OldestFirst;
+
+        let txs: Vec<_> = (0..4).map(|seed| pool_tx(&keys, 0, seed)).collect();
+        for tx in &txs[..3] {
+            assert_eq!(
+                schema.add_transaction_into_limited_pool(tx, 3, policy, |_| false),
+                Ok(vec![])
+            );
+        }
+        assert_eq!(schema.transactions_pool_len(), 3);
+
+        let protected = txs[0].hash();
+        let evicted = schema
+            .add_transaction_into_limited_pool(&txs[3], 3, policy, |hash| *hash == protected)
+            .unwrap();
+        assert_eq!(evicted, vec![txs[1].hash()]);
+        assert_eq!(schema.transactions_pool_len(), 3);
+        assert!(!schema.transactions_pool().contains(&txs[1].hash()));
+        assert!(schema.transactions().get(&txs[1].hash()).is_none());
+        assert!(schema.transactions_pool().contains(&txs[3].hash()));
+
+        let tx = pool_tx(&keys, 0, 4);
+        assert_eq!(
+            schema.add_transaction_into_limited_pool(&tx, 3, policy, |_| true),
+            Err(TxPoolError::PoolFull)
+        );
+        assert_eq!(schema.transactions_pool_len(), 3);
+    }
+
+    #[test]
+    fn test_pool_lowest_priority_first() {
+        let db = MemoryDB::new();
+        let mut fork = db.fork();
+        let mut schema = Schema::new(&mut fork);
+        let keys = gen_keypair();
+        let policy = TxPoolEvictionPolicy::LowestPriorityFirst;
+
+        let low = pool_tx(&keys, 1, 0);
+        let high = pool_tx(&keys, 5, 1);
+        schema
+            .add_transaction_into_limited_pool(&high, 2, policy, |_| false)
+            .unwrap();
+        schema
+            .add_transaction_into_limited_pool(&low, 2, policy, |_| false)
+            .unwrap();
+
+        assert_eq!(
+            schema.add_transaction_into_limited_pool(&pool_tx(&keys, 1, 2), 2, policy, |_| false),
+            Err(TxPoolError::LowPriority)
+        );
+        assert_eq!(
+            schema.add_transaction_into_limited_pool(&pool_tx(&keys, 3, 3), 2, policy, |_| false),
+            Ok(vec![low.hash()])
+        );
+        assert!(schema.transactions_pool().contains(&high.hash()));
+        assert_eq!(schema.transactions_pool_len(), 2);
+    }
+
+    #[test]
+    fn test_pool_per_sender_quota() {
+        let db = MemoryDB::new();
+        let mut fork = db.fork();
+        let mut schema = Schema::new(&mut fork);
+        let (alice, bob) = (gen_keypair(), gen_keypair());
+        let policy = TxPoolEvictionPolicy::PerSenderQuota { max_txs_per_sender: 2 };
+
+        let first = pool_tx(&alice, 0, 0);
+        schema
+            .add_transaction_into_limited_pool(&first, 10, policy, |_| false)
+            .unwrap();
+        schema
+            .add_transaction_into_limited_pool(&pool_tx(&alice, 0, 1), 10, policy, |_| false)
+            .unwrap();
+        assert_eq!(
+            schema.add_transaction_into_limited_pool(&pool_tx(&alice, 0, 2), 10, policy, |_| false),
+            Err(TxPoolError::SenderQuotaExceeded)
+        );
+        schema
+            .add_transaction_into_limited_pool(&pool_tx(&bob, 0, 2), 10, policy, |_| false)
+            .unwrap();
+
+        schema.commit_transaction(&first.hash());
+        assert_eq!(schema.transactions_pool_len(), 2);
+        schema
+            .add_transaction_into_limited_pool(&pool_tx(&alice, 0, 3), 10, policy, |_| false)
+            .unwrap();
+        assert_eq!(schema.transactions_pool_len(), 3);
+    }
+
+    #[test]
+    fn test_pool_rejects_duplicates() {
+        let db = MemoryDB::new();
+        let mut fork = db.fork();
+        let mut schema = Schema::new(&mut fork);
+        let tx = pool_tx(&gen_keypair(), 0, 0);
+        let policy = TxPoolEvictionPolicy::OldestFirst;
+
+        assert_eq!(
+            schema.add_transaction_into_limited_pool(&tx, 3, policy, |_| false),
+            Ok(vec![])
+        );
+        assert_eq!(
+            schema.add_transaction_into_limited_pool(&tx, 3, policy, |_| false),
+            Err(TxPoolError::Duplicate)
+        );
+        assert_eq!(schema.transactions_pool_len(), 1);
+
+        schema.commit_transaction(&tx.hash());
+        assert_eq!(
+            schema.add_transaction_into_limited_pool(&tx, 3, policy, |_| false),
+            Err(TxPoolError::Duplicate)
+        );
+        assert_eq!(schema.transactions_pool_len(), 0);
+    }
+
+    #[test]
+    fn test_pool_entries_migration() {
+        let db: Arc<Database> = Arc::new(MemoryDB::new());
+        let mut blockchain = test_helpers::create_blockchain(&db, vec![]);
+        blockchain.initialize(genesis()).unwrap();
+
+        // The pool is filled by a previous version that does not store the eviction metadata.
+        let keys = gen_keypair();
+        let legacy = pool_tx(&keys, 5, 0);
+        let mut fork = blockchain.fork();
+        KeySetIndex::new("core.transactions_pool", &mut fork).insert(legacy.hash());
+        MapIndex::new("core.transactions", &mut fork).put(&legacy.hash(), legacy.raw().clone());
+        Entry::<_, u64>::new("core.transactions_pool_len", &mut fork).remove();
+        blockchain.merge(fork.into_patch()).unwrap();
+
+        blockchain.initialize(genesis()).unwrap();
+        let mut fork = blockchain.fork();
+        let mut schema = Schema::new(&mut fork);
+        assert_eq!(schema.transactions_pool_len(), 1);
+        let policy = TxPoolEvictionPolicy::LowestPriorityFirst;
+        assert_eq!(
+            schema.add_transaction_into_limited_pool(&pool_tx(&keys, 1, 1), 1, policy, |_| false),
+            Ok(vec![legacy.hash()])
+        );
+        assert_eq!(schema.transactions_pool_len(), 1);
+    }
+
+    #[test]
+    fn test_pool_len_initialization() {
+        let db: Arc<Database> = Arc::new(MemoryDB::new());
+        let mut blockchain = test_helpers::create_blockchain(&db, vec![]);
+        blockchain.initialize(genesis()).unwrap();
+
+        // The pool is filled by a previous version that does not store its length.
+        let keys = gen_keypair();
+        let mut fork = blockchain.fork();
+        {
+            let mut schema = Schema::new(&mut fork);
+            schema.add_transaction_into_pool(pool_tx(&keys, 0, 0).raw().clone());
+            schema.add_transaction_into_pool(pool_tx(&keys, 0, 1).raw().clone());
+        }
+        Entry::<_, u64>::new("core.transactions_pool_len", &mut fork).remove();
+        blockchain.merge(fork.into_patch()).unwrap();
+        assert_eq!(Schema::new(&blockchain.snapshot()).transactions_pool_len(), 0);
+
+        blockchain.initialize(genesis()).unwrap();
+        assert_eq!(Schema::new(&blockchain.snapshot()).transactions_pool_len(), 2);
+    }
+}
+
+/// Helpers shared by the test modules.
+mod test_helpers {
+    use std::marker::PhantomData;
+    use std::sync::Arc;
+    use chrono::{DateTime, Utc};
+    use futures::sync::mpsc;
+    use blockchain::{Blockchain, GenesisConfig, Schema, Service, Snapshot, Transaction,
+                     TransactionSet, ValidatorKeys};
+    use crypto::{gen_keypair, Hash, PublicKey};
+    use encoding::Error as MessageError;
+    use helpers::{Height, ValidatorId};
+    use messages::RawTransaction;
+    use node::ApiSender;
+    use storage::{Database, MemoryDB};
+
+    /// Service without a state, which transactions are parsed with the given set.
+    pub struct TxService<T> {
+        id: u16,
+        name: &'static str,
+        _transactions: PhantomData<fn() -> T>,
+    }
+
+    impl<T> TxService<T> {
+        pub fn new(id: u16, name: &'static str) -> TxService<T> {
+            TxService {
+                id,
+                name,
+                _transactions: PhantomData,
+            }
+        }
+    }
+
+    impl<T: TransactionSet + 'static> Service for TxService<T> {
+        fn service_id(&self) -> u16 {
+            self.id
+        }
+
+        fn service_name(&self) -> &'static str {
+            self.name
+        }
+
+        fn state_hash(&self, _: &Snapshot) -> Vec<Hash> {
+            vec![]
+        }
+
+        fn tx_from_raw(&self, raw: RawTransaction) -> Result<Box<Transaction>, MessageError> {
+            let tx = T::tx_from_raw(raw)?;
+            Ok(tx.into())
+        }
+    }
+
+    /// Creates a blockchain with the given services on top of the database.
+    pub fn create_blockchain(db: &Arc<Database>, services: Vec<Box<Service>>) -> Blockchain {
+        let service_keypair = gen_keypair();
+        let api_channel = mpsc::channel(1);
+        Blockchain::new(
+            Arc::clone(db),
+            services,
+            service_keypair.0,
+            service_keypair.1,
+            ApiSender::new(api_channel.0),
+        )
+    }
+
+    /// Creates a blockchain with the given services in memory and commits the genesis block.
+    pub fn create_initialized_blockchain(
+        services: Vec<Box<Service>>,
+        genesis: GenesisConfig,
+    ) -> Blockchain {
+        let db: Arc<Database> = Arc::new(MemoryDB::new());
+        let mut blockchain = create_blockchain(&db, services);
+        blockchain.initialize(genesis).unwrap();
+        blockchain
+    }
+
+    /// Returns the keys of a validator with the given consensus key.
+    pub fn validator_keys(consensus_key: PublicKey) -> ValidatorKeys {
+        ValidatorKeys {
+            consensus_key,
+            service_key: gen_keypair().0,
+        }
+    }
+
+    /// Returns the genesis configuration with a single validator.
+    pub fn genesis() -> GenesisConfig {
+        genesis_with_key(gen_keypair().0)
+    }
+
+    /// Returns the genesis configuration with a single validator with the given
+    /// consensus key.
+    pub fn genesis_with_key(consensus_key: PublicKey) -> GenesisConfig {
+        GenesisConfig::new(vec![validator_keys(consensus_key)].into_iter())
+    }
+
+    /// Adds the transactions to the pool and commits them in a block at the given height.
+    pub fn commit_transactions<I>(
+        blockchain: &mut Blockchain,
+        height: u64,
+        time: DateTime<Utc>,
+        txs: I,
+    ) where
+        I: IntoIterator<Item = RawTransaction>,
+    {
+        let mut fork = blockchain.fork();
+        let mut hashes = Vec::new();
+        {
+            let mut schema = Schema::new(&mut fork);
+            for tx in txs {
+                hashes.push(tx.hash());
+                schema.add_transaction_into_pool(tx);
+            }
+        }
+        blockchain.merge(fork.into_patch()).unwrap();
+
+        let (_, patch) =
+            blockchain.create_patch(ValidatorId::zero(), Height(height), time, &hashes);
+        blockchain.merge(patch).unwrap();
+    }
+}
+
+mod service_activation_tests {
+    use std::sync::Arc;
+    use chrono::Utc;
+    use serde_json::Value;
+    use blockchain::{Blockchain, Schema, Service, Snapshot, Transaction};
+    use crypto::{CryptoHash, Hash};
+    use encoding::Error as MessageError;
+    use helpers::{Height, ValidatorId};
+    use messages::RawTransaction;
+    use storage::{Database, Entry, Fork, MemoryDB};
+    use super::test_helpers::{create_blockchain, genesis};
+
+    const DORMANT_SERVICE_ID: u16 = 254;
+    const DORMANT_SERVICE_NAME: &str = "dormant service";
+    const INITIALIZED: &str = "dormant_service.initialized";
+
+    struct DormantService;
+
+    impl Service for DormantService {
+        fn service_id(&self) -> u16 {
+            DORMANT_SERVICE_ID
+        }
+
+        fn service_name(&self) -> &'static str {
+            DORMANT_SERVICE_NAME
+        }
+
+        fn state_hash(&self, _: &Snapshot) -> Vec<Hash> {
+            vec![Hash::zero()]
+        }
+
+        fn tx_from_raw(&self, _: RawTransaction) -> Result<Box<Transaction>, MessageError> {
+            Err(MessageError::from("Dormant service has no transactions."))
+        }
+
+        fn initialize(&self, fork: &mut Fork) -> Value {
+            let mut initialized: Entry<&mut Fork, bool> = Entry::new(INITIALIZED, fork);
+            initialized.set(true);
+            Value::Null
+        }
+    }
+
+    #[test]
+    fn test_service_activation() {
+        let db: Arc<Database> = Arc::new(MemoryDB::new());
+        let mut blockchain =
+            create_blockchain(&db, vec![Box::new(super::TestService) as Box<Service>]);
+        blockchain.initialize(genesis()).unwrap();
+
+        // The node binary is updated with a new service.
+        let mut blockchain = create_blockchain(
+            &db,
+            vec![
+                Box::new(super::TestService) as Box<Service>,
+                Box::new(DormantService),
+            ],
+        );
+        let mut fork = blockchain.fork();
+        {
+            let mut schema = Schema::new(&mut fork);
+            let mut config = schema.actual_configuration();
+            assert!(!blockchain.is_service_active(DORMANT_SERVICE_ID, &config));
+
+            config.previous_cfg_hash = config.hash();
+            config.actual_from = Height(2);
+            config
+                .services
+                .insert(DORMANT_SERVICE_NAME.to_owned(), Value::Null);
+            assert!(blockchain.is_service_active(DORMANT_SERVICE_ID, &config));
+            schema.commit_configuration(config);
+        }
+        blockchain.merge(fork.into_patch()).unwrap();
+
+        let state_key = Blockchain::service_table_unique_key(DORMANT_SERVICE_ID, 0);
+        for height in 1..4 {
+            let (_, patch) =
+                blockchain.create_patch(ValidatorId::zero(), Height(height), Utc::now(), &[]);
+            blockchain.merge(patch).unwrap();
+
+            let snapshot = blockchain.snapshot();
+            let initialized: Entry<_, bool> = Entry::new(INITIALIZED, &snapshot);
+            let schema = Schema::new(&snapshot);
+            assert_eq!(initialized.exists(), height >= 2);
+            assert_eq!(
+                schema.state_hash_aggregator().contains(&state_key),
+                height >= 2
+            );
+        }
+    }
+}
+
+mod service_migration_tests {
+    use std::collections::BTreeMap;
+    use std::sync::Arc;
+    use chrono::Utc;
+    use serde_json::Value;
+    use failure;
+    use blockchain::{Blockchain, Schema, Service, Snapshot, Transaction};
+    use crypto::{CryptoHash, Hash};
+    use encoding::Error as MessageError;
+    use helpers::{Height, ValidatorId};
+    use messages::RawTransaction;
+    use storage::{Database, Entry, Fork, MemoryDB};
+    use super::test_helpers::{self, genesis};
+
+    const VERSIONED_SERVICE_ID: u16 = 253;
+    const VERSIONED_SERVICE_NAME: &str = "versioned service";
+    const VALUE: &str = "versioned_service.value";
+
+    struct VersionedService {
+        schema_version: u16,
+    }
+
+    impl Service for VersionedService {
+        fn service_id(&self) -> u16 {
+            VERSIONED_SERVICE_ID
+        }
+
+        fn service_name(&self) -> &'static str {
+            VERSIONED_SERVICE_NAME
+        }
+
+        fn state_hash(&self, snapshot: &Snapshot) -> Vec<Hash> {
+            let value: Entry<_, u64> = Entry::new(VALUE, snapshot);
+            vec![value.hash()]
+        }
+
+        fn tx_from_raw(&self, _: RawTransaction) -> Result<Box<Transaction>, MessageError> {
+            Err(MessageError::from("Versioned service has no transactions."))
+        }
+
+        fn initialize(&self, fork: &mut Fork) -> Value {
+            let mut value: Entry<&mut Fork, u64> = Entry::new(VALUE, fork);
+            value.set(21);
+            Value::Null
+        }
+
+        fn schema_version(&self) -> u16 {
+            self.schema_version
+        }
+
+        fn migrate(&self, fork: &mut Fork, to_version: u16) -> Result<(), failure::Error> {
+            let mut value: Entry<&mut Fork, u64> = Entry::new(VALUE, fork);
+            let migrated = value.get().unwrap() * 2;
+            value.set(migrated);
+            if to_version > 1 {
+                bail!("Migration to schema version {} is not implemented", to_version);
+            }
+            Ok(())
+        }
+    }
+
+    fn create_blockchain(db: &Arc<Database>, schema_version: u16) -> Blockchain {
+        let service = VersionedService { schema_version };
+        test_helpers::create_blockchain(db, vec![Box::new(service) as Box<Service>])
+    }
+
+    fn stored_value(blockchain: &Blockchain) -> u64 {
+        let snapshot = blockchain.snapshot();
+        let value: Entry<_, u64> = Entry::new(VALUE, &snapshot);
+        value.get().unwrap()
+    }
+
+    fn applied_version(blockchain: &Blockchain) -> u16 {
+        let snapshot = blockchain.snapshot();
+        Schema::new(&snapshot).service_schema_version(VERSIONED_SERVICE_ID)
+    }
+
+    fn commit_service_versions(
+        blockchain: &mut Blockchain,
+        service_versions: &BTreeMap<String, u16>,
+        actual_from: Height,
+    ) {
+        let mut fork = blockchain.fork();
+        {
+            let mut schema = Schema::new(&mut fork);
+            let mut config = schema.actual_configuration();
+            config.previous_cfg_hash = config.hash();
+            config.actual_from = actual_from;
+            config.service_versions = service_versions.clone();
+            schema.commit_configuration(config);
+        }
+        blockchain.merge(fork.into_patch()).unwrap();
+    }
+
+    #[test]
+    fn test_service_migration() {
+        let db: Arc<Database> = Arc::new(MemoryDB::new());
+        let mut blockchain = create_blockchain(&db, 0);
+        blockchain.initialize(genesis()).unwrap();
+        assert_eq!(applied_version(&blockchain), 0);
+
+        // The node binary is updated with a new version of the service.
+        let mut blockchain = create_blockchain(&db, 1);
+        let mut service_versions = BTreeMap::new();
+        service_versions.insert(VERSIONED_SERVICE_NAME.to_owned(), 2);
+        assert!(blockchain.dry_run_migrations(&service_versions).is_err());
+        assert!(blockchain.check_service_versions(&service_versions).is_err());
+        service_versions.insert(VERSIONED_SERVICE_NAME.to_owned(), 1);
+        assert!(blockchain.check_service_versions(&service_versions).is_ok());
+        commit_service_versions(&mut blockchain, &service_versions, Height(2));
+
+        let (_, patch) = blockchain.create_patch(ValidatorId::zero(), Height(1), Utc::now(), &[]);
+        blockchain.merge(patch).unwrap();
+        assert_eq!(stored_value(&blockchain), 21);
+
+        let expected_state_hash = blockchain.dry_run_migrations(&service_versions).unwrap();
+        assert_eq!(stored_value(&blockchain), 21);
+        assert_eq!(applied_version(&blockchain), 0);
+
+        let (_, patch) = blockchain.create_patch(ValidatorId::zero(), Height(2), Utc::now(), &[]);
+        blockchain.merge(patch).unwrap();
+        assert_eq!(stored_value(&blockchain), 42);
+        assert_eq!(applied_version(&blockchain), 1);
+        assert_eq!(blockchain.last_block().state_hash(), &expected_state_hash);
+
+        // Migrations are not applied twice.
+        let (_, patch) = blockchain.create_patch(ValidatorId::zero(), Height(3), Utc::now(), &[]);
+        blockchain.merge(patch).unwrap();
+        assert_eq!(stored_value(&blockchain), 42);
+    }
+
+    #[test]
+    #[should_panic(expected = "supports schema versions up to 0")]
+    fn test_unsupported_service_version() {
+        let db: Arc<Database> = Arc::new(MemoryDB::new());
+        let mut blockchain = create_blockchain(&db, 0);
+        blockchain.initialize(genesis()).unwrap();
+
+        let mut service_versions = BTreeMap::new();
+        service_versions.insert(VERSIONED_SERVICE_NAME.to_owned(), 1);
+        commit_service_versions(&mut blockchain, &service_versions, Height(1));
+        blockchain.create_patch(ValidatorId::zero(), Height(1), Utc::now(), &[]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Migration to schema version 2 is not implemented")]
+    fn test_service_migration_failure() {
+        let db: Arc<Database> = Arc::new(MemoryDB::new());
+        let mut blockchain = create_blockchain(&db, 0);
+        blockchain.initialize(genesis()).unwrap();
+
+        let mut blockchain = create_blockchain(&db, 2);
+        let mut service_versions = BTreeMap::new();
+        service_versions.insert(VERSIONED_SERVICE_NAME.to_owned(), 2);
+        assert!(blockchain.dry_run_migrations(&service_versions).is_err());
+        commit_service_versions(&mut blockchain, &service_versions, Height(1));
+        blockchain.create_patch(ValidatorId::zero(), Height(1), Utc::now(), &[]);
+    }
+}
+
+mod service_call_tests {
+    use chrono::Utc;
+    use blockchain::{call_service, Blockchain, CallResult, ExecutionError, ExecutionResult,
+                     Schema, Service, ServiceCall, ServiceMethod, Snapshot, Transaction,
+                     TransactionErrorType, TransactionResult};
+    use crypto::{gen_keypair, Hash, SecretKey};
+    use encoding::Error as MessageError;
+    use messages::{Message, RawTransaction};
+    use storage::{Database, Entry, Fork, MemoryDB, StorageValue};
+    use super::test_helpers::{self, commit_transactions, genesis, TxService};
+
+    const COUNTER_SERVICE_ID: u16 = 251;
+    const CALLER_SERVICE_ID: u16 = 252;
+    const COUNTER: &str = "counter_service.counter";
+    const CALLS: &str = "caller_service.calls";
+
+    encoding_struct! {
+        struct Increment {
+            by: u64,
+        }
+    }
+
+    impl ServiceMethod for Increment {
+        const SERVICE_ID: u16 = COUNTER_SERVICE_ID;
+        const METHOD_ID: u16 = 0;
+        type Output = u64;
+    }
+
+    encoding_struct! {
+        struct Recurse {
+            depth: u64,
+        }
+    }
+
+    impl ServiceMethod for Recurse {
+        const SERVICE_ID: u16 = COUNTER_SERVICE_ID;
+        const METHOD_ID: u16 = 1;
+        type Output = u64;
+    }
+
+    struct CounterService;
+
+    impl Service for CounterService {
+        fn service_id(&self) -> u16 {
+            COUNTER_SERVICE_ID
+        }
+
+        fn service_name(&self) -> &'static str {
+            "counter service"
+        }
+
+        fn state_hash(&self, _: &Snapshot) -> Vec<Hash> {
+            vec![]
+        }
+
+        fn tx_from_raw(&self, _: RawTransaction) -> Result<Box<Transaction>, MessageError> {
+            Err(MessageError::from("Counter service has no transactions."))
+        }
+
+        fn handle_call(&self, fork: &mut Fork, call: &ServiceCall) -> CallResult {
+            if let Some(increment) = call.args::<Increment>() {
+                if increment.by() == 0 {
+                    return Err(ExecutionError::new(7));
+                }
+                let mut counter: Entry<&mut Fork, u64> = Entry::new(COUNTER, fork);
+                let value = counter.get().unwrap_or(0) + increment.by();
+                counter.set(value);
+                return Ok(value.into_bytes());
+            }
+            if let Some(recurse) = call.args::<Recurse>() {
+                let depth = call_service(fork, Recurse::new(recurse.depth() + 1))?;
+                return Ok(depth.into_bytes());
+            }
+            Err(call.unsupported())
+        }
+    }
+
+    transactions! {
+        CallerTransactions {
+            const SERVICE_ID = CALLER_SERVICE_ID;
+
+            struct CallTx {
+                by: u64,
+                ignore_error: bool,
+            }
+
+            struct RecurseTx {
+                seed: u64,
+            }
+        }
+    }
+
+    impl Transaction for CallTx {
+        fn verify(&self) -> bool {
+            true
+        }
+
+        fn execute(&self, fork: &mut Fork) -> ExecutionResult {
+            {
+                let mut calls: Entry<&mut Fork, u64> = Entry::new(CALLS, &mut *fork);
+                let value = calls.get().unwrap_or(0) + 1;
+                calls.set(value);
+            }
+            let result = call_service(fork, Increment::new(self.by()));
+            if self.ignore_error() {
+                Ok(())
+            } else {
+                result.map(|_| ())
+            }
+        }
+    }
+
+    impl Transaction for RecurseTx {
+        fn verify(&self) -> bool {
+            true
+        }
+
+        fn execute(&self, fork: &mut Fork) -> ExecutionResult {
+            call_service(fork, Recurse::new(0)).map(|_| ())
+        }
+    }
+
+    fn create_blockchain() -> Blockchain {
+        let caller = TxService::<CallerTransactions>::new(CALLER_SERVICE_ID, "caller service");
+        test_helpers::create_initialized_blockchain(
+            vec![
+                Box::new(CounterService) as Box<Service>,
+                Box::new(caller),
+            ],
+            genesis(),
+        )
+    }
+
+    fn execute<T: Transaction>(
+        blockchain: &mut Blockchain,
+        height: u64,
+        tx: &T,
+    ) -> TransactionResult {
+        commit_transactions(blockchain, height, Utc::now(), vec![tx.raw().clone()]);
+        let hash = tx.raw().hash();
+        let snapshot = blockchain.snapshot();
+        Schema::new(&snapshot)
+            .transaction_results()
+            .get(&hash)
+            .unwrap()
+    }
+
+    fn entry_value(blockchain: &Blockchain, name: &str) -> Option<u64> {
+        let snapshot = blockchain.snapshot();
+        let entry: Entry<_, u64> = Entry::new(name, &snapshot);
+        entry.get()
+    }
+
+    fn error_type(result: TransactionResult) -> TransactionErrorType {
+        result.unwrap_err().error_type()
+    }
+
+    #[test]
+    fn test_service_call() {
+        let mut blockchain = create_blockchain();
+        let secret_key: SecretKey = gen_keypair().1;
+
+        let result = execute(&mut blockchain, 1, &CallTx::new(5, false, &secret_key));
+        assert_eq!(result, Ok(()));
+        assert_eq!(entry_value(&blockchain, COUNTER), Some(5));
+        assert_eq!(entry_value(&blockchain, CALLS), Some(1));
+
+        let result = execute(&mut blockchain, 2, &CallTx::new(0, false, &secret_key));
+        assert_eq!(error_type(result), TransactionErrorType::Code(7));
+        assert_eq!(entry_value(&blockchain, CALLS), Some(1));
+    }
+
+    #[test]
+    fn test_ignored_call_error_fails_transaction() {
+        let mut blockchain = create_blockchain();
+        let secret_key = gen_keypair().1;
+
+        let result = execute(&mut blockchain, 1, &CallTx::new(0, true, &secret_key));
+        assert_eq!(error_type(result), TransactionErrorType::Code(7));
+        assert_eq!(entry_value(&blockchain, CALLS), None);
+
+        // The error does not leak into the next transaction.
+        let result = execute(&mut blockchain, 2, &CallTx::new(1, true, &secret_key));
+        assert_eq!(result, Ok(()));
+        assert_eq!(entry_value(&blockchain, COUNTER), Some(1));
+    }
+
+    #[test]
+    fn test_call_depth_limit() {
+        let mut blockchain = create_blockchain();
+        let secret_key = gen_keypair().1;
+
+        let result = execute(&mut blockchain, 1, &RecurseTx::new(0, &secret_key));
+        assert_eq!(error_type(result), TransactionErrorType::ServiceCall);
+    }
+
+    #[test]
+    fn test_call_outside_of_transaction() {
+        let db = MemoryDB::new();
+        let mut fork = db.fork();
+        let error = call_service(&mut fork, Increment::new(1)).unwrap_err();
+        assert_eq!(
+            error,
+            ExecutionError::service_call(
+                "Services can be called only during transaction execution".to_owned(),
+            )
+        );
+    }
+}
+
+mod transaction_events_tests {
+    use chrono::Utc;
+    use std::iter;
+    use blockchain::{emit_event, Blockchain, ExecutionError, ExecutionResult, Notification,
+                     Schema, Service, ServiceEvent, SubscriptionFilter, Transaction,
+                     TRANSACTION_EVENTS_TABLE};
+    use crypto::{gen_keypair, Hash};
+    use helpers::{Height, ValidatorId};
+    use messages::{Message, Precommit};
+    use storage::Fork;
+    use super::test_helpers::{self, commit_transactions, genesis, TxService};
+
+    const EVENTS_SERVICE_ID: u16 = 250;
+
+    encoding_struct! {
+        struct Emitted {
+            index: u64,
+        }
+    }
+
+    impl ServiceEvent for Emitted {
+        const SERVICE_ID: u16 = EVENTS_SERVICE_ID;
+        const EVENT_ID: u16 = 0;
+    }
+
+    encoding_struct! {
+        struct Foreign {
+            index: u64,
+        }
+    }
+
+    impl ServiceEvent for Foreign {
+        const SERVICE_ID: u16 = EVENTS_SERVICE_ID + 1;
+        const EVENT_ID: u16 = 0;
+    }
+
+    transactions! {
+        EventsTransactions {
+            const SERVICE_ID = EVENTS_SERVICE_ID;
+
+            struct EmitTx {
+                count: u64,
+                fail: bool,
+            }
+
+            struct EmitForeignTx {
+                index: u64,
+            }
+        }
+    }
+
+    impl Transaction for EmitTx {
+        fn verify(&self) -> bool {
+            true
+        }
+
+        fn execute(&self, _: &mut Fork) -> ExecutionResult {
+            for index in 0..self.count() {
+                emit_event(Emitted::new(index));
+            }
+            if self.fail() {
+                Err(ExecutionError::new(1))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl Transaction for EmitForeignTx {
+        fn verify(&self) -> bool {
+            true
+        }
+
+        fn execute(&self, _: &mut Fork) -> ExecutionResult {
+            emit_event(Emitted::new(self.index()));
+            emit_event(Foreign::new(self.index()));
+            Ok(())
+        }
+    }
+
+    fn create_blockchain() -> Blockchain {
+        let service = TxService::<EventsTransactions>::new(EVENTS_SERVICE_ID, "events service");
+        test_helpers::create_initialized_blockchain(
+            vec![Box::new(service) as Box<Service>],
+            genesis(),
+        )
+    }
+
+    fn execute(blockchain: &mut Blockchain, height: u64, txs: &[EmitTx]) -> Hash {
+        let txs = txs.iter().map(|tx| tx.raw().clone());
+        commit_transactions(blockchain, height, Utc::now(), txs);
+        let snapshot = blockchain.snapshot();
+        Schema::new(&snapshot).state_hash_aggregator().merkle_root()
+    }
+
+    #[test]
+    fn test_transaction_events() {
+        let mut blockchain = create_blockchain();
+        let secret_key = gen_keypair().1;
+        let emitting = EmitTx::new(3, false, &secret_key);
+        let failing = EmitTx::new(2, true, &secret_key);
+        let silent = EmitTx::new(0, false, &secret_key);
+
+        let state_hash = execute(
+            &mut blockchain,
+            1,
+            &[emitting.clone(), failing.clone(), silent.clone()],
+        );
+
+        let snapshot = blockchain.snapshot();
+        let schema = Schema::new(&snapshot);
+        let events = schema.transaction_events(&emitting.raw().hash());
+        let emitted = events
+            .iter()
+            .map(|event| event.event::<Emitted>().unwrap().index())
+            .collect::<Vec<_>>();
+        assert_eq!(emitted, vec![0, 1, 2]);
+        assert_eq!(
+            schema.transaction_events_roots().get(&emitting.raw().hash()),
+            Some(events.merkle_root())
+        );
+
+        // Events of failed transactions are discarded.
+        assert!(schema.transaction_events(&failing.raw().hash()).is_empty());
+        assert_eq!(schema.transaction_events_roots().get(&failing.raw().hash()), None);
+        assert!(schema.transaction_events(&silent.raw().hash()).is_empty());
+
+        // Events are covered by the state hash.
+        let checked = schema
+            .get_proof_to_service_table(0, TRANSACTION_EVENTS_TABLE)
+            .check()
+            .unwrap();
+        assert_eq!(checked.merkle_root(), state_hash);
+        let table_key = Blockchain::service_table_unique_key(0, TRANSACTION_EVENTS_TABLE);
+        let table_root = schema.transaction_events_roots().merkle_root();
+        assert_eq!(checked.entries(), vec![(&table_key, &table_root)]);
+    }
+
+    #[test]
+    fn test_events_of_other_service_fail_transaction() {
+        let mut blockchain = create_blockchain();
+        let tx = EmitForeignTx::new(0, &gen_keypair().1);
+        commit_transactions(&mut blockchain, 1, Utc::now(), iter::once(tx.raw().clone()));
+
+        let snapshot = blockchain.snapshot();
+        let schema = Schema::new(&snapshot);
+        assert!(schema.transaction_results().get(&tx.hash()).unwrap().is_err());
+        assert!(schema.transaction_events(&tx.hash()).is_empty());
+    }
+
+    fn commit(blockchain: &mut Blockchain, height: u64, txs: &[EmitTx]) {
+        let mut fork = blockchain.fork();
+        for tx in txs {
+            Schema::new(&mut fork).add_transaction_into_pool(tx.raw().clone());
+        }
+        blockchain.merge(fork.into_patch()).unwrap();
+
+        let hashes = txs.iter().map(|tx| tx.raw().hash()).collect::<Vec<_>>();
+        let (block_hash, patch) =
+            blockchain.create_patch(ValidatorId::zero(), Height(height), Utc::now(), &hashes);
+        blockchain
+            .commit(&patch, block_hash, iter::empty::<&Precommit>())
+            .unwrap();
+    }
+
+    #[test]
+    fn test_subscriptions() {
+        let mut blockchain = create_blockchain();
+        let mut with_events = blockchain
+            .subscriptions()
+            .subscribe(SubscriptionFilter::new(vec![EVENTS_SERVICE_ID]), 16, 2)
+            .unwrap();
+        let mut without_events = blockchain
+            .subscriptions()
+            .subscribe(SubscriptionFilter::default(), 16, 2)
+            .unwrap();
+        let secret_key = gen_keypair().1;
+        let emitting = EmitTx::new(2, false, &secret_key);
+        let failing = EmitTx::new(1, true, &secret_key);
+
+        commit(&mut blockchain, 1, &[emitting.clone(), failing.clone()]);
+
+        let block = {
+            let snapshot = blockchain.snapshot();
+            Schema::new(&snapshot).last_block()
+        };
+        assert_eq!(with_events.try_recv(), Some(Notification::Block(block.clone())));
+        assert_eq!(without_events.try_recv(), Some(Notification::Block(block)));
+        for subscription in &mut [&mut with_events, &mut without_events] {
+            match subscription.try_recv() {
+                Some(Notification::Transaction { tx_hash, height, result }) => {
+                    assert_eq!(tx_hash, emitting.raw().hash());
+                    assert_eq!(height, Height(1));
+                    assert!(result.is_ok());
+                }
+                other => panic!("Unexpected notification: {:?}", other),
+            }
+        }
+        for index in 0..2 {
+            match with_events.try_recv() {
+                Some(Notification::Event { tx_hash, event }) => {
+                    assert_eq!(tx_hash, emitting.raw().hash());
+                    assert_eq!(event.event::<Emitted>().unwrap().index(), index);
+                }
+                other => panic!("Unexpected notification: {:?}", other),
+            }
+        }
+        for subscription in &mut [&mut with_events, &mut without_events] {
+            match subscription.try_recv() {
+                Some(Notification::Transaction { tx_hash, result, .. }) => {
+                    assert_eq!(tx_hash, failing.raw().hash());
+                    assert!(result.is_err());
+                }
+                other => panic!("Unexpected notification: {:?}", other),
+            }
+            assert_eq!(subscription.try_recv(), None);
+        }
+        assert_eq!(blockchain.subscriptions().len(), 2);
+        assert!(
+            blockchain
+                .subscriptions()
+                .subscribe(SubscriptionFilter::default(), 16, 2)
+                .is_none()
+        );
+
+        // Closed subscriptions do not count towards the limit.
+        drop(without_events);
+        assert_eq!(blockchain.subscriptions().len(), 1);
+        let mut new_subscription = blockchain
+            .subscriptions()
+            .subscribe(SubscriptionFilter::default(), 16, 2)
+            .unwrap();
+        commit(&mut blockchain, 2, &[]);
+        match new_subscription.try_recv() {
+            Some(Notification::Block(block)) => assert_eq!(block.height(), Height(2)),
+            other => panic!("Unexpected notification: {:?}", other),
+        }
+        assert!(!with_events.is_lagged());
+    }
+
+    #[test]
+    fn test_lagging_subscriber_is_dropped() {
+        let mut blockchain = create_blockchain();
+        let mut subscription = blockchain
+            .subscriptions()
+            .subscribe(SubscriptionFilter::default(), 1, 1)
+            .unwrap();
+        let secret_key = gen_keypair().1;
+
+        // The notifications of a block are buffered at once regardless of their number.
+        commit(&mut blockchain, 1, &[EmitTx::new(0, false, &secret_key)]);
+        assert!(!subscription.is_lagged());
+        commit(&mut blockchain, 2, &[]);
+
+        assert!(subscription.is_lagged());
+        assert!(blockchain.subscriptions().is_empty());
+        // The buffered notifications are still delivered.
+        match subscription.try_recv() {
+            Some(Notification::Block(block)) => assert_eq!(block.height(), Height(1)),
+            other => panic!("Unexpected notification: {:?}", other),
+        }
+        match subscription.try_recv() {
+            Some(Notification::Transaction { height, .. }) => assert_eq!(height, Height(1)),
+            other => panic!("Unexpected notification: {:?}", other),
+        }
+        assert_eq!(subscription.try_recv(), None);
+    }
+
+    #[test]
+    fn test_events_outside_of_transaction_are_ignored() {
+        let blockchain = create_blockchain();
+        emit_event(Emitted::new(0));
+        let snapshot = blockchain.snapshot();
+        assert!(Schema::new(&snapshot).transaction_events_roots().iter().next().is_none());
+    }
+}
+
+mod block_time_tests {
+    use std::sync::Arc;
+    use chrono::{DateTime, TimeZone, Utc};
+    use blockchain::{block_time, Block, Blockchain, ExecutionResult, Schema, Service,
+                     Transaction};
+    use crypto::{gen_keypair, CryptoHash, Hash};
+    use helpers::{Height, ValidatorId};
+    use messages::Message;
+    use storage::{Database, Entry, Fork, MemoryDB};
+    use super::test_helpers::{self, commit_transactions, genesis, TxService};
+
+    const TIME_SERVICE_ID: u16 = 251;
+    const TIME_ENTRY: &str = "block_time_tests.time";
+
+    transactions! {
+        TimeTransactions {
+            const SERVICE_ID = TIME_SERVICE_ID;
+
+            struct SaveTimeTx {
+                seed: u64,
+            }
+        }
+    }
+
+    impl Transaction for SaveTimeTx {
+        fn verify(&self) -> bool {
+            true
+        }
+
+        fn execute(&self, fork: &mut Fork) -> ExecutionResult {
+            let mut entry: Entry<&mut Fork, DateTime<Utc>> = Entry::new(TIME_ENTRY, fork);
+            entry.set(block_time().unwrap());
+            Ok(())
+        }
+    }
+
+    fn create_blockchain(db: &Arc<Database>) -> Blockchain {
+        let service = TxService::<TimeTransactions>::new(TIME_SERVICE_ID, "time service");
+        test_helpers::create_blockchain(db, vec![Box::new(service) as Box<Service>])
+    }
+
+    #[test]
+    fn test_block_time() {
+        let db: Arc<Database> = Arc::new(MemoryDB::new());
+        let mut blockchain = create_blockchain(&db);
+        blockchain.initialize(genesis()).unwrap();
+        assert_eq!(blockchain.last_block().time(), Utc.timestamp(0, 0));
+
+        let tx = SaveTimeTx::new(0, &gen_keypair().1);
+        let time = Utc.timestamp(1_486_720_340, 0);
+        commit_transactions(&mut blockchain, 1, time, vec![tx.raw().clone()]);
+
+        assert_eq!(blockchain.last_block().time(), time);
+        let snapshot = blockchain.snapshot();
+        let entry: Entry<_, DateTime<Utc>> = Entry::new(TIME_ENTRY, &snapshot);
+        assert_eq!(entry.get(), Some(time));
+        assert_eq!(block_time(), None);
+    }
+
+    #[test]
+    fn test_unsupported_schema_version() {
+        let db: Arc<Database> = Arc::new(MemoryDB::new());
+        let mut blockchain = create_blockchain(&db);
+        blockchain.initialize(genesis()).unwrap();
+
+        let mut fork = blockchain.fork();
+        {
+            let mut schema = Schema::new(&mut fork);
+            let block = Block::new(
+                0,
+                ValidatorId::zero(),
+                Height(1),
+                Utc::now(),
+                0,
+                &blockchain.last_hash(),
+                &Hash::zero(),
+                &Hash::zero(),
+            );
+            schema.block_hashes_by_height_mut().push(block.hash());
+            schema.blocks_mut().put(&block.hash(), block);
+        }
+        blockchain.merge(fork.into_patch()).unwrap();
+
+        let mut blockchain = create_blockchain(&db);
+        assert!(blockchain.initialize(genesis()).is_err());
+    }
+}
+
 mod memorydb_tests {
     use futures::sync::mpsc;
     use std::path::Path;
@@ -450,3 +1612,357 @@ mod rocksdb_tests {
         super::handling_tx_panic_storage_error(&mut blockchain);
     }
 }
+
+mod evidence_tests {
+    use chrono::Utc;
+    use blockchain::{Blockchain, Evidence, Schema, Transaction, TransactionErrorType,
+                     EVIDENCE_ERROR_CODE, EVIDENCE_TABLE};
+    use crypto::{gen_keypair, hash, CryptoHash, PublicKey, SecretKey};
+    use helpers::{Height, Round, ValidatorId};
+    use messages::{ConsensusMessage, Message, Prevote, ReportEvidence};
+    use super::test_helpers::{commit_transactions, create_initialized_blockchain,
+                              genesis_with_key};
+
+    fn create_blockchain(consensus_key: &PublicKey) -> Blockchain {
+        create_initialized_blockchain(Vec::new(), genesis_with_key(*consensus_key))
+    }
+
+    fn prevote(propose: &str, secret_key: &SecretKey) -> ConsensusMessage {
+        ConsensusMessage::Prevote(Prevote::new(
+            ValidatorId::zero(),
+            Height(1),
+            Round::first(),
+            &hash(propose.as_bytes()),
+            Round::zero(),
+            secret_key,
+        ))
+    }
+
+    fn execute(blockchain: &mut Blockchain, txs: &[ReportEvidence]) {
+        let txs = txs.iter().map(|tx| tx.raw().clone());
+        commit_transactions(blockchain, 1, Utc::now(), txs);
+    }
+
+    fn error_code(blockchain: &Blockchain, tx: &ReportEvidence) -> Option<u8> {
+        let snapshot = blockchain.snapshot();
+        let result = Schema::new(&snapshot)
+            .transaction_results()
+            .get(&tx.raw().hash())
+            .unwrap();
+        match result {
+            Ok(()) => None,
+            Err(e) => match e.error_type() {
+                TransactionErrorType::Code(code) => Some(code),
+                TransactionErrorType::Panic => panic!("Transaction panicked"),
+                TransactionErrorType::ServiceCall => panic!("Service call failed"),
+            },
+        }
+    }
+
+    #[test]
+    fn test_evidence_verify() {
+        let (public_key, secret_key) = gen_keypair();
+        let first = prevote("first", &secret_key);
+        let second = prevote("second", &secret_key);
+
+        assert!(Evidence::from_messages(&public_key, &first, &first.clone()).is_none());
+        let evidence = Evidence::from_messages(&public_key, &first, &second).unwrap();
+        assert!(evidence.verify());
+        assert_eq!(
+            Evidence::from_messages(&public_key, &second, &first),
+            Some(evidence.clone())
+        );
+
+        // The messages are not signed by the offender.
+        let forged = Evidence::new(
+            &gen_keypair().0,
+            evidence.height(),
+            evidence.round(),
+            evidence.messages(),
+        );
+        assert!(!forged.verify());
+
+        // The messages do not belong to the evidence round.
+        let wrong_round = Evidence::new(
+            &public_key,
+            evidence.height(),
+            Round(2),
+            evidence.messages(),
+        );
+        assert!(!wrong_round.verify());
+
+        // The messages are not ordered by their hashes.
+        let mut messages = evidence.messages();
+        messages.reverse();
+        let unordered = Evidence::new(&public_key, evidence.height(), evidence.round(), messages);
+        assert!(!unordered.verify());
+    }
+
+    #[test]
+    fn test_report_evidence() {
+        let (public_key, secret_key) = gen_keypair();
+        let mut blockchain = create_blockchain(&public_key);
+
+        let evidence = Evidence::from_messages(
+            &public_key,
+            &prevote("first", &secret_key),
+            &prevote("second", &secret_key),
+        ).unwrap();
+        let (other_key, other_secret_key) = gen_keypair();
+        let not_validator = Evidence::from_messages(
+            &other_key,
+            &prevote("first", &other_secret_key),
+            &prevote("second", &other_secret_key),
+        ).unwrap();
+
+        let reporter = gen_keypair();
+        let report = ReportEvidence::new(&reporter.0, evidence.clone(), &reporter.1);
+        let duplicate = ReportEvidence::new(&other_key, evidence.clone(), &other_secret_key);
+        let rejected = ReportEvidence::new(&reporter.0, not_validator.clone(), &reporter.1);
+
+        // Core transactions are parsed by the blockchain.
+        let tx = blockchain.tx_from_raw(report.raw().clone()).unwrap();
+        assert!(tx.verify());
+
+        execute(
+            &mut blockchain,
+            &[report.clone(), duplicate.clone(), rejected.clone()],
+        );
+
+        assert_eq!(error_code(&blockchain, &report), None);
+        assert_eq!(
+            error_code(&blockchain, &duplicate),
+            Some(EVIDENCE_ERROR_CODE)
+        );
+        assert_eq!(error_code(&blockchain, &rejected), Some(EVIDENCE_ERROR_CODE));
+
+        let snapshot = blockchain.snapshot();
+        let schema = Schema::new(&snapshot);
+        assert_eq!(schema.evidence().get(&evidence.hash()), Some(evidence));
+        assert_eq!(schema.evidence().get(&not_validator.hash()), None);
+
+        // The evidence is covered by the state hash.
+        let checked = schema.get_proof_to_service_table(0, EVIDENCE_TABLE).check().unwrap();
+        let table_key = Blockchain::service_table_unique_key(0, EVIDENCE_TABLE);
+        let table_root = schema.evidence().merkle_root();
+        assert_eq!(checked.entries(), vec![(&table_key, &table_root)]);
+    }
+}
+
+mod commit_certificate_tests {
+    use chrono::{TimeZone, Utc};
+    use serde_json;
+    use blockchain::{Block, CertificateError, CommitCertificate, CompactBlockProof,
+                     ValidatorKeys};
+    use crypto::{gen_keypair, hash, CryptoHash, PublicKey, SecretKey};
+    use helpers::{Height, Round, ValidatorId};
+    use messages::{Message, Precommit};
+    use storage::StorageValue;
+    use super::test_helpers;
+
+    fn validators(count: usize) -> Vec<(PublicKey, SecretKey)> {
+        (0..count).map(|_| gen_keypair()).collect()
+    }
+
+    fn validator_keys(validators: &[(PublicKey, SecretKey)]) -> Vec<ValidatorKeys> {
+        validators
+            .iter()
+            .map(|&(consensus_key, _)| test_helpers::validator_keys(consensus_key))
+            .collect()
+    }
+
+    fn block() -> Block {
+        Block::new(
+            0,
+            ValidatorId(1),
+            Height(5),
+            Utc.timestamp(1_500_000_000, 0),
+            10,
+            &hash(&[1]),
+            &hash(&[2]),
+            &hash(&[3]),
+        )
+    }
+
+    fn precommits(validators: &[(PublicKey, SecretKey)], ids: &[u16]) -> Vec<Precommit> {
+        let block_hash = block().hash();
+        ids.iter()
+            .map(|&id| {
+                Precommit::new(
+                    ValidatorId(id),
+                    Height(5),
+                    Round(2),
+                    &hash(&[4]),
+                    &block_hash,
+                    Utc::now(),
+                    &validators[id as usize].1,
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_certificate_round_trip() {
+        let validators = validators(4);
+        let precommits = precommits(&validators, &[3, 0, 2]);
+        let certificate = CommitCertificate::from_precommits(&precommits).unwrap();
+
+        assert_eq!(certificate.height(), Height(5));
+        assert_eq!(certificate.round(), Round(2));
+        let mut sorted = precommits.clone();
+        sorted.sort_by_key(|precommit| precommit.validator());
+        assert_eq!(certificate.precommits(), sorted);
+
+        let proof = CompactBlockProof {
+            block: block(),
+            certificate,
+        };
+        assert_eq!(proof.verify(&validator_keys(&validators)), Ok(()));
+        let json = serde_json::to_string(&proof).unwrap();
+        let restored: CompactBlockProof = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored, proof);
+        assert_eq!(restored.verify(&validator_keys(&validators)), Ok(()));
+    }
+
+    #[test]
+    fn test_certificate_size() {
+        let validators = validators(30);
+        let ids = (0..30).collect::<Vec<_>>();
+        let precommits = precommits(&validators, &ids);
+        let certificate = CommitCertificate::from_precommits(&precommits).unwrap();
+
+        let precommits_size: usize = precommits.iter().map(|p| p.raw().as_ref().len()).sum();
+        let certificate_size = certificate.into_bytes().len();
+        assert!(certificate_size * 2 < precommits_size);
+    }
+
+    #[test]
+    fn test_certificate_errors() {
+        let validators = validators(4);
+        let keys = validator_keys(&validators);
+
+        assert_eq!(
+            CommitCertificate::from_precommits(&[]),
+            Err(CertificateError::Empty)
+        );
+        let duplicate = precommits(&validators, &[0, 1, 1]);
+        assert_eq!(
+            CommitCertificate::from_precommits(&duplicate),
+            Err(CertificateError::Duplicate(ValidatorId(1)))
+        );
+
+        let minority = CommitCertificate::from_precommits(&precommits(&validators, &[0, 1]))
+            .unwrap();
+        assert_eq!(
+            minority.verify(&keys),
+            Err(CertificateError::NotEnoughVotes {
+                votes: 2,
+                required: 3,
+            })
+        );
+
+        let certificate = CommitCertificate::from_precommits(&precommits(&validators, &[0, 1, 2]))
+            .unwrap();
+        assert_eq!(
+            certificate.verify(&keys[..2]),
+            Err(CertificateError::UnknownValidator(ValidatorId(2)))
+        );
+        let mut other_keys = keys.clone();
+        other_keys[1].consensus_key = gen_keypair().0;
+        assert_eq!(
+            certificate.verify(&other_keys),
+            Err(CertificateError::InvalidSignature(ValidatorId(1)))
+        );
+
+        let other_block = CompactBlockProof {
+            block: Block::new(
+                0,
+                ValidatorId(1),
+                Height(5),
+                Utc::now(),
+                0,
+                &hash(&[1]),
+                &hash(&[2]),
+                &hash(&[3]),
+            ),
+            certificate,
+        };
+        assert_eq!(
+            other_block.verify(&keys),
+            Err(CertificateError::BlockMismatch)
+        );
+    }
+}
+
+mod state_sync_tests {
+    use blockchain::{Blockchain, GenesisConfig, Service, Snapshot, StateSyncError, Transaction};
+    use crypto::Hash;
+    use encoding::Error as MessageError;
+    use messages::RawTransaction;
+    use super::test_helpers::{create_initialized_blockchain, genesis};
+
+    /// Service that keeps the default `restore_state`.
+    struct UnsyncedService;
+
+    impl Service for UnsyncedService {
+        fn service_id(&self) -> u16 {
+            254
+        }
+
+        fn service_name(&self) -> &'static str {
+            "unsynced service"
+        }
+
+        fn state_hash(&self, _: &Snapshot) -> Vec<Hash> {
+            vec![]
+        }
+
+        fn tx_from_raw(&self, _: RawTransaction) -> Result<Box<Transaction>, MessageError> {
+            Err(MessageError::from("Unsynced service has no transactions."))
+        }
+    }
+
+    fn create_blockchain(genesis: GenesisConfig) -> Blockchain {
+        create_initialized_blockchain(vec![], genesis)
+    }
+
+    #[test]
+    fn test_restore_state() {
+        let genesis = genesis();
+        let source = create_blockchain(genesis.clone());
+        let mut target = create_blockchain(genesis);
+
+        let block = source.last_block();
+        let patch = target
+            .restore_state(source.snapshot().as_ref(), &block)
+            .unwrap();
+        target.merge_sync(patch).unwrap();
+        assert_eq!(target.last_block(), block);
+    }
+
+    #[test]
+    fn test_restore_state_of_another_chain() {
+        let source = create_blockchain(genesis());
+        let target = create_blockchain(genesis());
+
+        let block = source.last_block();
+        match target.restore_state(source.snapshot().as_ref(), &block) {
+            Err(StateSyncError::InvalidConfigs(_)) => {}
+            other => panic!("Unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_restore_state_unsupported_by_service() {
+        let genesis = genesis();
+        let services = || vec![Box::new(UnsyncedService) as Box<Service>];
+        let source = create_initialized_blockchain(services(), genesis.clone());
+        let target = create_initialized_blockchain(services(), genesis);
+
+        let block = source.last_block();
+        let error = target
+            .restore_state(source.snapshot().as_ref(), &block)
+            .unwrap_err();
+        assert!(!error.is_peer_fault());
+    }
+}
@@ -25,7 +25,7 @@ use serde::de::DeserializeOwned;
 
 use messages::{Message, RawTransaction};
 use storage::{Fork, StorageValue};
-use crypto::{Hash, CryptoHash};
+use crypto::{Hash, CryptoHash, PublicKey};
 use encoding;
 use encoding::serialize::json::ExonumJson;
 
@@ -36,6 +36,8 @@ const MAX_ERROR_CODE: u16 = u8::MAX as u16;
 const TRANSACTION_STATUS_OK: u16 = MAX_ERROR_CODE + 1;
 // `Err(TransactionErrorType::Panic)`.
 const TRANSACTION_STATUS_PANIC: u16 = TRANSACTION_STATUS_OK + 1;
+// `Err(TransactionErrorType::ServiceCall)`.
+const TRANSACTION_STATUS_SERVICE_CALL: u16 = TRANSACTION_STATUS_PANIC + 1;
 
 /// Return value of the `Transaction`'s `execute' method. Changes made by the transaction are
 /// discarded if `Err` is returned, see `Transaction` documentation for the details.
@@ -144,6 +146,29 @@ pub trait Transaction: Message + ExonumJson + 'static {
     /// }
     /// # fn main() {}
     fn execute(&self, fork: &mut Fork) -> ExecutionResult;
+
+    /// Returns the priority of the transaction in the memory pool. Used by the
+    /// [`LowestPriorityFirst`] eviction policy; transactions with lower priority are
+    /// evicted first once the pool is full.
+    ///
+    /// The default implementation returns `0`.
+    ///
+    /// *This method should not use external data, that is, it must be a pure function.*
+    ///
+    /// [`LowestPriorityFirst`]: ../node/enum.TxPoolEvictionPolicy.html#variant.LowestPriorityFirst
+    fn pool_priority(&self) -> u64 {
+        0
+    }
+
+    /// Returns the key of the transaction author. Used by the [`PerSenderQuota`]
+    /// eviction policy to limit the number of uncommitted transactions of a single sender.
+    ///
+    /// The default implementation returns `None`, which exempts the transaction from quotas.
+    ///
+    /// [`PerSenderQuota`]: ../node/enum.TxPoolEvictionPolicy.html#variant.PerSenderQuota
+    fn pool_sender(&self) -> Option<PublicKey> {
+        None
+    }
 }
 
 /// Result of unsuccessful transaction execution.
@@ -154,6 +179,8 @@ pub struct ExecutionError {
     code: u8,
     /// Optional error description.
     description: Option<String>,
+    /// Set by the framework if a service call cannot be dispatched, see `call_service`.
+    service_call: bool,
 }
 
 impl ExecutionError {
@@ -162,6 +189,7 @@ impl ExecutionError {
         Self {
             code,
             description: None,
+            service_call: false,
         }
     }
 
@@ -170,6 +198,17 @@ impl ExecutionError {
         Self {
             code,
             description: Some(description),
+            service_call: false,
+        }
+    }
+
+    /// Constructs a new `ExecutionError` instance signalling that a service call cannot
+    /// be dispatched.
+    pub(crate) fn service_call(description: String) -> Self {
+        Self {
+            code: 0,
+            description: Some(description),
+            service_call: true,
         }
     }
 }
@@ -182,6 +221,10 @@ pub enum TransactionErrorType {
     /// User-defined error code. Can have different meanings for different transactions and
     /// services.
     Code(u8),
+    /// A call of another service made by the transaction cannot be dispatched: the service
+    /// is unknown or not active, the method is not supported by the service or the call
+    /// depth is exceeded.
+    ServiceCall,
 }
 
 /// Result of unsuccessful transaction execution encompassing both service and framework-wide error
@@ -193,6 +236,8 @@ pub enum TransactionErrorType {
 ///   implementation for the details).
 /// - `TransactionErrorType::Panic` is set by the framework if panic is raised during transaction
 ///   execution.
+/// - `TransactionErrorType::ServiceCall` is set by the framework if a service call made by
+///   the transaction cannot be dispatched.
 /// - `TransactionError` implements `Display` which can be used for obtaining a simple error
 ///   description.
 ///
@@ -272,6 +317,7 @@ impl fmt::Display for TransactionError {
         match self.error_type {
             TransactionErrorType::Panic => write!(f, "Panic during execution")?,
             TransactionErrorType::Code(c) => write!(f, "Error code: {}", c)?,
+            TransactionErrorType::ServiceCall => write!(f, "Service call failed")?,
         }
 
         if let Some(ref description) = self.description {
@@ -293,10 +339,12 @@ impl CryptoHash for TransactionResult {
 
 impl From<ExecutionError> for TransactionError {
     fn from(error: ExecutionError) -> Self {
-        Self {
-            error_type: TransactionErrorType::Code(error.code),
-            description: error.description,
-        }
+        let error_type = if error.service_call {
+            TransactionErrorType::ServiceCall
+        } else {
+            TransactionErrorType::Code(error.code)
+        };
+        Self::new(error_type, error.description)
     }
 }
 
@@ -326,6 +374,10 @@ impl StorageValue for TransactionResult {
             value @ 0...MAX_ERROR_CODE => Err(TransactionError::code(value as u8, description)),
             TRANSACTION_STATUS_OK => Ok(()),
             TRANSACTION_STATUS_PANIC => Err(TransactionError::panic(description)),
+            TRANSACTION_STATUS_SERVICE_CALL => Err(TransactionError::new(
+                TransactionErrorType::ServiceCall,
+                description,
+            )),
             value => panic!("Invalid TransactionResult value: {}", value),
         }
     }
@@ -338,6 +390,7 @@ fn status_as_u16(status: &TransactionResult) -> u16 {
             match e.error_type {
                 TransactionErrorType::Panic => TRANSACTION_STATUS_PANIC,
                 TransactionErrorType::Code(c) => u16::from(c),
+                TransactionErrorType::ServiceCall => TRANSACTION_STATUS_SERVICE_CALL,
             }
         }
     }
@@ -623,11 +676,11 @@ fn panic_description(any: &Box<Any + Send>) -> Option<String> {
 
 #[cfg(test)]
 mod tests {
+    use chrono::Utc;
     use futures::sync::mpsc;
 
     use std::sync::Mutex;
     use std::panic;
-
     use super::*;
     use crypto;
     use encoding;
@@ -673,6 +726,7 @@ mod tests {
             (TransactionErrorType::Code(1), Some("")),
             (TransactionErrorType::Code(100), None),
             (TransactionErrorType::Code(255), Some("error description")),
+            (TransactionErrorType::ServiceCall, Some("call error")),
         ];
 
         for value in &values {
@@ -701,6 +755,13 @@ mod tests {
             };
             assert_eq!(execution_error.code, code);
         }
+
+        let call_error = ExecutionError::service_call("Unknown service".to_owned());
+        let transaction_error: TransactionError = call_error.into();
+        assert_eq!(
+            transaction_error.error_type(),
+            TransactionErrorType::ServiceCall
+        );
     }
 
     #[test]
@@ -728,6 +789,11 @@ mod tests {
                 255,
                 Some("(Not) really long error description".to_owned()),
             )),
+            Err(TransactionError::new(TransactionErrorType::ServiceCall, None)),
+            Err(TransactionError::new(
+                TransactionErrorType::ServiceCall,
+                Some("Unknown service".to_owned()),
+            )),
         ];
 
         for result in &results {
@@ -773,7 +839,8 @@ mod tests {
                 blockchain.merge(fork.into_patch()).unwrap();
             }
 
-            let (_, patch) = blockchain.create_patch(ValidatorId::zero(), Height(index), &[hash]);
+            let (_, patch) =
+                blockchain.create_patch(ValidatorId::zero(), Height(index), Utc::now(), &[hash]);
 
             db.merge(patch).unwrap();
 
@@ -364,7 +364,7 @@ impl ExonumJson for BitVec {
     ) -> Result<(), Box<Error>> {
         let string = value.as_str().ok_or("Can't cast json as string")?;
         let mut vec = BitVec::new();
-        for (i, ch) in string.chars().enumerate() {
+        for ch in string.chars() {
             let val = if ch == '1' {
                 true
             } else if ch == '0' {
@@ -372,7 +372,7 @@ impl ExonumJson for BitVec {
             } else {
                 Err(format!("BitVec should contain only 0 or 1, not {}", ch))?
             };
-            vec.set(i, val);
+            vec.push(val);
         }
         buffer.write(from, to, vec);
         Ok(())
@@ -22,7 +22,7 @@ use chrono::Utc;
 use crypto::{hash, gen_keypair};
 use blockchain::{self, BlockProof, Block};
 use messages::{RawMessage, Message, Connect, Propose, Prevote, Precommit, Status, BlockResponse,
-               BlockRequest};
+               BlockRequest, BlocksRequest, SUPPORTED_PROTOCOL_VERSIONS};
 use helpers::{Height, Round, ValidatorId, user_agent};
 use super::{Field, Offset};
 
@@ -89,6 +89,23 @@ fn test_bitvec() {
     assert_write_check_read(b, 8);
 }
 
+#[test]
+fn test_bitvec_json() {
+    encoding_struct! {
+        struct Bits {
+            bits: BitVec,
+        }
+    }
+
+    let mut bits = BitVec::from_elem(10, false);
+    bits.set(3, true);
+    bits.set(9, true);
+    let value = Bits::new(bits.clone());
+    let json_str = ::serde_json::to_string(&value).unwrap();
+    let value1: Bits = ::serde_json::from_str(&json_str).unwrap();
+    assert_eq!(value1.bits(), bits);
+}
+
 #[test]
 fn test_str_segment() {
     let mut buf = vec![0; 8];
@@ -257,12 +274,17 @@ fn test_connect() {
         socket_address,
         time,
         &user_agent::get(),
+        SUPPORTED_PROTOCOL_VERSIONS,
+        &hash(&[1]),
+        false,
         &secret_key,
     );
     // read
     assert_eq!(connect.pub_key(), &public_key);
     assert_eq!(connect.addr(), socket_address);
     assert_eq!(connect.time(), time);
+    assert_eq!(connect.protocol_versions(), SUPPORTED_PROTOCOL_VERSIONS);
+    assert_eq!(connect.network_id(), &hash(&[1]));
     assert!(connect.verify_signature(&public_key));
 }
 
@@ -270,10 +292,11 @@ fn test_connect() {
 fn test_propose() {
     let prev_hash = hash(&[1, 2, 3]);
     let txs = vec![hash(&[1]), hash(&[2]), hash(&[2])];
+    let time = Utc::now();
     let (public_key, secret_key) = gen_keypair();
 
     // write
-    let propose = Propose::new(VALIDATOR, HEIGHT, ROUND, &prev_hash, &txs, &secret_key);
+    let propose = Propose::new(VALIDATOR, HEIGHT, ROUND, &prev_hash, &txs, time, &secret_key);
     // read
     assert_eq!(propose.validator(), VALIDATOR);
     assert_eq!(propose.height(), HEIGHT);
@@ -283,6 +306,7 @@ fn test_propose() {
     assert_eq!(propose.transactions()[0], txs[0]);
     assert_eq!(propose.transactions()[1], txs[1]);
     assert_eq!(propose.transactions()[2], txs[2]);
+    assert_eq!(propose.time(), time);
     assert!(propose.verify_signature(&public_key));
 }
 
@@ -366,6 +390,7 @@ fn test_block() {
         blockchain::SCHEMA_MAJOR_VERSION,
         ValidatorId::zero(),
         Height(500),
+        ts,
         tx_count,
         &hash(&[1]),
         &hash(&txs),
@@ -450,6 +475,7 @@ fn test_empty_block() {
         blockchain::SCHEMA_MAJOR_VERSION,
         ValidatorId::zero(),
         Height(200),
+        Utc::now(),
         1,
         &hash(&[1]),
         &hash(&[2]),
@@ -494,6 +520,20 @@ fn test_request_block() {
     assert!(request.verify_signature(&public_key));
 }
 
+#[test]
+fn test_request_blocks() {
+    let (public_key, secret_key) = gen_keypair();
+
+    // write
+    let request = BlocksRequest::new(&public_key, &public_key, Height(1), 16, &secret_key);
+    // read
+    assert_eq!(request.from(), &public_key);
+    assert_eq!(request.height(), Height(1));
+    assert_eq!(request.count(), 16);
+    assert_eq!(request.to(), &public_key);
+    assert!(request.verify_signature(&public_key));
+}
+
 #[test]
 fn test_correct_encoding_struct() {
     encoding_struct! {
@@ -17,9 +17,11 @@ use std::thread;
 
 use test::Bencher;
 
-use node::EventsPoolCapacity;
+use crypto::gen_keypair;
 use events::network::NetworkConfiguration;
-use events::tests::{connect_message, raw_message, TestEvents};
+use events::rate_limit::RateLimits;
+use events::tests::{connect_message, raw_message, raw_service_message, TestEvents};
+use messages::{Message, RawMessage, TransactionsBatch};
 
 struct BenchConfig {
     times: usize,
@@ -28,15 +30,13 @@ struct BenchConfig {
 }
 
 fn test_events(cfg: &BenchConfig, listen_address: SocketAddr) -> TestEvents {
-    let network_config = NetworkConfiguration {
+    let mut events = TestEvents::with_addr(listen_address);
+    events.network_config = NetworkConfiguration {
         tcp_nodelay: cfg.tcp_nodelay,
+        rate_limits: RateLimits::unlimited(),
         ..Default::default()
     };
-    TestEvents {
-        listen_address,
-        network_config,
-        events_config: EventsPoolCapacity::default(),
-    }
+    events
 }
 
 fn bench_network(b: &mut Bencher, addrs: [SocketAddr; 2], cfg: &BenchConfig) {
@@ -198,3 +198,71 @@ fn bench_msg_long_100_nodelay(b: &mut Bencher) {
     let addrs = ["127.0.0.1:9198".parse().unwrap(), "127.0.0.1:9199".parse().unwrap()];
     bench_network(b, addrs, &cfg);
 }
+
+/// Sends `cfg.times` transactions of `cfg.len` bytes from one node to another in batches
+/// of `batch_size` transactions. Batches of a single transaction are sent as is.
+fn bench_transactions(
+    b: &mut Bencher,
+    addrs: [SocketAddr; 2],
+    cfg: &BenchConfig,
+    batch_size: usize,
+) {
+    let (public_key, secret_key) = gen_keypair();
+    let messages = (0..cfg.times)
+        .map(|i| raw_service_message(1, i as u16, cfg.len))
+        .collect::<Vec<_>>()
+        .chunks(batch_size)
+        .map(|txs| if txs.len() == 1 {
+            txs[0].clone()
+        } else {
+            TransactionsBatch::new(&public_key, txs.to_vec(), &secret_key)
+                .raw()
+                .clone()
+        })
+        .collect::<Vec<RawMessage>>();
+
+    b.iter(|| {
+        let first = addrs[0];
+        let second = addrs[1];
+
+        let mut t1 = test_events(cfg, first).spawn();
+        let mut t2 = test_events(cfg, second).spawn();
+
+        t1.connect_with(second);
+        t2.wait_for_connect();
+
+        for msg in &messages {
+            t1.send_to(second, msg.clone());
+        }
+        for msg in &messages {
+            assert_eq!(&t2.wait_for_message(), msg);
+        }
+
+        t1.disconnect_with(second);
+        assert_eq!(t1.wait_for_disconnect(), second);
+        drop(t1);
+        drop(t2);
+    })
+}
+
+#[bench]
+fn bench_txs_1000_single(b: &mut Bencher) {
+    let cfg = BenchConfig {
+        tcp_nodelay: true,
+        len: 100,
+        times: 1000,
+    };
+    let addrs = ["127.0.0.1:9310".parse().unwrap(), "127.0.0.1:9311".parse().unwrap()];
+    bench_transactions(b, addrs, &cfg, 1);
+}
+
+#[bench]
+fn bench_txs_1000_batched_100(b: &mut Bencher) {
+    let cfg = BenchConfig {
+        tcp_nodelay: true,
+        len: 100,
+        times: 1000,
+    };
+    let addrs = ["127.0.0.1:9312".parse().unwrap(), "127.0.0.1:9313".parse().unwrap()];
+    bench_transactions(b, addrs, &cfg, 100);
+}
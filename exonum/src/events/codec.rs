@@ -20,16 +20,59 @@ use tokio_io::codec::{Decoder, Encoder};
 
 use messages::{HEADER_LENGTH, MessageBuffer, RawMessage};
 use super::error::other_error;
+use super::noise::{encrypted_len, NoiseWrapper};
+
+/// Length of the prefix with the length of an encrypted frame.
+const ENCRYPTED_FRAME_PREFIX_LEN: usize = 4;
 
 #[derive(Debug)]
 pub struct MessagesCodec {
     /// Maximum message length (in bytes), gets populated from `ConsensusConfig`.
     max_message_len: u32,
+    /// Noise session of the encrypted connection.
+    session: Option<NoiseWrapper>,
 }
 
 impl MessagesCodec {
-    pub fn new(max_message_len: u32) -> MessagesCodec {
-        MessagesCodec { max_message_len }
+    pub fn new(max_message_len: u32, session: Option<NoiseWrapper>) -> MessagesCodec {
+        MessagesCodec {
+            max_message_len,
+            session,
+        }
+    }
+
+    /// Decodes the message from a frame consisting of the length of the encrypted data
+    /// and the data itself.
+    fn decode_encrypted(&mut self, buf: &mut BytesMut) -> Result<Option<RawMessage>, io::Error> {
+        if buf.len() < ENCRYPTED_FRAME_PREFIX_LEN {
+            return Ok(None);
+        }
+        let frame_len = LittleEndian::read_u32(&buf[..ENCRYPTED_FRAME_PREFIX_LEN]) as usize;
+        let max_frame_len = encrypted_len(self.max_message_len as usize);
+        if frame_len > max_frame_len {
+            return Err(other_error(format!(
+                "Received encrypted frame is too long: {}, maximum allowed length is {} bytes",
+                frame_len,
+                max_frame_len,
+            )));
+        }
+        if buf.len() < ENCRYPTED_FRAME_PREFIX_LEN + frame_len {
+            return Ok(None);
+        }
+
+        let frame = buf.split_to(ENCRYPTED_FRAME_PREFIX_LEN + frame_len);
+        let data = self.session.as_mut().unwrap().decrypt(
+            &frame[ENCRYPTED_FRAME_PREFIX_LEN..],
+        )?;
+        if data.len() < HEADER_LENGTH ||
+            LittleEndian::read_u32(&data[6..10]) as usize != data.len()
+        {
+            return Err(other_error(format!(
+                "Received malicious encrypted message with invalid size: {}",
+                data.len()
+            )));
+        }
+        Ok(Some(RawMessage::new(MessageBuffer::from_vec(data))))
     }
 }
 
@@ -38,6 +81,9 @@ impl Decoder for MessagesCodec {
     type Error = io::Error;
 
     fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<Self::Item>, io::Error> {
+        if self.session.is_some() {
+            return self.decode_encrypted(buf);
+        }
         // Read header
         if buf.len() < HEADER_LENGTH {
             return Ok(None);
@@ -77,7 +123,15 @@ impl Encoder for MessagesCodec {
     type Error = io::Error;
 
     fn encode(&mut self, msg: Self::Item, buf: &mut BytesMut) -> io::Result<()> {
-        buf.extend_from_slice(msg.as_ref());
+        if let Some(ref mut session) = self.session {
+            let data = session.encrypt(msg.as_ref())?;
+            let mut frame_len = [0; ENCRYPTED_FRAME_PREFIX_LEN];
+            LittleEndian::write_u32(&mut frame_len, data.len() as u32);
+            buf.extend_from_slice(&frame_len);
+            buf.extend_from_slice(&data);
+        } else {
+            buf.extend_from_slice(msg.as_ref());
+        }
         Ok(())
     }
 }
@@ -94,7 +148,7 @@ mod test {
     fn decode_message_valid_header_size() {
         let data = vec![0u8, 0, 0, 0, 0, 0, 10, 0, 0, 0];
         let mut bytes: BytesMut = data.as_slice().into();
-        let mut codec = MessagesCodec { max_message_len: 10000 };
+        let mut codec = MessagesCodec::new(10000, None);
         match codec.decode(&mut bytes) {
             Ok(Some(ref r)) if r == &RawMessage::new(MessageBuffer::from_vec(data)) => {}
             _ => panic!("Wrong input"),
@@ -105,7 +159,7 @@ mod test {
     fn decode_message_small_size_in_header() {
         let data = vec![0u8, 0, 0, 0, 0, 0, 0, 0, 0, 0];
         let mut bytes: BytesMut = data.as_slice().into();
-        let mut codec = MessagesCodec { max_message_len: 10000 };
+        let mut codec = MessagesCodec::new(10000, None);
         assert!(codec.decode(&mut bytes).is_err());
     }
 }
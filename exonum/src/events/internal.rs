@@ -18,8 +18,11 @@ use futures::sync::mpsc;
 use tokio_core::reactor::{Handle, Timeout};
 
 use std::io;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime};
 
+use node::Signer;
+use node::signer::spawn_signer_thread;
 use super::error::{into_other, other_error};
 use super::{InternalRequest, TimeoutRequest, InternalEvent, to_box};
 
@@ -27,11 +30,20 @@ use super::{InternalRequest, TimeoutRequest, InternalEvent, to_box};
 pub struct InternalPart {
     pub internal_tx: mpsc::Sender<InternalEvent>,
     pub internal_requests_rx: mpsc::Receiver<InternalRequest>,
+    /// Remote signer of the messages, if configured.
+    pub signer: Option<Arc<Signer>>,
 }
 
 impl InternalPart {
     pub fn run(self, handle: Handle) -> Box<Future<Item = (), Error = io::Error>> {
         let internal_tx = self.internal_tx.clone();
+        let sign_requests = match self.signer {
+            Some(signer) => match spawn_signer_thread(signer, internal_tx.clone()) {
+                Ok(sign_requests) => Some(sign_requests),
+                Err(e) => return to_box(futures::failed::<(), io::Error>(e)),
+            },
+            None => None,
+        };
         let fut = self.internal_requests_rx
             .for_each(move |request| {
                 let event = match request {
@@ -72,6 +84,18 @@ impl InternalPart {
                         }).map_err(|_| panic!("Can't execute shutdown"));
                         to_box(f)
                     }
+                    InternalRequest::Sign(request) => {
+                        // The signature is sent back by the signer thread.
+                        match sign_requests {
+                            Some(ref sign_requests) => {
+                                if sign_requests.send(request).is_err() {
+                                    error!("Signer thread is stopped");
+                                }
+                            }
+                            None => error!("Remote signer is not configured"),
+                        }
+                        to_box(futures::finished::<(), ()>(()))
+                    }
                 };
 
                 handle.spawn(event);
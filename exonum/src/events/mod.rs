@@ -16,6 +16,8 @@ pub mod codec;
 pub mod error;
 pub mod network;
 pub mod internal;
+pub mod noise;
+pub mod rate_limit;
 
 #[cfg(test)]
 mod tests;
@@ -24,18 +26,25 @@ mod benches;
 
 use std::time::SystemTime;
 use std::cmp::Ordering;
+use std::collections::VecDeque;
 
 use futures::{Future, Async, Poll, Stream};
 use futures::sink::Wait;
 use futures::sync::mpsc::{self, Sender};
 
+use messages::MessageClass;
 use node::{ExternalMessage, NodeTimeout};
+use node::signer::{SignRequest, SignResponse};
 pub use self::network::{NetworkEvent, NetworkRequest, NetworkPart, NetworkConfiguration};
 pub use self::internal::InternalPart;
 use helpers::{Height, Round};
 
 pub type SyncSender<T> = Wait<Sender<T>>;
 
+/// Maximum number of network events buffered by the events aggregator to reorder them
+/// by priority.
+pub const NETWORK_EVENTS_QUEUE_LEN: usize = 256;
+
 /// This kind of events is used to schedule execution in next event-loop ticks
 /// Usable to make flat logic and remove recursions.
 #[derive(Debug)]
@@ -46,13 +55,16 @@ pub enum InternalEvent {
     Timeout(NodeTimeout),
     /// Shutdown the node.
     Shutdown,
+    /// Message is signed by the remote signer.
+    MessageSigned(SignResponse),
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, PartialEq)]
 pub enum InternalRequest {
     Timeout(TimeoutRequest),
     JumpToRound(Height, Round),
     Shutdown,
+    Sign(SignRequest),
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -133,8 +145,43 @@ impl Into<Event> for InternalEvent {
     }
 }
 
+/// Network events ordered by priority: transactions are processed after all the other
+/// events, so a flood of transactions does not delay the consensus messages.
+#[derive(Debug, Default)]
+struct NetworkEventsQueue {
+    high: VecDeque<NetworkEvent>,
+    low: VecDeque<NetworkEvent>,
+}
+
+impl NetworkEventsQueue {
+    fn len(&self) -> usize {
+        self.high.len() + self.low.len()
+    }
+
+    fn push(&mut self, event: NetworkEvent) {
+        let is_low = match event {
+            NetworkEvent::MessageReceived(_, ref raw) => {
+                MessageClass::of(raw) == MessageClass::Transactions
+            }
+            _ => false,
+        };
+        if is_low {
+            self.low.push_back(event);
+        } else {
+            self.high.push_back(event);
+        }
+    }
+
+    fn pop(&mut self) -> Option<NetworkEvent> {
+        self.high.pop_front().or_else(|| self.low.pop_front())
+    }
+}
+
 /// Receives timeout, network and api events and invokes `handle_event` method of handler.
 /// If one of these streams closes, the aggregator stream completes immediately.
+///
+/// Up to `NETWORK_EVENTS_QUEUE_LEN` network events are buffered to process the consensus
+/// messages before the transactions.
 #[derive(Debug)]
 pub struct EventsAggregator<S1, S2, S3>
 where
@@ -145,6 +192,7 @@ where
     done: bool,
     internal: S1,
     network: S2,
+    network_queue: NetworkEventsQueue,
     api: S3,
 }
 
@@ -158,6 +206,7 @@ where
         EventsAggregator {
             done: false,
             network,
+            network_queue: NetworkEventsQueue::default(),
             internal,
             api,
         }
@@ -194,16 +243,19 @@ where
                 }
                 Async::NotReady => {}
             };
-            match self.network.poll()? {
-                Async::Ready(Some(item)) => {
-                    return Ok(Async::Ready(Some(Event::Network(item))));
-                }
-                Async::Ready(None) => {
-                    self.done = true;
-                    return Ok(Async::Ready(None));
+            while self.network_queue.len() < NETWORK_EVENTS_QUEUE_LEN {
+                match self.network.poll()? {
+                    Async::Ready(Some(item)) => self.network_queue.push(item),
+                    Async::Ready(None) => {
+                        self.done = true;
+                        return Ok(Async::Ready(None));
+                    }
+                    Async::NotReady => break,
                 }
-                Async::NotReady => {}
-            };
+            }
+            if let Some(item) = self.network_queue.pop() {
+                return Ok(Async::Ready(Some(Event::Network(item))));
+            }
             match self.api.poll()? {
                 Async::Ready(None) => {
                     self.done = true;
@@ -12,30 +12,39 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io;
+use std::cmp;
+use std::io::{self, Read, Write};
 use std::net::SocketAddr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::collections::HashMap;
 use std::rc::Rc;
-use std::cell::RefCell;
+use std::cell::{Cell, RefCell};
 
 use futures::{future, unsync, Future, IntoFuture, Poll, Sink, Stream};
 use futures::future::Either;
 use futures::sync::mpsc;
 use tokio_core::net::{TcpListener, TcpStream};
 use tokio_core::reactor::Handle;
-use tokio_io::AsyncRead;
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_io::io::read_exact;
 use tokio_retry::Retry;
 use tokio_retry::strategy::{jitter, FixedInterval};
 
-use messages::{Any, Connect, Message, RawMessage};
+use blockchain::SharedNodeState;
+use crypto::{Hash, PublicKey};
+use messages::{Any, Connect, Message, RawMessage, TransactionsRequest, CONSENSUS,
+               SUPPORTED_PROTOCOL_VERSIONS, TRANSACTIONS_REQUEST_MESSAGE_ID};
 use helpers::Milliseconds;
 use super::to_box;
 use super::error::{into_other, log_error, other_error, result_ok};
 use super::codec::MessagesCodec;
+use super::noise::{self, HandshakeParams, NoiseWrapper, NOISE_PREAMBLE};
+use super::rate_limit::{PeerRateLimiters, RateLimits};
 
 const OUTGOING_CHANNEL_SIZE: usize = 10;
 
+type HandshakeFuture<S> = Box<Future<Item = (S, Option<NoiseWrapper>), Error = io::Error>>;
+
 #[derive(Debug)]
 pub enum NetworkEvent {
     MessageReceived(SocketAddr, RawMessage),
@@ -48,9 +57,34 @@ pub enum NetworkEvent {
 pub enum NetworkRequest {
     SendMessage(SocketAddr, RawMessage),
     DisconnectWithPeer(SocketAddr),
+    /// Rejects the connections of the peer for the given duration.
+    BanPeer(PublicKey, Duration),
+    /// Replaces the network configuration used for the new connections.
+    UpdateConfiguration(NetworkConfiguration),
     Shutdown,
 }
 
+/// Encryption of the connections between peers.
+///
+/// To switch a network to encrypted connections, first set `Optional` on all nodes
+/// and then `Required` on each of them.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptionMode {
+    /// Connections are not encrypted. Encrypted incoming connections are rejected.
+    Disabled,
+    /// Outgoing connections are not encrypted, while incoming connections may be either
+    /// encrypted or not.
+    Optional,
+    /// All connections are encrypted. Plaintext incoming connections are rejected.
+    Required,
+}
+
+impl Default for EncryptionMode {
+    fn default() -> EncryptionMode {
+        EncryptionMode::Optional
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, Copy)]
 pub struct NetworkConfiguration {
     // TODO: think more about config parameters (ECR-162)
@@ -60,6 +94,11 @@ pub struct NetworkConfiguration {
     pub tcp_keep_alive: Option<u64>,
     pub tcp_connect_retry_timeout: Milliseconds,
     pub tcp_connect_max_retries: u64,
+    #[serde(default)]
+    pub encryption: EncryptionMode,
+    /// Limits of the rate of the messages received from a single peer.
+    #[serde(default)]
+    pub rate_limits: RateLimits,
 }
 
 impl Default for NetworkConfiguration {
@@ -71,6 +110,8 @@ impl Default for NetworkConfiguration {
             tcp_nodelay: true,
             tcp_connect_retry_timeout: 15_000,
             tcp_connect_max_retries: 10,
+            encryption: EncryptionMode::default(),
+            rate_limits: RateLimits::default(),
         }
     }
 }
@@ -83,6 +124,8 @@ pub struct NetworkPart {
     pub max_message_len: u32,
     pub network_requests: (mpsc::Sender<NetworkRequest>, mpsc::Receiver<NetworkRequest>),
     pub network_tx: mpsc::Sender<NetworkEvent>,
+    pub handshake_params: HandshakeParams,
+    pub api_state: SharedNodeState,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -90,6 +133,51 @@ struct ConnectionsPool {
     inner: Rc<RefCell<HashMap<SocketAddr, mpsc::Sender<RawMessage>>>>,
 }
 
+/// Peers banned because of their low reputation.
+#[derive(Debug, Default, Clone)]
+struct BannedPeers {
+    inner: Rc<RefCell<HashMap<PublicKey, Instant>>>,
+}
+
+impl BannedPeers {
+    fn ban(&self, peer: PublicKey, duration: Duration) {
+        self.inner.borrow_mut().insert(peer, Instant::now() + duration);
+    }
+
+    fn is_banned(&self, peer: &PublicKey) -> bool {
+        let mut inner = self.inner.borrow_mut();
+        match inner.get(peer).cloned() {
+            Some(until) if Instant::now() < until => true,
+            Some(_) => {
+                inner.remove(peer);
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+/// Network configuration shared between the listener and the requests handler,
+/// so it can be updated while the node is running.
+#[derive(Debug, Clone)]
+struct SharedNetworkConfig {
+    inner: Rc<Cell<NetworkConfiguration>>,
+}
+
+impl SharedNetworkConfig {
+    fn new(config: NetworkConfiguration) -> SharedNetworkConfig {
+        SharedNetworkConfig { inner: Rc::new(Cell::new(config)) }
+    }
+
+    fn get(&self) -> NetworkConfiguration {
+        self.inner.get()
+    }
+
+    fn set(&self, config: NetworkConfiguration) {
+        self.inner.set(config);
+    }
+}
+
 impl ConnectionsPool {
     fn new() -> ConnectionsPool {
         ConnectionsPool::default()
@@ -117,6 +205,7 @@ impl ConnectionsPool {
         self,
         network_config: NetworkConfiguration,
         max_message_len: u32,
+        handshake_params: &HandshakeParams,
         peer: SocketAddr,
         network_tx: mpsc::Sender<NetworkEvent>,
         handle: &Handle,
@@ -140,6 +229,7 @@ impl ConnectionsPool {
             max_tries,
         );
         let handle_clonned = handle.clone();
+        let handshake_params = handshake_params.clone();
 
         let action = move || TcpStream::connect(&peer, &handle_clonned);
         let connect_handle = Retry::spawn(handle.clone(), strategy, action)
@@ -152,11 +242,21 @@ impl ConnectionsPool {
                 sock.set_keepalive(duration)?;
                 Ok(sock)
             })
+            // Perform the encrypted handshake
+            .and_then(move |sock| -> HandshakeFuture<TcpStream> {
+                if network_config.encryption == EncryptionMode::Required {
+                    let fut = noise::initiate(sock, &handshake_params)
+                        .map(|(sock, session)| (sock, Some(session)));
+                    Box::new(fut)
+                } else {
+                    Box::new(future::ok((sock, None)))
+                }
+            })
             // Connect socket with the outgoing channel
-            .and_then(move |sock| {
+            .and_then(move |(sock, session)| {
                 trace!("Established connection with peer={}", peer);
 
-                let stream = sock.framed(MessagesCodec::new(max_message_len));
+                let stream = sock.framed(MessagesCodec::new(max_message_len, session));
                 let (sink, stream) = stream.split();
 
                 let writer = conn_rx
@@ -205,26 +305,37 @@ impl ConnectionsPool {
 
 impl NetworkPart {
     pub fn run(self, handle: &Handle) -> Box<Future<Item = (), Error = io::Error>> {
-        let network_config = self.network_config;
+        let network_config = SharedNetworkConfig::new(self.network_config);
         // Cancellation token
         let (cancel_sender, cancel_handler) = unsync::oneshot::channel();
+        let banned_peers = BannedPeers::default();
+        let rate_limiters = PeerRateLimiters::new(network_config.get().rate_limits);
+        let network_id = *self.our_connect_message.network_id();
 
         let requests_handle = RequestHandler::new(
             self.our_connect_message,
-            network_config,
+            network_config.clone(),
             self.max_message_len,
+            self.handshake_params.clone(),
             self.network_tx.clone(),
             handle.clone(),
             self.network_requests.1,
             cancel_sender,
+            banned_peers.clone(),
+            rate_limiters.clone(),
         );
         // TODO Don't use unwrap here!
         let server = Listener::bind(
+            network_id,
             network_config,
             self.max_message_len,
+            self.handshake_params,
             self.listen_address,
             handle.clone(),
             &self.network_tx,
+            banned_peers,
+            rate_limiters,
+            self.api_state,
         ).unwrap();
 
         let cancel_handler = cancel_handler.or_else(|e| {
@@ -246,14 +357,18 @@ struct RequestHandler(
 );
 
 impl RequestHandler {
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
     fn new(
         connect_message: Connect,
-        network_config: NetworkConfiguration,
+        network_config: SharedNetworkConfig,
         max_message_len: u32,
+        handshake_params: HandshakeParams,
         network_tx: mpsc::Sender<NetworkEvent>,
         handle: Handle,
         receiver: mpsc::Receiver<NetworkRequest>,
         cancel_sender: unsync::oneshot::Sender<()>,
+        banned_peers: BannedPeers,
+        rate_limiters: PeerRateLimiters,
     ) -> RequestHandler {
         let mut cancel_sender = Some(cancel_sender);
         let outgoing_connections = ConnectionsPool::new();
@@ -262,6 +377,11 @@ impl RequestHandler {
             .for_each(move |request| {
                 match request {
                     NetworkRequest::SendMessage(peer, msg) => {
+                        // Responses to our requests are not limited.
+                        if let Some(request) = transactions_request(&msg) {
+                            let count = request.txs().len() as u64;
+                            rate_limiters.expect_transactions(request.to(), count);
+                        }
                         let conn_tx = outgoing_connections
                             .get(peer)
                             .map(|conn_tx| conn_fut(Ok(conn_tx).into_future()))
@@ -269,8 +389,9 @@ impl RequestHandler {
                                 outgoing_connections
                                     .clone()
                                     .connect_to_peer(
-                                        network_config,
+                                        network_config.get(),
                                         max_message_len,
+                                        &handshake_params,
                                         peer,
                                         network_tx.clone(),
                                         &handle,
@@ -307,6 +428,14 @@ impl RequestHandler {
                     NetworkRequest::DisconnectWithPeer(peer) => {
                         outgoing_connections.disconnect_with_peer(peer, network_tx.clone())
                     }
+                    NetworkRequest::BanPeer(peer, duration) => {
+                        banned_peers.ban(peer, duration);
+                        to_box(future::ok(()))
+                    }
+                    NetworkRequest::UpdateConfiguration(config) => {
+                        network_config.set(config);
+                        to_box(future::ok(()))
+                    }
                     // Immediately stop the event loop.
                     NetworkRequest::Shutdown => {
                         to_box(
@@ -334,25 +463,30 @@ impl Future for RequestHandler {
 struct Listener(Box<Future<Item = (), Error = io::Error>>);
 
 impl Listener {
+    #[cfg_attr(feature = "cargo-clippy", allow(too_many_arguments))]
     fn bind(
-        network_config: NetworkConfiguration,
+        network_id: Hash,
+        network_config: SharedNetworkConfig,
         max_message_len: u32,
+        handshake_params: HandshakeParams,
         listen_address: SocketAddr,
         handle: Handle,
         network_tx: &mpsc::Sender<NetworkEvent>,
+        banned_peers: BannedPeers,
+        rate_limiters: PeerRateLimiters,
+        api_state: SharedNodeState,
     ) -> Result<Listener, io::Error> {
-        // Incoming connections limiter
-        let incoming_connections_limit = network_config.max_incoming_connections;
         // The reference counter is used to automatically count the number of the open connections.
         let incoming_connections_counter: Rc<()> = Rc::default();
         // Incoming connections handler
         let listener = TcpListener::bind(&listen_address, &handle)?;
         let network_tx = network_tx.clone();
         let server = listener.incoming().for_each(move |(sock, addr)| {
+            let config = network_config.get();
             let holder = Rc::downgrade(&incoming_connections_counter);
             // Check incoming connections count
             let connections_count = Rc::weak_count(&incoming_connections_counter);
-            if connections_count > incoming_connections_limit {
+            if connections_count > config.max_incoming_connections {
                 warn!(
                     "Rejected incoming connection with peer={}, \
                      connections limit reached.",
@@ -361,22 +495,79 @@ impl Listener {
                 return to_box(future::ok(()));
             }
             trace!("Accepted incoming connection with peer={}", addr);
-            let stream = sock.framed(MessagesCodec::new(max_message_len));
-            let (_, stream) = stream.split();
             let network_tx = network_tx.clone();
-            let connection_handler = stream
-                .into_future()
-                .map_err(|e| e.0)
-                .and_then(move |(raw, stream)| match raw.map(Any::from_raw) {
-                    Some(Ok(Any::Connect(msg))) => Ok((msg, stream)),
-                    Some(Ok(other)) => Err(other_error(
-                        &format!("First message is not Connect, got={:?}", other),
-                    )),
-                    Some(Err(e)) => Err(into_other(e)),
-                    None => Err(other_error("Incoming socket closed")),
+            let encryption = config.encryption;
+            let handshake_params = handshake_params.clone();
+            let banned_peers = banned_peers.clone();
+            let whitelist = handshake_params.whitelist().clone();
+            let rate_limiters = rate_limiters.clone();
+            let api_state = api_state.clone();
+            let connection_handler = read_exact(sock, [0u8; 8])
+                .and_then(move |(sock, preamble)| -> HandshakeFuture<PrefixedStream<TcpStream>> {
+                    if &preamble == NOISE_PREAMBLE {
+                        if encryption == EncryptionMode::Disabled {
+                            return Box::new(future::err(
+                                other_error("Encrypted connections are disabled"),
+                            ));
+                        }
+                        let fut = noise::respond(sock, &handshake_params).map(|(sock, session)| {
+                            (PrefixedStream::new(Vec::new(), sock), Some(session))
+                        });
+                        Box::new(fut)
+                    } else if encryption == EncryptionMode::Required {
+                        Box::new(future::err(
+                            other_error("Plaintext connections are not allowed"),
+                        ))
+                    } else {
+                        Box::new(future::ok(
+                            (PrefixedStream::new(preamble.to_vec(), sock), None),
+                        ))
+                    }
                 })
-                .and_then(move |(connect, stream)| {
+                .and_then(move |(sock, session)| {
+                    let remote_key = session.as_ref().map(|session| *session.remote_key());
+                    let stream = sock.framed(MessagesCodec::new(max_message_len, session));
+                    let (_, stream) = stream.split();
+                    stream.into_future().map_err(|e| e.0).map(
+                        move |(raw, stream)| (raw, stream, remote_key),
+                    )
+                })
+                .and_then(move |(raw, stream, remote_key)| {
+                    let raw = raw.ok_or_else(|| other_error("Incoming socket closed"))?;
+                    check_protocol_version(&raw)?;
+                    match Any::from_raw(raw) {
+                        Ok(Any::Connect(ref msg))
+                            if remote_key.map_or(false, |key| key != *msg.pub_key()) => {
+                            Err(other_error(
+                                "Key of the Connect message differs from the handshake one",
+                            ))
+                        }
+                        Ok(Any::Connect(ref msg)) if banned_peers.is_banned(msg.pub_key()) => {
+                            Err(other_error(&format!("Peer {:?} is banned", msg.pub_key())))
+                        }
+                        Ok(Any::Connect(ref msg)) if !whitelist.allow(msg.pub_key()) => {
+                            Err(other_error(
+                                &format!("Peer {:?} is not in the whitelist", msg.pub_key()),
+                            ))
+                        }
+                        Ok(Any::Connect(msg)) => {
+                            check_connect(&msg, &network_id)?;
+                            Ok((msg, stream, banned_peers, whitelist))
+                        }
+                        Ok(other) => Err(other_error(
+                            &format!("First message is not Connect, got={:?}", other),
+                        )),
+                        Err(e) => Err(into_other(e)),
+                    }
+                })
+                .and_then(move |(connect, stream, banned_peers, whitelist)| {
                     trace!("Received handshake message={:?}", connect);
+                    let peer = *connect.pub_key();
+                    // Messages of a banned peer or a peer removed from the whitelist are
+                    // dropped along with its connection.
+                    let stream = stream.take_while(move |_| {
+                        Ok(!banned_peers.is_banned(&peer) && whitelist.allow(&peer))
+                    });
                     let event = NetworkEvent::PeerConnected(addr, connect);
                     let stream = network_tx
                         .clone()
@@ -385,10 +576,24 @@ impl Listener {
                         .and_then(move |_| Ok(stream))
                         .flatten_stream();
 
-                    stream.for_each(move |raw| {
-                        let event = NetworkEvent::MessageReceived(addr, raw);
-                        network_tx.clone().send(event).map_err(into_other).map(drop)
-                    })
+                    let limiters = rate_limiters.clone();
+                    limiters.add_connection(&peer, Instant::now());
+                    stream
+                        .for_each(move |raw| {
+                            let checked = limiters.check_message(&peer, &raw, Instant::now());
+                            if let Err(class) = checked {
+                                trace!("Dropped message from peer={}, class={:?}", addr, class);
+                                api_state.add_dropped_message(class);
+                                metric!("network.dropped_messages", 1);
+                                return to_box(future::ok(()));
+                            }
+                            let event = NetworkEvent::MessageReceived(addr, raw);
+                            to_box(network_tx.clone().send(event).map_err(into_other))
+                        })
+                        .then(move |res| {
+                            rate_limiters.remove_connection(&peer);
+                            res
+                        })
                 })
                 .map(|_| {
                     // Ensure that holder lives until the stream ends.
@@ -412,6 +617,96 @@ impl Future for Listener {
     }
 }
 
+/// Checks that the protocol version of the first message received from the peer is supported
+/// by the node.
+fn check_protocol_version(raw: &RawMessage) -> io::Result<()> {
+    if !SUPPORTED_PROTOCOL_VERSIONS.contains(&raw.version()) {
+        return Err(other_error(&format!(
+            "Peer uses the protocol version {}, while the node supports versions {:?}",
+            raw.version(),
+            SUPPORTED_PROTOCOL_VERSIONS
+        )));
+    }
+    Ok(())
+}
+
+/// Checks that the peer belongs to the same network as the node, and that they have
+/// at least one protocol version in common.
+pub(crate) fn check_connect(connect: &Connect, network_id: &Hash) -> io::Result<()> {
+    if connect.network_id() != network_id {
+        return Err(other_error(&format!(
+            "Peer belongs to the network {:?}, while the node belongs to the network {:?}",
+            connect.network_id(),
+            network_id
+        )));
+    }
+    let versions = connect.protocol_versions();
+    if !versions.iter().any(|v| SUPPORTED_PROTOCOL_VERSIONS.contains(v)) {
+        return Err(other_error(&format!(
+            "Peer supports the protocol versions {:?}, while the node supports versions {:?}",
+            versions,
+            SUPPORTED_PROTOCOL_VERSIONS
+        )));
+    }
+    Ok(())
+}
+
+/// Parses the outgoing message if it is a `TransactionsRequest`.
+fn transactions_request(raw: &RawMessage) -> Option<TransactionsRequest> {
+    if raw.service_id() != CONSENSUS || raw.message_type() != TRANSACTIONS_REQUEST_MESSAGE_ID {
+        return None;
+    }
+    TransactionsRequest::from_raw(raw.clone()).ok()
+}
+
+/// Stream that returns the bytes consumed while detecting the type of an incoming connection
+/// before the data of the underlying stream.
+struct PrefixedStream<S> {
+    prefix: Vec<u8>,
+    position: usize,
+    inner: S,
+}
+
+impl<S> PrefixedStream<S> {
+    fn new(prefix: Vec<u8>, inner: S) -> PrefixedStream<S> {
+        PrefixedStream {
+            prefix,
+            position: 0,
+            inner,
+        }
+    }
+}
+
+impl<S: Read> Read for PrefixedStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.position < self.prefix.len() {
+            let len = cmp::min(buf.len(), self.prefix.len() - self.position);
+            buf[..len].copy_from_slice(&self.prefix[self.position..self.position + len]);
+            self.position += len;
+            return Ok(len);
+        }
+        self.inner.read(buf)
+    }
+}
+
+impl<S: Write> Write for PrefixedStream<S> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for PrefixedStream<S> {}
+
+impl<S: AsyncWrite> AsyncWrite for PrefixedStream<S> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.inner.shutdown()
+    }
+}
+
 fn conn_fut<F>(fut: F) -> Box<Future<Item = mpsc::Sender<RawMessage>, Error = io::Error>>
 where
     F: Future<Item = mpsc::Sender<RawMessage>, Error = io::Error> + 'static,
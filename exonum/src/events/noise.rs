@@ -0,0 +1,334 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Encrypted and authenticated connections between peers based on the [Noise] protocol.
+//!
+//! The initiator of a connection sends `NOISE_PREAMBLE` followed by the `XX` handshake.
+//! Each handshake message is prefixed by its length as `u16` in the little-endian byte
+//! order. The static X25519 key of a node is generated on start and is bound to its
+//! consensus key: the handshake payload contains the consensus public key and the signature
+//! of the static key made with the consensus secret key. The consensus secret key may be
+//! kept by an external signer, so the static key is not derived from it.
+//!
+//! [Noise]: http://noiseprotocol.org/
+
+use std::fmt;
+use std::io;
+
+use byteorder::{ByteOrder, LittleEndian};
+use futures::{future, Future};
+use snow::{NoiseBuilder, Session};
+use sodiumoxide::crypto::box_;
+use tokio_io::{AsyncRead, AsyncWrite};
+use tokio_io::io::{read_exact, write_all};
+
+use crypto::{self, PublicKey, Signature, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
+use node::{SharedWhitelist, Signer, SignerError};
+use super::error::other_error;
+
+/// Bytes sent by the initiator of an encrypted connection before the handshake. Bytes
+/// `2..6` are never zero, so the preamble cannot be confused with the header of the
+/// `Connect` message, which is sent first over plaintext connections.
+pub const NOISE_PREAMBLE: &[u8; 8] = b"EXNOISE1";
+/// Maximum length of a single Noise message.
+pub const NOISE_MAX_MESSAGE_LEN: usize = 65_535;
+/// Length of the authentication tag of an encrypted Noise message.
+pub const NOISE_TAG_LEN: usize = 16;
+
+const NOISE_PARAMS: &str = "Noise_XX_25519_ChaChaPoly_SHA256";
+const AUTH_CONTEXT: &[u8] = b"exonum-noise-static-key";
+const AUTH_PAYLOAD_LEN: usize = PUBLIC_KEY_LENGTH + SIGNATURE_LENGTH;
+
+type HandshakeFuture<S> = Box<Future<Item = (S, NoiseWrapper), Error = io::Error>>;
+
+/// Parameters of the handshake shared by all connections of the node.
+#[derive(Clone)]
+pub struct HandshakeParams {
+    static_secret_key: box_::SecretKey,
+    auth_payload: Vec<u8>,
+    whitelist: SharedWhitelist,
+}
+
+impl HandshakeParams {
+    /// Generates the static key of the node and signs it with the consensus key.
+    pub fn new(
+        signer: &Signer,
+        whitelist: SharedWhitelist,
+    ) -> Result<HandshakeParams, SignerError> {
+        let (static_public_key, static_secret_key) = box_::gen_keypair();
+        let signature = signer.sign(&auth_data(&static_public_key.0))?;
+
+        let mut auth_payload = Vec::with_capacity(AUTH_PAYLOAD_LEN);
+        auth_payload.extend_from_slice(signer.public_key().as_ref());
+        auth_payload.extend_from_slice(signature.as_ref());
+        Ok(HandshakeParams {
+            static_secret_key,
+            auth_payload,
+            whitelist,
+        })
+    }
+
+    /// Returns the whitelist of the node.
+    pub fn whitelist(&self) -> &SharedWhitelist {
+        &self.whitelist
+    }
+}
+
+impl fmt::Debug for HandshakeParams {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("HandshakeParams")
+            .field("whitelist", &self.whitelist)
+            .finish()
+    }
+}
+
+/// Performs the handshake as the initiator of the connection.
+pub fn initiate<S>(stream: S, params: &HandshakeParams) -> HandshakeFuture<S>
+where
+    S: AsyncRead + AsyncWrite + 'static,
+{
+    let mut handshake = match NoiseHandshake::initiator(params) {
+        Ok(handshake) => handshake,
+        Err(e) => return Box::new(future::err(e)),
+    };
+    // -> e
+    let message = match handshake.write(&[]) {
+        Ok(message) => message,
+        Err(e) => return Box::new(future::err(e)),
+    };
+
+    let fut = write_all(stream, NOISE_PREAMBLE.to_vec())
+        .and_then(move |(stream, _)| send_handshake_message(stream, &message))
+        .and_then(receive_handshake_message)
+        .and_then(move |(stream, message)| {
+            // <- e, ee, s, es
+            let payload = handshake.read(&message)?;
+            let remote_key = handshake.authenticate(&payload)?;
+            // -> s, se
+            let payload = handshake.params.auth_payload.clone();
+            let message = handshake.write(&payload)?;
+            Ok((stream, message, handshake, remote_key))
+        })
+        .and_then(|(stream, message, handshake, remote_key)| {
+            send_handshake_message(stream, &message).and_then(move |stream| {
+                Ok((stream, handshake.finalize(remote_key)?))
+            })
+        });
+    Box::new(fut)
+}
+
+/// Performs the handshake as the responder. The preamble should be already consumed
+/// from the stream.
+pub fn respond<S>(stream: S, params: &HandshakeParams) -> HandshakeFuture<S>
+where
+    S: AsyncRead + AsyncWrite + 'static,
+{
+    let mut handshake = match NoiseHandshake::responder(params) {
+        Ok(handshake) => handshake,
+        Err(e) => return Box::new(future::err(e)),
+    };
+
+    let fut = receive_handshake_message(stream)
+        .and_then(move |(stream, message)| {
+            // -> e
+            handshake.read(&message)?;
+            // <- e, ee, s, es
+            let payload = handshake.params.auth_payload.clone();
+            let message = handshake.write(&payload)?;
+            Ok((stream, message, handshake))
+        })
+        .and_then(|(stream, message, handshake)| {
+            send_handshake_message(stream, &message).map(move |stream| (stream, handshake))
+        })
+        .and_then(|(stream, handshake)| {
+            receive_handshake_message(stream).map(move |received| (received, handshake))
+        })
+        .and_then(|((stream, message), mut handshake)| {
+            // -> s, se
+            let payload = handshake.read(&message)?;
+            let remote_key = handshake.authenticate(&payload)?;
+            Ok((stream, handshake.finalize(remote_key)?))
+        });
+    Box::new(fut)
+}
+
+/// Encrypts and decrypts the messages of an established connection.
+pub struct NoiseWrapper {
+    session: Session,
+    remote_key: PublicKey,
+}
+
+impl NoiseWrapper {
+    /// Returns the consensus public key of the peer authenticated during the handshake.
+    pub fn remote_key(&self) -> &PublicKey {
+        &self.remote_key
+    }
+
+    /// Encrypts the data splitting it into chunks that fit into a single Noise message.
+    pub fn encrypt(&mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut encrypted = Vec::with_capacity(encrypted_len(data.len()));
+        let mut buf = vec![0; NOISE_MAX_MESSAGE_LEN];
+        for chunk in data.chunks(NOISE_MAX_MESSAGE_LEN - NOISE_TAG_LEN) {
+            let len = self.session.write_message(chunk, &mut buf).map_err(
+                noise_error,
+            )?;
+            encrypted.extend_from_slice(&buf[..len]);
+        }
+        Ok(encrypted)
+    }
+
+    /// Decrypts the data encrypted by `encrypt` on the other side of the connection.
+    pub fn decrypt(&mut self, data: &[u8]) -> io::Result<Vec<u8>> {
+        let mut decrypted = Vec::with_capacity(data.len());
+        let mut buf = vec![0; NOISE_MAX_MESSAGE_LEN];
+        for chunk in data.chunks(NOISE_MAX_MESSAGE_LEN) {
+            let len = self.session.read_message(chunk, &mut buf).map_err(
+                noise_error,
+            )?;
+            decrypted.extend_from_slice(&buf[..len]);
+        }
+        Ok(decrypted)
+    }
+}
+
+impl fmt::Debug for NoiseWrapper {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("NoiseWrapper")
+            .field("remote_key", &self.remote_key)
+            .finish()
+    }
+}
+
+/// Returns the length of the data of the given length after encryption.
+pub fn encrypted_len(len: usize) -> usize {
+    let max_chunk_len = NOISE_MAX_MESSAGE_LEN - NOISE_TAG_LEN;
+    let chunks = (len + max_chunk_len - 1) / max_chunk_len;
+    len + chunks * NOISE_TAG_LEN
+}
+
+struct NoiseHandshake {
+    session: Session,
+    params: HandshakeParams,
+}
+
+impl NoiseHandshake {
+    fn initiator(params: &HandshakeParams) -> io::Result<NoiseHandshake> {
+        let session = noise_builder(params).build_initiator().map_err(
+            noise_error,
+        )?;
+        Ok(NoiseHandshake {
+            session,
+            params: params.clone(),
+        })
+    }
+
+    fn responder(params: &HandshakeParams) -> io::Result<NoiseHandshake> {
+        let session = noise_builder(params).build_responder().map_err(
+            noise_error,
+        )?;
+        Ok(NoiseHandshake {
+            session,
+            params: params.clone(),
+        })
+    }
+
+    fn write(&mut self, payload: &[u8]) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0; NOISE_MAX_MESSAGE_LEN];
+        let len = self.session.write_message(payload, &mut buf).map_err(
+            noise_error,
+        )?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    fn read(&mut self, message: &[u8]) -> io::Result<Vec<u8>> {
+        let mut buf = vec![0; NOISE_MAX_MESSAGE_LEN];
+        let len = self.session.read_message(message, &mut buf).map_err(
+            noise_error,
+        )?;
+        buf.truncate(len);
+        Ok(buf)
+    }
+
+    /// Checks that the static key of the peer is signed with its consensus key and that
+    /// the peer is in the whitelist.
+    fn authenticate(&self, payload: &[u8]) -> io::Result<PublicKey> {
+        if payload.len() != AUTH_PAYLOAD_LEN {
+            return Err(other_error("Invalid handshake payload"));
+        }
+        let public_key = PublicKey::from_slice(&payload[..PUBLIC_KEY_LENGTH]).unwrap();
+        let signature = Signature::from_slice(&payload[PUBLIC_KEY_LENGTH..]).unwrap();
+        let remote_static = self.session.get_remote_static().ok_or_else(|| {
+            other_error("Static key of the peer is unknown")
+        })?;
+        if !crypto::verify(&signature, &auth_data(remote_static), &public_key) {
+            return Err(other_error("Invalid signature of the peer static key"));
+        }
+        if !self.params.whitelist.allow(&public_key) {
+            return Err(other_error(
+                format!("Peer {:?} is not in the whitelist", public_key),
+            ));
+        }
+        Ok(public_key)
+    }
+
+    fn finalize(self, remote_key: PublicKey) -> io::Result<NoiseWrapper> {
+        let session = self.session.into_transport_mode().map_err(noise_error)?;
+        Ok(NoiseWrapper {
+            session,
+            remote_key,
+        })
+    }
+}
+
+fn noise_builder(params: &HandshakeParams) -> NoiseBuilder {
+    NoiseBuilder::new(NOISE_PARAMS.parse().unwrap())
+        .local_private_key(&params.static_secret_key.0)
+}
+
+fn auth_data(static_public_key: &[u8]) -> Vec<u8> {
+    let mut data = AUTH_CONTEXT.to_vec();
+    data.extend_from_slice(static_public_key);
+    data
+}
+
+fn noise_error<E: fmt::Debug>(e: E) -> io::Error {
+    other_error(format!("Noise error: {:?}", e))
+}
+
+fn send_handshake_message<S>(
+    stream: S,
+    message: &[u8],
+) -> Box<Future<Item = S, Error = io::Error>>
+where
+    S: AsyncWrite + 'static,
+{
+    let mut frame = vec![0; 2];
+    LittleEndian::write_u16(&mut frame, message.len() as u16);
+    frame.extend_from_slice(message);
+    Box::new(write_all(stream, frame).map(|(stream, _)| stream))
+}
+
+fn receive_handshake_message<S>(
+    stream: S,
+) -> Box<Future<Item = (S, Vec<u8>), Error = io::Error>>
+where
+    S: AsyncRead + 'static,
+{
+    let fut = read_exact(stream, [0u8; 2]).and_then(|(stream, len)| {
+        let len = LittleEndian::read_u16(&len) as usize;
+        read_exact(stream, vec![0u8; len])
+    });
+    Box::new(fut)
+}
@@ -0,0 +1,449 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Rate limits of the messages received from peers.
+//!
+//! Every peer has a token bucket for each `MessageClass`. A message consumes a token from
+//! the bucket of its class (a `TransactionsBatch` consumes a token per transaction),
+//! and the message is dropped if the bucket does not have enough tokens. Buckets are
+//! refilled at the configured rate up to the configured burst size, so one chatty peer
+//! cannot starve the consensus traffic of the other peers.
+//!
+//! Transactions responses are exempt from the limits as long as they carry no more
+//! transactions than the node has requested from the peer; the excess is charged to
+//! the transactions bucket.
+
+use std::cmp;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+use crypto::PublicKey;
+use messages::{Message, MessageClass, RawMessage, TransactionsBatch, TransactionsResponse,
+               CONSENSUS, TRANSACTIONS_BATCH_MESSAGE_ID, TRANSACTIONS_RESPONSE_MESSAGE_ID};
+
+/// Limit of the rate of the messages of a single class.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// Number of messages per second.
+    pub rate: u32,
+    /// Maximum number of messages that may be received at once.
+    pub burst: u32,
+}
+
+/// Rate limits of the messages received from a single peer. Messages of the classes
+/// without a limit are never dropped.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimits {
+    /// Limit of the consensus messages.
+    pub consensus: Option<RateLimit>,
+    /// Limit of the requests.
+    pub requests: Option<RateLimit>,
+    /// Limit of the transactions.
+    pub transactions: Option<RateLimit>,
+}
+
+impl RateLimits {
+    /// Returns the limits that never drop messages.
+    pub fn unlimited() -> RateLimits {
+        RateLimits {
+            consensus: None,
+            requests: None,
+            transactions: None,
+        }
+    }
+
+    /// Returns the limit of the given message class.
+    pub fn get(&self, class: MessageClass) -> Option<RateLimit> {
+        match class {
+            MessageClass::Consensus => self.consensus,
+            MessageClass::Requests => self.requests,
+            MessageClass::Transactions => self.transactions,
+        }
+    }
+}
+
+impl Default for RateLimits {
+    fn default() -> RateLimits {
+        RateLimits {
+            consensus: Some(RateLimit {
+                rate: 1_000,
+                burst: 5_000,
+            }),
+            requests: Some(RateLimit {
+                rate: 100,
+                burst: 1_000,
+            }),
+            transactions: Some(RateLimit {
+                rate: 5_000,
+                burst: 10_000,
+            }),
+        }
+    }
+}
+
+/// Token bucket limiting the rate of the messages of a single class.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    limit: RateLimit,
+    tokens: u64,
+    updated: Instant,
+}
+
+impl TokenBucket {
+    /// Creates a full bucket.
+    pub fn new(limit: RateLimit, now: Instant) -> TokenBucket {
+        TokenBucket {
+            limit,
+            tokens: u64::from(limit.burst),
+            updated: now,
+        }
+    }
+
+    /// Takes the given number of tokens from the bucket. Returns `false` if the bucket
+    /// does not have enough tokens. Taking more tokens than the burst size requires
+    /// the full bucket, so such messages are not dropped forever.
+    pub fn try_take(&mut self, tokens: u64, now: Instant) -> bool {
+        self.refill(now);
+        let tokens = cmp::max(cmp::min(tokens, u64::from(self.limit.burst)), 1);
+        if self.tokens < tokens {
+            return false;
+        }
+        self.tokens -= tokens;
+        true
+    }
+
+    fn refill(&mut self, now: Instant) {
+        if now <= self.updated || self.limit.rate == 0 {
+            return;
+        }
+        let elapsed = duration_millis(now - self.updated);
+        let tokens = elapsed * u64::from(self.limit.rate) / 1000;
+        if tokens == 0 {
+            return;
+        }
+        self.tokens = cmp::min(self.tokens + tokens, u64::from(self.limit.burst));
+        // Keep the remainder of the elapsed time to not lose tokens on frequent refills.
+        self.updated += Duration::from_millis(tokens * 1000 / u64::from(self.limit.rate));
+    }
+}
+
+/// Token buckets of a single peer.
+#[derive(Debug, Clone)]
+struct PeerBuckets {
+    consensus: Option<TokenBucket>,
+    requests: Option<TokenBucket>,
+    transactions: Option<TokenBucket>,
+}
+
+impl PeerBuckets {
+    fn new(limits: &RateLimits, now: Instant) -> PeerBuckets {
+        PeerBuckets {
+            consensus: limits.consensus.map(|limit| TokenBucket::new(limit, now)),
+            requests: limits.requests.map(|limit| TokenBucket::new(limit, now)),
+            transactions: limits.transactions.map(|limit| TokenBucket::new(limit, now)),
+        }
+    }
+
+    fn get_mut(&mut self, class: MessageClass) -> Option<&mut TokenBucket> {
+        match class {
+            MessageClass::Consensus => self.consensus.as_mut(),
+            MessageClass::Requests => self.requests.as_mut(),
+            MessageClass::Transactions => self.transactions.as_mut(),
+        }
+    }
+}
+
+/// Limiter state of a single peer.
+#[derive(Debug, Clone)]
+struct PeerState {
+    buckets: PeerBuckets,
+    /// Number of the open incoming connections of the peer.
+    connections: usize,
+    /// Number of the transactions requested from the peer and not received yet.
+    expected_transactions: u64,
+}
+
+impl PeerState {
+    fn new(limits: &RateLimits, now: Instant) -> PeerState {
+        PeerState {
+            buckets: PeerBuckets::new(limits, now),
+            connections: 0,
+            expected_transactions: 0,
+        }
+    }
+}
+
+/// Rate limiters of the peers connected to the node.
+#[derive(Debug, Clone)]
+pub struct PeerRateLimiters {
+    limits: RateLimits,
+    inner: Rc<RefCell<HashMap<PublicKey, PeerState>>>,
+}
+
+impl PeerRateLimiters {
+    /// Creates limiters with the given limits.
+    pub fn new(limits: RateLimits) -> PeerRateLimiters {
+        PeerRateLimiters {
+            limits,
+            inner: Rc::default(),
+        }
+    }
+
+    /// Returns `true` if the message of the given class and cost (see `message_cost`)
+    /// received from the peer fits into the limits.
+    pub fn check(&self, peer: &PublicKey, class: MessageClass, cost: u64, now: Instant) -> bool {
+        if self.limits.get(class).is_none() {
+            return true;
+        }
+        let limits = self.limits;
+        let mut inner = self.inner.borrow_mut();
+        let state = inner.entry(*peer).or_insert_with(
+            || PeerState::new(&limits, now),
+        );
+        state.buckets.get_mut(class).map_or(
+            true,
+            |bucket| bucket.try_take(cost, now),
+        )
+    }
+
+    /// Checks the message received from the peer. Returns the class the message has been
+    /// charged to if it does not fit into the limits.
+    pub fn check_message(
+        &self,
+        peer: &PublicKey,
+        raw: &RawMessage,
+        now: Instant,
+    ) -> Result<(), MessageClass> {
+        let cost = message_cost(raw);
+        let class = if is_transactions_response(raw) {
+            if self.take_expected_transactions(peer, cost) {
+                return Ok(());
+            }
+            MessageClass::Transactions
+        } else {
+            MessageClass::of(raw)
+        };
+        if self.check(peer, class, cost, now) {
+            Ok(())
+        } else {
+            Err(class)
+        }
+    }
+
+    /// Records the transactions requested from the peer, so that the response is not
+    /// limited. Requests to the peers without incoming connections are ignored.
+    pub fn expect_transactions(&self, peer: &PublicKey, count: u64) {
+        if let Some(state) = self.inner.borrow_mut().get_mut(peer) {
+            state.expected_transactions = state.expected_transactions.saturating_add(count);
+        }
+    }
+
+    /// Registers a new incoming connection of the peer.
+    pub fn add_connection(&self, peer: &PublicKey, now: Instant) {
+        let limits = self.limits;
+        let mut inner = self.inner.borrow_mut();
+        let state = inner.entry(*peer).or_insert_with(
+            || PeerState::new(&limits, now),
+        );
+        state.connections += 1;
+    }
+
+    /// Unregisters the closed incoming connection of the peer. The state of the peer is
+    /// forgotten once its last connection closes.
+    pub fn remove_connection(&self, peer: &PublicKey) {
+        let mut inner = self.inner.borrow_mut();
+        let is_last = match inner.get_mut(peer) {
+            Some(state) => {
+                state.connections = state.connections.saturating_sub(1);
+                state.connections == 0
+            }
+            None => false,
+        };
+        if is_last {
+            inner.remove(peer);
+        }
+    }
+
+    fn take_expected_transactions(&self, peer: &PublicKey, count: u64) -> bool {
+        let mut inner = self.inner.borrow_mut();
+        let state = match inner.get_mut(peer) {
+            Some(state) => state,
+            None => return false,
+        };
+        if state.expected_transactions < count {
+            return false;
+        }
+        state.expected_transactions -= count;
+        true
+    }
+}
+
+/// Returns the number of tokens consumed by the message: the number of the transactions
+/// for a `TransactionsBatch` or `TransactionsResponse` and one for the other messages.
+pub fn message_cost(raw: &RawMessage) -> u64 {
+    if raw.service_id() != CONSENSUS {
+        return 1;
+    }
+    // Malformed messages are rejected by the node later.
+    let len = match raw.message_type() {
+        TRANSACTIONS_BATCH_MESSAGE_ID => {
+            TransactionsBatch::from_raw(raw.clone()).map(|batch| batch.transactions().len())
+        }
+        TRANSACTIONS_RESPONSE_MESSAGE_ID => {
+            TransactionsResponse::from_raw(raw.clone()).map(|msg| msg.transactions().len())
+        }
+        _ => return 1,
+    };
+    len.map_or(1, |len| cmp::max(len as u64, 1))
+}
+
+fn is_transactions_response(raw: &RawMessage) -> bool {
+    raw.service_id() == CONSENSUS && raw.message_type() == TRANSACTIONS_RESPONSE_MESSAGE_ID
+}
+
+fn duration_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_nanos() / 1_000_000)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use crypto::gen_keypair;
+    use messages::{Message, MessageClass, PeersRequest, TransactionsBatch, TransactionsResponse};
+    use super::{message_cost, PeerRateLimiters, RateLimit, RateLimits, TokenBucket};
+
+    #[test]
+    fn test_token_bucket() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(RateLimit { rate: 10, burst: 3 }, now);
+        for _ in 0..3 {
+            assert!(bucket.try_take(1, now));
+        }
+        assert!(!bucket.try_take(1, now));
+
+        // A token is added every 100 ms.
+        assert!(!bucket.try_take(1, now + Duration::from_millis(50)));
+        assert!(bucket.try_take(1, now + Duration::from_millis(100)));
+        assert!(!bucket.try_take(1, now + Duration::from_millis(150)));
+        assert!(bucket.try_take(1, now + Duration::from_millis(200)));
+
+        // The bucket is not filled over the burst size.
+        let later = now + Duration::from_secs(10);
+        for _ in 0..3 {
+            assert!(bucket.try_take(1, later));
+        }
+        assert!(!bucket.try_take(1, later));
+
+        // A batch takes several tokens at once.
+        let later = later + Duration::from_secs(10);
+        assert!(bucket.try_take(2, later));
+        assert!(!bucket.try_take(2, later));
+        assert!(bucket.try_take(1, later));
+    }
+
+    #[test]
+    fn test_peer_rate_limiters() {
+        let limits = RateLimits {
+            consensus: None,
+            requests: Some(RateLimit { rate: 1, burst: 1 }),
+            transactions: Some(RateLimit { rate: 1, burst: 2 }),
+        };
+        let limiters = PeerRateLimiters::new(limits);
+        let (first, _) = gen_keypair();
+        let (second, _) = gen_keypair();
+        let now = Instant::now();
+
+        for _ in 0..100 {
+            assert!(limiters.check(&first, MessageClass::Consensus, 1, now));
+        }
+        assert!(limiters.check(&first, MessageClass::Requests, 1, now));
+        assert!(!limiters.check(&first, MessageClass::Requests, 1, now));
+        // Classes are limited separately.
+        assert!(limiters.check(&first, MessageClass::Transactions, 1, now));
+        assert!(limiters.check(&first, MessageClass::Transactions, 1, now));
+        assert!(!limiters.check(&first, MessageClass::Transactions, 1, now));
+        // Peers are limited separately.
+        assert!(limiters.check(&second, MessageClass::Requests, 1, now));
+    }
+
+    #[test]
+    fn test_peer_connections() {
+        let limits = RateLimits {
+            consensus: None,
+            requests: Some(RateLimit { rate: 1, burst: 1 }),
+            transactions: None,
+        };
+        let limiters = PeerRateLimiters::new(limits);
+        let (peer, _) = gen_keypair();
+        let now = Instant::now();
+
+        limiters.add_connection(&peer, now);
+        limiters.add_connection(&peer, now);
+        assert!(limiters.check(&peer, MessageClass::Requests, 1, now));
+        // The state is kept while the peer has open connections.
+        limiters.remove_connection(&peer);
+        assert!(!limiters.check(&peer, MessageClass::Requests, 1, now));
+        limiters.remove_connection(&peer);
+        assert!(limiters.check(&peer, MessageClass::Requests, 1, now));
+    }
+
+    #[test]
+    fn test_expected_transactions() {
+        let limits = RateLimits {
+            consensus: None,
+            requests: None,
+            transactions: Some(RateLimit { rate: 1, burst: 1 }),
+        };
+        let limiters = PeerRateLimiters::new(limits);
+        let (public_key, secret_key) = gen_keypair();
+        let now = Instant::now();
+        let txs = (0..2)
+            .map(|_| PeersRequest::new(&public_key, &public_key, &secret_key).raw().clone())
+            .collect::<Vec<_>>();
+        let response =
+            TransactionsResponse::new(&public_key, &public_key, txs.clone(), &secret_key);
+
+        // Unrequested transactions are charged to the transactions bucket.
+        assert!(limiters.check_message(&public_key, response.raw(), now).is_ok());
+        assert_eq!(
+            limiters.check_message(&public_key, response.raw(), now),
+            Err(MessageClass::Transactions)
+        );
+
+        limiters.expect_transactions(&public_key, 3);
+        assert!(limiters.check_message(&public_key, response.raw(), now).is_ok());
+        // Only one requested transaction is left.
+        assert!(limiters.check_message(&public_key, response.raw(), now).is_err());
+        let single =
+            TransactionsResponse::new(&public_key, &public_key, txs[..1].to_vec(), &secret_key);
+        assert!(limiters.check_message(&public_key, single.raw(), now).is_ok());
+    }
+
+    #[test]
+    fn test_batch_cost() {
+        let (public_key, secret_key) = gen_keypair();
+        let messages = (0..5)
+            .map(|_| PeersRequest::new(&public_key, &public_key, &secret_key).raw().clone())
+            .collect::<Vec<_>>();
+        let batch = TransactionsBatch::new(&public_key, messages.clone(), &secret_key);
+        assert_eq!(message_cost(batch.raw()), 5);
+        let response =
+            TransactionsResponse::new(&public_key, &public_key, messages.clone(), &secret_key);
+        assert_eq!(message_cost(response.raw()), 5);
+        assert_eq!(message_cost(&messages[0]), 1);
+    }
+}
@@ -12,7 +12,9 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::net::SocketAddr;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::sync::Arc;
 use std::thread;
 use std::time::{self, Duration};
 
@@ -22,19 +24,23 @@ use futures::sync::mpsc;
 use tokio_core::reactor::Core;
 use tokio_timer::{TimeoutStream, Timer};
 
-use crypto::{gen_keypair, PublicKey, Signature};
-use messages::{Connect, Message, MessageWriter, RawMessage};
+use crypto::{gen_keypair, hash, Hash, PublicKey, SecretKey, Signature};
+use messages::{Connect, Message, MessageWriter, RawMessage, SUPPORTED_PROTOCOL_VERSIONS};
 use events::{NetworkEvent, NetworkRequest};
-use events::network::{NetworkConfiguration, NetworkPart};
+use events::network::{EncryptionMode, NetworkConfiguration, NetworkPart};
+use events::noise::HandshakeParams;
+use events::rate_limit::{RateLimit, RateLimits};
 use events::error::log_error;
-use node::{EventsPoolCapacity, NodeChannel};
-use blockchain::ConsensusConfig;
+use node::{EventsPoolCapacity, NodeChannel, SharedWhitelist, Signer, Whitelist};
+use node::signer::LocalSigner;
+use blockchain::{ConsensusConfig, SharedNodeState};
 use helpers::user_agent;
 
 #[derive(Debug)]
 pub struct TestHandler {
     handle: Option<thread::JoinHandle<()>>,
     listen_address: SocketAddr,
+    connect: Connect,
     network_events_rx: Wait<TimeoutStream<mpsc::Receiver<NetworkEvent>>>,
     network_requests_tx: mpsc::Sender<NetworkRequest>,
 }
@@ -42,6 +48,7 @@ pub struct TestHandler {
 impl TestHandler {
     pub fn new(
         listen_address: SocketAddr,
+        connect: Connect,
         network_requests_tx: mpsc::Sender<NetworkRequest>,
         network_events_rx: mpsc::Receiver<NetworkEvent>,
     ) -> TestHandler {
@@ -50,6 +57,7 @@ impl TestHandler {
         TestHandler {
             handle: None,
             listen_address,
+            connect,
             network_requests_tx,
             network_events_rx: receiver.wait(),
         }
@@ -61,10 +69,9 @@ impl TestHandler {
     }
 
     pub fn connect_with(&self, addr: SocketAddr) {
-        let connect = connect_message(self.listen_address);
         self.network_requests_tx
             .clone()
-            .send(NetworkRequest::SendMessage(addr, connect.raw().clone()))
+            .send(NetworkRequest::SendMessage(addr, self.connect.raw().clone()))
             .wait()
             .unwrap();
     }
@@ -77,6 +84,14 @@ impl TestHandler {
             .unwrap();
     }
 
+    pub fn ban(&self, peer: PublicKey, duration: Duration) {
+        self.network_requests_tx
+            .clone()
+            .send(NetworkRequest::BanPeer(peer, duration))
+            .wait()
+            .unwrap();
+    }
+
     pub fn send_to(&self, addr: SocketAddr, raw: RawMessage) {
         self.network_requests_tx
             .clone()
@@ -132,17 +147,36 @@ pub struct TestEvents {
     pub listen_address: SocketAddr,
     pub network_config: NetworkConfiguration,
     pub events_config: EventsPoolCapacity,
+    pub connect: Connect,
+    pub signer: Arc<Signer>,
+    pub whitelist: SharedWhitelist,
 }
 
 impl TestEvents {
     pub fn with_addr(listen_address: SocketAddr) -> TestEvents {
+        let (public_key, secret_key) = gen_keypair();
         TestEvents {
             listen_address,
             network_config: NetworkConfiguration::default(),
             events_config: EventsPoolCapacity::default(),
+            connect: connect_message(listen_address),
+            signer: Arc::new(LocalSigner::new(public_key, secret_key)),
+            whitelist: SharedWhitelist::default(),
         }
     }
 
+    pub fn with_keys(
+        listen_address: SocketAddr,
+        keys: (PublicKey, SecretKey),
+        encryption: EncryptionMode,
+    ) -> TestEvents {
+        let mut events = TestEvents::with_addr(listen_address);
+        events.network_config.encryption = encryption;
+        events.connect = connect_message_with_key(listen_address, &keys.0);
+        events.signer = Arc::new(LocalSigner::new(keys.0, keys.1));
+        events
+    }
+
     pub fn spawn(self) -> TestHandler {
         let (mut handler_part, network_part) = self.into_reactor();
         let handle = thread::spawn(move || {
@@ -159,37 +193,68 @@ impl TestEvents {
         let network_config = self.network_config;
         let (network_tx, network_rx) = channel.network_events;
         let network_requests_tx = channel.network_requests.0.clone();
+        let handshake_params = HandshakeParams::new(&*self.signer, self.whitelist).unwrap();
 
         let network_part = NetworkPart {
-            our_connect_message: connect_message(self.listen_address),
+            our_connect_message: self.connect.clone(),
             listen_address: self.listen_address,
             network_config,
             max_message_len: ConsensusConfig::DEFAULT_MAX_MESSAGE_LEN,
             network_requests: channel.network_requests,
             network_tx: network_tx.clone(),
+            handshake_params,
+            api_state: SharedNodeState::new(1000),
         };
 
-        let handler_part = TestHandler::new(self.listen_address, network_requests_tx, network_rx);
+        let handler_part = TestHandler::new(
+            self.listen_address,
+            self.connect,
+            network_requests_tx,
+            network_rx,
+        );
         (handler_part, network_part)
     }
 }
 
 pub fn connect_message(addr: SocketAddr) -> Connect {
+    connect_message_with_key(addr, &PublicKey::zero())
+}
+
+pub fn connect_message_with_key(addr: SocketAddr, public_key: &PublicKey) -> Connect {
     let time = time::UNIX_EPOCH;
     Connect::new_with_signature(
-        &PublicKey::zero(),
+        public_key,
         addr,
         time.into(),
         &user_agent::get(),
+        SUPPORTED_PROTOCOL_VERSIONS,
+        &Hash::zero(),
+        false,
         &Signature::zero(),
     )
 }
 
+/// Sends the given bytes to the node as the first message of a plaintext connection
+/// and checks that the node closes the connection.
+fn assert_connection_rejected(addr: SocketAddr, first_message: &[u8]) {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream
+        .set_read_timeout(Some(Duration::from_secs(30)))
+        .unwrap();
+    stream.write_all(first_message).unwrap();
+    let mut buf = [0u8; 1];
+    assert_eq!(stream.read(&mut buf).unwrap(), 0);
+}
+
 pub fn raw_message(id: u16, len: usize) -> RawMessage {
+    raw_service_message(0, id, len)
+}
+
+pub fn raw_service_message(service_id: u16, id: u16, len: usize) -> RawMessage {
     let writer = MessageWriter::new(
         ::messages::PROTOCOL_MAJOR_VERSION,
         ::messages::TEST_NETWORK_ID,
-        0,
+        service_id,
         id,
         len,
     );
@@ -374,3 +439,212 @@ fn test_send_first_not_connect() {
     assert_eq!(node.wait_for_connect(), connect_message(other));
     assert_eq!(node.wait_for_message(), message);
 }
+
+#[test]
+fn test_network_encrypted_handshake() {
+    let first = "127.0.0.1:17240".parse().unwrap();
+    let second = "127.0.0.1:17241".parse().unwrap();
+
+    let (first_key, first_secret) = gen_keypair();
+    let (second_key, second_secret) = gen_keypair();
+    let c1 = connect_message_with_key(first, &first_key);
+    let c2 = connect_message_with_key(second, &second_key);
+
+    let m1 = raw_message(15, 100000);
+    let m2 = raw_message(16, 400);
+
+    let mut e1 =
+        TestEvents::with_keys(first, (first_key, first_secret), EncryptionMode::Required).spawn();
+    let mut e2 =
+        TestEvents::with_keys(second, (second_key, second_secret), EncryptionMode::Required)
+            .spawn();
+
+    e1.connect_with(second);
+    assert_eq!(e2.wait_for_connect(), c1);
+
+    e2.connect_with(first);
+    assert_eq!(e1.wait_for_connect(), c2);
+
+    e1.send_to(second, m1.clone());
+    assert_eq!(e2.wait_for_message(), m1);
+
+    e1.send_to(second, m2.clone());
+    assert_eq!(e2.wait_for_message(), m2);
+
+    e2.send_to(first, m1.clone());
+    assert_eq!(e1.wait_for_message(), m1);
+
+    e1.disconnect_with(second);
+    assert_eq!(e1.wait_for_disconnect(), second);
+}
+
+#[test]
+fn test_network_optional_encryption() {
+    let main = "127.0.0.1:17242".parse().unwrap();
+    let encrypted = "127.0.0.1:17243".parse().unwrap();
+    let plain = "127.0.0.1:17244".parse().unwrap();
+
+    let keys = gen_keypair();
+    let c1 = connect_message_with_key(encrypted, &keys.0);
+    let c2 = connect_message(plain);
+
+    let mut node = TestEvents::with_keys(main, gen_keypair(), EncryptionMode::Optional).spawn();
+    let encrypted_node = TestEvents::with_keys(encrypted, keys, EncryptionMode::Required).spawn();
+    let plain_node = TestEvents::with_addr(plain).spawn();
+
+    encrypted_node.connect_with(main);
+    assert_eq!(node.wait_for_connect(), c1);
+
+    plain_node.connect_with(main);
+    assert_eq!(node.wait_for_connect(), c2);
+}
+
+#[test]
+fn test_network_required_encryption_rejects_plaintext() {
+    let main = "127.0.0.1:17245".parse().unwrap();
+    let other = "127.0.0.1:17246".parse().unwrap();
+
+    let mut node = TestEvents::with_keys(main, gen_keypair(), EncryptionMode::Required).spawn();
+    let mut other_node = TestEvents::with_addr(other).spawn();
+
+    other_node.connect_with(main);
+    assert_eq!(other_node.wait_for_disconnect(), main);
+    assert!(node.wait_for_event().is_err());
+}
+
+#[test]
+fn test_network_encrypted_handshake_checks_whitelist() {
+    let main = "127.0.0.1:17247".parse().unwrap();
+    let allowed = "127.0.0.1:17248".parse().unwrap();
+    let rejected = "127.0.0.1:17249".parse().unwrap();
+
+    let allowed_keys = gen_keypair();
+    let c1 = connect_message_with_key(allowed, &allowed_keys.0);
+
+    let mut whitelist: Whitelist =
+        ::serde_json::from_str(r#"{ "whitelist_enabled": true }"#).unwrap();
+    whitelist.add(allowed_keys.0);
+    let mut node = TestEvents::with_keys(main, gen_keypair(), EncryptionMode::Required);
+    node.whitelist = SharedWhitelist::new(whitelist);
+    let mut node = node.spawn();
+
+    let mut rejected_node =
+        TestEvents::with_keys(rejected, gen_keypair(), EncryptionMode::Required).spawn();
+    rejected_node.connect_with(main);
+    assert_eq!(rejected_node.wait_for_disconnect(), main);
+
+    let allowed_node =
+        TestEvents::with_keys(allowed, allowed_keys, EncryptionMode::Required).spawn();
+    allowed_node.connect_with(main);
+    assert_eq!(node.wait_for_connect(), c1);
+}
+
+#[test]
+fn test_network_rejects_other_network() {
+    let main = "127.0.0.1:17250".parse().unwrap();
+    let other = "127.0.0.1:17251".parse().unwrap();
+
+    let mut node = TestEvents::with_addr(main).spawn();
+
+    let connect = Connect::new_with_signature(
+        &PublicKey::zero(),
+        other,
+        time::UNIX_EPOCH.into(),
+        &user_agent::get(),
+        SUPPORTED_PROTOCOL_VERSIONS,
+        &hash(&[1]),
+        false,
+        &Signature::zero(),
+    );
+    assert_connection_rejected(main, connect.raw().as_ref());
+
+    let other_node = TestEvents::with_addr(other).spawn();
+    other_node.connect_with(main);
+    assert_eq!(node.wait_for_connect(), connect_message(other));
+}
+
+#[test]
+fn test_network_rejects_unsupported_protocol_versions() {
+    let main = "127.0.0.1:17252".parse().unwrap();
+    let other = "127.0.0.1:17253".parse().unwrap();
+
+    let mut node = TestEvents::with_addr(main).spawn();
+
+    let unsupported_version = ::messages::PROTOCOL_MAJOR_VERSION.wrapping_add(1);
+    let connect = Connect::new_with_signature(
+        &PublicKey::zero(),
+        other,
+        time::UNIX_EPOCH.into(),
+        &user_agent::get(),
+        &[unsupported_version],
+        &Hash::zero(),
+        false,
+        &Signature::zero(),
+    );
+    assert_connection_rejected(main, connect.raw().as_ref());
+
+    let other_node = TestEvents::with_addr(other).spawn();
+    other_node.connect_with(main);
+    assert_eq!(node.wait_for_connect(), connect_message(other));
+}
+
+#[test]
+fn test_network_banned_peer() {
+    let main = "127.0.0.1:17254".parse().unwrap();
+    let other = "127.0.0.1:17255".parse().unwrap();
+
+    let (public_key, secret_key) = gen_keypair();
+    let connect = connect_message_with_key(other, &public_key);
+
+    let mut node = TestEvents::with_addr(main).spawn();
+    let mut other_node =
+        TestEvents::with_keys(other, (public_key, secret_key), EncryptionMode::Optional).spawn();
+
+    other_node.connect_with(main);
+    assert_eq!(node.wait_for_connect(), connect);
+
+    // The existing connection of the peer is closed after the ban.
+    node.ban(public_key, Duration::from_secs(60));
+    other_node.send_to(main, raw_message(11, 1000));
+    assert_eq!(other_node.wait_for_disconnect(), main);
+
+    // New connections of the peer are rejected.
+    other_node.connect_with(main);
+    assert_eq!(other_node.wait_for_disconnect(), main);
+    assert!(node.wait_for_event().is_err());
+}
+
+#[test]
+fn test_network_rate_limits() {
+    let first = "127.0.0.1:17256".parse().unwrap();
+    let second = "127.0.0.1:17257".parse().unwrap();
+
+    let e1 = TestEvents::with_addr(first);
+    let mut e2 = TestEvents::with_addr(second);
+    // Tokens are not refilled during the test.
+    e2.network_config.rate_limits = RateLimits {
+        transactions: Some(RateLimit { rate: 0, burst: 3 }),
+        ..RateLimits::unlimited()
+    };
+
+    let mut e1 = e1.spawn();
+    let mut e2 = e2.spawn();
+
+    e1.connect_with(second);
+    e2.wait_for_connect();
+
+    let transactions = (0..10)
+        .map(|i| raw_service_message(1, i, 100))
+        .collect::<Vec<_>>();
+    for tx in &transactions {
+        e1.send_to(second, tx.clone());
+    }
+    for tx in &transactions[..3] {
+        assert_eq!(&e2.wait_for_message(), tx);
+    }
+
+    // The rest of the transactions are dropped, while the consensus messages are not limited.
+    let msg = raw_message(11, 100);
+    e1.send_to(second, msg.clone());
+    assert_eq!(e2.wait_for_message(), msg);
+}
@@ -78,7 +78,10 @@ impl NodeBuilder {
                     .into_iter()
                     .map(|mut factory| factory.make_service(ctx))
                     .collect();
-                let node = Node::new(db, services, config);
+                let mut node = Node::new(db, services, config);
+                if let Ok(config_path) = ctx.arg::<String>("NODE_CONFIG_PATH") {
+                    node.set_config_path(config_path);
+                }
                 Some(node)
             }
             _ => None,
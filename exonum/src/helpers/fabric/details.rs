@@ -570,7 +570,7 @@ impl Command for Finalize {
                 whitelist: Default::default(),
                 peers,
                 consensus_public_key: secret_config.consensus_public_key,
-                consensus_secret_key: secret_config.consensus_secret_key,
+                consensus_secret_key: Some(secret_config.consensus_secret_key),
                 service_public_key: secret_config.service_public_key,
                 service_secret_key: secret_config.service_secret_key,
                 genesis,
@@ -582,6 +582,13 @@ impl Command for Finalize {
                 mempool: Default::default(),
                 services_configs: Default::default(),
                 database: Default::default(),
+                state_sync: Default::default(),
+                signer: Default::default(),
+                service_signer: Default::default(),
+                reputation: Default::default(),
+                log_level: None,
+                shutdown_timeout: None,
+                replica: None,
             }
         };
 
@@ -79,7 +79,7 @@ pub fn generate_testnet_config(count: u8, start_port: u16) -> Vec<NodeConfig> {
                 network: Default::default(),
                 peers: peers.clone(),
                 consensus_public_key: validator.0,
-                consensus_secret_key: validator.1,
+                consensus_secret_key: Some(validator.1),
                 service_public_key: service.0,
                 service_secret_key: service.1,
                 genesis: genesis.clone(),
@@ -88,6 +88,13 @@ pub fn generate_testnet_config(count: u8, start_port: u16) -> Vec<NodeConfig> {
                 mempool: Default::default(),
                 services_configs: Default::default(),
                 database: Default::default(),
+                state_sync: Default::default(),
+                signer: Default::default(),
+                service_signer: Default::default(),
+                reputation: Default::default(),
+                log_level: None,
+                shutdown_timeout: None,
+                replica: None,
             }
         })
         .collect::<Vec<_>>()
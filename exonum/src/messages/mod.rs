@@ -22,8 +22,9 @@ use crypto::PublicKey;
 use encoding::Error;
 use helpers::{Height, Round, ValidatorId};
 
-pub use self::raw::{RawMessage, MessageWriter, MessageBuffer, ServiceMessage, Message,
-                    HEADER_LENGTH, PROTOCOL_MAJOR_VERSION, TEST_NETWORK_ID};
+pub use self::raw::{network_id, RawMessage, MessageWriter, MessageBuffer, ServiceMessage,
+                    Message, HEADER_LENGTH, PROTOCOL_MAJOR_VERSION, SUPPORTED_PROTOCOL_VERSIONS,
+                    TEST_NETWORK_ID};
 pub use self::protocol::*;
 
 #[macro_use]
@@ -49,12 +50,22 @@ pub enum Any {
     Status(Status),
     /// `Block` message.
     Block(BlockResponse),
+    /// `StateSnapshotResponse` message.
+    StateSnapshot(StateSnapshotResponse),
+    /// `StateChunkResponse` message.
+    StateChunk(StateChunkResponse),
     /// Consensus message.
     Consensus(ConsensusMessage),
     /// Request for the some data.
     Request(RequestMessage),
     /// Transaction.
     Transaction(RawTransaction),
+    /// Batch of transactions.
+    TransactionsBatch(TransactionsBatch),
+    /// `TransactionsResponse` message.
+    TransactionsResponse(TransactionsResponse),
+    /// `Disconnect` message.
+    Disconnect(Disconnect),
 }
 
 /// Consensus message.
@@ -81,6 +92,12 @@ pub enum RequestMessage {
     Peers(PeersRequest),
     /// Block request.
     Block(BlockRequest),
+    /// State snapshot request.
+    StateSnapshot(StateSnapshotRequest),
+    /// State chunk request.
+    StateChunk(StateChunkRequest),
+    /// Blocks request.
+    Blocks(BlocksRequest),
 }
 
 impl RequestMessage {
@@ -92,6 +109,9 @@ impl RequestMessage {
             RequestMessage::Prevotes(ref msg) => msg.from(),
             RequestMessage::Peers(ref msg) => msg.from(),
             RequestMessage::Block(ref msg) => msg.from(),
+            RequestMessage::StateSnapshot(ref msg) => msg.from(),
+            RequestMessage::StateChunk(ref msg) => msg.from(),
+            RequestMessage::Blocks(ref msg) => msg.from(),
         }
     }
 
@@ -103,6 +123,9 @@ impl RequestMessage {
             RequestMessage::Prevotes(ref msg) => msg.to(),
             RequestMessage::Peers(ref msg) => msg.to(),
             RequestMessage::Block(ref msg) => msg.to(),
+            RequestMessage::StateSnapshot(ref msg) => msg.to(),
+            RequestMessage::StateChunk(ref msg) => msg.to(),
+            RequestMessage::Blocks(ref msg) => msg.to(),
         }
     }
 
@@ -115,6 +138,9 @@ impl RequestMessage {
             RequestMessage::Prevotes(ref msg) => msg.verify_signature(public_key),
             RequestMessage::Peers(ref msg) => msg.verify_signature(public_key),
             RequestMessage::Block(ref msg) => msg.verify_signature(public_key),
+            RequestMessage::StateSnapshot(ref msg) => msg.verify_signature(public_key),
+            RequestMessage::StateChunk(ref msg) => msg.verify_signature(public_key),
+            RequestMessage::Blocks(ref msg) => msg.verify_signature(public_key),
         }
     }
 
@@ -126,6 +152,9 @@ impl RequestMessage {
             RequestMessage::Prevotes(ref msg) => msg.raw(),
             RequestMessage::Peers(ref msg) => msg.raw(),
             RequestMessage::Block(ref msg) => msg.raw(),
+            RequestMessage::StateSnapshot(ref msg) => msg.raw(),
+            RequestMessage::StateChunk(ref msg) => msg.raw(),
+            RequestMessage::Blocks(ref msg) => msg.raw(),
         }
     }
 }
@@ -138,6 +167,9 @@ impl fmt::Debug for RequestMessage {
             RequestMessage::Prevotes(ref msg) => write!(fmt, "{:?}", msg),
             RequestMessage::Peers(ref msg) => write!(fmt, "{:?}", msg),
             RequestMessage::Block(ref msg) => write!(fmt, "{:?}", msg),
+            RequestMessage::StateSnapshot(ref msg) => write!(fmt, "{:?}", msg),
+            RequestMessage::StateChunk(ref msg) => write!(fmt, "{:?}", msg),
+            RequestMessage::Blocks(ref msg) => write!(fmt, "{:?}", msg),
         }
     }
 }
@@ -208,6 +240,12 @@ impl Any {
                 CONNECT_MESSAGE_ID => Any::Connect(Connect::from_raw(raw)?),
                 STATUS_MESSAGE_ID => Any::Status(Status::from_raw(raw)?),
                 BLOCK_RESPONSE_MESSAGE_ID => Any::Block(BlockResponse::from_raw(raw)?),
+                STATE_SNAPSHOT_RESPONSE_MESSAGE_ID => {
+                    Any::StateSnapshot(StateSnapshotResponse::from_raw(raw)?)
+                }
+                STATE_CHUNK_RESPONSE_MESSAGE_ID => {
+                    Any::StateChunk(StateChunkResponse::from_raw(raw)?)
+                }
 
                 PROPOSE_MESSAGE_ID => {
                     Any::Consensus(ConsensusMessage::Propose(Propose::from_raw(raw)?))
@@ -234,6 +272,24 @@ impl Any {
                 BLOCK_REQUEST_MESSAGE_ID => {
                     Any::Request(RequestMessage::Block(BlockRequest::from_raw(raw)?))
                 }
+                STATE_SNAPSHOT_REQUEST_MESSAGE_ID => Any::Request(RequestMessage::StateSnapshot(
+                    StateSnapshotRequest::from_raw(raw)?,
+                )),
+                STATE_CHUNK_REQUEST_MESSAGE_ID => Any::Request(RequestMessage::StateChunk(
+                    StateChunkRequest::from_raw(raw)?,
+                )),
+                BLOCKS_REQUEST_MESSAGE_ID => {
+                    Any::Request(RequestMessage::Blocks(BlocksRequest::from_raw(raw)?))
+                }
+                // Core transactions are processed like transactions of the services.
+                REPORT_EVIDENCE_MESSAGE_ID => Any::Transaction(raw),
+                TRANSACTIONS_BATCH_MESSAGE_ID => {
+                    Any::TransactionsBatch(TransactionsBatch::from_raw(raw)?)
+                }
+                DISCONNECT_MESSAGE_ID => Any::Disconnect(Disconnect::from_raw(raw)?),
+                TRANSACTIONS_RESPONSE_MESSAGE_ID => {
+                    Any::TransactionsResponse(TransactionsResponse::from_raw(raw)?)
+                }
 
                 message_type => {
                     return Err(Error::IncorrectMessageType { message_type });
@@ -245,3 +301,37 @@ impl Any {
         Ok(msg)
     }
 }
+
+/// Class of a message used to limit the rate of the messages received from peers and
+/// to prioritize the processing of the consensus messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageClass {
+    /// Consensus messages, `Connect`, `Status` and responses to the requests.
+    Consensus,
+    /// Requests for the some data.
+    Requests,
+    /// Transactions.
+    Transactions,
+}
+
+impl MessageClass {
+    /// Returns the class of the message determined by its header.
+    pub fn of(raw: &RawMessage) -> MessageClass {
+        if raw.service_id() != CONSENSUS {
+            return MessageClass::Transactions;
+        }
+        match raw.message_type() {
+            PROPOSE_REQUEST_MESSAGE_ID |
+            TRANSACTIONS_REQUEST_MESSAGE_ID |
+            PREVOTES_REQUEST_MESSAGE_ID |
+            PEERS_REQUEST_MESSAGE_ID |
+            BLOCK_REQUEST_MESSAGE_ID |
+            STATE_SNAPSHOT_REQUEST_MESSAGE_ID |
+            STATE_CHUNK_REQUEST_MESSAGE_ID |
+            BLOCKS_REQUEST_MESSAGE_ID => MessageClass::Requests,
+            REPORT_EVIDENCE_MESSAGE_ID |
+            TRANSACTIONS_BATCH_MESSAGE_ID => MessageClass::Transactions,
+            _ => MessageClass::Consensus,
+        }
+    }
+}
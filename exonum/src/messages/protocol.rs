@@ -30,10 +30,10 @@ use chrono::{DateTime, Utc};
 
 use std::net::SocketAddr;
 
-use crypto::{Hash, PublicKey};
+use crypto::{Hash, PublicKey, PUBLIC_KEY_LENGTH, SIGNATURE_LENGTH};
 use blockchain;
 use helpers::{Height, Round, ValidatorId};
-use super::{ServiceMessage, RawMessage, BitVec};
+use super::{ServiceMessage, RawMessage, BitVec, HEADER_LENGTH};
 
 /// Consensus message type.
 pub const CONSENSUS: u16 = 0;
@@ -62,6 +62,37 @@ pub const PREVOTES_REQUEST_MESSAGE_ID: u16 = PrevotesRequest::MESSAGE_ID;
 pub const PEERS_REQUEST_MESSAGE_ID: u16 = PeersRequest::MESSAGE_ID;
 /// `BlockRequest` message id.
 pub const BLOCK_REQUEST_MESSAGE_ID: u16 = BlockRequest::MESSAGE_ID;
+/// `StateSnapshotRequest` message id.
+pub const STATE_SNAPSHOT_REQUEST_MESSAGE_ID: u16 = StateSnapshotRequest::MESSAGE_ID;
+/// `StateSnapshotResponse` message id.
+pub const STATE_SNAPSHOT_RESPONSE_MESSAGE_ID: u16 = StateSnapshotResponse::MESSAGE_ID;
+/// `StateChunkRequest` message id.
+pub const STATE_CHUNK_REQUEST_MESSAGE_ID: u16 = StateChunkRequest::MESSAGE_ID;
+/// `StateChunkResponse` message id.
+pub const STATE_CHUNK_RESPONSE_MESSAGE_ID: u16 = StateChunkResponse::MESSAGE_ID;
+/// `BlocksRequest` message id.
+pub const BLOCKS_REQUEST_MESSAGE_ID: u16 = BlocksRequest::MESSAGE_ID;
+/// `ReportEvidence` message id.
+pub const REPORT_EVIDENCE_MESSAGE_ID: u16 = ReportEvidence::MESSAGE_ID;
+/// `TransactionsBatch` message id.
+pub const TRANSACTIONS_BATCH_MESSAGE_ID: u16 = TransactionsBatch::MESSAGE_ID;
+/// `Disconnect` message id.
+pub const DISCONNECT_MESSAGE_ID: u16 = Disconnect::MESSAGE_ID;
+/// `TransactionsResponse` message id.
+pub const TRANSACTIONS_RESPONSE_MESSAGE_ID: u16 = TransactionsResponse::MESSAGE_ID;
+
+/// Length of a segment pointer in the serialized message.
+const SEGMENT_POINTER_LENGTH: usize = 8;
+
+encoding_struct! {
+    /// Raw storage entry transferred during the state sync.
+    struct StateEntry {
+        /// Key of the entry within the index column family.
+        key: &[u8],
+        /// Value of the entry.
+        value: &[u8],
+    }
+}
 
 
 messages! {
@@ -70,6 +101,8 @@ messages! {
     /// Connect to a node.
     ///
     /// ### Validation
+    /// The connection is rejected if the peer belongs to another network or
+    /// supports none of the protocol versions of the node.
     /// The message is ignored if its time is earlier than in the previous
     /// `Connect` message received from the same peer.
     ///
@@ -89,6 +122,12 @@ messages! {
         time: DateTime<Utc>,
         /// String containing information about this node including Exonum, Rust and OS versions.
         user_agent: &str,
+        /// Versions of the protocol supported by the node.
+        protocol_versions: &[u8],
+        /// Identifier of the network the node belongs to, i.e., the hash of its genesis block.
+        network_id: &Hash,
+        /// Whether the node is a read-only replica, which does not take part in the consensus.
+        is_replica: bool,
     }
 
 
@@ -100,7 +139,7 @@ messages! {
     ///
     /// ### Processing
     /// If the message's `height` number is bigger than a node's one, then
-    /// `BlockRequest` with current node's height is sent in reply.
+    /// `BlocksRequest` for the next heights is sent in reply.
     ///
     /// ### Generation
     /// `Status` message is broadcast regularly with the timeout controlled by
@@ -120,6 +159,9 @@ messages! {
     /// ### Validation
     /// The message is ignored if it
     ///     * contains incorrect `prev_hash`
+    ///     * contains `time` earlier than the time of the previous block or
+    ///       more than `ConsensusConfig::max_propose_time_drift` ahead of
+    ///       the node's time
     ///     * is sent by non-leader
     ///     * contains already committed transactions
     ///     * is already known
@@ -143,6 +185,8 @@ messages! {
         prev_hash: &Hash,
         /// The list of transactions to include in the next block.
         transactions: &[Hash],
+        /// Time of the proposed block.
+        time: DateTime<Utc>,
     }
 
     /// Pre-vote for a new block.
@@ -218,10 +262,14 @@ messages! {
     ///     parsed or verified
     ///
     /// ### Processing
-    /// The block is added to the blockchain.
+    /// The block is added to the blockchain. If the message contains only a part
+    /// of the block transactions, it is accumulated with the following messages
+    /// for the same block until all the transactions are received.
     ///
     /// ### Generation
-    /// The message is sent as response to `BlockRequest`.
+    /// The message is sent as response to `BlockRequest`. If the block does not
+    /// fit into `ConsensusConfig::max_message_len`, its transactions are split
+    /// between several messages in the order of the block.
     struct BlockResponse {
         /// The sender's public key.
         from: &PublicKey,
@@ -338,4 +386,243 @@ messages! {
         /// The height to which the message is related.
         height: Height,
     }
+
+    /// Request for the latest state snapshot of a node.
+    ///
+    /// ### Processing
+    /// `StateSnapshotResponse` message is sent as the response if the node
+    /// has a state snapshot.
+    ///
+    /// ### Generation
+    /// This message is sent by a node with the enabled state sync, which lags
+    /// behind its peers by more than the snapshot interval.
+    struct StateSnapshotRequest {
+        /// The sender's public key.
+        from: &PublicKey,
+        /// Public key of the recipient.
+        to: &PublicKey,
+    }
+
+    /// Block at the height of the state snapshot.
+    ///
+    /// ### Validation
+    /// The message is ignored if
+    ///     * its `to` field corresponds to a different node
+    ///     * the block is not higher than the node's one
+    ///     * the `precommits` are not given for the block
+    ///
+    /// ### Processing
+    /// The node starts downloading the state snapshot with `StateChunkRequest`s. The signatures
+    /// of the `precommits` are verified together with the downloaded state.
+    ///
+    /// ### Generation
+    /// The message is sent as response to `StateSnapshotRequest`.
+    struct StateSnapshotResponse {
+        /// The sender's public key.
+        from: &PublicKey,
+        /// Public key of the recipient.
+        to: &PublicKey,
+        /// Block header.
+        block: blockchain::Block,
+        /// List of pre-commits.
+        precommits: Vec<Precommit>,
+    }
+
+    /// Request for a chunk of the state snapshot.
+    ///
+    /// ### Validation
+    /// The message is ignored if the node has no snapshot at the given `height`.
+    ///
+    /// ### Processing
+    /// `StateChunkResponse` message with entries of the snapshot starting from
+    /// `from_key` of the `index_name` index is sent as the response.
+    ///
+    /// ### Generation
+    /// This message is sent during `StateSnapshotResponse` and
+    /// `StateChunkResponse` processing.
+    struct StateChunkRequest {
+        /// The sender's public key.
+        from: &PublicKey,
+        /// Public key of the recipient.
+        to: &PublicKey,
+        /// Height of the snapshot.
+        height: Height,
+        /// Name of the index the chunk starts from.
+        index_name: &str,
+        /// Key of the first entry in the chunk.
+        from_key: &[u8],
+    }
+
+    /// A chunk of the state snapshot.
+    ///
+    /// ### Validation
+    /// The message is ignored if
+    ///     * its `to` field corresponds to a different node
+    ///     * the chunk was not requested from the sender
+    ///
+    /// The sender is banned if the entries are not in the ascending order of keys or
+    /// the chunk is given for an index that is not transferred during the state sync.
+    ///
+    /// ### Processing
+    /// The entries are staged and the next chunk is requested. After the last chunk,
+    /// the state is verified and rebuilt, written to the storage and the node continues
+    /// with the block sync.
+    ///
+    /// ### Generation
+    /// The message is sent as response to `StateChunkRequest`.
+    struct StateChunkResponse {
+        /// The sender's public key.
+        from: &PublicKey,
+        /// Public key of the recipient.
+        to: &PublicKey,
+        /// Height of the snapshot.
+        height: Height,
+        /// Name of the index the chunk starts from.
+        index_name: &str,
+        /// Key of the first entry in the chunk.
+        from_key: &[u8],
+        /// Entries of the `index_name` index in the chunk.
+        entries: Vec<StateEntry>,
+        /// Name of the index the next chunk starts from. Empty for the last chunk.
+        next_index_name: &str,
+        /// Key of the first entry in the next chunk.
+        next_key: &[u8],
+    }
+
+    /// Request for `count` consecutive blocks starting from the given `height`.
+    ///
+    /// ### Validation
+    /// The message is ignored if its `height` is bigger than the node's one.
+    ///
+    /// ### Processing
+    /// A separate `BlockResponse` message is sent for each of the requested blocks
+    /// known to the node. The number of sent blocks is limited by
+    /// `MAX_BLOCKS_PER_REQUEST`.
+    ///
+    /// ### Generation
+    /// This message is sent by a node catching up with the other nodes' height.
+    struct BlocksRequest {
+        /// The sender's public key.
+        from: &PublicKey,
+        /// Public key of the recipient.
+        to: &PublicKey,
+        /// Height of the first requested block.
+        height: Height,
+        /// Number of the requested blocks.
+        count: u32,
+    }
+
+    /// Core transaction reporting the evidence of a validator that signed two
+    /// conflicting consensus messages.
+    ///
+    /// ### Validation
+    /// The transaction is incorrect if its signature or the evidence is invalid.
+    ///
+    /// ### Processing
+    /// The evidence is stored in the `evidence` index of the core schema if the
+    /// offender is a validator at the evidence height and the evidence is not
+    /// known yet.
+    ///
+    /// ### Generation
+    /// The transaction is created and broadcast by a node that has received
+    /// conflicting consensus messages.
+    struct ReportEvidence {
+        /// Consensus public key of the reporting node.
+        from: &PublicKey,
+        /// The evidence.
+        evidence: blockchain::Evidence,
+    }
+
+    /// A batch of transactions.
+    ///
+    /// ### Processing
+    /// Every transaction of the batch is processed as if it was received
+    /// separately.
+    ///
+    /// ### Generation
+    /// Transactions received by a node from its clients are accumulated for
+    /// `MemoryPoolConfig::tx_batch_timeout` and broadcast in a single batch.
+    /// The size of a batch is limited by `ConsensusConfig::max_message_len`.
+    struct TransactionsBatch {
+        /// The sender's public key.
+        from: &PublicKey,
+        /// The transactions.
+        transactions: Vec<RawMessage>,
+    }
+
+    /// Notice about the disconnection of the sender.
+    ///
+    /// ### Validation
+    /// The message is ignored if the sender is not connected or its time is
+    /// earlier than in the `Connect` message received from the sender.
+    ///
+    /// ### Processing
+    /// Disconnect from the sender without trying to reconnect to it. The sender
+    /// connects again by its `Connect` message.
+    ///
+    /// ### Generation
+    /// A node broadcasts `Disconnect` message when it shuts down.
+    struct Disconnect {
+        /// The sender's public key.
+        from: &PublicKey,
+        /// Time when the message was created.
+        time: DateTime<Utc>,
+    }
+
+    /// Transactions requested by the recipient.
+    ///
+    /// ### Validation
+    /// The message is ignored if its recipient is not the node.
+    ///
+    /// ### Processing
+    /// Every transaction of the response is processed as if it was received
+    /// separately. Responses to the outstanding requests are prioritized
+    /// like the consensus messages and are not rate limited.
+    ///
+    /// ### Generation
+    /// A node sends `TransactionsResponse` as a response to `TransactionsRequest`.
+    /// Transactions which do not fit into `ConsensusConfig::max_message_len`
+    /// are sent in several responses.
+    struct TransactionsResponse {
+        /// The sender's public key.
+        from: &PublicKey,
+        /// Public key of the recipient.
+        to: &PublicKey,
+        /// The requested transactions.
+        transactions: Vec<RawMessage>,
+    }
+}
+
+impl BlockResponse {
+    /// Returns `true` if the message contains all the transactions of the block.
+    pub fn is_complete(&self) -> bool {
+        self.transactions().len() as u64 == u64::from(self.block().tx_count())
+    }
+
+    /// Returns the number of bytes the transaction occupies in the message.
+    pub fn tx_len(tx: &RawMessage) -> usize {
+        SEGMENT_POINTER_LENGTH + tx.len()
+    }
+}
+
+impl TransactionsBatch {
+    /// Length of the message without transactions.
+    pub const EMPTY_LEN: usize = HEADER_LENGTH + PUBLIC_KEY_LENGTH + SEGMENT_POINTER_LENGTH +
+        SIGNATURE_LENGTH;
+
+    /// Returns the number of bytes the transaction occupies in the message.
+    pub fn tx_len(tx: &RawMessage) -> usize {
+        SEGMENT_POINTER_LENGTH + tx.len()
+    }
+}
+
+impl TransactionsResponse {
+    /// Length of the message without transactions.
+    pub const EMPTY_LEN: usize = HEADER_LENGTH + 2 * PUBLIC_KEY_LENGTH + SEGMENT_POINTER_LENGTH +
+        SIGNATURE_LENGTH;
+
+    /// Returns the number of bytes the transaction occupies in the message.
+    pub fn tx_len(tx: &RawMessage) -> usize {
+        SEGMENT_POINTER_LENGTH + tx.len()
+    }
 }
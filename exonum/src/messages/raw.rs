@@ -29,8 +29,23 @@ pub const HEADER_LENGTH: usize = 10;
 // TODO: Better name (ECR-166).
 #[doc(hidden)]
 pub const TEST_NETWORK_ID: u8 = 0;
-/// Version of the protocol. Different versions are incompatible.
-pub const PROTOCOL_MAJOR_VERSION: u8 = 0;
+/// Version of the protocol. Messages of a later version are rejected, while messages of
+/// the earlier versions are parsed as long as their layout has not changed, so the messages
+/// stored by the node remain readable. Peers communicate if they support a common version,
+/// see `SUPPORTED_PROTOCOL_VERSIONS`.
+///
+/// Version 1 adds the supported protocol versions, the network identifier and the replica
+/// flag to `Connect`, and the time of the block to `Propose`.
+pub const PROTOCOL_MAJOR_VERSION: u8 = 1;
+/// Versions of the protocol supported by the node. They are advertised in the `Connect`
+/// message, and connections with peers that support none of them are rejected.
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[u8] = &[PROTOCOL_MAJOR_VERSION];
+
+/// Returns the identifier of the network stamped into the headers of the messages
+/// signed by the nodes of the network with the given genesis block hash.
+pub fn network_id(genesis_hash: &Hash) -> u8 {
+    genesis_hash.as_ref()[0]
+}
 
 /// Thread-safe reference-counting pointer to the `MessageBuffer`.
 #[derive(Debug, Clone, PartialEq)]
@@ -51,6 +66,14 @@ impl RawMessage {
     pub fn hash(&self) -> Hash {
         hash(self.as_ref())
     }
+
+    /// Returns a copy of the message with the given network identifier in the header.
+    /// The header is signed, so the message has to be signed again.
+    pub fn with_network_id(&self, network_id: u8) -> RawMessage {
+        let mut raw = self.as_ref().to_vec();
+        raw[0] = network_id;
+        RawMessage::from_vec(raw)
+    }
 }
 
 impl Deref for RawMessage {
@@ -91,17 +91,16 @@ macro_rules! __ex_message {
                     });
                 }
 
-                // Check identifiers
-                if raw.version() != $crate::messages::PROTOCOL_MAJOR_VERSION {
+                // Check identifiers. Messages of the earlier protocol versions are accepted,
+                // so that the transactions stored before the version was bumped can be read;
+                // a message whose layout has changed since then fails the body check.
+                // The network identifier is checked by the node, since it depends on
+                // the network the message is received from.
+                if raw.version() > $crate::messages::PROTOCOL_MAJOR_VERSION {
                     return Err($crate::encoding::Error::UnsupportedProtocolVersion {
                         version: $crate::messages::PROTOCOL_MAJOR_VERSION
                     });
                 }
-                if raw.network_id() != $crate::messages::TEST_NETWORK_ID {
-                    return Err($crate::encoding::Error::IncorrectNetworkId {
-                        network_id: $crate::messages::TEST_NETWORK_ID
-                    });
-                }
                 if raw.message_type() != <Self as $crate::messages::ServiceMessage>::MESSAGE_ID {
                     return Err($crate::encoding::Error::IncorrectMessageType {
                         message_type: <Self as $crate::messages::ServiceMessage>::MESSAGE_ID
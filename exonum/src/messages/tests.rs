@@ -47,12 +47,15 @@ fn test_unsupported_version() {
 }
 
 #[test]
-#[should_panic(expected = "IncorrectNetworkId")]
-fn test_incorrect_network_id() {
-    let tx = TxSimple::new_with_signature(&PublicKey::zero(), "My little pony", &Signature::zero());
-    let mut vec = tx.as_ref().as_ref().to_vec();
-    vec[0] = 128;
-    let _msg = TxSimple::from_raw(RawMessage::from_vec(vec)).unwrap();
+fn test_network_id_is_signed() {
+    let (public_key, secret_key) = gen_keypair();
+    let tx = TxSimple::new(&public_key, "My little pony", &secret_key);
+    let raw = tx.raw().with_network_id(128);
+    assert_eq!(raw.network_id(), 128);
+    assert!(!raw.verify_signature(&public_key));
+
+    let tx = TxSimple::from_raw(raw).unwrap();
+    assert_eq!(tx.msg(), "My little pony");
 }
 
 #[test]
@@ -12,41 +12,90 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::fs;
 use std::net::SocketAddr;
 use std::error::Error;
+use std::path::Path;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
 
+use failure;
 use rand::{self, Rng};
+use toml;
 
+use crypto::{PublicKey, Signature};
+use events::NetworkRequest;
+use events::error::LogError;
+use events::network::check_connect;
 use messages::{Any, RawMessage, Connect, Status, Message, PeersRequest};
 use helpers::Height;
-use super::{NodeHandler, RequestData};
+use helpers::config::ConfigFile;
+use super::{NodeHandler, PeerBehavior, RequestData, SignedAction, Whitelist};
 
 impl NodeHandler {
     /// Redirects message to the corresponding `handle_...` function.
     pub fn handle_message(&mut self, raw: RawMessage) {
-        // TODO: check message headers (network id, protocol version)
+        // TODO: check message headers (protocol version)
         // FIXME: call message.verify method
         //     if !raw.verify() {
         //         return;
         //     }
 
+        if self.is_foreign_message(&raw) {
+            warn!(
+                "Received a message of the network {} from {:?}",
+                raw.network_id(),
+                self.message_source
+            );
+            return;
+        }
+
         match Any::from_raw(raw) {
             Ok(Any::Connect(msg)) => self.handle_connect(msg),
             Ok(Any::Status(msg)) => self.handle_status(&msg),
             Ok(Any::Consensus(msg)) => self.handle_consensus(msg),
             Ok(Any::Request(msg)) => self.handle_request(msg),
             Ok(Any::Block(msg)) => self.handle_block(&msg),
+            Ok(Any::StateSnapshot(msg)) => self.handle_state_snapshot(&msg),
+            Ok(Any::StateChunk(msg)) => self.handle_state_chunk(&msg),
             Ok(Any::Transaction(msg)) => self.handle_tx(msg),
+            Ok(Any::TransactionsBatch(msg)) => self.handle_txs_batch(&msg),
+            Ok(Any::TransactionsResponse(msg)) => self.handle_txs_response(&msg),
+            Ok(Any::Disconnect(msg)) => self.handle_disconnect(&msg),
             Err(err) => {
                 error!("Invalid message received: {:?}", err.description());
+                self.penalize_message_source(PeerBehavior::MalformedMessage);
+            }
+        }
+    }
+
+    /// Handles the message received over the connection from the given address. Messages
+    /// of banned peers are ignored.
+    pub fn handle_received_message(&mut self, addr: SocketAddr, raw: RawMessage) {
+        let source = self.state.reputation().peer_by_connection(&addr).cloned();
+        if let Some(ref peer) = source {
+            let now = self.system_state.current_time();
+            if self.state.reputation().is_banned(peer, now) {
+                trace!("Ignoring a message from the banned peer {:?}", peer);
+                return;
             }
         }
+        self.message_source = source;
+        self.handle_message(raw);
+        self.message_source = None;
     }
 
     /// Handles the `Connected` event. Node's `Connect` message is sent as response
     /// if received `Connect` message is correct.
     pub fn handle_connected(&mut self, addr: SocketAddr, connect: Connect) {
         info!("Received Connect message from peer: {}", addr);
+        if connect.verify_signature(connect.pub_key()) {
+            self.state.reputation_mut().add_connection(
+                addr,
+                *connect.pub_key(),
+            );
+        }
         self.handle_connect(connect);
     }
 
@@ -55,6 +104,7 @@ impl NodeHandler {
     pub fn handle_disconnected(&mut self, addr: SocketAddr) {
         info!("Disconnected from: {}", addr);
         self.remove_peer_with_addr(addr);
+        self.confirm_disconnection(&addr);
     }
 
     /// Handles the `UnableConnectToPeer` event. Node will try to connect to that address again
@@ -62,13 +112,14 @@ impl NodeHandler {
     pub fn handle_unable_to_connect(&mut self, addr: SocketAddr) {
         info!("Could not connect to: {}", addr);
         self.remove_peer_with_addr(addr);
+        self.confirm_disconnection(&addr);
     }
 
     /// Removes peer from the state and from the cache. Node will try to connect to that address
     /// again if it was in the validators list.
     fn remove_peer_with_addr(&mut self, addr: SocketAddr) {
         let need_reconnect = self.state.remove_peer_with_addr(&addr);
-        if need_reconnect {
+        if need_reconnect && !self.is_shutting_down() {
             self.connect(&addr);
         }
         self.blockchain.remove_peer_with_addr(&addr);
@@ -77,6 +128,10 @@ impl NodeHandler {
     /// Handles the `Connect` message and connects to a peer as result.
     pub fn handle_connect(&mut self, message: Connect) {
         // TODO add spam protection (ECR-170)
+        if self.is_shutting_down() {
+            trace!("Ignoring Connect message because the node is shutting down.");
+            return;
+        }
         let address = message.addr();
         if address == self.state.our_connect_message().addr() {
             trace!("Received Connect with same address as our external_address.");
@@ -89,6 +144,11 @@ impl NodeHandler {
             return;
         }
 
+        if let Err(e) = check_connect(&message, self.state.our_connect_message().network_id()) {
+            warn!("Received incompatible Connect message from {:?}: {}", pub_key, e);
+            return;
+        }
+
         if !self.state.whitelist().allow(message.pub_key()) {
             error!(
                 "Received connect message from {:?} peer which not in whitelist.",
@@ -97,12 +157,22 @@ impl NodeHandler {
             return;
         }
 
+        let now = self.system_state.current_time();
+        if self.state.reputation().is_banned(message.pub_key(), now) {
+            warn!(
+                "Received connect message from banned peer {:?}.",
+                message.pub_key()
+            );
+            return;
+        }
+
         let public_key = *message.pub_key();
         if !message.verify_signature(&public_key) {
             error!(
                 "Received connect-message with incorrect signature, msg={:?}",
                 message
             );
+            self.penalize_message_source(PeerBehavior::InvalidSignature);
             return;
         }
 
@@ -133,9 +203,12 @@ impl NodeHandler {
             info!("Send Connect message to {}", address);
             self.connect(&address);
         }
+        if self.is_replica() {
+            self.send_forwarded_txs();
+        }
     }
 
-    /// Handles the `Status` message. Node sends `BlockRequest` as response if height in the
+    /// Handles the `Status` message. Node sends `BlocksRequest` as response if height in the
     /// message is higher than node's height.
     pub fn handle_status(&mut self, msg: &Status) {
         let height = self.state.height();
@@ -162,6 +235,7 @@ impl NodeHandler {
                     "Received status message with incorrect signature, msg={:?}",
                     msg
                 );
+                self.penalize_message_source(PeerBehavior::InvalidSignature);
                 return;
             }
 
@@ -171,8 +245,16 @@ impl NodeHandler {
                 self.state.set_node_height(*peer, msg.height());
             }
 
-            // Request block
-            self.request(RequestData::Block(height), *peer);
+            if self.state.state_sync_block().is_some() {
+                return;
+            }
+            if self.need_state_sync(msg.height()) {
+                self.request(RequestData::StateSnapshot, *peer);
+                return;
+            }
+
+            // Request blocks
+            self.request_next_block();
         }
     }
 
@@ -214,13 +296,15 @@ impl NodeHandler {
                 .nth(gen_peer_id())
                 .unwrap();
             let peer = peer.clone();
-            let msg = PeersRequest::new(
+            let msg = PeersRequest::new_with_signature(
                 self.state.consensus_public_key(),
                 peer.pub_key(),
-                self.state.consensus_secret_key(),
+                &Signature::zero(),
             );
-            trace!("Request peers from peer with addr {:?}", peer.addr());
-            self.send_to_peer(*peer.pub_key(), msg.raw());
+            if let Some(msg) = self.sign(&msg, SignedAction::Send(*peer.pub_key())) {
+                trace!("Request peers from peer with addr {:?}", peer.addr());
+                self.send_to_peer(*peer.pub_key(), msg.raw());
+            }
         }
         self.add_peer_exchange_timeout();
     }
@@ -231,16 +315,124 @@ impl NodeHandler {
         self.add_update_api_state_timeout();
     }
 
+    /// Updates the reputation of the peer according to its behavior. If the peer gets
+    /// banned, the node disconnects from it and asks the network part to reject its
+    /// connections until the ban expires.
+    pub fn update_reputation(&mut self, peer: &PublicKey, behavior: PeerBehavior) {
+        let now = self.system_state.current_time();
+        if self.state.reputation_mut().update(peer, behavior, now) {
+            warn!("Peer {:?} is banned because of its low reputation", peer);
+            self.handle_peer_ban(peer);
+        }
+    }
+
+    /// Bans the peer regardless of its reputation, e.g., for sending the data that cannot
+    /// be sent by an honest peer.
+    pub fn ban_peer(&mut self, peer: &PublicKey) {
+        let now = self.system_state.current_time();
+        if self.state.reputation_mut().ban(peer, now) {
+            warn!("Peer {:?} is banned because of its misbehavior", peer);
+            self.handle_peer_ban(peer);
+        }
+    }
+
+    fn handle_peer_ban(&mut self, peer: &PublicKey) {
+        let duration = Duration::from_millis(self.state.reputation().config().ban_duration);
+        let request = NetworkRequest::BanPeer(*peer, duration);
+        self.channel.network_requests.send(request).log_error();
+        self.disconnect_peer(peer);
+    }
+
+    /// Disconnects from the peer if it is connected. The network part closes the incoming
+    /// connections of the peer unless it is allowed by the whitelist, so the peer can
+    /// connect again if it is allowed.
+    pub fn disconnect_peer(&mut self, peer: &PublicKey) {
+        let address = self.state.peers().get(peer).map(Connect::addr);
+        if let Some(address) = address {
+            info!("Disconnect from peer {:?} at {}", peer, address);
+            self.state.remove_peer_with_addr(&address);
+            self.blockchain.remove_peer_with_addr(&address);
+            let request = NetworkRequest::DisconnectWithPeer(address);
+            self.channel.network_requests.send(request).log_error();
+        }
+    }
+
+    /// Adds the peer to the whitelist and saves the whitelist to the node configuration.
+    pub fn handle_whitelist_add(&mut self, peer: PublicKey) {
+        info!("Add peer {:?} to the whitelist", peer);
+        self.state.whitelist().write().add(peer);
+        self.save_whitelist();
+    }
+
+    /// Removes the peer from the whitelist and saves the whitelist to the node configuration.
+    /// The node disconnects from the peer if it is not allowed to connect anymore.
+    pub fn handle_whitelist_remove(&mut self, peer: &PublicKey) {
+        if !self.state.whitelist().write().remove(peer) {
+            info!("Peer {:?} is not in the whitelist", peer);
+            return;
+        }
+        info!("Remove peer {:?} from the whitelist", peer);
+        if !self.state.whitelist().allow(peer) {
+            self.disconnect_peer(peer);
+        }
+        self.save_whitelist();
+    }
+
+    // Saves the whitelist to the node configuration file in a separate thread,
+    // if the file is known.
+    fn save_whitelist(&self) {
+        let path = match self.config_path {
+            Some(ref path) => path.clone(),
+            None => return,
+        };
+        let whitelist = self.state.whitelist().clone();
+        let lock = Arc::clone(&self.config_file_lock);
+        thread::spawn(move || {
+            // Writes are serialized and each of them takes the current whitelist,
+            // so the file ends up with the latest changes.
+            let _guard = lock.lock().expect("Config file lock is poisoned");
+            let whitelist = whitelist.read().clone();
+            if let Err(e) = save_whitelist(&path, &whitelist) {
+                error!("Unable to save the whitelist to {}: {}", path.display(), e);
+            }
+        });
+    }
+
+    /// Updates the reputation of the peer over whose connection the message being handled
+    /// has been received, if the peer is known.
+    pub(crate) fn penalize_message_source(&mut self, behavior: PeerBehavior) {
+        if let Some(peer) = self.message_source {
+            self.update_reputation(&peer, behavior);
+        }
+    }
+
     /// Broadcasts the `Status` message to all peers.
     pub fn broadcast_status(&mut self) {
         let hash = self.blockchain.last_hash();
-        let status = Status::new(
+        let status = Status::new_with_signature(
             self.state.consensus_public_key(),
             self.state.height(),
             &hash,
-            self.state.consensus_secret_key(),
+            &Signature::zero(),
         );
-        trace!("Broadcast status: {:?}", status);
-        self.broadcast(status.raw());
+        if let Some(status) = self.sign(&status, SignedAction::Broadcast) {
+            trace!("Broadcast status: {:?}", status);
+            self.broadcast(status.raw());
+        }
+    }
+}
+
+// Replaces the whitelist in the node configuration file, leaving other parts
+// of the configuration intact. The configuration is written to a temporary file
+// first, so the file is never left partially written.
+fn save_whitelist(path: &Path, whitelist: &Whitelist) -> Result<(), failure::Error> {
+    let mut config: toml::Value = ConfigFile::load(path)?;
+    if let Some(table) = config.as_table_mut() {
+        table.insert("whitelist".to_owned(), toml::Value::try_from(whitelist)?);
     }
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(".tmp");
+    ConfigFile::save(&config, &temp_path)?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
 }
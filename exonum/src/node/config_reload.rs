@@ -0,0 +1,248 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reload of the node configuration without restarting the node.
+//!
+//! The following parameters of `NodeConfig` are applied to the running node:
+//!
+//! - `peers`: the node connects to the added peers;
+//! - `whitelist`: the node disconnects from the peers which are not allowed anymore;
+//! - `network`: the connection limits, TCP options and connect retries are used for
+//!   the new connections;
+//! - `api.allow_origin`: CORS options are used for the subsequent requests;
+//! - `log_level`: the maximum level of the log messages;
+//! - `shutdown_timeout`: the timeout of the subsequent graceful shutdown.
+//!
+//! Changes of any other parameter require restart of the node, so a configuration with
+//! such changes is rejected as a whole.
+
+use std::collections::BTreeSet;
+use std::path::Path;
+
+use futures::sync::oneshot;
+use log::{self, LevelFilter};
+use serde_json::{self, Value};
+
+use crypto::PublicKey;
+use events::NetworkRequest;
+use events::error::LogError;
+use helpers::config::ConfigFile;
+use super::{NodeConfig, NodeHandler};
+
+/// Errors that may occur while reloading the node configuration.
+#[derive(Debug, Fail)]
+pub enum ConfigReloadError {
+    /// The node configuration file is not known or cannot be loaded.
+    #[fail(display = "Unable to load the node configuration: {}", _0)]
+    Load(String),
+    /// The node configuration contains invalid values.
+    #[fail(display = "Invalid node configuration: {}", _0)]
+    Invalid(String),
+    /// The node configuration changes the parameters which cannot be applied
+    /// to the running node.
+    #[fail(display = "Changes of the following parameters require restart of the node: {}",
+           _0)]
+    RestartRequired(String),
+}
+
+/// Parses the maximum level of the log messages.
+pub fn parse_log_level(level: &str) -> Result<LevelFilter, ConfigReloadError> {
+    level.parse().map_err(|_| {
+        ConfigReloadError::Invalid(format!("Unknown log level: {}", level))
+    })
+}
+
+/// Checks that the new configuration differs from the current one only in the parameters
+/// which can be applied to the running node.
+pub fn check_reloadable(current: &NodeConfig, new: &NodeConfig) -> Result<(), ConfigReloadError> {
+    if let Some(ref level) = new.log_level {
+        parse_log_level(level)?;
+    }
+
+    let mut normalized = new.clone();
+    normalized.peers = current.peers.clone();
+    normalized.whitelist = current.whitelist.clone();
+    normalized.api.allow_origin = current.api.allow_origin.clone();
+    normalized.log_level = current.log_level.clone();
+    normalized.shutdown_timeout = current.shutdown_timeout;
+    normalized.network.max_incoming_connections = current.network.max_incoming_connections;
+    normalized.network.max_outgoing_connections = current.network.max_outgoing_connections;
+    normalized.network.tcp_nodelay = current.network.tcp_nodelay;
+    normalized.network.tcp_keep_alive = current.network.tcp_keep_alive;
+    normalized.network.tcp_connect_retry_timeout = current.network.tcp_connect_retry_timeout;
+    normalized.network.tcp_connect_max_retries = current.network.tcp_connect_max_retries;
+
+    let to_value = |config: &NodeConfig| {
+        serde_json::to_value(config).map_err(|e| ConfigReloadError::Invalid(e.to_string()))
+    };
+    let mut changes = Vec::new();
+    diff_paths("", &to_value(current)?, &to_value(&normalized)?, &mut changes);
+    if changes.is_empty() {
+        Ok(())
+    } else {
+        Err(ConfigReloadError::RestartRequired(changes.join(", ")))
+    }
+}
+
+// Collects the paths of the differing values of two JSON documents.
+fn diff_paths(prefix: &str, a: &Value, b: &Value, paths: &mut Vec<String>) {
+    match (a, b) {
+        (&Value::Object(ref a), &Value::Object(ref b)) => {
+            let keys = a.keys().chain(b.keys()).collect::<BTreeSet<_>>();
+            for key in keys {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", prefix, key)
+                };
+                match (a.get(key), b.get(key)) {
+                    (Some(a), Some(b)) => diff_paths(&path, a, b, paths),
+                    _ => paths.push(path),
+                }
+            }
+        }
+        _ => {
+            if a != b {
+                paths.push(prefix.to_owned());
+            }
+        }
+    }
+}
+
+impl NodeHandler {
+    /// Handles the request to reload the node configuration from the configuration file
+    /// and sends the result to the requester.
+    pub fn handle_reload_config(
+        &mut self,
+        result_tx: oneshot::Sender<Result<(), ConfigReloadError>>,
+    ) {
+        let result = self.reload_config();
+        match result {
+            Ok(()) => info!("Node configuration is reloaded"),
+            Err(ref e) => warn!("{}", e),
+        }
+        // The requester may have gone away.
+        let _ = result_tx.send(result);
+    }
+
+    // Loads the node configuration from the configuration file and applies it.
+    fn reload_config(&mut self) -> Result<(), ConfigReloadError> {
+        let new_config = {
+            let path = self.config_path.as_ref().ok_or_else(|| {
+                ConfigReloadError::Load("the configuration file is not known".to_owned())
+            })?;
+            load_config(path)?
+        };
+        {
+            let current_config = self.node_config.as_ref().ok_or_else(|| {
+                ConfigReloadError::Load("the running configuration is not known".to_owned())
+            })?;
+            check_reloadable(current_config, &new_config)?;
+        }
+        self.apply_config(new_config);
+        Ok(())
+    }
+
+    // Applies the changes of the parameters which do not require restart of the node.
+    fn apply_config(&mut self, config: NodeConfig) {
+        for address in &config.peers {
+            if !self.peer_discovery.contains(address) {
+                self.connect(address);
+            }
+        }
+        self.peer_discovery = config.peers.clone();
+
+        self.state.whitelist().write().replace_peers(&config.whitelist);
+        let disallowed_peers: Vec<PublicKey> = {
+            let whitelist = self.state.whitelist().read();
+            self.state
+                .peers()
+                .keys()
+                .filter(|peer| !whitelist.allow(peer))
+                .cloned()
+                .collect()
+        };
+        for peer in &disallowed_peers {
+            self.disconnect_peer(peer);
+        }
+
+        let request = NetworkRequest::UpdateConfiguration(config.network);
+        self.channel.network_requests.send(request).log_error();
+
+        self.api_state.set_allow_origin(config.api.allow_origin.clone());
+
+        if let Some(ref level) = config.log_level {
+            if let Ok(level) = parse_log_level(level) {
+                log::set_max_level(level);
+            }
+        }
+
+        self.node_config = Some(config);
+    }
+}
+
+fn load_config(path: &Path) -> Result<NodeConfig, ConfigReloadError> {
+    ConfigFile::load(path).map_err(|e| ConfigReloadError::Load(e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crypto::gen_keypair;
+    use helpers::generate_testnet_config;
+    use node::AllowOrigin;
+    use events::network::EncryptionMode;
+    use super::{check_reloadable, ConfigReloadError};
+
+    #[test]
+    fn test_reloadable_changes() {
+        let current = generate_testnet_config(1, 16_300).remove(0);
+        let mut new = current.clone();
+        new.peers.push("127.0.0.1:16400".parse().unwrap());
+        new.whitelist.add(gen_keypair().0);
+        new.api.allow_origin = Some(AllowOrigin::Any);
+        new.network.max_incoming_connections = 16;
+        new.network.tcp_keep_alive = Some(10_000);
+        new.log_level = Some("debug".to_owned());
+        assert!(check_reloadable(&current, &new).is_ok());
+    }
+
+    #[test]
+    fn test_restart_required_changes() {
+        let current = generate_testnet_config(1, 16_310).remove(0);
+        let mut new = current.clone();
+        new.listen_address = "127.0.0.1:16410".parse().unwrap();
+        new.api.public_api_address = Some("127.0.0.1:16411".parse().unwrap());
+        new.network.encryption = EncryptionMode::Required;
+        new.peers.push("127.0.0.1:16412".parse().unwrap());
+
+        match check_reloadable(&current, &new) {
+            Err(ConfigReloadError::RestartRequired(changes)) => assert_eq!(
+                changes,
+                "api.public_api_address, listen_address, network.encryption"
+            ),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_invalid_log_level() {
+        let current = generate_testnet_config(1, 16_320).remove(0);
+        let mut new = current.clone();
+        new.log_level = Some("verbose".to_owned());
+        match check_reloadable(&current, &new) {
+            Err(ConfigReloadError::Invalid(_)) => {}
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
+}
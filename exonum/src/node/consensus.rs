@@ -12,15 +12,23 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::cmp;
 use std::collections::HashSet;
+use std::mem;
+use std::time::Duration;
 
-use crypto::{Hash, CryptoHash, PublicKey};
-use blockchain::{Schema, Transaction};
-use messages::{BlockRequest, BlockResponse, ConsensusMessage, Message, Precommit, Prevote,
-               PrevotesRequest, Propose, ProposeRequest, RawTransaction, TransactionsRequest};
+use chrono::{DateTime, Utc};
+
+use crypto::{Hash, CryptoHash, PublicKey, Signature};
+use blockchain::{ConsensusLock, Evidence, Schema, Transaction, TxPoolError};
+use messages::{Any, BlockRequest, BlockResponse, BlocksRequest, ConsensusMessage, Message,
+               Precommit, Prevote, PrevotesRequest, Propose, ProposeRequest, RawMessage,
+               RawTransaction, ReportEvidence, StateChunkRequest, StateSnapshotRequest,
+               TransactionsBatch, TransactionsRequest, TransactionsResponse};
 use helpers::{Height, Round, ValidatorId};
 use storage::Patch;
-use node::{NodeHandler, RequestData};
+use node::{NodeHandler, NodeTimeout, PeerBehavior, RequestData, SignedAction};
+use node::state::{BLOCK_SYNC_RANGE, BLOCK_SYNC_WINDOW};
 use events::InternalRequest;
 use std::error::Error;
 
@@ -36,6 +44,10 @@ impl NodeHandler {
             );
             return;
         }
+        if self.is_replica() {
+            trace!("Ignoring a consensus message {:?} because the node is a replica", msg);
+            return;
+        }
 
         // Ignore messages from previous and future height
         if msg.height() < self.state.height() || msg.height() > self.state.height().next() {
@@ -77,6 +89,7 @@ impl NodeHandler {
                         "Received consensus message with incorrect signature, msg={:?}",
                         msg
                     );
+                    self.penalize_message_source(PeerBehavior::InvalidSignature);
                     return;
                 }
                 public_key
@@ -87,6 +100,10 @@ impl NodeHandler {
             }
         };
 
+        if let Some(evidence) = self.state.add_signed_message(&msg) {
+            self.report_evidence(evidence);
+        }
+
         trace!("Handle message={:?}", msg);
         match msg {
             ConsensusMessage::Propose(msg) => self.handle_propose(key, &msg),
@@ -95,6 +112,25 @@ impl NodeHandler {
         }
     }
 
+    /// Broadcasts the `ReportEvidence` transaction with the evidence of a validator that has
+    /// signed conflicting consensus messages.
+    pub fn report_evidence(&mut self, evidence: Evidence) {
+        warn!(
+            "Validator {} has signed conflicting consensus messages at height {}, round {}",
+            evidence.validator().to_hex(),
+            evidence.height(),
+            evidence.round()
+        );
+        let tx = ReportEvidence::new_with_signature(
+            self.state.consensus_public_key(),
+            evidence,
+            &Signature::zero(),
+        );
+        if let Some(tx) = self.sign(&tx, SignedAction::Transaction) {
+            self.handle_incoming_tx(Box::new(tx));
+        }
+    }
+
     /// Handles the `Propose` message. For details see the message documentation.
     pub fn handle_propose(&mut self, from: PublicKey, msg: &Propose) {
         debug_assert_eq!(
@@ -108,6 +144,21 @@ impl NodeHandler {
             return;
         }
 
+        // Check time
+        if msg.time() < self.blockchain.last_block().time() {
+            error!(
+                "Received propose with time earlier than the time of the last block, msg={:?}",
+                msg
+            );
+            return;
+        }
+        let max_drift = self.state.consensus_config().max_propose_time_drift;
+        let max_time = self.system_state.current_time() + Duration::from_millis(max_drift);
+        if msg.time() > DateTime::<Utc>::from(max_time) {
+            warn!("Received propose with time too far in the future, msg={:?}", msg);
+            return;
+        }
+
         // Check leader
         if msg.validator() != self.state.leader(msg.round()) {
             error!(
@@ -213,19 +264,69 @@ impl NodeHandler {
 
         if !msg.verify_signature(msg.from()) {
             error!("Received block with incorrect signature, msg={:?}", msg);
+            self.penalize_message_source(PeerBehavior::InvalidSignature);
             return;
         }
 
         trace!("Handle block");
 
-        let block = msg.block();
-        let block_hash = block.hash();
+        let height = msg.block().height();
+        if height < self.state.height() {
+            return;
+        }
+
+        // Transactions of a large block are received in several messages. Only the parts
+        // of the blocks requested from the peer are accumulated.
+        let msg = if msg.is_complete() {
+            msg.clone()
+        } else {
+            if msg.block().tx_count() > self.txs_block_limit() {
+                warn!(
+                    "Received block with too many transactions, msg={:?}",
+                    msg.block()
+                );
+                return;
+            }
+            if !self.state.is_requested_from(&RequestData::Block(height), msg.from()) {
+                trace!("Received part of the block that is not requested, msg={:?}", msg);
+                return;
+            }
+            match self.state.add_block_chunk(msg.clone()) {
+                Some(msg) => msg,
+                None => return,
+            }
+        };
 
-        // TODO add block with greater height to queue (ECR-171)
-        if self.state.height() != block.height() {
+        if height > self.state.height() {
+            // Blocks of the next heights are queued until the previous blocks are committed.
+            let data = RequestData::Block(height);
+            if self.state.is_requested(&data) {
+                self.remove_request(&data);
+                self.state.add_pending_block(msg);
+            }
             return;
         }
 
+        let mut next = Some(msg);
+        while let Some(msg) = next {
+            if !self.commit_block(&msg) {
+                self.state.add_invalid_block(msg.from());
+                self.update_reputation(msg.from(), PeerBehavior::InvalidBlock);
+                break;
+            }
+            self.update_reputation(msg.from(), PeerBehavior::UsefulResponse);
+            let height = self.state.height();
+            next = self.state.take_pending_block(height);
+        }
+        self.request_next_block();
+    }
+
+    // Validates the block of the current height and commits it. Returns `false` if the block
+    // is invalid.
+    fn commit_block(&mut self, msg: &BlockResponse) -> bool {
+        let block = msg.block();
+        let block_hash = block.hash();
+
         // Check block content
         if block.prev_hash() != &self.last_block_hash() {
             error!(
@@ -235,12 +336,12 @@ impl NodeHandler {
                 *block.prev_hash(),
                 self.last_block_hash()
             );
-            return;
+            return false;
         }
 
         if let Err(err) = self.verify_precommits(&msg.precommits(), &block_hash, block.height()) {
             error!("{}, block={:?}", err, msg);
-            return;
+            return false;
         }
 
         if self.state.block(&block_hash).is_none() {
@@ -252,11 +353,15 @@ impl NodeHandler {
                 );
                 res.0
             } else {
-                return;
+                return false;
             };
 
-            let (block_hash, patch) =
-                self.create_block(block.proposer_id(), block.height(), tx_hashes.as_slice());
+            let (block_hash, patch) = self.create_block(
+                block.proposer_id(),
+                block.height(),
+                block.time(),
+                tx_hashes.as_slice(),
+            );
             // Verify block_hash
             if block_hash != block.hash() {
                 panic!(
@@ -275,14 +380,53 @@ impl NodeHandler {
             );
         }
         self.commit(block_hash, msg.precommits().iter(), None);
-        self.request_next_block();
+        true
+    }
+
+    // Returns `true` if the total length of the transactions of the known propose exceeds
+    // `max_block_size`.
+    fn exceeds_max_block_size(&self, propose_hash: &Hash) -> bool {
+        let max_size = match self.state.consensus_config().max_block_size {
+            Some(max_size) => max_size as usize,
+            None => return false,
+        };
+        let propose = match self.state.propose(propose_hash) {
+            Some(propose_state) => propose_state.message(),
+            None => return false,
+        };
+        let snapshot = self.blockchain.snapshot();
+        let transactions = Schema::new(&snapshot).transactions();
+        let size: usize = propose
+            .transactions()
+            .iter()
+            .map(|hash| transactions.get(hash).map_or(0, |tx| tx.len()))
+            .sum();
+        size > max_size
+    }
+
+    // Panics if the propose precommitted by the majority of the validators exceeds
+    // `max_block_size`, since the honest validators never vote for such a propose.
+    fn check_committed_block_size(&self, propose_hash: &Hash) {
+        if self.exceeds_max_block_size(propose_hash) {
+            panic!(
+                "Propose exceeding the maximum block size is precommitted, propose_hash={:?}. \
+                 Either a node's implementation is incorrect or validators majority works \
+                 incorrectly",
+                propose_hash
+            );
+        }
     }
 
     /// Executes and commits block. This function is called when node has full propose information.
     pub fn has_full_propose(&mut self, hash: Hash, propose_round: Round) {
         // Send prevote
         if self.state.locked_round() == Round::zero() {
-            if self.state.is_validator() && !self.state.have_prevote(propose_round) {
+            if self.exceeds_max_block_size(&hash) {
+                error!(
+                    "Received propose exceeding the maximum block size, propose_hash={:?}",
+                    hash
+                );
+            } else if self.state.is_validator() && !self.state.have_prevote(propose_round) {
                 self.broadcast_prevote(propose_round, &hash);
             } else {
                 // TODO: what if we HAVE prevote for the propose round (ECR-171)?
@@ -300,6 +444,7 @@ impl NodeHandler {
 
         // Commit propose
         for (round, block_hash) in self.state.unknown_propose_with_precommits(&hash) {
+            self.check_committed_block_size(&hash);
             // Execute block and get state hash
             let our_block_hash = self.execute(&hash);
 
@@ -390,6 +535,7 @@ impl NodeHandler {
             return;
         }
 
+        self.check_committed_block_size(propose_hash);
         // Execute block and get state hash
         let our_block_hash = self.execute(propose_hash);
         assert_eq!(
@@ -406,6 +552,14 @@ impl NodeHandler {
     /// Locks node to the specified round, so pre-votes for the lower round will be ignored.
     pub fn lock(&mut self, prevote_round: Round, propose_hash: Hash) {
         trace!("MAKE LOCK {:?} {:?}", prevote_round, propose_hash);
+        if self.exceeds_max_block_size(&propose_hash) {
+            error!(
+                "Refusing to lock to the propose exceeding the maximum block size, \
+                 propose_hash={:?}",
+                propose_hash
+            );
+            return;
+        }
         for round in prevote_round.iter_to(self.state.round().next()) {
             // Send prevotes
             if self.state.is_validator() && !self.state.have_prevote(round) {
@@ -422,7 +576,9 @@ impl NodeHandler {
                     .iter()
                     .map(|msg| msg.raw().clone())
                     .collect::<Vec<_>>();
-                self.blockchain.save_messages(round, raw_messages);
+                // Record the lock in the write-ahead log before any vote is sent under it.
+                let lock = ConsensusLock::new(self.state.height(), round, &propose_hash);
+                self.blockchain.save_lock(lock, raw_messages);
 
                 self.state.lock(round, propose_hash);
                 // Send precommit
@@ -504,6 +660,7 @@ impl NodeHandler {
             );
             (block_state.txs().len(), block_state.proposer_id())
         };
+        self.update_state_snapshot();
         let snapshot = self.blockchain.snapshot();
         let schema = Schema::new(&snapshot);
         let pool_len = schema.transactions_pool_len();
@@ -527,14 +684,17 @@ impl NodeHandler {
         self.broadcast_status();
         self.add_status_timeout();
 
-        // Adjust propose timeout after accepting a new block.
-        self.state.adjust_timeout(&*self.blockchain.snapshot());
+        // Adjust propose timeout and choose the leaders after accepting a new block.
+        self.state.adjust_timeout(&*snapshot);
+        self.state.update_leaders(&*snapshot);
 
-        // Add timeout for first round
-        self.add_round_timeout();
-        // Send propose we is leader
-        if self.state.is_leader() {
-            self.add_propose_timeout();
+        if !self.is_replica() {
+            // Add timeout for first round
+            self.add_round_timeout();
+            // Send propose we is leader
+            if self.state.is_leader() {
+                self.add_propose_timeout();
+            }
         }
 
         // Handle queued messages
@@ -544,10 +704,17 @@ impl NodeHandler {
     }
 
     /// Handles raw transaction. Transaction is ignored if it is already known, otherwise it is
-    /// added to the transactions pool.
+    /// added to the transactions pool. Transactions forwarded by replicas are additionally
+    /// broadcast to the peers, since replicas do not gossip transactions themselves.
+    /// A replica passes the transactions forwarded by its own replicas to its upstream.
     #[cfg_attr(feature = "flame_profile", flame)]
     pub fn handle_tx(&mut self, msg: RawTransaction) {
         //trace!("Handle transaction");
+        let is_forwarded = self.is_forwarded_by_replica();
+        if self.is_replica() && !is_forwarded {
+            // Replicas do not keep the transactions pool.
+            return;
+        }
         let hash = msg.hash();
         let tx = {
             let service_id = msg.service_id();
@@ -572,14 +739,16 @@ impl NodeHandler {
                 return;
             }
         });
-        let mut fork = self.blockchain.fork();
-        {
-            let mut schema = Schema::new(&mut fork);
-            schema.add_transaction_into_pool(msg);
+        if self.is_replica() {
+            self.forward_tx(msg);
+            return;
+        }
+        if !self.add_transaction_into_pool(tx.as_ref()) {
+            return;
+        }
+        if is_forwarded {
+            self.gossip_tx(&msg);
         }
-        self.blockchain.merge(fork.into_patch()).expect(
-            "Unable to save transaction to persistent pool.",
-        );
 
         let full_proposes = self.state.check_incomplete_proposes(hash);
         // Go to has full propose if we get last transaction
@@ -587,25 +756,24 @@ impl NodeHandler {
             self.remove_request(&RequestData::Transactions(hash));
             self.has_full_propose(hash, round);
         }
+        self.send_postponed_propose();
     }
 
     /// Handles external boxed transaction. Additionally transaction will be broadcast to the
-    /// Node's peers.
+    /// Node's peers. Replicas forward the transaction to their upstream instead.
     #[cfg_attr(feature = "cargo-clippy", allow(needless_pass_by_value))]
     pub fn handle_incoming_tx(&mut self, msg: Box<Transaction>) {
         trace!("Handle incoming transaction");
+        if self.is_replica() {
+            self.forward_tx(msg.raw().clone());
+            return;
+        }
         let hash = msg.hash();
-        let mut fork = self.blockchain.fork();
-        {
-            let mut schema = Schema::new(&mut fork);
-            schema.add_transaction_into_pool(msg.raw().clone());
+        if !self.add_transaction_into_pool(msg.as_ref()) {
+            return;
         }
-        self.blockchain.merge(fork.into_patch()).expect(
-            "Unable to save transaction to persistent pool.",
-        );
         // Broadcast transaction to validators
-        trace!("Broadcast transactions: {:?}", msg.raw());
-        self.broadcast(msg.raw());
+        self.gossip_tx(msg.raw());
 
         let full_proposes = self.state.check_incomplete_proposes(hash);
         // Go to has full propose if we get last transaction
@@ -613,6 +781,256 @@ impl NodeHandler {
             self.remove_request(&RequestData::Transactions(hash));
             self.has_full_propose(hash, round);
         }
+        self.send_postponed_propose();
+    }
+
+    /// Sends the transaction received from the api or from a downstream replica to the
+    /// upstream of the replica. While
+    /// the upstream is not connected, the transactions are queued and sent once
+    /// the connection is established.
+    fn forward_tx(&mut self, tx: RawTransaction) {
+        let upstream = self.replica.as_ref().expect("Node is not a replica").upstream;
+        if self.is_upstream_connected() {
+            trace!("Forward transaction to the upstream {}", upstream);
+            self.send_to_addr(&upstream, &tx);
+        } else if self.forwarded_txs.len() < self.state.tx_pool_capacity() {
+            warn!(
+                "Upstream {} is not connected, transaction {:?} is queued",
+                upstream,
+                tx.hash()
+            );
+            self.forwarded_txs.push(tx);
+        } else {
+            error!(
+                "Upstream {} is not connected and the queue is full, transaction {:?} is dropped",
+                upstream,
+                tx.hash()
+            );
+        }
+    }
+
+    /// Sends the queued transactions to the upstream of the replica once it is connected.
+    pub fn send_forwarded_txs(&mut self) {
+        if self.forwarded_txs.is_empty() || !self.is_upstream_connected() {
+            return;
+        }
+        let upstream = self.replica.as_ref().expect("Node is not a replica").upstream;
+        info!(
+            "Forward {} queued transactions to the upstream {}",
+            self.forwarded_txs.len(),
+            upstream
+        );
+        for tx in mem::replace(&mut self.forwarded_txs, Vec::new()) {
+            self.send_to_addr(&upstream, &tx);
+        }
+    }
+
+    /// Returns `true` if the message being handled is received from a replica.
+    fn is_forwarded_by_replica(&self) -> bool {
+        self.message_source.map_or(false, |source| {
+            self.state.peers().get(&source).map_or(
+                false,
+                |peer| peer.is_replica(),
+            )
+        })
+    }
+
+    fn is_upstream_connected(&self) -> bool {
+        self.replica.as_ref().map_or(false, |replica| {
+            self.state.peers().values().any(
+                |peer| peer.addr() == replica.upstream,
+            )
+        })
+    }
+
+    /// Returns `true` if empty blocks are skipped and there is nothing to propose at
+    /// the current height: the transactions pool is empty, no proposes are known and
+    /// `max_idle_interval` has not passed since the time of the last block.
+    fn is_idle(&self) -> bool {
+        let max_idle_interval = match self.state.consensus_config().max_idle_interval {
+            Some(interval) => interval,
+            None => return false,
+        };
+        if self.state.has_proposes() || self.state.locked_propose().is_some() {
+            return false;
+        }
+        let snapshot = self.blockchain.snapshot();
+        let schema = Schema::new(&snapshot);
+        if schema.transactions_pool_len() > 0 {
+            return false;
+        }
+        let now = DateTime::<Utc>::from(self.system_state.current_time());
+        let idle_until =
+            schema.last_block().time() + ::chrono::Duration::milliseconds(max_idle_interval as i64);
+        now < idle_until
+    }
+
+    /// Sends the propose postponed by the leader while the transactions pool was empty.
+    fn send_postponed_propose(&mut self) {
+        if self.state.consensus_config().max_idle_interval.is_none() || !self.state.is_leader() {
+            return;
+        }
+        let round = self.state.round();
+        let propose_time = self.round_start_time(round) +
+            Duration::from_millis(self.state.propose_timeout());
+        if self.system_state.current_time() >= propose_time {
+            let height = self.state.height();
+            self.handle_propose_timeout(height, round);
+        }
+    }
+
+    /// Handles `TransactionsBatch` message. For details see the message documentation.
+    pub fn handle_txs_batch(&mut self, msg: &TransactionsBatch) {
+        trace!("Handle transactions batch");
+        if self.is_replica() {
+            return;
+        }
+        if !msg.verify_signature(msg.from()) {
+            error!("Received transactions batch with incorrect signature, msg={:?}", msg);
+            self.penalize_message_source(PeerBehavior::InvalidSignature);
+            return;
+        }
+        for tx in msg.transactions() {
+            self.handle_tx(tx);
+        }
+    }
+
+    /// Handles `TransactionsResponse` message. For details see the message documentation.
+    pub fn handle_txs_response(&mut self, msg: &TransactionsResponse) {
+        trace!("Handle transactions response");
+        if msg.to() != self.state.consensus_public_key() {
+            error!(
+                "Received transactions that intended for another peer, to={}, from={}",
+                msg.to().to_hex(),
+                msg.from().to_hex()
+            );
+            return;
+        }
+        if !msg.verify_signature(msg.from()) {
+            error!("Received transactions response with incorrect signature, msg={:?}", msg);
+            self.penalize_message_source(PeerBehavior::InvalidSignature);
+            return;
+        }
+        for tx in msg.transactions() {
+            self.handle_tx(tx);
+        }
+    }
+
+    /// Handles `NodeTimeout::TxBatch`, broadcasting the accumulated transactions.
+    pub fn handle_tx_batch_timeout(&mut self) {
+        let txs = mem::replace(&mut self.tx_batch, Vec::new());
+        self.tx_batch_len = TransactionsBatch::EMPTY_LEN;
+        if let Some(msg) = self.transactions_message(txs, SignedAction::Broadcast) {
+            trace!("Broadcast transactions: {:?}", msg);
+            self.broadcast(&msg);
+        }
+    }
+
+    /// Broadcasts the transaction received from a client. Unless batching is disabled,
+    /// the transaction is accumulated and broadcast along with the other transactions
+    /// received during `tx_batch_timeout`.
+    fn gossip_tx(&mut self, tx: &RawTransaction) {
+        if self.tx_batch_timeout == 0 {
+            trace!("Broadcast transactions: {:?}", tx);
+            self.broadcast(tx);
+            return;
+        }
+
+        let tx_len = TransactionsBatch::tx_len(tx);
+        let max_message_len = self.state.config().consensus.max_message_len as usize;
+        if !self.tx_batch.is_empty() && self.tx_batch_len + tx_len > max_message_len {
+            self.handle_tx_batch_timeout();
+        }
+        if self.tx_batch.is_empty() {
+            self.add_tx_batch_timeout();
+        }
+        self.tx_batch.push(tx.clone());
+        self.tx_batch_len += tx_len;
+    }
+
+    /// Returns the message with the given transactions: the transaction itself if it is
+    /// the only one or the signed `TransactionsBatch` otherwise. If the batch is signed by
+    /// the remote signer, `None` is returned and `action` is performed once it is signed.
+    pub(crate) fn transactions_message(
+        &mut self,
+        mut txs: Vec<RawTransaction>,
+        action: SignedAction,
+    ) -> Option<RawMessage> {
+        match txs.len() {
+            0 => None,
+            1 => txs.pop(),
+            _ => {
+                let batch = TransactionsBatch::new_with_signature(
+                    self.state.consensus_public_key(),
+                    txs,
+                    &Signature::zero(),
+                );
+                self.sign(&batch, action).map(|batch| batch.raw().clone())
+            }
+        }
+    }
+
+    /// Saves the transaction into the persistent pool, enforcing the pool capacity and
+    /// the eviction policy. Transactions awaited by the proposes of the current height are
+    /// always accepted, while transactions of dormant services are always rejected.
+    /// Returns `false` if the transaction has been rejected.
+    fn add_transaction_into_pool(&mut self, tx: &Transaction) -> bool {
+        let hash = tx.raw().hash();
+        if !self.blockchain
+            .is_service_active(tx.raw().service_id(), self.state.config())
+        {
+            trace!("Transaction {:?} belongs to a dormant service", hash);
+            self.api_state.add_rejected_txs(1);
+            return false;
+        }
+        if let Some(max_size) = self.state.consensus_config().max_block_size {
+            // Transactions awaited by the proposes are accepted, the proposes themselves
+            // are checked against the maximum block size.
+            if tx.raw().len() > max_size as usize && !self.state.is_tx_awaited(&hash) {
+                trace!("Transaction {:?} exceeds the maximum block size", hash);
+                self.api_state.add_rejected_txs(1);
+                return false;
+            }
+        }
+        let mut fork = self.blockchain.fork();
+        let result = {
+            let mut schema = Schema::new(&mut fork);
+            if self.state.is_tx_awaited(&hash) {
+                schema.add_transaction_into_pool(tx.raw().clone());
+                Ok(Vec::new())
+            } else {
+                let state = &self.state;
+                schema.add_transaction_into_limited_pool(
+                    tx,
+                    state.tx_pool_capacity(),
+                    state.tx_pool_eviction_policy(),
+                    |hash| state.is_tx_proposed(hash),
+                )
+            }
+        };
+
+        match result {
+            Ok(evicted) => {
+                self.blockchain.merge(fork.into_patch()).expect(
+                    "Unable to save transaction to persistent pool.",
+                );
+                if !evicted.is_empty() {
+                    trace!("Evicted transactions from the pool: {:?}", evicted);
+                    self.api_state.add_evicted_txs(evicted.len() as u64);
+                }
+                true
+            }
+            Err(TxPoolError::Duplicate) => {
+                // Known transactions are not gossiped again.
+                trace!("Transaction {:?} is already known", hash);
+                false
+            }
+            Err(e) => {
+                trace!("Transaction {:?} is rejected: {}", hash, e);
+                self.api_state.add_rejected_txs(1);
+                false
+            }
+        }
     }
 
     /// Handle new round, after jump.
@@ -661,6 +1079,18 @@ impl NodeHandler {
         if round != self.state.round() {
             return;
         }
+        if self.is_idle() {
+            // There is nothing to agree on, so the round is extended instead of
+            // starting a new one.
+            trace!("IDLE ROUND TIMEOUT height={}, round={}", height, round);
+            let round_timeout = self.round_timeout();
+            self.state.postpone_height_start(Duration::from_millis(round_timeout));
+            self.add_round_timeout();
+            if self.state.is_leader() {
+                self.add_propose_timeout();
+            }
+            return;
+        }
         warn!("ROUND TIMEOUT height={}, round={}", height, round);
 
         // Update state to new round
@@ -686,7 +1116,11 @@ impl NodeHandler {
             return;
         }
         if let Some(validator_id) = self.state.validator_id() {
-            if self.state.have_prevote(round) {
+            if self.state.have_prevote(round) || self.state.have_self_propose(round) {
+                return;
+            }
+            if self.is_idle() {
+                trace!("Skip proposing an empty block");
                 return;
             }
             let snapshot = self.blockchain.snapshot();
@@ -699,29 +1133,128 @@ impl NodeHandler {
             let round = self.state.round();
             let max_count = ::std::cmp::min(self.txs_block_limit() as usize, pool_len);
 
-            let txs: Vec<Hash> = pool.iter().take(max_count).collect();
-            let propose = Propose::new(
+            let mut oversized = Vec::new();
+            let txs: Vec<Hash> = match self.state.consensus_config().max_block_size {
+                Some(max_size) => {
+                    // Transactions that do not fit into the block are skipped, so a single
+                    // large transaction does not stall the rest of the pool. Transactions
+                    // larger than the block itself are removed from the pool.
+                    let max_size = max_size as usize;
+                    let transactions = schema.transactions();
+                    let mut size = 0;
+                    pool.iter()
+                        .filter(|hash| {
+                            let tx_len = transactions.get(hash).map_or(0, |tx| tx.len());
+                            if tx_len > max_size {
+                                oversized.push(*hash);
+                                return false;
+                            }
+                            if size + tx_len > max_size {
+                                return false;
+                            }
+                            size += tx_len;
+                            true
+                        })
+                        .take(max_count)
+                        .collect()
+                }
+                None => pool.iter().take(max_count).collect(),
+            };
+            self.remove_oversized_txs(&oversized);
+            // Block time must not decrease even if the clock of the node is behind.
+            let time = cmp::max(
+                DateTime::<Utc>::from(self.system_state.current_time()),
+                schema.last_block().time(),
+            );
+            let propose = Propose::new_with_signature(
                 validator_id,
                 self.state.height(),
                 round,
                 self.state.last_hash(),
                 &txs,
-                self.state.consensus_secret_key(),
+                time,
+                &Signature::zero(),
             );
+            if let Some(propose) = self.sign(&propose, SignedAction::Consensus) {
+                self.send_propose(propose);
+            }
+        }
+    }
 
-            // Put our propose to the consensus messages cache
-            self.blockchain.save_message(round, propose.raw());
+    /// Removes the transactions larger than `max_block_size` from the pool, since they can
+    /// never be included into a block. Transactions of the known proposes are kept.
+    fn remove_oversized_txs(&mut self, hashes: &[Hash]) {
+        let hashes = hashes
+            .iter()
+            .filter(|hash| !self.state.is_tx_proposed(hash))
+            .collect::<Vec<_>>();
+        if hashes.is_empty() {
+            return;
+        }
+
+        let mut fork = self.blockchain.fork();
+        {
+            let mut schema = Schema::new(&mut fork);
+            for hash in &hashes {
+                warn!("Transaction {:?} exceeds the maximum block size, removing it", hash);
+                schema.reject_transaction(hash).ok();
+            }
+        }
+        self.blockchain.merge(fork.into_patch()).expect(
+            "Unable to remove transactions from the pool",
+        );
+        self.api_state.add_evicted_txs(hashes.len() as u64);
+    }
 
-            trace!("Broadcast propose: {:?}", propose);
-            self.broadcast(propose.raw());
+    /// Broadcasts the own signed `Propose` message and prevotes for it.
+    fn send_propose(&mut self, propose: Propose) {
+        let round = propose.round();
 
-            // Save our propose into state
-            let hash = self.state.add_self_propose(propose);
+        // Put our propose to the consensus messages cache before broadcasting it, so the node
+        // restores it after a restart instead of proposing again
+        self.blockchain.save_propose(round, propose.raw());
 
-            // Send prevote
-            let has_majority_prevotes = self.broadcast_prevote(round, &hash);
-            if has_majority_prevotes {
-                self.has_majority_prevotes(round, &hash);
+        trace!("Broadcast propose: {:?}", propose);
+        self.broadcast(propose.raw());
+
+        // Save our propose into state
+        let hash = self.state.add_self_propose(propose);
+
+        // Send prevote
+        let has_majority_prevotes = self.broadcast_prevote(round, &hash);
+        if has_majority_prevotes {
+            self.has_majority_prevotes(round, &hash);
+        }
+    }
+
+    /// Handles the own consensus message signed by the remote signer. The message is dropped
+    /// if the node has moved to another height or round in the meantime.
+    pub(crate) fn handle_own_consensus(&mut self, msg: ConsensusMessage) {
+        if msg.height() != self.state.height() {
+            trace!("Drop the outdated own message {:?}", msg);
+            return;
+        }
+        match msg {
+            ConsensusMessage::Propose(propose) => {
+                if propose.round() == self.state.round() && self.state.locked_propose().is_none() &&
+                    !self.state.have_prevote(propose.round())
+                {
+                    self.send_propose(propose);
+                }
+            }
+            ConsensusMessage::Prevote(prevote) => {
+                let (round, propose_hash) = (prevote.round(), *prevote.propose_hash());
+                if !self.state.have_prevote(round) && self.send_prevote(&prevote) {
+                    self.has_majority_prevotes(round, &propose_hash);
+                }
+            }
+            ConsensusMessage::Precommit(precommit) => {
+                self.send_precommit(&precommit);
+                let (round, block_hash) = (precommit.round(), *precommit.block_hash());
+                if self.state.has_majority_precommits(round, block_hash) {
+                    let propose_hash = *precommit.propose_hash();
+                    self.has_majority_precommits(round, &propose_hash, &block_hash);
+                }
             }
         }
     }
@@ -735,12 +1268,12 @@ impl NodeHandler {
 
             let message = match *data {
                 RequestData::Propose(ref propose_hash) => {
-                    ProposeRequest::new(
+                    ProposeRequest::new_with_signature(
                         self.state.consensus_public_key(),
                         &peer,
                         self.state.height(),
                         propose_hash,
-                        self.state.consensus_secret_key(),
+                        &Signature::zero(),
                     ).raw()
                         .clone()
                 }
@@ -752,38 +1285,65 @@ impl NodeHandler {
                         .iter()
                         .cloned()
                         .collect();
-                    TransactionsRequest::new(
+                    TransactionsRequest::new_with_signature(
                         self.state.consensus_public_key(),
                         &peer,
                         &txs,
-                        self.state.consensus_secret_key(),
+                        &Signature::zero(),
                     ).raw()
                         .clone()
                 }
                 RequestData::Prevotes(round, ref propose_hash) => {
-                    PrevotesRequest::new(
+                    PrevotesRequest::new_with_signature(
                         self.state.consensus_public_key(),
                         &peer,
                         self.state.height(),
                         round,
                         propose_hash,
                         self.state.known_prevotes(round, propose_hash),
-                        self.state.consensus_secret_key(),
+                        &Signature::zero(),
                     ).raw()
                         .clone()
                 }
                 RequestData::Block(height) => {
-                    BlockRequest::new(
+                    BlockRequest::new_with_signature(
                         self.state.consensus_public_key(),
                         &peer,
                         height,
-                        self.state.consensus_secret_key(),
+                        &Signature::zero(),
+                    ).raw()
+                        .clone()
+                }
+                RequestData::StateSnapshot => {
+                    StateSnapshotRequest::new_with_signature(
+                        self.state.consensus_public_key(),
+                        &peer,
+                        &Signature::zero(),
+                    ).raw()
+                        .clone()
+                }
+                RequestData::StateChunk(height, ref index_name, ref from_key) => {
+                    StateChunkRequest::new_with_signature(
+                        self.state.consensus_public_key(),
+                        &peer,
+                        height,
+                        index_name,
+                        from_key,
+                        &Signature::zero(),
                     ).raw()
                         .clone()
                 }
             };
-            trace!("Send request {:?} to peer {:?}", data, peer);
-            self.send_to_peer(peer, &message);
+            if let Some(message) = self.sign(&message, SignedAction::Send(peer)) {
+                trace!("Send request {:?} to peer {:?}", data, peer);
+                self.send_to_peer(peer, &message);
+            }
+        } else if *data == RequestData::StateSnapshot {
+            // None of the peers has a state snapshot, fall back to the block sync.
+            warn!("No state snapshot available, state sync is disabled");
+            self.state_sync.enabled = false;
+        } else if let RequestData::StateChunk(..) = *data {
+            self.handle_state_chunk_timeout(data);
         }
     }
 
@@ -792,9 +1352,10 @@ impl NodeHandler {
         &mut self,
         proposer_id: ValidatorId,
         height: Height,
+        time: DateTime<Utc>,
         tx_hashes: &[Hash],
     ) -> (Hash, Patch) {
-        self.blockchain.create_patch(proposer_id, height, tx_hashes)
+        self.blockchain.create_patch(proposer_id, height, time, tx_hashes)
     }
 
     /// Calls `create_block` with transactions from the corresponding `Propose` and returns the
@@ -810,8 +1371,12 @@ impl NodeHandler {
 
         let tx_hashes = propose.transactions().to_vec();
 
-        let (block_hash, patch) =
-            self.create_block(propose.validator(), propose.height(), tx_hashes.as_slice());
+        let (block_hash, patch) = self.create_block(
+            propose.validator(),
+            propose.height(),
+            propose.time(),
+            tx_hashes.as_slice(),
+        );
         // Save patch
         self.state.add_block(
             block_hash,
@@ -852,22 +1417,84 @@ impl NodeHandler {
         }
     }
 
-    /// Requests a block for the next height from all peers with a bigger height. Called when the
-    /// node tries to catch up with other nodes' height.
+    /// Requests blocks of the next heights from the peers with a bigger height. Called when
+    /// the node tries to catch up with other nodes' height.
+    ///
+    /// Up to `BLOCK_SYNC_WINDOW` heights are downloaded in parallel. The heights are split
+    /// into ranges of `BLOCK_SYNC_RANGE` blocks, which are requested from different peers
+    /// with the `BlocksRequest` message. If a block is not received in time, it is requested
+    /// from another peer with the `BlockRequest` message.
     pub fn request_next_block(&mut self) {
+        if self.state.state_sync_block().is_some() {
+            return;
+        }
         // TODO randomize next peer (ECR-171)
-        let heights: Vec<_> = self.state
+        let peers: Vec<(PublicKey, Height)> = self.state
             .nodes_with_bigger_height()
             .into_iter()
-            .cloned()
+            .filter(|peer| {
+                self.state.peers().contains_key(peer) && !self.state.is_block_peer_banned(peer)
+            })
+            .map(|peer| (*peer, self.state.node_height(peer)))
             .collect();
-        if !heights.is_empty() {
-            for peer in heights {
-                if self.state.peers().contains_key(&peer) {
-                    let height = self.state.height();
-                    self.request(RequestData::Block(height), peer);
-                    break;
+        let max_height = match peers.iter().map(|&(_, height)| height).max() {
+            Some(height) => height,
+            None => return,
+        };
+        let start = self.state.height().0;
+        let end = cmp::min(max_height.0, start + BLOCK_SYNC_WINDOW);
+
+        let now = self.system_state.current_time();
+        let mut range_start = start;
+        let mut range_index = 0;
+        while range_start < end {
+            let range_end = cmp::min(end, range_start + BLOCK_SYNC_RANGE);
+            let missing: Vec<Height> = (range_start..range_end)
+                .map(Height)
+                .filter(|height| {
+                    !self.state.is_requested(&RequestData::Block(*height)) &&
+                        !self.state.has_pending_block(*height)
+                })
+                .collect();
+            let candidates: Vec<PublicKey> = peers
+                .iter()
+                .filter(|&&(_, height)| height.0 > range_start)
+                .map(|&(peer, _)| peer)
+                .collect();
+            range_start = range_end;
+            if missing.is_empty() {
+                continue;
+            }
+            let peer = candidates[range_index % candidates.len()];
+            range_index += 1;
+
+            for (i, height) in missing.iter().enumerate() {
+                let data = RequestData::Block(*height);
+                self.state.request(data.clone(), peer);
+                self.state.request_sent(&data, peer);
+                for &(other, other_height) in &peers {
+                    if other_height > *height {
+                        self.state.request(data.clone(), other);
+                    }
                 }
+                // The peer sends the blocks one by one, so the later blocks of the range
+                // are waited for longer.
+                let timeout = data.timeout() * (i as u32 + 1);
+                self.add_timeout(NodeTimeout::Request(data, Some(peer)), now + timeout);
+            }
+
+            let first = missing[0];
+            let count = missing[missing.len() - 1].0 - first.0 + 1;
+            let message = BlocksRequest::new_with_signature(
+                self.state.consensus_public_key(),
+                &peer,
+                first,
+                count as u32,
+                &Signature::zero(),
+            );
+            if let Some(message) = self.sign(&message, SignedAction::Send(peer)) {
+                trace!("Send blocks request {:?} to peer {:?}", message, peer);
+                self.send_to_peer(peer, message.raw());
             }
         }
     }
@@ -884,19 +1511,27 @@ impl NodeHandler {
             "called broadcast_prevote in Auditor node.",
         );
         let locked_round = self.state.locked_round();
-        let prevote = Prevote::new(
+        let prevote = Prevote::new_with_signature(
             validator_id,
             self.state.height(),
             round,
             propose_hash,
             locked_round,
-            self.state.consensus_secret_key(),
+            &Signature::zero(),
         );
-        let has_majority_prevotes = self.state.add_prevote(&prevote);
+        match self.sign(&prevote, SignedAction::Consensus) {
+            Some(prevote) => self.send_prevote(&prevote),
+            None => false,
+        }
+    }
+
+    /// Broadcasts the own signed `Prevote` message. Returns `true` if there are +2/3 pre-votes.
+    fn send_prevote(&mut self, prevote: &Prevote) -> bool {
+        let has_majority_prevotes = self.state.add_prevote(prevote);
 
-        // save outgoing Prevote to the consensus messages cache before broadcast
-        self.check_propose_saved(round, propose_hash);
-        self.blockchain.save_message(round, prevote.raw());
+        // save outgoing Prevote to the consensus write-ahead log before broadcast
+        self.check_propose_saved(prevote.round(), prevote.propose_hash());
+        self.blockchain.save_vote(prevote.round(), prevote.raw());
 
         trace!("Broadcast prevote: {:?}", prevote);
         self.broadcast(prevote.raw());
@@ -909,26 +1544,33 @@ impl NodeHandler {
         let validator_id = self.state.validator_id().expect(
             "called broadcast_precommit in Auditor node.",
         );
-        let precommit = Precommit::new(
+        let precommit = Precommit::new_with_signature(
             validator_id,
             self.state.height(),
             round,
             propose_hash,
             block_hash,
             self.system_state.current_time().into(),
-            self.state.consensus_secret_key(),
+            &Signature::zero(),
         );
-        self.state.add_precommit(&precommit);
+        if let Some(precommit) = self.sign(&precommit, SignedAction::Consensus) {
+            self.send_precommit(&precommit);
+        }
+    }
 
-        // Put our Precommit to the consensus cache before broadcast
-        self.blockchain.save_message(round, precommit.raw());
+    /// Broadcasts the own signed `Precommit` message.
+    fn send_precommit(&mut self, precommit: &Precommit) {
+        self.state.add_precommit(precommit);
+
+        // Put our Precommit to the consensus write-ahead log before broadcast
+        self.blockchain.save_vote(precommit.round(), precommit.raw());
 
         trace!("Broadcast precommit: {:?}", precommit);
         self.broadcast(precommit.raw());
     }
 
     /// Checks that pre-commits count is correct and calls `verify_precommit` for each of them.
-    fn verify_precommits(
+    pub(crate) fn verify_precommits(
         &self,
         precommits: &[Precommit],
         block_hash: &Hash,
@@ -1003,7 +1645,78 @@ impl NodeHandler {
         Ok(())
     }
 
-    /// Checks whether Propose is saved to the consensus cache and saves it otherwise
+    /// Restores the votes and the lock of the node at the current height from the consensus
+    /// write-ahead log and broadcasts the votes again. This should be done after a restart
+    /// before the cached consensus messages are handled, so the node does not sign votes
+    /// conflicting with the ones sent before the restart.
+    ///
+    /// Returns the lock under which the node has not sent a precommit before the restart.
+    /// Such a lock is not restored; instead, it should be passed to `complete_restored_lock`
+    /// once the cached consensus messages are handled.
+    pub(crate) fn restore_consensus_log(&mut self) -> Option<ConsensusLock> {
+        let height = self.state.height();
+        let (votes, lock) = {
+            let snapshot = self.blockchain.snapshot();
+            let schema = Schema::new(&snapshot);
+            let votes = schema.consensus_votes().iter().collect::<Vec<_>>();
+            (votes, schema.consensus_lock())
+        };
+
+        let mut precommit_rounds = Vec::new();
+        for raw in votes {
+            match Any::from_raw(raw.clone()) {
+                Ok(Any::Consensus(ConsensusMessage::Prevote(prevote))) => {
+                    if prevote.height() == height {
+                        self.state.add_prevote(&prevote);
+                        self.broadcast(&raw);
+                    }
+                }
+                Ok(Any::Consensus(ConsensusMessage::Precommit(precommit))) => {
+                    if precommit.height() == height {
+                        self.state.add_precommit(&precommit);
+                        precommit_rounds.push(precommit.round());
+                        self.broadcast(&raw);
+                    }
+                }
+                other => error!("Invalid message in the consensus write-ahead log: {:?}", other),
+            }
+        }
+
+        let lock = match lock {
+            Some(lock) => lock,
+            None => return None,
+        };
+        if lock.height() != height || lock.round() <= self.state.locked_round() {
+            return None;
+        }
+        if !precommit_rounds.contains(&lock.round()) {
+            // The node has stopped between the lock and the precommit, so the lock should
+            // be made again to send the precommit.
+            return Some(lock);
+        }
+        info!("Restore lock round={}, propose={:?}", lock.round(), lock.propose_hash());
+        self.state.lock(lock.round(), *lock.propose_hash());
+        None
+    }
+
+    /// Makes the lock returned by `restore_consensus_log` again and sends the precommit
+    /// under it. Cached prevotes of the lock usually make it while being handled; otherwise,
+    /// the lock is made once the transactions of the propose are received.
+    pub(crate) fn complete_restored_lock(&mut self, lock: &ConsensusLock) {
+        let (round, propose_hash) = (lock.round(), *lock.propose_hash());
+        if self.state.locked_round() >= round {
+            return;
+        }
+        let has_full_propose = self.state.propose(&propose_hash).map_or(false, |propose| {
+            !propose.has_unknown_txs()
+        });
+        if has_full_propose && self.state.has_majority_prevotes(round, propose_hash) {
+            info!("Restore lock round={}, propose={:?}", round, propose_hash);
+            self.has_majority_prevotes(round, &propose_hash);
+        }
+    }
+
+    /// Checks whether Propose is saved to the consensus cache and saves it otherwise.
     fn check_propose_saved(&mut self, round: Round, propose_hash: &Hash) {
         if let Some(propose_state) = self.state.propose_mut(propose_hash) {
             if !propose_state.is_saved() {
@@ -35,6 +35,7 @@ impl NodeHandler {
             InternalEvent::Timeout(timeout) => self.handle_timeout(timeout),
             InternalEvent::JumpToRound(height, round) => self.handle_new_round(height, round),
             InternalEvent::Shutdown => panic!("Shutdown should be processed in the event loop"),
+            InternalEvent::MessageSigned(response) => self.handle_signed_message(response),
         }
     }
 
@@ -50,7 +51,7 @@ impl NodeHandler {
             NetworkEvent::PeerConnected(peer, connect) => self.handle_connected(peer, connect),
             NetworkEvent::PeerDisconnected(peer) => self.handle_disconnected(peer),
             NetworkEvent::UnableConnectToPeer(peer) => self.handle_unable_to_connect(peer),
-            NetworkEvent::MessageReceived(_, raw) => self.handle_message(raw),
+            NetworkEvent::MessageReceived(peer, raw) => self.handle_received_message(peer, raw),
         }
     }
 
@@ -64,6 +65,13 @@ impl NodeHandler {
                     );
                     return;
                 }
+                if self.is_shutting_down() {
+                    info!(
+                        "Ignoring a transaction {:?} because the node is shutting down",
+                        tx
+                    );
+                    return;
+                }
                 self.handle_incoming_tx(tx);
             }
             ExternalMessage::PeerAdd(address) => {
@@ -90,12 +98,16 @@ impl NodeHandler {
                     }
                 }
             }
-            ExternalMessage::Shutdown => self.execute_later(InternalRequest::Shutdown),
+            ExternalMessage::WhitelistAdd(peer) => self.handle_whitelist_add(peer),
+            ExternalMessage::WhitelistRemove(peer) => self.handle_whitelist_remove(&peer),
+            ExternalMessage::PeerDisconnect(peer) => self.disconnect_peer(&peer),
+            ExternalMessage::ReloadConfig(result_tx) => self.handle_reload_config(result_tx),
+            ExternalMessage::Shutdown => self.handle_shutdown(),
         }
     }
 
     fn handle_timeout(&mut self, timeout: NodeTimeout) {
-        if !self.is_enabled {
+        if !self.is_enabled && timeout != NodeTimeout::Shutdown {
             info!(
                 "Ignoring a timeout {:?} because the node is disabled",
                 timeout
@@ -108,7 +120,9 @@ impl NodeHandler {
             NodeTimeout::Status(height) => self.handle_status_timeout(height),
             NodeTimeout::PeerExchange => self.handle_peer_exchange_timeout(),
             NodeTimeout::UpdateApiState => self.handle_update_api_state_timeout(),
+            NodeTimeout::TxBatch => self.handle_tx_batch_timeout(),
             NodeTimeout::Propose(height, round) => self.handle_propose_timeout(height, round),
+            NodeTimeout::Shutdown => self.handle_shutdown_timeout(),
         }
     }
 
@@ -0,0 +1,328 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! `LeaderElection` is used to choose the leaders of the consensus rounds.
+
+use std::collections::HashSet;
+use std::fmt::Debug;
+
+use helpers::{Height, ValidatorId};
+use storage::Snapshot;
+use blockchain::Schema;
+
+/// `LeaderElection` trait is used to choose the leaders of the rounds of a height.
+///
+/// The order of the leaders must be derived deterministically from the committed blocks,
+/// so that all the validators agree on it.
+///
+/// # Examples
+///
+/// Implementing `LeaderElection`:
+///
+/// ```
+/// use exonum::node::leader_election::LeaderElection;
+/// use exonum::helpers::{Height, ValidatorId};
+/// use exonum::storage::Snapshot;
+///
+/// # #[allow(dead_code)]
+/// # #[derive(Debug)]
+/// struct ReverseOrder {}
+///
+/// impl LeaderElection for ReverseOrder {
+///     fn leaders(
+///         &mut self,
+///         _: Height,
+///         validators_count: usize,
+///         _: &Snapshot,
+///     ) -> Vec<ValidatorId> {
+///         // The last validator always leads the first round.
+///         (0..validators_count)
+///             .rev()
+///             .map(|id| ValidatorId(id as u16))
+///             .collect()
+///     }
+/// }
+/// ```
+/// For more examples see `RoundRobin`, `ReputationWeighted` and `Weighted` implementations.
+pub trait LeaderElection: Send + Debug {
+    /// Returns the validators in the order in which they lead the rounds of the given height,
+    /// starting from the first round. The order is repeated for the subsequent rounds.
+    /// Each validator must be listed exactly once.
+    ///
+    /// Called during node initialization and after accepting a new height.
+    fn leaders(
+        &mut self,
+        height: Height,
+        validators_count: usize,
+        view: &Snapshot,
+    ) -> Vec<ValidatorId>;
+}
+
+/// `LeaderElection` implementation in which validators lead the rounds in turn.
+#[derive(Debug, Default)]
+pub struct RoundRobin;
+
+impl RoundRobin {
+    /// Creates a new `RoundRobin` leader election instance.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #![allow(unused_mut)]
+    /// use exonum::node::leader_election::RoundRobin;
+    ///
+    /// let mut election = RoundRobin::new();
+    /// ```
+    pub fn new() -> Self {
+        RoundRobin
+    }
+}
+
+impl LeaderElection for RoundRobin {
+    fn leaders(
+        &mut self,
+        height: Height,
+        validators_count: usize,
+        _: &Snapshot,
+    ) -> Vec<ValidatorId> {
+        round_robin(height, validators_count)
+    }
+}
+
+/// `LeaderElection` implementation that deprioritizes validators which have not proposed
+/// any of the recently committed blocks.
+///
+/// The proposers of the last `window` blocks are taken from `Block::proposer_id`. The
+/// validators that have proposed at least one of these blocks keep the round-robin order,
+/// while the others lead the rounds after them. To give the recovered validators a chance
+/// to propose a block, every `window` heights all the validators lead the first rounds
+/// in turn for `validators_count` heights.
+#[derive(Debug)]
+pub struct ReputationWeighted {
+    window: u64,
+}
+
+impl ReputationWeighted {
+    /// Creates a new `ReputationWeighted` leader election instance with the given window.
+    /// The window should be greater than the number of validators.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #![allow(unused_mut)]
+    /// use exonum::node::leader_election::ReputationWeighted;
+    ///
+    /// let mut election = ReputationWeighted::new(100);
+    /// ```
+    pub fn new(window: u64) -> Self {
+        ReputationWeighted { window }
+    }
+
+    fn is_probation(&self, height: Height, validators_count: usize) -> bool {
+        height.0.saturating_sub(1) % self.window < validators_count as u64
+    }
+
+    fn leaders_impl<F>(
+        &self,
+        height: Height,
+        validators_count: usize,
+        proposer: F,
+    ) -> Vec<ValidatorId>
+    where
+        F: Fn(Height) -> Option<ValidatorId>,
+    {
+        let order = round_robin(height, validators_count);
+        if self.is_probation(height, validators_count) {
+            return order;
+        }
+
+        let from = height.0.saturating_sub(self.window).max(1);
+        let active = (from..height.0)
+            .filter_map(|h| proposer(Height(h)))
+            .collect::<HashSet<_>>();
+        let (mut leaders, inactive): (Vec<_>, Vec<_>) =
+            order.into_iter().partition(|id| active.contains(id));
+        leaders.extend(inactive);
+        leaders
+    }
+}
+
+impl LeaderElection for ReputationWeighted {
+    fn leaders(
+        &mut self,
+        height: Height,
+        validators_count: usize,
+        view: &Snapshot,
+    ) -> Vec<ValidatorId> {
+        let schema = Schema::new(view);
+        let blocks = schema.blocks();
+        let hashes = schema.block_hashes_by_height();
+        self.leaders_impl(height, validators_count, |h| {
+            hashes.get(h.0).and_then(|hash| blocks.get(&hash)).map(
+                |block| block.proposer_id(),
+            )
+        })
+    }
+}
+
+/// `LeaderElection` implementation in which validators lead the first rounds of the heights
+/// proportionally to their weights.
+///
+/// The leaders of the first rounds are taken from a sequence spreading the turns of each
+/// validator evenly, so that a validator with a large weight does not lead several heights
+/// in a row. The leaders of the subsequent rounds follow the leader of the first round
+/// in the round-robin order.
+///
+/// The weights are bound to the validators of a configuration, so the node creates a new
+/// instance each time the configuration changes. If the number of validators still does not
+/// match the number of weights, the validators lead the rounds in turn.
+#[derive(Debug)]
+pub struct Weighted {
+    sequence: Vec<ValidatorId>,
+    validators_count: usize,
+}
+
+impl Weighted {
+    /// Creates a new `Weighted` leader election instance with the given weights of
+    /// the validators. The total weight must be positive.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # #![allow(unused_mut)]
+    /// use exonum::node::leader_election::Weighted;
+    ///
+    /// let mut election = Weighted::new(&[3, 1, 1, 1]);
+    /// ```
+    pub fn new(weights: &[u64]) -> Self {
+        let total = weights.iter().sum::<u64>() as i64;
+        assert!(total > 0, "Total weight of the validators must be positive");
+
+        // Smooth weighted round-robin.
+        let mut current = vec![0i64; weights.len()];
+        let sequence = (0..total)
+            .map(|_| {
+                for (current, weight) in current.iter_mut().zip(weights) {
+                    *current += *weight as i64;
+                }
+                let mut best = 0;
+                for (id, value) in current.iter().enumerate() {
+                    if *value > current[best] {
+                        best = id;
+                    }
+                }
+                current[best] -= total;
+                ValidatorId(best as u16)
+            })
+            .collect();
+        Weighted {
+            sequence,
+            validators_count: weights.len(),
+        }
+    }
+
+    fn leaders_impl(&self, height: Height, validators_count: usize) -> Vec<ValidatorId> {
+        if validators_count != self.validators_count {
+            return round_robin(height, validators_count);
+        }
+
+        let len = self.sequence.len() as u64;
+        let first = self.sequence[((height.0 + 1) % len) as usize];
+        (0..validators_count)
+            .map(|i| ValidatorId(((first.0 as usize + i) % validators_count) as u16))
+            .collect()
+    }
+}
+
+impl LeaderElection for Weighted {
+    fn leaders(
+        &mut self,
+        height: Height,
+        validators_count: usize,
+        _: &Snapshot,
+    ) -> Vec<ValidatorId> {
+        self.leaders_impl(height, validators_count)
+    }
+}
+
+fn round_robin(height: Height, validators_count: usize) -> Vec<ValidatorId> {
+    let count = validators_count as u64;
+    (0..count)
+        .map(|i| ValidatorId(((height.0 + 1 + i) % count) as u16))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use helpers::{Height, ValidatorId};
+    use super::{round_robin, ReputationWeighted, Weighted};
+
+    fn ids(ids: &[u16]) -> Vec<ValidatorId> {
+        ids.iter().map(|id| ValidatorId(*id)).collect()
+    }
+
+    #[test]
+    fn test_round_robin() {
+        assert_eq!(round_robin(Height(1), 4), ids(&[2, 3, 0, 1]));
+        assert_eq!(round_robin(Height(6), 4), ids(&[3, 0, 1, 2]));
+    }
+
+    #[test]
+    fn test_reputation_weighted() {
+        let election = ReputationWeighted::new(8);
+        // Validator 1 has not proposed any blocks.
+        let proposer = |h: Height| match (h.0 + 1) % 4 {
+            1 => None,
+            id => Some(ValidatorId(id as u16)),
+        };
+
+        // Probation heights.
+        for height in 1..5 {
+            assert_eq!(
+                election.leaders_impl(Height(height), 4, &proposer),
+                round_robin(Height(height), 4)
+            );
+        }
+        assert_eq!(election.leaders_impl(Height(5), 4, &proposer), ids(&[2, 3, 0, 1]));
+        assert_eq!(election.leaders_impl(Height(7), 4, &proposer), ids(&[0, 2, 3, 1]));
+        assert_eq!(election.leaders_impl(Height(8), 4, &proposer), ids(&[2, 3, 0, 1]));
+        assert_eq!(
+            election.leaders_impl(Height(9), 4, &proposer),
+            round_robin(Height(9), 4)
+        );
+    }
+
+    #[test]
+    fn test_weighted() {
+        let election = Weighted::new(&[1, 1, 1]);
+        for height in 0..10 {
+            assert_eq!(
+                election.leaders_impl(Height(height), 3),
+                round_robin(Height(height), 3)
+            );
+        }
+
+        let election = Weighted::new(&[3, 1, 0]);
+        assert_eq!(election.sequence, ids(&[0, 0, 1, 0]));
+        assert_eq!(election.leaders_impl(Height(1), 3), ids(&[1, 2, 0]));
+        assert_eq!(election.leaders_impl(Height(2), 3), ids(&[0, 1, 2]));
+    }
+
+    #[test]
+    fn test_weighted_with_other_validators_count() {
+        let election = Weighted::new(&[3, 1, 0]);
+        assert_eq!(election.leaders_impl(Height(1), 4), round_robin(Height(1), 4));
+        assert_eq!(election.leaders_impl(Height(2), 2), round_robin(Height(2), 2));
+    }
+}
@@ -18,9 +18,10 @@
 // spell-checker:ignore cors
 
 use std::io;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::thread;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::time::{Duration, SystemTime};
 use std::collections::BTreeMap;
 use std::collections::HashSet;
@@ -29,31 +30,49 @@ use std::fmt;
 use toml::Value;
 use router::Router;
 use mount::Mount;
-use iron::{Chain, Iron};
+use iron::{status, AroundMiddleware, BeforeMiddleware, Chain, Handler, Iron, IronError,
+           IronResult, Request, Response};
+use iron::method::Method;
 use iron_cors::CorsMiddleware;
 use serde::{de, ser};
 use futures::{Future, Sink};
-use futures::sync::mpsc;
+use futures::sync::{mpsc, oneshot};
 use tokio_core::reactor::Core;
 
-use crypto::{self, CryptoHash, Hash, PublicKey, SecretKey};
+use crypto::{self, CryptoHash, Hash, PublicKey, SecretKey, Signature};
 use blockchain::{Blockchain, GenesisConfig, Schema, Service, SharedNodeState, Transaction};
 use api::{private, public, Api};
-use messages::{Connect, Message, RawMessage};
+use messages::{self, Any, Connect, Message, RawMessage, RawTransaction, TransactionsBatch,
+               CONSENSUS, STATUS_MESSAGE_ID, SUPPORTED_PROTOCOL_VERSIONS};
 use events::{HandlerPart, InternalEvent, InternalPart, InternalRequest, NetworkConfiguration,
              NetworkEvent, NetworkPart, NetworkRequest, SyncSender, TimeoutRequest};
 use events::error::{into_other, log_error, other_error, LogError};
+use events::noise::HandshakeParams;
 use helpers::{user_agent, Height, Milliseconds, Round, ValidatorId};
 use storage::{Database, DbOptions};
 
+use self::signer::{sign_message, LocalSigner, SignRequest, SignResponse};
+use self::state_sync::{StateDownload, StateSnapshot};
+use self::shutdown::ShutdownStage;
+
 pub use self::state::{RequestData, State, ValidatorState};
-pub use self::whitelist::Whitelist;
+pub use self::whitelist::{SharedWhitelist, Whitelist};
+pub use self::signer::{SignedAction, Signer, SignerConfig, SignerError};
+pub use self::reputation::{PeerBehavior, PeerReputation, Reputation};
+pub use self::config_reload::ConfigReloadError;
+pub use self::shutdown::DEFAULT_SHUTDOWN_TIMEOUT;
 
 mod events;
 mod basic;
 mod consensus;
 mod requests;
+mod state_sync;
 mod whitelist;
+mod reputation;
+mod config_reload;
+mod shutdown;
+pub mod leader_election;
+pub mod signer;
 pub mod state; // TODO: temporary solution to get access to WAIT constants (ECR-167)
 pub mod timeout_adjuster;
 
@@ -66,7 +85,16 @@ pub enum ExternalMessage {
     Transaction(Box<Transaction>),
     /// Enable or disable the node.
     Enable(bool),
-    /// Shutdown the node.
+    /// Add a peer to the whitelist.
+    WhitelistAdd(PublicKey),
+    /// Remove a peer from the whitelist and disconnect from it.
+    WhitelistRemove(PublicKey),
+    /// Disconnect from a peer.
+    PeerDisconnect(PublicKey),
+    /// Reload the node configuration from the configuration file and send the result
+    /// to the given channel.
+    ReloadConfig(oneshot::Sender<Result<(), ConfigReloadError>>),
+    /// Shutdown the node gracefully. For details see `NodeConfig::shutdown_timeout`.
     Shutdown,
 }
 
@@ -85,6 +113,10 @@ pub enum NodeTimeout {
     UpdateApiState,
     /// Exchange peers timeout.
     PeerExchange,
+    /// Broadcast the accumulated transactions.
+    TxBatch,
+    /// Finish the shutdown of the node.
+    Shutdown,
 }
 
 /// A helper trait that provides the node with information about the state of the system such
@@ -117,6 +149,38 @@ pub struct NodeHandler {
     pub peer_discovery: Vec<SocketAddr>,
     /// Does this node participate in the consensus?
     is_enabled: bool,
+    /// State sync configuration.
+    state_sync: StateSyncConfig,
+    /// Snapshot of the storage served to peers during the state sync.
+    state_snapshot: Option<StateSnapshot>,
+    /// State snapshot being downloaded from a peer.
+    state_download: Option<StateDownload>,
+    /// Peer from whose connection the message being handled has been received.
+    message_source: Option<PublicKey>,
+    /// Time during which the transactions received from the clients are accumulated.
+    tx_batch_timeout: Milliseconds,
+    /// Transactions waiting to be broadcast in a batch.
+    tx_batch: Vec<RawTransaction>,
+    /// Length of the `TransactionsBatch` message with the accumulated transactions.
+    tx_batch_len: usize,
+    /// Path to the node configuration file the whitelist changes are saved to
+    /// and the configuration is reloaded from.
+    config_path: Option<PathBuf>,
+    /// Lock serializing the writes of the node configuration file.
+    config_file_lock: Arc<Mutex<()>>,
+    /// Configuration the node is running with.
+    node_config: Option<NodeConfig>,
+    /// Stage of the node shutdown.
+    shutdown_stage: ShutdownStage,
+    /// Read-only replica configuration, if the node is a replica.
+    replica: Option<ReplicaConfig>,
+    /// Remote signer of the messages, if configured.
+    remote_signer: Option<Arc<Signer>>,
+    /// Identifier of the network stamped into the headers of the messages signed by the node.
+    network_id: u8,
+    /// Own consensus messages being signed by the remote signer, identified by their height,
+    /// round and message type.
+    pending_signatures: HashSet<(Height, Round, u16)>,
 }
 
 /// Service configuration.
@@ -133,8 +197,8 @@ pub struct ServiceConfig {
 pub struct ListenerConfig {
     /// Public key.
     pub consensus_public_key: PublicKey,
-    /// Secret key.
-    pub consensus_secret_key: SecretKey,
+    /// Secret key. It may be absent if the remote signer is configured.
+    pub consensus_secret_key: Option<SecretKey>,
     /// Whitelist.
     pub whitelist: Whitelist,
     /// Socket address.
@@ -157,6 +221,12 @@ pub struct NodeApiConfig {
     ///
     /// [cors]: https://developer.mozilla.org/en-US/docs/Web/HTTP/CORS
     pub allow_origin: Option<AllowOrigin>,
+    /// Maximum number of simultaneous subscribers of the explorer event stream,
+    /// `api::public::DEFAULT_MAX_SUBSCRIPTIONS` if not set. Each subscriber occupies
+    /// a thread of the public api server, so the threads for the subscribers are reserved
+    /// in addition to the ones serving other requests.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_subscriptions: Option<usize>,
 }
 
 impl Default for NodeApiConfig {
@@ -167,10 +237,17 @@ impl Default for NodeApiConfig {
             public_api_address: None,
             private_api_address: None,
             allow_origin: None,
+            max_subscriptions: None,
         }
     }
 }
 
+impl NodeApiConfig {
+    fn max_subscriptions(&self) -> usize {
+        self.max_subscriptions.unwrap_or(public::DEFAULT_MAX_SUBSCRIPTIONS)
+    }
+}
+
 /// CORS header specification
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum AllowOrigin {
@@ -191,6 +268,74 @@ impl From<AllowOrigin> for CorsMiddleware {
     }
 }
 
+/// CORS middleware which takes the options from `SharedNodeState` on each request,
+/// so they can be changed while the api is running.
+struct ReloadableCors(SharedNodeState);
+
+impl AroundMiddleware for ReloadableCors {
+    fn around(self, handler: Box<Handler>) -> Box<Handler> {
+        Box::new(ReloadableCorsHandler {
+            handler: SharedHandler(Arc::new(handler)),
+            api_state: self.0,
+        })
+    }
+}
+
+struct ReloadableCorsHandler {
+    handler: SharedHandler,
+    api_state: SharedNodeState,
+}
+
+impl Handler for ReloadableCorsHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        match self.api_state.allow_origin() {
+            Some(allow_origin) => {
+                CorsMiddleware::from(allow_origin)
+                    .around(Box::new(self.handler.clone()))
+                    .handle(request)
+            }
+            None => self.handler.handle(request),
+        }
+    }
+}
+
+/// Rejects the requests modifying the state of the node, e.g. the transactions submitted
+/// by the clients, after the node has started to shut down, and all the requests after
+/// the node has stopped.
+struct RejectOnShutdown(SharedNodeState);
+
+impl BeforeMiddleware for RejectOnShutdown {
+    fn before(&self, request: &mut Request) -> IronResult<()> {
+        let is_modifying = match request.method {
+            Method::Get | Method::Head | Method::Options => false,
+            _ => true,
+        };
+        let body = if self.0.is_stopped() {
+            Some("The node is stopped")
+        } else if is_modifying && self.0.is_shutting_down() {
+            Some("The node is shutting down")
+        } else {
+            None
+        };
+        if let Some(body) = body {
+            return Err(IronError::new(
+                io::Error::new(io::ErrorKind::Other, body),
+                (status::ServiceUnavailable, body),
+            ));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+struct SharedHandler(Arc<Box<Handler>>);
+
+impl Handler for SharedHandler {
+    fn handle(&self, request: &mut Request) -> IronResult<Response> {
+        self.0.handle(request)
+    }
+}
+
 impl ser::Serialize for AllowOrigin {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
@@ -295,11 +440,47 @@ impl Default for EventsPoolCapacity {
     }
 }
 
+/// Strategy used to free space in the memory pool once `tx_pool_capacity` is reached.
+///
+/// Transactions referenced by the proposes of the current height are never evicted.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum TxPoolEvictionPolicy {
+    /// The transaction that has been in the pool for the longest time is evicted.
+    OldestFirst,
+    /// The transaction with the lowest [`pool_priority`] is evicted. A new transaction
+    /// is rejected if its priority is not greater than the lowest one in the full pool.
+    ///
+    /// [`pool_priority`]: ../blockchain/trait.Transaction.html#method.pool_priority
+    LowestPriorityFirst,
+    /// Each [`pool_sender`] may have at most `max_txs_per_sender` transactions in the pool;
+    /// if the pool is full, the oldest transaction is evicted.
+    ///
+    /// [`pool_sender`]: ../blockchain/trait.Transaction.html#method.pool_sender
+    PerSenderQuota {
+        /// Maximum number of uncommitted transactions from a single sender.
+        max_txs_per_sender: u64,
+    },
+}
+
+impl Default for TxPoolEvictionPolicy {
+    fn default() -> TxPoolEvictionPolicy {
+        TxPoolEvictionPolicy::OldestFirst
+    }
+}
+
 /// Memory pool configuration parameters.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct MemoryPoolConfig {
     /// Maximum number of uncommitted transactions.
     pub tx_pool_capacity: usize,
+    /// Policy applied when the number of uncommitted transactions reaches `tx_pool_capacity`.
+    #[serde(default)]
+    pub eviction_policy: TxPoolEvictionPolicy,
+    /// Time in milliseconds during which the transactions received from the clients are
+    /// accumulated to be broadcast in a single `TransactionsBatch`. Zero disables batching.
+    #[serde(default)]
+    pub tx_batch_timeout: Milliseconds,
     /// Sets the maximum number of messages that can be buffered on the event loop's
     /// notification channel before a send will fail.
     pub events_pool_capacity: EventsPoolCapacity,
@@ -309,11 +490,70 @@ impl Default for MemoryPoolConfig {
     fn default() -> MemoryPoolConfig {
         MemoryPoolConfig {
             tx_pool_capacity: 100_000,
+            eviction_policy: TxPoolEvictionPolicy::default(),
+            tx_batch_timeout: 10,
             events_pool_capacity: EventsPoolCapacity::default(),
         }
     }
 }
 
+/// State sync configuration parameters.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StateSyncConfig {
+    /// Whether a fresh node lagging behind its peers by more than `snapshot_interval` blocks
+    /// bootstraps from a state snapshot instead of executing all the blocks since genesis.
+    pub enabled: bool,
+    /// Interval in blocks between state snapshots that the node keeps for its peers.
+    pub snapshot_interval: u64,
+    /// Maximum number of storage entries in a single `StateChunkResponse`.
+    pub max_chunk_entries: u32,
+}
+
+impl Default for StateSyncConfig {
+    fn default() -> StateSyncConfig {
+        StateSyncConfig {
+            enabled: false,
+            snapshot_interval: 1000,
+            max_chunk_entries: 1000,
+        }
+    }
+}
+
+/// Peer reputation configuration parameters.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct ReputationConfig {
+    /// Peers are banned once their score drops to this value.
+    pub ban_threshold: i64,
+    /// Duration of a ban in milliseconds.
+    pub ban_duration: Milliseconds,
+}
+
+impl Default for ReputationConfig {
+    fn default() -> ReputationConfig {
+        ReputationConfig {
+            ban_threshold: -100,
+            ban_duration: 600_000,
+        }
+    }
+}
+
+/// Read-only replica configuration parameters.
+///
+/// A replica does not take part in the consensus: it ignores the consensus messages and
+/// the transactions received from its peers and follows the committed blocks through
+/// the block sync with the nodes listed in `NodeConfig::peers`, which may be validators
+/// or other replicas. The replica does not request the peers of its peers, so it is
+/// connected only to the listed nodes and the nodes connected to it. The replica
+/// advertises itself in its `Connect` message, so its peers send it only `Status`
+/// messages and the responses to its requests.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ReplicaConfig {
+    /// Address of the validator the transactions submitted through the api are
+    /// forwarded to. While the upstream is not connected, the transactions are queued
+    /// (up to `tx_pool_capacity` of them) and sent once the connection is established.
+    pub upstream: SocketAddr,
+}
+
 /// Configuration for the `Node`.
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct NodeConfig {
@@ -330,8 +570,9 @@ pub struct NodeConfig {
     pub peers: Vec<SocketAddr>,
     /// Consensus public key.
     pub consensus_public_key: PublicKey,
-    /// Consensus secret key.
-    pub consensus_secret_key: SecretKey,
+    /// Consensus secret key. It may be omitted if the remote signer is configured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub consensus_secret_key: Option<SecretKey>,
     /// Service public key.
     pub service_public_key: PublicKey,
     /// Service secret key.
@@ -348,6 +589,31 @@ pub struct NodeConfig {
     /// Optional database configuration.
     #[serde(default)]
     pub database: DbOptions,
+    /// State sync configuration.
+    #[serde(default)]
+    pub state_sync: StateSyncConfig,
+    /// Signer of the consensus messages.
+    #[serde(default)]
+    pub signer: SignerConfig,
+    /// Signer of the messages with the service key.
+    #[serde(default)]
+    pub service_signer: SignerConfig,
+    /// Peer reputation configuration.
+    #[serde(default)]
+    pub reputation: ReputationConfig,
+    /// Maximum level of the log messages, e.g. `info` or `trace`. The messages
+    /// are also filtered by the logger, so the level cannot be raised above
+    /// the one the logger is initialized with.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub log_level: Option<String>,
+    /// Maximum time in milliseconds the node waits for the connections with its peers
+    /// to be closed during the graceful shutdown. `DEFAULT_SHUTDOWN_TIMEOUT` is used
+    /// if not specified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shutdown_timeout: Option<Milliseconds>,
+    /// Runs the node as a read-only replica if specified.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub replica: Option<ReplicaConfig>,
 }
 
 /// Configuration for the `NodeHandler`.
@@ -363,6 +629,17 @@ pub struct Configuration {
     pub peer_discovery: Vec<SocketAddr>,
     /// Memory pool configuration.
     pub mempool: MemoryPoolConfig,
+    /// State sync configuration.
+    pub state_sync: StateSyncConfig,
+    /// Signer of the consensus messages.
+    pub signer: SignerConfig,
+    /// Peer reputation configuration.
+    pub reputation: ReputationConfig,
+    /// Read-only replica configuration, if the node is a replica.
+    pub replica: Option<ReplicaConfig>,
+    /// Identifier of the network stamped into the headers of the messages signed by the node.
+    /// `Node::new` derives it from the hash of the genesis block with `messages::network_id`.
+    pub network_id: u8,
 }
 
 /// Channel for messages, timeouts and api requests.
@@ -379,7 +656,7 @@ pub struct NodeSender {
 impl NodeHandler {
     /// Creates `NodeHandler` using specified `Configuration`.
     pub fn new(
-        blockchain: Blockchain,
+        mut blockchain: Blockchain,
         external_address: SocketAddr,
         sender: NodeSender,
         system_state: Box<SystemStateProvider>,
@@ -395,6 +672,10 @@ impl NodeHandler {
         let snapshot = blockchain.snapshot();
 
         let stored = Schema::new(&snapshot).actual_configuration();
+        // The network is identified by the hash of its genesis block.
+        let network_id = Schema::new(&snapshot)
+            .block_hash_by_height(Height::zero())
+            .expect("Genesis block is not found");
         info!("Creating a node with config: {:#?}", stored);
 
         let validator_id = stored
@@ -405,13 +686,35 @@ impl NodeHandler {
             })
             .map(|id| ValidatorId(id as u16));
         info!("Validator id = '{:?}'", validator_id);
-        let connect = Connect::new(
+        if validator_id.is_some() && config.replica.is_some() {
+            warn!("The node is a validator, but it runs as a read-only replica and does not vote");
+        }
+        // The presence of the consensus secret key is checked by `Node::run_handler`.
+        let remote_signer = config.signer.remote_signer(config.listener.consensus_public_key);
+        // The header of `Connect` is stamped with the network identifier, so it is signed
+        // after it is created. If the consensus secret key is not available, the message
+        // is signed by the remote signer before the node connects to the peers,
+        // see `Node::run_handler`.
+        let connect = Connect::new_with_signature(
             &config.listener.consensus_public_key,
             external_address,
             system_state.current_time().into(),
             &user_agent::get(),
-            &config.listener.consensus_secret_key,
+            SUPPORTED_PROTOCOL_VERSIONS,
+            &network_id,
+            config.replica.is_some(),
+            &Signature::zero(),
         );
+        let connect = Connect::from_raw(connect.raw().with_network_id(config.network_id))
+            .expect("Unable to stamp the network identifier into `Connect`");
+        let connect = match config.listener.consensus_secret_key {
+            Some(ref secret_key) => {
+                let signer =
+                    LocalSigner::new(config.listener.consensus_public_key, secret_key.clone());
+                sign_message(&signer, &connect).expect("Unable to sign the `Connect` message")
+            }
+            None => connect,
+        };
 
         let mut whitelist = config.listener.whitelist;
         whitelist.set_validators(stored.validator_keys.iter().map(|x| x.consensus_key));
@@ -422,6 +725,7 @@ impl NodeHandler {
             config.service.service_public_key,
             config.service.service_secret_key,
             config.mempool.tx_pool_capacity,
+            config.mempool.eviction_policy,
             whitelist,
             stored,
             connect,
@@ -429,10 +733,12 @@ impl NodeHandler {
             last_hash,
             last_height,
             system_state.current_time(),
+            config.reputation,
         );
 
-        // Adjust propose timeout for the first time.
+        // Adjust propose timeout and choose the leaders for the first time.
         state.adjust_timeout(&*snapshot);
+        state.update_leaders(&*snapshot);
 
         NodeHandler {
             blockchain,
@@ -442,9 +748,29 @@ impl NodeHandler {
             channel: sender,
             peer_discovery: config.peer_discovery,
             is_enabled: true,
+            state_sync: config.state_sync,
+            state_snapshot: None,
+            state_download: None,
+            message_source: None,
+            tx_batch_timeout: config.mempool.tx_batch_timeout,
+            tx_batch: Vec::new(),
+            tx_batch_len: TransactionsBatch::EMPTY_LEN,
+            config_path: None,
+            config_file_lock: Arc::default(),
+            node_config: None,
+            shutdown_stage: ShutdownStage::Running,
+            replica: config.replica,
+            remote_signer,
+            network_id: config.network_id,
+            pending_signatures: HashSet::new(),
         }
     }
 
+    /// Returns `true` if the node is a read-only replica.
+    pub fn is_replica(&self) -> bool {
+        self.replica.is_some()
+    }
+
     /// Return internal `SharedNodeState`
     pub fn api_state(&self) -> &SharedNodeState {
         &self.api_state
@@ -483,6 +809,7 @@ impl NodeHandler {
         let peers: HashSet<_> = {
             let it = self.state.peers().values().map(Connect::addr);
             let it = it.chain(self.peer_discovery.iter().cloned());
+            let it = it.chain(self.replica.as_ref().map(|replica| replica.upstream));
             let it = it.filter(|&address| address != listen_address);
             it.collect()
         };
@@ -500,10 +827,18 @@ impl NodeHandler {
         self.state.jump_round(round);
         info!("Jump to round {}", round);
 
-        self.add_round_timeout();
+        // Replicas do not take part in the consensus and do not look for new peers.
+        if !self.is_replica() {
+            self.add_round_timeout();
+            self.add_peer_exchange_timeout();
+        }
         self.add_status_timeout();
-        self.add_peer_exchange_timeout();
         self.add_update_api_state_timeout();
+        self.update_state_snapshot();
+
+        // Recover our votes and lock from the consensus write-ahead log, so that the cached
+        // messages are handled with the same lock and do not make the node vote again.
+        let unfinished_lock = self.restore_consensus_log();
 
         // Recover cached consensus messages if any. We do this after main initialization and before
         // the start of event processing.
@@ -511,6 +846,125 @@ impl NodeHandler {
         for msg in messages.iter() {
             self.handle_message(msg);
         }
+        if let Some(lock) = unfinished_lock {
+            self.complete_restored_lock(&lock);
+        }
+    }
+
+    /// Returns the signer of the messages with the consensus key: the in-process one if
+    /// the consensus secret key is available and the remote one otherwise.
+    ///
+    /// The remote signer blocks the caller, so it is used only before the event loop starts.
+    /// Returns an error if neither the consensus secret key nor the remote signer is
+    /// configured.
+    pub fn signer(&self) -> Result<Arc<Signer>, SignerError> {
+        match (self.state.consensus_secret_key(), self.remote_signer.as_ref()) {
+            (Some(secret_key), _) => Ok(Arc::new(LocalSigner::new(
+                *self.state.consensus_public_key(),
+                secret_key.clone(),
+            ))),
+            (None, Some(signer)) => Ok(Arc::clone(signer)),
+            (None, None) => Err(SignerError::Config(
+                "Consensus secret key is required unless the remote signer is configured"
+                    .to_owned(),
+            )),
+        }
+    }
+
+    /// Signs the message with the consensus key in-process. Returns `None` if the consensus
+    /// secret key is kept by the remote signer only.
+    pub fn sign_locally<M: Message>(&self, message: &M) -> Option<M> {
+        let secret_key = self.state.consensus_secret_key()?;
+        let signer = LocalSigner::new(*self.state.consensus_public_key(), secret_key.clone());
+        match sign_message(&signer, &self.with_network_id(message)) {
+            Ok(message) => Some(message),
+            Err(e) => {
+                error!("Unable to sign {:?}: {}", message, e);
+                None
+            }
+        }
+    }
+
+    /// Signs the message with the consensus key of the node.
+    ///
+    /// The consensus messages are signed by the remote signer if it is configured, the other
+    /// messages are signed in-process if the consensus secret key is available. The message
+    /// signed in-process is returned immediately. Otherwise `None` is returned, and the signed
+    /// message is handled according to `action` once the signature is received from
+    /// the signer thread.
+    pub fn sign<M: Message>(&mut self, message: &M, action: SignedAction) -> Option<M> {
+        let is_remote = self.remote_signer.is_some() &&
+            (action == SignedAction::Consensus || self.state.consensus_secret_key().is_none());
+        if !is_remote {
+            return self.sign_locally(message);
+        }
+
+        if action == SignedAction::Consensus {
+            let key = match Any::from_raw(message.raw().clone()) {
+                Ok(Any::Consensus(msg)) => (msg.height(), msg.round(), msg.raw().message_type()),
+                _ => panic!("Not a consensus message: {:?}", message),
+            };
+            // The same vote may be requested several times before the signature is received.
+            if !self.pending_signatures.insert(key) {
+                return None;
+            }
+        }
+        trace!("Send {:?} to the remote signer", message);
+        self.execute_later(InternalRequest::Sign(SignRequest {
+            message: self.with_network_id(message).raw().clone(),
+            action,
+        }));
+        None
+    }
+
+    /// Returns a copy of the message with the network identifier of the node in the header.
+    /// The signature of the copy has to be replaced.
+    fn with_network_id<M: Message>(&self, message: &M) -> M {
+        M::from_raw(message.raw().with_network_id(self.network_id))
+            .expect("Unable to stamp the network identifier into the message")
+    }
+
+    /// Returns `true` if the message of the node protocol is stamped with the network
+    /// identifier of another network. Transactions are stamped by the clients and
+    /// are not checked.
+    pub(crate) fn is_foreign_message(&self, raw: &RawMessage) -> bool {
+        raw.service_id() == CONSENSUS && raw.network_id() != self.network_id
+    }
+
+    /// Handles the message signed by the remote signer.
+    pub(crate) fn handle_signed_message(&mut self, response: SignResponse) {
+        let SignResponse { request, result } = response;
+        if request.action == SignedAction::Consensus {
+            if let Ok(Any::Consensus(msg)) = Any::from_raw(request.message.clone()) {
+                self.pending_signatures.remove(
+                    &(msg.height(), msg.round(), msg.raw().message_type()),
+                );
+            }
+        }
+        let message = match result {
+            Ok(message) => message,
+            Err(e) => {
+                error!("Unable to sign {:?}: {}", request.message, e);
+                return;
+            }
+        };
+
+        match request.action {
+            SignedAction::Send(peer) => self.send_to_peer(peer, &message),
+            SignedAction::Broadcast => self.broadcast(&message),
+            SignedAction::Transaction => {
+                match self.blockchain.tx_from_raw(message) {
+                    Ok(tx) => self.handle_incoming_tx(tx),
+                    Err(e) => error!("Signed transaction is invalid: {}", e),
+                }
+            }
+            SignedAction::Consensus => {
+                match Any::from_raw(message) {
+                    Ok(Any::Consensus(msg)) => self.handle_own_consensus(msg),
+                    other => error!("Signed consensus message is invalid: {:?}", other),
+                }
+            }
+        }
     }
 
     /// Sends the given message to a peer by its id.
@@ -542,9 +996,15 @@ impl NodeHandler {
         self.channel.network_requests.send(request).log_error();
     }
 
-    /// Broadcasts given message to all peers.
+    /// Broadcasts given message to all peers. Read-only replicas only receive `Status`
+    /// messages, since they take part neither in the consensus nor in the transactions gossip.
     pub fn broadcast(&mut self, message: &RawMessage) {
+        let is_status =
+            message.service_id() == CONSENSUS && message.message_type() == STATUS_MESSAGE_ID;
         for conn in self.state.peers().values() {
+            if conn.is_replica() && !is_status {
+                continue;
+            }
             let address = conn.addr();
             trace!("Send to address: {}", address);
             let request = NetworkRequest::SendMessage(address, message.clone());
@@ -632,6 +1092,13 @@ impl NodeHandler {
         self.add_timeout(NodeTimeout::UpdateApiState, time);
     }
 
+    /// Adds `NodeTimeout::TxBatch` timeout to the channel.
+    pub fn add_tx_batch_timeout(&mut self) {
+        let time = self.system_state.current_time() +
+            Duration::from_millis(self.tx_batch_timeout);
+        self.add_timeout(NodeTimeout::TxBatch, time);
+    }
+
     /// Returns hash of the last block.
     pub fn last_block_hash(&self) -> Hash {
         self.blockchain.last_block().hash()
@@ -781,6 +1248,14 @@ impl Node {
             )
         };
 
+        if let Some(ref level) = node_cfg.log_level {
+            match config_reload::parse_log_level(level) {
+                Ok(level) => ::log::set_max_level(level),
+                Err(e) => warn!("{}", e),
+            }
+        }
+        let running_config = node_cfg.clone();
+
         let channel = NodeChannel::new(&node_cfg.mempool.events_pool_capacity);
         let mut blockchain = Blockchain::new(
             db,
@@ -789,7 +1264,13 @@ impl Node {
             node_cfg.service_secret_key.clone(),
             ApiSender::new(channel.api_requests.0.clone()),
         );
+        if let Some(signer) = node_cfg.service_signer.remote_signer(node_cfg.service_public_key) {
+            blockchain.set_service_signer(signer);
+        }
         blockchain.initialize(node_cfg.genesis.clone()).unwrap();
+        let genesis_hash = Schema::new(&blockchain.snapshot())
+            .block_hash_by_height(Height::zero())
+            .expect("Genesis block is not found");
 
         let config = Configuration {
             listener: ListenerConfig {
@@ -805,6 +1286,11 @@ impl Node {
             mempool: node_cfg.mempool,
             network: node_cfg.network,
             peer_discovery: node_cfg.peers,
+            state_sync: node_cfg.state_sync,
+            signer: node_cfg.signer,
+            reputation: node_cfg.reputation,
+            replica: node_cfg.replica,
+            network_id: messages::network_id(&genesis_hash),
         };
 
         let external_address = if let Some(v) = node_cfg.external_address {
@@ -816,7 +1302,7 @@ impl Node {
         let api_state = SharedNodeState::new(node_cfg.api.state_update_timeout as u64);
         let system_state = Box::new(DefaultSystemState(node_cfg.listen_address));
         let network_config = config.network;
-        let handler = NodeHandler::new(
+        let mut handler = NodeHandler::new(
             blockchain,
             external_address,
             channel.node_sender(),
@@ -824,6 +1310,7 @@ impl Node {
             config,
             api_state,
         );
+        handler.node_config = Some(running_config);
         Node {
             api_options: node_cfg.api,
             handler,
@@ -836,9 +1323,17 @@ impl Node {
     /// Launches only consensus messages handler.
     /// This may be used if you want to customize api with the `ApiContext`.
     pub fn run_handler(mut self) -> io::Result<()> {
+        let signer = self.handler.signer().map_err(|e| other_error(&e.to_string()))?;
+        if self.state().consensus_secret_key().is_none() {
+            let connect = sign_message(&*signer, self.state().our_connect_message())
+                .map_err(|e| {
+                    other_error(&format!("Unable to sign the `Connect` message: {}", e))
+                })?;
+            self.handler.state.set_our_connect_message(connect);
+        }
         self.handler.initialize();
 
-        let (handler_part, network_part, timeouts_part) = self.into_reactor();
+        let (handler_part, network_part, timeouts_part) = self.into_reactor()?;
 
         let network_thread = thread::spawn(move || {
             let mut core = Core::new()?;
@@ -864,55 +1359,70 @@ impl Node {
     /// Explorer api prefix is `/api/explorer`
     /// Public api prefix is `/api/services/{service_name}`
     /// Private api prefix is `/api/services/{service_name}`
+    ///
+    /// After the node has stopped, the api servers reject all the requests with
+    /// `503 Service Unavailable`, but keep listening until the process exits.
     pub fn run(self) -> io::Result<()> {
         let blockchain = self.handler().blockchain.clone();
         let api_sender = self.channel();
 
-        let private_config_api_thread = match self.api_options.private_api_address {
+        let private_api = match self.api_options.private_api_address {
             Some(listen_address) => {
                 let handler = create_private_api_handler(
                     blockchain.clone(),
                     self.handler().api_state().clone(),
                     api_sender,
                 );
-                let thread = thread::spawn(move || {
-                    info!("Private exonum api started on {}", listen_address);
-                    Iron::new(handler).http(listen_address).unwrap();
-                });
-                Some(thread)
+                let listening = Iron::new(handler).http(listen_address).map_err(|e| {
+                    other_error(&format!("Unable to start private api: {}", e))
+                })?;
+                info!("Private exonum api started on {}", listen_address);
+                Some(listening)
             }
             None => None,
         };
-        let public_config_api_thread = match self.api_options.public_api_address {
+        let public_api = match self.api_options.public_api_address {
             Some(listen_address) => {
                 let handler = create_public_api_handler(
                     blockchain,
                     self.handler.api_state().clone(),
                     &self.api_options,
                 );
-                let thread = thread::spawn(move || {
-                    info!("Public exonum api started on {}", listen_address);
-                    Iron::new(handler).http(listen_address).unwrap();
-                });
-                Some(thread)
+                let mut server = Iron::new(handler);
+                // Event stream subscribers hold their threads until they disconnect.
+                if self.api_options.enable_blockchain_explorer {
+                    server.threads += self.api_options.max_subscriptions();
+                }
+                let listening = server.http(listen_address).map_err(|e| {
+                    other_error(&format!("Unable to start public api: {}", e))
+                })?;
+                info!("Public exonum api started on {}", listen_address);
+                Some(listening)
             }
             None => None,
         };
 
-        self.run_handler()?;
+        let api_state = self.handler.api_state().clone();
+        let result = self.run_handler();
 
-        if let Some(private_config_api_thread) = private_config_api_thread {
-            private_config_api_thread.join().unwrap();
-        }
-        if let Some(public_config_api_thread) = public_config_api_thread {
-            public_config_api_thread.join().unwrap();
+        // The api servers of hyper cannot be stopped: they keep accepting the connections
+        // until the process exits. The requests are rejected from now on, and the server
+        // threads are detached, since they never finish.
+        api_state.set_stopped();
+        for mut listening in private_api.into_iter().chain(public_api) {
+            if let Err(e) = listening.close() {
+                warn!("Unable to detach api server: {}", e);
+            }
         }
 
-        Ok(())
+        result
     }
 
-    fn into_reactor(self) -> (HandlerPart<NodeHandler>, NetworkPart, InternalPart) {
+    fn into_reactor(self) -> io::Result<(HandlerPart<NodeHandler>, NetworkPart, InternalPart)> {
         let connect_message = self.state().our_connect_message().clone();
+        let signer = self.handler.signer().map_err(|e| other_error(&e.to_string()))?;
+        let handshake_params = HandshakeParams::new(&*signer, self.state().whitelist().clone())
+            .map_err(|e| other_error(&format!("Unable to sign the static key: {}", e)))?;
         let (network_tx, network_rx) = self.channel.network_events;
         let internal_requests_rx = self.channel.internal_requests.1;
         let network_part = NetworkPart {
@@ -922,6 +1432,8 @@ impl Node {
             network_tx,
             network_config: self.network_config,
             max_message_len: self.max_message_len,
+            handshake_params,
+            api_state: self.handler.api_state.clone(),
         };
 
         let (internal_tx, internal_rx) = self.channel.internal_events;
@@ -935,8 +1447,9 @@ impl Node {
         let timeouts_part = InternalPart {
             internal_tx,
             internal_requests_rx,
+            signer: self.handler.remote_signer.clone(),
         };
-        (handler_part, network_part, timeouts_part)
+        Ok((handler_part, network_part, timeouts_part))
     }
 
     /// Returns `Blockchain` instance.
@@ -949,6 +1462,12 @@ impl Node {
         self.handler.state()
     }
 
+    /// Sets the path to the node configuration file. The changes of the whitelist made
+    /// through the private api are saved to this file.
+    pub fn set_config_path<P: Into<PathBuf>>(&mut self, path: P) {
+        self.handler.config_path = Some(path.into());
+    }
+
     /// Returns `NodeHandler`.
     pub fn handler(&self) -> &NodeHandler {
         &self.handler
@@ -972,20 +1491,22 @@ pub fn create_public_api_handler(
 
     if config.enable_blockchain_explorer {
         let mut router = Router::new();
-        let explorer_api = public::ExplorerApi::new(blockchain.clone());
+        let explorer_api = public::ExplorerApi::new(blockchain.clone())
+            .with_max_subscriptions(config.max_subscriptions());
         explorer_api.wire(&mut router);
         mount.mount("api/explorer", router);
     }
 
     let mut router = Router::new();
-    let system_api = public::SystemApi::new(blockchain, shared_api_state);
+    let system_api = public::SystemApi::new(blockchain, shared_api_state.clone());
     system_api.wire(&mut router);
     mount.mount("api/system", router);
 
+    // CORS options can be changed by reloading the node configuration.
+    shared_api_state.set_allow_origin(config.allow_origin.clone());
     let mut chain = Chain::new(mount);
-    if let Some(ref allow_origin) = config.allow_origin {
-        chain.link_around(CorsMiddleware::from(allow_origin.clone()));
-    }
+    chain.link_before(RejectOnShutdown(shared_api_state.clone()));
+    chain.link_around(ReloadableCors(shared_api_state));
     chain
 }
 
@@ -1000,10 +1521,17 @@ pub fn create_private_api_handler(
     mount.mount("api/services", blockchain.mount_private_api());
 
     let mut router = Router::new();
-    let node_info = private::NodeInfo::new(blockchain.service_map().iter().map(|(_, s)| s));
-    let system_api = private::SystemApi::new(node_info, blockchain, shared_api_state, api_sender);
+    let genesis_hash = Schema::new(&blockchain.snapshot())
+        .block_hash_by_height(Height::zero())
+        .expect("Genesis block is not found");
+    let node_info = private::NodeInfo::new(blockchain.service_map().iter().map(|(_, s)| s))
+        .with_network_id(messages::network_id(&genesis_hash));
+    let system_api =
+        private::SystemApi::new(node_info, blockchain, shared_api_state.clone(), api_sender);
     system_api.wire(&mut router);
     mount.mount("api/system", router);
 
-    Chain::new(mount)
+    let mut chain = Chain::new(mount);
+    chain.link_before(RejectOnShutdown(shared_api_state));
+    chain
 }
@@ -0,0 +1,257 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Reputation of peers.
+//!
+//! Every peer has a score that is lowered for misbehavior (invalid signatures, undecodable
+//! messages and invalid blocks) and raised for useful responses. A peer whose score drops
+//! to `ReputationConfig::ban_threshold` is banned for `ReputationConfig::ban_duration`:
+//! the network part rejects its connections and the node ignores its messages. The score
+//! of a banned peer is reset, so the peer starts from scratch once the ban expires.
+
+use std::cmp;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::{Duration, SystemTime};
+
+use crypto::PublicKey;
+use super::ReputationConfig;
+
+/// Maximum score of a peer, so that a long record of useful responses cannot outweigh
+/// the subsequent misbehavior.
+pub const MAX_SCORE: i64 = 100;
+
+/// Behavior of a peer affecting its reputation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerBehavior {
+    /// The peer has sent a message with an invalid signature.
+    InvalidSignature,
+    /// The peer has sent a message that cannot be decoded.
+    MalformedMessage,
+    /// The peer has sent an invalid block.
+    InvalidBlock,
+    /// The peer has sent a useful response, e.g., a block that has been committed.
+    UsefulResponse,
+}
+
+impl PeerBehavior {
+    /// Returns the change of the peer score caused by the behavior.
+    pub fn score(&self) -> i64 {
+        match *self {
+            PeerBehavior::InvalidSignature => -20,
+            PeerBehavior::MalformedMessage => -10,
+            PeerBehavior::InvalidBlock => -50,
+            PeerBehavior::UsefulResponse => 1,
+        }
+    }
+}
+
+/// Reputation of a single peer.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PeerReputation {
+    score: i64,
+    banned_until: Option<SystemTime>,
+}
+
+impl PeerReputation {
+    /// Returns the current score of the peer.
+    pub fn score(&self) -> i64 {
+        self.score
+    }
+
+    /// Returns the time when the last ban of the peer expires.
+    pub fn banned_until(&self) -> Option<SystemTime> {
+        self.banned_until
+    }
+
+    /// Returns `true` if the peer is banned at the given time.
+    pub fn is_banned(&self, now: SystemTime) -> bool {
+        self.banned_until.map_or(false, |until| now < until)
+    }
+}
+
+/// Reputation of the known peers.
+#[derive(Debug)]
+pub struct Reputation {
+    config: ReputationConfig,
+    peers: HashMap<PublicKey, PeerReputation>,
+    connections: HashMap<SocketAddr, PublicKey>,
+}
+
+impl Reputation {
+    /// Creates an empty reputation with the given configuration.
+    pub fn new(config: ReputationConfig) -> Reputation {
+        Reputation {
+            config,
+            peers: HashMap::new(),
+            connections: HashMap::new(),
+        }
+    }
+
+    /// Returns the reputation configuration.
+    pub fn config(&self) -> &ReputationConfig {
+        &self.config
+    }
+
+    /// Returns the reputation of all the peers that have been scored.
+    pub fn peers(&self) -> &HashMap<PublicKey, PeerReputation> {
+        &self.peers
+    }
+
+    /// Returns the reputation of the peer.
+    pub fn get(&self, peer: &PublicKey) -> PeerReputation {
+        self.peers.get(peer).cloned().unwrap_or_default()
+    }
+
+    /// Returns `true` if the peer is banned at the given time.
+    pub fn is_banned(&self, peer: &PublicKey, now: SystemTime) -> bool {
+        self.peers.get(peer).map_or(false, |peer| peer.is_banned(now))
+    }
+
+    /// Updates the score of the peer according to its behavior. Returns `true` if the peer
+    /// has been banned as a result.
+    pub fn update(&mut self, peer: &PublicKey, behavior: PeerBehavior, now: SystemTime) -> bool {
+        let ban_threshold = self.config.ban_threshold;
+
+        {
+            let reputation = self.peers.entry(*peer).or_insert_with(Default::default);
+            if reputation.is_banned(now) {
+                return false;
+            }
+            reputation.score = cmp::min(reputation.score + behavior.score(), MAX_SCORE);
+            if reputation.score > ban_threshold {
+                return false;
+            }
+        }
+        self.ban(peer, now)
+    }
+
+    /// Bans the peer regardless of its score, e.g., for sending the data that cannot be sent
+    /// by an honest peer. Returns `false` if the peer is already banned.
+    pub fn ban(&mut self, peer: &PublicKey, now: SystemTime) -> bool {
+        let ban_duration = Duration::from_millis(self.config.ban_duration);
+
+        let reputation = self.peers.entry(*peer).or_insert_with(Default::default);
+        if reputation.is_banned(now) {
+            return false;
+        }
+        reputation.score = 0;
+        reputation.banned_until = Some(now + ban_duration);
+        true
+    }
+
+    /// Records that the peer has connected to the node from the given address, so that
+    /// the messages received over the connection are attributed to the peer.
+    pub fn add_connection(&mut self, address: SocketAddr, peer: PublicKey) {
+        self.connections.retain(|_, key| *key != peer);
+        self.connections.insert(address, peer);
+    }
+
+    /// Returns the peer that has connected to the node from the given address.
+    pub fn peer_by_connection(&self, address: &SocketAddr) -> Option<&PublicKey> {
+        self.connections.get(address)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use crypto::gen_keypair;
+    use node::ReputationConfig;
+    use super::{PeerBehavior, Reputation, MAX_SCORE};
+
+    fn reputation() -> Reputation {
+        Reputation::new(ReputationConfig {
+            ban_threshold: -100,
+            ban_duration: 1000,
+        })
+    }
+
+    #[test]
+    fn test_ban_on_low_score() {
+        let mut reputation = reputation();
+        let (peer, _) = gen_keypair();
+        let now = UNIX_EPOCH + Duration::from_secs(10);
+
+        assert!(!reputation.update(&peer, PeerBehavior::InvalidBlock, now));
+        assert_eq!(reputation.get(&peer).score(), -50);
+        assert!(!reputation.is_banned(&peer, now));
+
+        assert!(reputation.update(&peer, PeerBehavior::InvalidBlock, now));
+        assert!(reputation.is_banned(&peer, now));
+        assert_eq!(reputation.get(&peer).score(), 0);
+        assert_eq!(
+            reputation.get(&peer).banned_until(),
+            Some(now + Duration::from_millis(1000))
+        );
+
+        // Behavior of a banned peer is not scored.
+        assert!(!reputation.update(&peer, PeerBehavior::InvalidBlock, now));
+        assert_eq!(reputation.get(&peer).score(), 0);
+
+        // The ban expires.
+        let later = now + Duration::from_millis(1000);
+        assert!(!reputation.is_banned(&peer, later));
+        assert!(!reputation.update(&peer, PeerBehavior::MalformedMessage, later));
+        assert_eq!(reputation.get(&peer).score(), -10);
+    }
+
+    #[test]
+    fn test_explicit_ban() {
+        let mut reputation = reputation();
+        let (peer, _) = gen_keypair();
+        let now = UNIX_EPOCH;
+
+        for _ in 0..MAX_SCORE {
+            reputation.update(&peer, PeerBehavior::UsefulResponse, now);
+        }
+        assert!(reputation.ban(&peer, now));
+        assert!(reputation.is_banned(&peer, now));
+        assert_eq!(reputation.get(&peer).score(), 0);
+        assert!(!reputation.ban(&peer, now));
+    }
+
+    #[test]
+    fn test_score_is_limited() {
+        let mut reputation = reputation();
+        let (peer, _) = gen_keypair();
+        let now = UNIX_EPOCH;
+
+        for _ in 0..MAX_SCORE + 10 {
+            reputation.update(&peer, PeerBehavior::UsefulResponse, now);
+        }
+        assert_eq!(reputation.get(&peer).score(), MAX_SCORE);
+
+        for _ in 0..3 {
+            assert!(!reputation.update(&peer, PeerBehavior::InvalidBlock, now));
+        }
+        assert!(reputation.update(&peer, PeerBehavior::InvalidBlock, now));
+    }
+
+    #[test]
+    fn test_connections() {
+        let mut reputation = reputation();
+        let (peer, _) = gen_keypair();
+        let first = "127.0.0.1:5000".parse().unwrap();
+        let second = "127.0.0.1:5001".parse().unwrap();
+
+        reputation.add_connection(first, peer);
+        assert_eq!(reputation.peer_by_connection(&first), Some(&peer));
+
+        reputation.add_connection(second, peer);
+        assert_eq!(reputation.peer_by_connection(&first), None);
+        assert_eq!(reputation.peer_by_connection(&second), Some(&peer));
+    }
+}
@@ -12,10 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::cmp;
+use std::mem;
+
+use crypto::{PublicKey, Signature};
 use messages::{RequestMessage, Message, ProposeRequest, TransactionsRequest, PrevotesRequest,
-               BlockRequest, BlockResponse};
+               BlockRequest, BlockResponse, BlocksRequest, RawMessage, TransactionsResponse};
 use blockchain::Schema;
-use super::NodeHandler;
+use helpers::Height;
+use super::{NodeHandler, PeerBehavior, SignedAction};
+use super::state::MAX_BLOCKS_PER_REQUEST;
 
 // TODO: height should be updated after any message, not only after status (if signature is correct)
 // TODO: Request propose makes sense only if we know that node is on our height.
@@ -39,6 +45,7 @@ impl NodeHandler {
 
         if !msg.verify(msg.from()) {
             error!("Received request with incorrect signature, msg={:?}", msg);
+            self.penalize_message_source(PeerBehavior::InvalidSignature);
             return;
         }
 
@@ -48,6 +55,9 @@ impl NodeHandler {
             RequestMessage::Prevotes(msg) => self.handle_request_prevotes(&msg),
             RequestMessage::Peers(msg) => self.handle_request_peers(&msg),
             RequestMessage::Block(msg) => self.handle_request_block(&msg),
+            RequestMessage::StateSnapshot(msg) => self.handle_request_state_snapshot(&msg),
+            RequestMessage::StateChunk(msg) => self.handle_request_state_chunk(&msg),
+            RequestMessage::Blocks(msg) => self.handle_request_blocks(&msg),
         }
     }
 
@@ -74,13 +84,43 @@ impl NodeHandler {
     /// Handles `TransactionsRequest` message. For details see the message documentation.
     pub fn handle_request_txs(&mut self, msg: &TransactionsRequest) {
         trace!("HANDLE TRANSACTIONS REQUEST");
-        let snapshot = self.blockchain.snapshot();
-        let schema = Schema::new(&snapshot);
-        for hash in msg.txs() {
-            let tx = schema.transactions().get(hash);
-            if let Some(tx) = tx {
-                self.send_to_peer(*msg.from(), &tx);
+        let txs = {
+            let snapshot = self.blockchain.snapshot();
+            let schema = Schema::new(&snapshot);
+            msg.txs()
+                .iter()
+                .filter_map(|hash| schema.transactions().get(hash))
+                .collect::<Vec<_>>()
+        };
+
+        // Transactions are sent in responses limited by the maximum message length.
+        let max_message_len = self.state.config().consensus.max_message_len as usize;
+        let mut response = Vec::new();
+        let mut response_len = TransactionsResponse::EMPTY_LEN;
+        for tx in txs {
+            let tx_len = TransactionsResponse::tx_len(&tx);
+            if !response.is_empty() && response_len + tx_len > max_message_len {
+                let txs = mem::replace(&mut response, Vec::new());
+                self.send_transactions_response(*msg.from(), txs);
+                response_len = TransactionsResponse::EMPTY_LEN;
             }
+            response.push(tx);
+            response_len += tx_len;
+        }
+        if !response.is_empty() {
+            self.send_transactions_response(*msg.from(), response);
+        }
+    }
+
+    fn send_transactions_response(&mut self, peer: PublicKey, txs: Vec<RawMessage>) {
+        let response = TransactionsResponse::new_with_signature(
+            self.state.consensus_public_key(),
+            &peer,
+            txs,
+            &Signature::zero(),
+        );
+        if let Some(response) = self.sign(&response, SignedAction::Send(peer)) {
+            self.send_to_peer(peer, response.raw());
         }
     }
 
@@ -115,28 +155,75 @@ impl NodeHandler {
             return;
         }
 
+        self.send_block(msg.from(), msg.height());
+    }
+
+    /// Handles `BlocksRequest` message. For details see the message documentation.
+    pub fn handle_request_blocks(&mut self, msg: &BlocksRequest) {
+        trace!(
+            "Handle blocks request with height:{}, count: {}, our height: {}",
+            msg.height(),
+            msg.count(),
+            self.state.height()
+        );
+        let count = u64::from(cmp::min(msg.count(), MAX_BLOCKS_PER_REQUEST));
+        let end = cmp::min(msg.height().0.saturating_add(count), self.state.height().0);
+        for height in msg.height().0..end {
+            self.send_block(msg.from(), Height(height));
+        }
+    }
+
+    // Sends the committed block of the given height to the peer. If the block does not fit
+    // into `max_message_len`, its transactions are split between several messages.
+    fn send_block(&mut self, to: &PublicKey, height: Height) {
         let snapshot = self.blockchain.snapshot();
         let schema = Schema::new(&snapshot);
 
-        let height = msg.height();
         let block_hash = schema.block_hash_by_height(height).unwrap();
 
         let block = schema.blocks().get(&block_hash).unwrap();
-        let precommits = schema.precommits(&block_hash);
-        let transactions = schema.block_transactions(height);
-
+        let precommits = schema.precommits(&block_hash).iter().collect::<Vec<_>>();
+        let transactions = schema
+            .block_transactions(height)
+            .iter()
+            .map(|tx_hash| schema.transactions().get(&tx_hash).unwrap())
+            .collect::<Vec<_>>();
 
-        let block_msg = BlockResponse::new(
+        let max_message_len = self.state.config().consensus.max_message_len as usize;
+        let empty_msg = BlockResponse::new_with_signature(
             self.state.consensus_public_key(),
-            msg.from(),
-            block,
-            precommits.iter().collect(),
-            transactions
-                .iter()
-                .map(|tx_hash| schema.transactions().get(&tx_hash).unwrap())
-                .collect(),
-            self.state.consensus_secret_key(),
+            to,
+            block.clone(),
+            precommits.clone(),
+            Vec::new(),
+            &Signature::zero(),
         );
-        self.send_to_peer(*msg.from(), block_msg.raw());
+        let empty_len = empty_msg.raw().len();
+
+        let mut chunks = vec![Vec::new()];
+        let mut chunk_len = empty_len;
+        for tx in transactions {
+            let tx_len = BlockResponse::tx_len(&tx);
+            if chunk_len + tx_len > max_message_len && !chunks.last().unwrap().is_empty() {
+                chunks.push(Vec::new());
+                chunk_len = empty_len;
+            }
+            chunk_len += tx_len;
+            chunks.last_mut().unwrap().push(tx);
+        }
+
+        for chunk in chunks {
+            let block_msg = BlockResponse::new_with_signature(
+                self.state.consensus_public_key(),
+                to,
+                block.clone(),
+                precommits.clone(),
+                chunk,
+                &Signature::zero(),
+            );
+            if let Some(block_msg) = self.sign(&block_msg, SignedAction::Send(*to)) {
+                self.send_to_peer(*to, block_msg.raw());
+            }
+        }
     }
 }
@@ -0,0 +1,179 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Graceful shutdown of the node.
+//!
+//! The shutdown proceeds as follows:
+//!
+//! - the node stops accepting transactions from the api and broadcasts the accumulated
+//!   transactions batch;
+//! - the node sends `Disconnect` message to every connected peer and closes the
+//!   connections once the pending messages are written;
+//! - when all the connections are closed or `NodeConfig::shutdown_timeout` expires,
+//!   the `shutdown` hooks of the services are invoked, the storage is flushed to the disk
+//!   and the event loops are stopped.
+//!
+//! A block being committed is always committed completely, because the node handles
+//! the shutdown request only between the events.
+
+use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::time::Duration;
+
+use crypto::{PublicKey, Signature};
+use events::{InternalRequest, NetworkRequest};
+use events::error::LogError;
+use helpers::Milliseconds;
+use messages::{Disconnect, Message};
+use super::{NodeHandler, NodeTimeout};
+
+/// Default time in milliseconds the node waits for its peers to disconnect
+/// during shutdown.
+pub const DEFAULT_SHUTDOWN_TIMEOUT: Milliseconds = 5_000;
+
+/// Stage of the node shutdown.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum ShutdownStage {
+    /// The node is running.
+    Running,
+    /// The node waits for the connections with the given addresses to be closed.
+    Draining(HashSet<SocketAddr>),
+    /// The node is stopped.
+    Stopped,
+}
+
+impl NodeHandler {
+    /// Returns `true` if the node has started to shut down.
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutdown_stage != ShutdownStage::Running
+    }
+
+    /// Starts the graceful shutdown of the node.
+    pub fn handle_shutdown(&mut self) {
+        if self.is_shutting_down() {
+            info!("The node is already shutting down");
+            return;
+        }
+        info!("Shutting down the node");
+        self.api_state.set_shutting_down();
+        self.handle_tx_batch_timeout();
+
+        let disconnect = Disconnect::new_with_signature(
+            self.state.consensus_public_key(),
+            self.system_state.current_time().into(),
+            &Signature::zero(),
+        );
+        // The connections are closed right away, so there is no time to wait for
+        // the remote signer.
+        let disconnect = self.sign_locally(&disconnect);
+        let peers: Vec<(PublicKey, SocketAddr)> = self.state
+            .peers()
+            .iter()
+            .map(|(key, connect)| (*key, connect.addr()))
+            .collect();
+        let mut connections = HashSet::new();
+        for (key, address) in peers {
+            if let Some(ref disconnect) = disconnect {
+                self.send_to_addr(&address, disconnect.raw());
+            }
+            self.disconnect_peer(&key);
+            connections.insert(address);
+        }
+
+        let is_drained = connections.is_empty();
+        self.shutdown_stage = ShutdownStage::Draining(connections);
+        if is_drained {
+            self.finish_shutdown();
+        } else {
+            let timeout = self.node_config
+                .as_ref()
+                .and_then(|config| config.shutdown_timeout)
+                .unwrap_or(DEFAULT_SHUTDOWN_TIMEOUT);
+            let time = self.system_state.current_time() + Duration::from_millis(timeout);
+            self.add_timeout(NodeTimeout::Shutdown, time);
+        }
+    }
+
+    /// Handles the `Disconnect` message. For details see the message documentation.
+    pub fn handle_disconnect(&mut self, msg: &Disconnect) {
+        let connect_time = match self.state.peers().get(msg.from()) {
+            Some(connect) => connect.time(),
+            None => {
+                trace!("Received Disconnect from unknown peer {:?}", msg.from());
+                return;
+            }
+        };
+        if msg.time() < connect_time {
+            trace!("Received outdated Disconnect from {:?}", msg.from());
+            return;
+        }
+        if !msg.verify_signature(msg.from()) {
+            error!("Received Disconnect with incorrect signature: {:?}", msg);
+            return;
+        }
+        info!("Peer {:?} is shutting down", msg.from());
+        self.disconnect_peer(msg.from());
+    }
+
+    /// Notes that the connection with the given address is closed. The node finishes
+    /// the shutdown when all the connections are closed.
+    pub(crate) fn confirm_disconnection(&mut self, address: &SocketAddr) {
+        let is_drained = match self.shutdown_stage {
+            ShutdownStage::Draining(ref mut connections) => {
+                connections.remove(address);
+                connections.is_empty()
+            }
+            _ => false,
+        };
+        if is_drained {
+            self.finish_shutdown();
+        }
+    }
+
+    /// Handles `NodeTimeout::Shutdown`, finishing the shutdown regardless of the connections
+    /// which are not closed yet.
+    pub fn handle_shutdown_timeout(&mut self) {
+        if let ShutdownStage::Draining(ref connections) = self.shutdown_stage {
+            warn!(
+                "Shutdown timeout expired, connections with {:?} are not closed",
+                connections
+            );
+        } else {
+            return;
+        }
+        self.finish_shutdown();
+    }
+
+    // Notifies the services, flushes the storage and stops the event loops.
+    fn finish_shutdown(&mut self) {
+        self.shutdown_stage = ShutdownStage::Stopped;
+
+        for service in self.blockchain.service_map().values() {
+            service.shutdown();
+        }
+
+        let patch = self.blockchain.fork().into_patch();
+        match self.blockchain.merge_sync(patch) {
+            Ok(()) => info!("The storage is flushed to the disk"),
+            Err(e) => error!("Unable to flush the storage: {}", e),
+        }
+
+        self.channel
+            .network_requests
+            .send(NetworkRequest::Shutdown)
+            .log_error();
+        self.execute_later(InternalRequest::Shutdown);
+        info!("The node is stopped");
+    }
+}
@@ -0,0 +1,689 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Signing of the node messages with the consensus key.
+//!
+//! The node signs all its messages with a [`Signer`]. By default the consensus secret key
+//! from the node configuration is used in-process ([`LocalSigner`]), but the key may also
+//! be kept by an external signer process ([`RemoteSigner`]) that is reachable over TCP or
+//! a Unix domain socket.
+//!
+//! If the remote signer is configured, the consensus messages (`Propose`, `Prevote` and
+//! `Precommit`) are always signed by it. The other messages are signed in-process if
+//! the consensus secret key is present in the node configuration, and by the remote signer
+//! otherwise. Requests to the remote signer are handled by a separate thread, so the node
+//! keeps processing events while waiting for the signature.
+//!
+//! The messages of the services, e.g. the transactions sent by the validators, are signed
+//! with the service key by a separate signer, which is available to the services as
+//! [`ServiceContext::signer`] and [`ApiContext::signer`]. It may be remote as well; such
+//! requests block the service until the message is signed.
+//!
+//! # Protocol
+//!
+//! Each request and response is a frame: the length of the payload as `u32` in the
+//! little-endian byte order followed by the payload itself. The first byte of a request
+//! payload is the request type:
+//!
+//! - [`SIGN_REQUEST`] followed by the message bytes without the signature,
+//! - [`PUBLIC_KEY_REQUEST`] without any data.
+//!
+//! The first byte of a response payload is the status. [`RESPONSE_OK`] is followed by
+//! the signature or the public key, [`RESPONSE_REFUSED`] and [`RESPONSE_ERROR`] are
+//! followed by the UTF-8 description of the reason.
+//!
+//! The reference [`SignerServer`] refuses to sign consensus messages that conflict with
+//! the ones signed before for the same height and round, as well as consensus messages
+//! for the heights lower than the last signed one. Its state is persisted with
+//! [`DoubleSignGuard`], so the protection survives restarts of the signer.
+//!
+//! [`Signer`]: trait.Signer.html
+//! [`ServiceContext::signer`]: ../../blockchain/struct.ServiceContext.html#method.signer
+//! [`ApiContext::signer`]: ../../blockchain/struct.ApiContext.html#method.signer
+//! [`LocalSigner`]: struct.LocalSigner.html
+//! [`RemoteSigner`]: struct.RemoteSigner.html
+//! [`SignerServer`]: struct.SignerServer.html
+//! [`DoubleSignGuard`]: struct.DoubleSignGuard.html
+//! [`SIGN_REQUEST`]: constant.SIGN_REQUEST.html
+//! [`PUBLIC_KEY_REQUEST`]: constant.PUBLIC_KEY_REQUEST.html
+//! [`RESPONSE_OK`]: constant.RESPONSE_OK.html
+//! [`RESPONSE_REFUSED`]: constant.RESPONSE_REFUSED.html
+//! [`RESPONSE_ERROR`]: constant.RESPONSE_ERROR.html
+
+use byteorder::{ByteOrder, LittleEndian};
+use serde_json;
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc as std_mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use futures::Sink;
+use futures::sync::mpsc;
+
+use blockchain::is_conflicting;
+use crypto::{self, PublicKey, SecretKey, Signature};
+use encoding::serialize::{decode_hex, encode_hex};
+use events::InternalEvent;
+use helpers::{Height, Milliseconds};
+use messages::{Any, ConsensusMessage, Message, RawMessage, HEADER_LENGTH};
+
+/// Request to sign the message.
+pub const SIGN_REQUEST: u8 = 0;
+/// Request to return the public key of the signer.
+pub const PUBLIC_KEY_REQUEST: u8 = 1;
+
+/// The request is fulfilled.
+pub const RESPONSE_OK: u8 = 0;
+/// The signer refuses to sign the message.
+pub const RESPONSE_REFUSED: u8 = 1;
+/// The request is malformed.
+pub const RESPONSE_ERROR: u8 = 2;
+
+/// Maximum length of a frame payload in bytes.
+pub const MAX_FRAME_LENGTH: u32 = 16 * 1024 * 1024;
+
+/// Signs the node messages with the consensus key.
+pub trait Signer: Send + Sync + fmt::Debug {
+    /// Returns the consensus public key of the node.
+    fn public_key(&self) -> &PublicKey;
+
+    /// Signs the message bytes without the signature.
+    fn sign(&self, data: &[u8]) -> Result<Signature, SignerError>;
+}
+
+/// Replaces the signature of the message with the one produced by the signer.
+pub fn sign_message<M: Message>(signer: &Signer, message: &M) -> Result<M, SignerError> {
+    let raw = sign_raw(signer, message.raw())?;
+    M::from_raw(raw).map_err(|e| SignerError::Protocol(e.to_string()))
+}
+
+/// Replaces the signature of the raw message with the one produced by the signer.
+pub fn sign_raw(signer: &Signer, message: &RawMessage) -> Result<RawMessage, SignerError> {
+    let body = message.body();
+    let signature = signer.sign(body)?;
+    let mut raw = body.to_vec();
+    raw.extend_from_slice(signature.as_ref());
+    Ok(RawMessage::from_vec(raw))
+}
+
+/// Errors that may occur while signing a message.
+#[derive(Debug, Fail)]
+pub enum SignerError {
+    /// Unable to communicate with the signer or to persist its state.
+    #[fail(display = "Signer IO error: {}", _0)]
+    Io(#[cause] io::Error),
+    /// The signer refuses to sign the message.
+    #[fail(display = "Signing is refused: {}", _0)]
+    Refused(String),
+    /// The signer violates the protocol.
+    #[fail(display = "Signer protocol error: {}", _0)]
+    Protocol(String),
+    /// The signer configuration of the node is invalid.
+    #[fail(display = "Invalid signer configuration: {}", _0)]
+    Config(String),
+}
+
+impl From<io::Error> for SignerError {
+    fn from(e: io::Error) -> SignerError {
+        SignerError::Io(e)
+    }
+}
+
+/// Signer configuration.
+///
+/// The node configuration contains separate signer configurations for the consensus key
+/// (`signer`) and the service key (`service_signer`).
+///
+/// The consensus secret key is optional in the node configuration if the remote signer is
+/// configured: it is only used to sign the messages other than the consensus ones without
+/// a round trip to the signer. The service secret key is still required in the node
+/// configuration, because it is returned by `ServiceContext::secret_key` and
+/// `ApiContext::secret_key` to the services which sign their messages in-process, but it is
+/// not used by the node itself if the remote service signer is configured.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum SignerConfig {
+    /// Messages are signed in-process with the consensus secret key of the node.
+    Local,
+    /// Messages are signed by an external signer process.
+    Remote {
+        /// Address of the signer: `host:port` for TCP or `unix:path` for a Unix socket.
+        address: String,
+        /// Timeout of a single request to the signer.
+        timeout: Milliseconds,
+    },
+}
+
+impl Default for SignerConfig {
+    fn default() -> SignerConfig {
+        SignerConfig::Local
+    }
+}
+
+impl SignerConfig {
+    /// Creates the remote signer for the node with the given consensus public key.
+    /// Returns `None` if the messages are signed in-process.
+    pub fn remote_signer(&self, public_key: PublicKey) -> Option<Arc<Signer>> {
+        match *self {
+            SignerConfig::Local => None,
+            SignerConfig::Remote {
+                ref address,
+                timeout,
+            } => Some(Arc::new(RemoteSigner::new(
+                address,
+                public_key,
+                Duration::from_millis(timeout),
+            ))),
+        }
+    }
+}
+
+/// Action performed by the node with a message once it is signed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SignedAction {
+    /// Send the message to the peer.
+    Send(PublicKey),
+    /// Broadcast the message to all peers.
+    Broadcast,
+    /// Handle the message as an incoming transaction.
+    Transaction,
+    /// Handle the message as an own consensus message.
+    Consensus,
+}
+
+/// Request to sign a message in the signer thread.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SignRequest {
+    /// The message with an arbitrary signature.
+    pub message: RawMessage,
+    /// Action performed with the signed message.
+    pub action: SignedAction,
+}
+
+/// Result of signing a message in the signer thread.
+#[derive(Debug)]
+pub struct SignResponse {
+    /// The message as it was requested to be signed.
+    pub request: SignRequest,
+    /// The signed message.
+    pub result: Result<RawMessage, SignerError>,
+}
+
+/// Starts the thread that signs the messages with the remote signer and sends the results
+/// to the node as `InternalEvent::MessageSigned` events. The thread stops once
+/// the returned sender or the events receiver is dropped.
+pub(crate) fn spawn_signer_thread(
+    signer: Arc<Signer>,
+    events: mpsc::Sender<InternalEvent>,
+) -> io::Result<std_mpsc::Sender<SignRequest>> {
+    let (requests_tx, requests_rx) = std_mpsc::channel::<SignRequest>();
+    thread::Builder::new()
+        .name("signer".to_owned())
+        .spawn(move || {
+            let mut events = events.wait();
+            for request in requests_rx {
+                let result = sign_raw(&*signer, &request.message);
+                let response = SignResponse { request, result };
+                if events.send(InternalEvent::MessageSigned(response)).is_err() {
+                    break;
+                }
+            }
+        })?;
+    Ok(requests_tx)
+}
+
+/// Signer that keeps the secret key in memory.
+#[derive(Debug, Clone)]
+pub struct LocalSigner {
+    public_key: PublicKey,
+    secret_key: SecretKey,
+}
+
+impl LocalSigner {
+    /// Creates a signer with the given key pair.
+    pub fn new(public_key: PublicKey, secret_key: SecretKey) -> LocalSigner {
+        LocalSigner {
+            public_key,
+            secret_key,
+        }
+    }
+}
+
+impl Signer for LocalSigner {
+    fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    fn sign(&self, data: &[u8]) -> Result<Signature, SignerError> {
+        Ok(crypto::sign(data, &self.secret_key))
+    }
+}
+
+/// Address of the remote signer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignerAddress {
+    /// TCP address in the `host:port` form.
+    Tcp(String),
+    /// Path to the Unix domain socket.
+    Unix(PathBuf),
+}
+
+impl SignerAddress {
+    /// Parses the address: the `unix:` prefix denotes a Unix socket, otherwise the address
+    /// is treated as a TCP one.
+    pub fn parse(address: &str) -> SignerAddress {
+        if address.starts_with("unix:") {
+            SignerAddress::Unix(PathBuf::from(&address["unix:".len()..]))
+        } else {
+            SignerAddress::Tcp(address.to_owned())
+        }
+    }
+}
+
+trait Stream: Read + Write + Send {}
+
+impl<T: Read + Write + Send> Stream for T {}
+
+/// Signer that sends the messages to an external signer process.
+///
+/// The connection to the signer is established on the first request and is reused by
+/// the subsequent ones. A request that fails because of an IO error is retried once with
+/// a new connection.
+pub struct RemoteSigner {
+    public_key: PublicKey,
+    address: SignerAddress,
+    timeout: Duration,
+    stream: Mutex<Option<Box<Stream>>>,
+}
+
+impl RemoteSigner {
+    /// Creates a signer for the given address that is expected to sign with the key
+    /// corresponding to `public_key`.
+    pub fn new(address: &str, public_key: PublicKey, timeout: Duration) -> RemoteSigner {
+        RemoteSigner {
+            public_key,
+            address: SignerAddress::parse(address),
+            timeout,
+            stream: Mutex::new(None),
+        }
+    }
+
+    fn connect(&self) -> Result<Box<Stream>, SignerError> {
+        let mut stream: Box<Stream> = match self.address {
+            SignerAddress::Tcp(ref address) => {
+                let address = address.to_socket_addrs()?.next().ok_or_else(|| {
+                    SignerError::Protocol(format!("Unable to resolve {}", address))
+                })?;
+                let stream = TcpStream::connect_timeout(&address, self.timeout)?;
+                stream.set_read_timeout(Some(self.timeout))?;
+                stream.set_write_timeout(Some(self.timeout))?;
+                stream.set_nodelay(true)?;
+                Box::new(stream)
+            }
+            #[cfg(unix)]
+            SignerAddress::Unix(ref path) => {
+                let stream = UnixStream::connect(path)?;
+                stream.set_read_timeout(Some(self.timeout))?;
+                stream.set_write_timeout(Some(self.timeout))?;
+                Box::new(stream)
+            }
+            #[cfg(not(unix))]
+            SignerAddress::Unix(_) => {
+                return Err(SignerError::Protocol(
+                    "Unix sockets are not supported on this platform".to_owned(),
+                ))
+            }
+        };
+
+        let public_key = response_data(request(&mut stream, &[PUBLIC_KEY_REQUEST])?)?;
+        if public_key != self.public_key.as_ref() {
+            return Err(SignerError::Protocol(
+                "Signer uses a different public key".to_owned(),
+            ));
+        }
+        Ok(stream)
+    }
+
+    fn request(&self, payload: &[u8]) -> Result<Vec<u8>, SignerError> {
+        let mut guard = self.stream.lock().expect("Signer connection is poisoned");
+        for _ in 0..2 {
+            if guard.is_none() {
+                *guard = Some(self.connect()?);
+            }
+            match request(guard.as_mut().unwrap(), payload) {
+                Ok(response) => return Ok(response),
+                Err(e) => {
+                    warn!("Signer request failed: {}", e);
+                    *guard = None;
+                }
+            }
+        }
+        Err(SignerError::Io(io::Error::new(
+            io::ErrorKind::Other,
+            "Signer is unavailable",
+        )))
+    }
+}
+
+impl fmt::Debug for RemoteSigner {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("RemoteSigner")
+            .field("public_key", &self.public_key)
+            .field("address", &self.address)
+            .field("timeout", &self.timeout)
+            .finish()
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn public_key(&self) -> &PublicKey {
+        &self.public_key
+    }
+
+    fn sign(&self, data: &[u8]) -> Result<Signature, SignerError> {
+        let mut payload = Vec::with_capacity(data.len() + 1);
+        payload.push(SIGN_REQUEST);
+        payload.extend_from_slice(data);
+
+        let response = self.request(&payload)?;
+        let signature = response_data(response)?;
+        let signature = Signature::from_slice(&signature).ok_or_else(|| {
+            SignerError::Protocol("Invalid signature length".to_owned())
+        })?;
+        if !crypto::verify(&signature, data, &self.public_key) {
+            return Err(SignerError::Protocol("Invalid signature".to_owned()));
+        }
+        Ok(signature)
+    }
+}
+
+fn request<S: Read + Write + ?Sized>(stream: &mut S, payload: &[u8]) -> io::Result<Vec<u8>> {
+    write_frame(stream, payload)?;
+    read_frame(stream)
+}
+
+fn response_data(mut response: Vec<u8>) -> Result<Vec<u8>, SignerError> {
+    if response.is_empty() {
+        return Err(SignerError::Protocol("Empty response".to_owned()));
+    }
+    let data = response.split_off(1);
+    match response[0] {
+        RESPONSE_OK => Ok(data),
+        RESPONSE_REFUSED => Err(SignerError::Refused(
+            String::from_utf8_lossy(&data).into_owned(),
+        )),
+        RESPONSE_ERROR => Err(SignerError::Protocol(
+            String::from_utf8_lossy(&data).into_owned(),
+        )),
+        status => Err(SignerError::Protocol(
+            format!("Unknown response status {}", status),
+        )),
+    }
+}
+
+/// Writes the payload as a single frame.
+pub fn write_frame<W: Write + ?Sized>(writer: &mut W, payload: &[u8]) -> io::Result<()> {
+    let mut length = [0; 4];
+    LittleEndian::write_u32(&mut length, payload.len() as u32);
+    writer.write_all(&length)?;
+    writer.write_all(payload)?;
+    writer.flush()
+}
+
+/// Reads the payload of a single frame.
+pub fn read_frame<R: Read + ?Sized>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut length = [0; 4];
+    reader.read_exact(&mut length)?;
+    let length = LittleEndian::read_u32(&length);
+    if length > MAX_FRAME_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Frame is too long: {} bytes", length),
+        ));
+    }
+    let mut payload = vec![0; length as usize];
+    reader.read_exact(&mut payload)?;
+    Ok(payload)
+}
+
+/// Persisted state of the `DoubleSignGuard`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct GuardState {
+    height: Height,
+    messages: Vec<String>,
+}
+
+/// Keeps track of the signed consensus messages to prevent double signing.
+///
+/// Only the messages of the last signed height are kept, consensus messages for the lower
+/// heights are refused.
+#[derive(Debug)]
+pub struct DoubleSignGuard {
+    path: Option<PathBuf>,
+    height: Height,
+    messages: Vec<ConsensusMessage>,
+}
+
+impl DoubleSignGuard {
+    /// Creates a guard that keeps its state only in memory.
+    pub fn new() -> DoubleSignGuard {
+        DoubleSignGuard {
+            path: None,
+            height: Height::zero(),
+            messages: Vec::new(),
+        }
+    }
+
+    /// Creates a guard that persists its state to the given file. The previous state is
+    /// loaded if the file exists.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<DoubleSignGuard> {
+        let path = path.as_ref().to_owned();
+        let mut guard = DoubleSignGuard::new();
+        if path.exists() {
+            let state: GuardState = serde_json::from_reader(File::open(&path)?)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+            guard.height = state.height;
+            for message in &state.messages {
+                let data = decode_hex(message)
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+                match parse_consensus_message(&data) {
+                    Some(msg) => guard.messages.push(msg),
+                    None => {
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            "Invalid consensus message in the signer state",
+                        ))
+                    }
+                }
+            }
+        }
+        guard.path = Some(path);
+        Ok(guard)
+    }
+
+    /// Returns the last signed height.
+    pub fn height(&self) -> Height {
+        self.height
+    }
+
+    /// Checks whether the message bytes without the signature may be signed and records
+    /// the message if it is a consensus one. The state is persisted before returning.
+    pub fn check(&mut self, data: &[u8]) -> Result<(), SignerError> {
+        if data.len() < HEADER_LENGTH {
+            return Err(SignerError::Refused("Message is too short".to_owned()));
+        }
+        let message = match parse_consensus_message(data) {
+            Some(message) => message,
+            None => return Ok(()),
+        };
+
+        if message.height() < self.height {
+            return Err(SignerError::Refused(format!(
+                "Height {} is lower than the last signed height {}",
+                message.height(),
+                self.height
+            )));
+        }
+        if message.height() > self.height {
+            self.height = message.height();
+            self.messages.clear();
+        }
+        if self.messages.iter().any(|m| is_conflicting(m, &message)) {
+            return Err(SignerError::Refused(format!(
+                "Conflicting message for height {}, round {}",
+                message.height(),
+                message.round()
+            )));
+        }
+        if !self.messages.contains(&message) {
+            self.messages.push(message);
+        }
+        self.persist()?;
+        Ok(())
+    }
+
+    fn persist(&self) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        let state = GuardState {
+            height: self.height,
+            messages: self.messages
+                .iter()
+                .map(|m| encode_hex(m.raw().body()))
+                .collect(),
+        };
+        let tmp_path = path.with_extension("tmp");
+        {
+            let mut file = File::create(&tmp_path)?;
+            serde_json::to_writer(&mut file, &state)
+                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            file.sync_all()?;
+        }
+        fs::rename(&tmp_path, path)
+    }
+}
+
+impl Default for DoubleSignGuard {
+    fn default() -> DoubleSignGuard {
+        DoubleSignGuard::new()
+    }
+}
+
+fn parse_consensus_message(data: &[u8]) -> Option<ConsensusMessage> {
+    if data.len() < HEADER_LENGTH {
+        return None;
+    }
+    let mut raw = data.to_vec();
+    raw.extend_from_slice(Signature::zero().as_ref());
+    match Any::from_raw(RawMessage::from_vec(raw)) {
+        Ok(Any::Consensus(message)) => Some(message),
+        _ => None,
+    }
+}
+
+/// Reference implementation of the external signer.
+///
+/// The server processes the connections one by one, so it is intended to be used by
+/// a single node.
+#[derive(Debug)]
+pub struct SignerServer {
+    signer: LocalSigner,
+    guard: DoubleSignGuard,
+}
+
+impl SignerServer {
+    /// Creates a server that signs the messages with the given signer.
+    pub fn new(signer: LocalSigner, guard: DoubleSignGuard) -> SignerServer {
+        SignerServer { signer, guard }
+    }
+
+    /// Handles the request payload and returns the response payload.
+    pub fn handle_request(&mut self, request: &[u8]) -> Vec<u8> {
+        let result = match request.split_first() {
+            Some((&SIGN_REQUEST, data)) => self.sign(data),
+            Some((&PUBLIC_KEY_REQUEST, _)) => Ok(self.signer.public_key().as_ref().to_vec()),
+            _ => Err(SignerError::Protocol("Unknown request".to_owned())),
+        };
+
+        let mut response = Vec::new();
+        match result {
+            Ok(data) => {
+                response.push(RESPONSE_OK);
+                response.extend_from_slice(&data);
+            }
+            Err(SignerError::Refused(reason)) => {
+                warn!("Signing is refused: {}", reason);
+                response.push(RESPONSE_REFUSED);
+                response.extend_from_slice(reason.as_bytes());
+            }
+            Err(e) => {
+                error!("Unable to handle the signer request: {}", e);
+                response.push(RESPONSE_ERROR);
+                response.extend_from_slice(e.to_string().as_bytes());
+            }
+        }
+        response
+    }
+
+    fn sign(&mut self, data: &[u8]) -> Result<Vec<u8>, SignerError> {
+        self.guard.check(data)?;
+        let signature = self.signer.sign(data)?;
+        Ok(signature.as_ref().to_vec())
+    }
+
+    /// Serves the requests from the stream until it is closed.
+    pub fn serve<S: Read + Write>(&mut self, mut stream: S) -> io::Result<()> {
+        loop {
+            let request = match read_frame(&mut stream) {
+                Ok(request) => request,
+                Err(ref e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                Err(e) => return Err(e),
+            };
+            let response = self.handle_request(&request);
+            write_frame(&mut stream, &response)?;
+        }
+    }
+
+    /// Accepts the TCP connections and serves them one by one.
+    pub fn run_tcp(&mut self, listener: &TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            stream.set_nodelay(true)?;
+            if let Err(e) = self.serve(stream) {
+                warn!("Signer connection is closed with an error: {}", e);
+            }
+        }
+        Ok(())
+    }
+
+    /// Accepts the Unix socket connections and serves them one by one.
+    #[cfg(unix)]
+    pub fn run_unix(&mut self, listener: &UnixListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            if let Err(e) = self.serve(stream?) {
+                warn!("Signer connection is closed with an error: {}", e);
+            }
+        }
+        Ok(())
+    }
+}
@@ -23,13 +23,18 @@ use failure;
 use serde_json::Value;
 use bit_vec::BitVec;
 
-use messages::{RawMessage, Message, Propose, Prevote, Precommit, ConsensusMessage, Connect};
+use messages::{RawMessage, Message, Propose, Prevote, Precommit, ConsensusMessage, Connect,
+               BlockResponse};
 use crypto::{CryptoHash, PublicKey, SecretKey, Hash};
 use storage::{Patch, Snapshot, MapIndex, KeySetIndex};
-use blockchain::{ValidatorKeys, ConsensusConfig, StoredConfiguration, TimeoutAdjusterConfig};
+use blockchain::{Block, ConsensusConfig, Evidence, LeaderElectionConfig, StoredConfiguration,
+                 TimeoutAdjusterConfig, ValidatorKeys};
 use helpers::{Height, Round, ValidatorId, Milliseconds};
-use node::whitelist::Whitelist;
+use node::whitelist::{SharedWhitelist, Whitelist};
+use node::reputation::Reputation;
+use node::{ReputationConfig, TxPoolEvictionPolicy};
 use node::timeout_adjuster::{TimeoutAdjuster, Constant, Dynamic, MovingAverage};
+use node::leader_election::{LeaderElection, ReputationWeighted, RoundRobin, Weighted};
 
 // TODO: move request timeouts into node configuration (ECR-171)
 
@@ -41,6 +46,23 @@ pub const TRANSACTIONS_REQUEST_TIMEOUT: Milliseconds = 100;
 pub const PREVOTES_REQUEST_TIMEOUT: Milliseconds = 100;
 /// Timeout value for the `BlockRequest` message.
 pub const BLOCK_REQUEST_TIMEOUT: Milliseconds = 100;
+/// Timeout value for the `StateSnapshotRequest` message.
+pub const STATE_SNAPSHOT_REQUEST_TIMEOUT: Milliseconds = 1000;
+/// Timeout value for the `StateChunkRequest` message.
+pub const STATE_CHUNK_REQUEST_TIMEOUT: Milliseconds = 1000;
+
+/// Maximum number of blocks sent in response to a single `BlocksRequest` message.
+pub const MAX_BLOCKS_PER_REQUEST: u32 = 64;
+/// Number of heights following the current one that are downloaded in parallel during
+/// the block sync.
+pub const BLOCK_SYNC_WINDOW: u64 = 256;
+/// Number of consecutive heights requested from a single peer with one `BlocksRequest`.
+pub const BLOCK_SYNC_RANGE: u64 = 16;
+/// Number of invalid blocks after which a peer is no longer asked for blocks.
+pub const MAX_INVALID_BLOCKS: u32 = 3;
+/// Maximum total length in bytes of the transactions accumulated from the parts of
+/// the blocks of the next heights.
+pub const MAX_BLOCK_CHUNKS_LEN: usize = 128 * 1024 * 1024;
 
 
 /// State of the `NodeHandler`.
@@ -50,13 +72,15 @@ pub struct State {
     our_connect_message: Connect,
 
     consensus_public_key: PublicKey,
-    consensus_secret_key: SecretKey,
+    consensus_secret_key: Option<SecretKey>,
     service_public_key: PublicKey,
     service_secret_key: SecretKey,
 
     config: StoredConfiguration,
-    whitelist: Whitelist,
+    whitelist: SharedWhitelist,
+    reputation: Reputation,
     tx_pool_capacity: usize,
+    tx_pool_eviction_policy: TxPoolEvictionPolicy,
 
     peers: HashMap<PublicKey, Connect>,
     connections: HashMap<SocketAddr, PublicKey>,
@@ -82,13 +106,37 @@ pub struct State {
     // Our requests state.
     requests: HashMap<RequestData, RequestState>,
 
+    // Block of the state snapshot being downloaded.
+    state_sync_block: Option<Block>,
+
+    // Blocks received ahead of the current height during the block sync.
+    pending_blocks: BTreeMap<Height, BlockResponse>,
+    // Parts of the blocks which transactions are split between several messages,
+    // by the block hash and the sender, along with the length of the transactions.
+    block_chunks: HashMap<(Hash, PublicKey), (BlockResponse, Vec<RawMessage>, usize)>,
+    // Total length of the transactions in `block_chunks`.
+    block_chunks_len: usize,
+    // Number of invalid blocks served by peers.
+    invalid_blocks: HashMap<PublicKey, u32>,
+
     // maximum of node height in consensus messages
     nodes_max_height: BTreeMap<PublicKey, Height>,
 
     validators_rounds: BTreeMap<ValidatorId, Round>,
 
+    // The first consensus messages of each kind signed by validators in the rounds
+    // of the current height. Used to detect conflicting messages.
+    signed_messages: HashMap<(ValidatorId, Round, u16), ConsensusMessage>,
+    // Validators reported for signing conflicting messages, so each of them is reported
+    // only once per round.
+    reported_evidence: HashSet<(ValidatorId, Height, Round)>,
+
     timeout_adjuster: Box<TimeoutAdjuster>,
     propose_timeout: Milliseconds,
+
+    leader_election: Box<LeaderElection>,
+    // Leaders of the rounds of the current height.
+    leaders: Vec<ValidatorId>,
 }
 
 /// State of a validator-node.
@@ -111,6 +159,11 @@ pub enum RequestData {
     Prevotes(Round, Hash),
     /// Represents `BlockRequest` message.
     Block(Height),
+    /// Represents `StateSnapshotRequest` message.
+    StateSnapshot,
+    /// Represents `StateChunkRequest` message with the snapshot height, the index name
+    /// and the key of the first entry in the chunk.
+    StateChunk(Height, String, Vec<u8>),
 }
 
 #[derive(Debug)]
@@ -119,6 +172,8 @@ struct RequestState {
     retries: u16,
     // Nodes that have the required information.
     known_nodes: HashSet<PublicKey>,
+    // Node the request has been sent to last.
+    sent_to: Option<PublicKey>,
 }
 
 /// `ProposeState` represents the state of some propose and is used for tracking of unknown
@@ -248,6 +303,8 @@ impl RequestData {
             RequestData::Transactions(..) => TRANSACTIONS_REQUEST_TIMEOUT,
             RequestData::Prevotes(..) => PREVOTES_REQUEST_TIMEOUT,
             RequestData::Block(..) => BLOCK_REQUEST_TIMEOUT,
+            RequestData::StateSnapshot => STATE_SNAPSHOT_REQUEST_TIMEOUT,
+            RequestData::StateChunk(..) => STATE_CHUNK_REQUEST_TIMEOUT,
         };
         Duration::from_millis(ms)
     }
@@ -258,6 +315,7 @@ impl RequestState {
         RequestState {
             retries: 0,
             known_nodes: HashSet::new(),
+            sent_to: None,
         }
     }
 
@@ -359,10 +417,11 @@ impl State {
     pub fn new(
         validator_id: Option<ValidatorId>,
         consensus_public_key: PublicKey,
-        consensus_secret_key: SecretKey,
+        consensus_secret_key: Option<SecretKey>,
         service_public_key: PublicKey,
         service_secret_key: SecretKey,
         tx_pool_capacity: usize,
+        tx_pool_eviction_policy: TxPoolEvictionPolicy,
         whitelist: Whitelist,
         stored: StoredConfiguration,
         connect: Connect,
@@ -370,6 +429,7 @@ impl State {
         last_hash: Hash,
         last_height: Height,
         height_start_time: SystemTime,
+        reputation: ReputationConfig,
     ) -> Self {
         State {
             validator_state: validator_id.map(ValidatorState::new),
@@ -378,7 +438,9 @@ impl State {
             service_public_key,
             service_secret_key,
             tx_pool_capacity,
-            whitelist,
+            tx_pool_eviction_policy,
+            whitelist: SharedWhitelist::new(whitelist),
+            reputation: Reputation::new(reputation),
             peers,
             connections: HashMap::new(),
             height: last_height,
@@ -400,13 +462,24 @@ impl State {
 
             nodes_max_height: BTreeMap::new(),
             validators_rounds: BTreeMap::new(),
+            signed_messages: HashMap::new(),
+            reported_evidence: HashSet::new(),
 
             our_connect_message: connect,
 
             requests: HashMap::new(),
 
+            state_sync_block: None,
+
+            pending_blocks: BTreeMap::new(),
+            block_chunks: HashMap::new(),
+            block_chunks_len: 0,
+            invalid_blocks: HashMap::new(),
+
             timeout_adjuster: make_timeout_adjuster(&stored.consensus),
             propose_timeout: 0,
+            leader_election: make_leader_election(&stored.consensus),
+            leaders: Vec::new(),
             config: stored,
         }
     }
@@ -448,10 +521,30 @@ impl State {
     }
 
     /// Returns node's whitelist.
-    pub fn whitelist(&self) -> &Whitelist {
+    pub fn whitelist(&self) -> &SharedWhitelist {
         &self.whitelist
     }
 
+    /// Returns the reputation of the peers.
+    pub fn reputation(&self) -> &Reputation {
+        &self.reputation
+    }
+
+    /// Returns the mutable reputation of the peers.
+    pub fn reputation_mut(&mut self) -> &mut Reputation {
+        &mut self.reputation
+    }
+
+    /// Returns the maximum number of uncommitted transactions.
+    pub fn tx_pool_capacity(&self) -> usize {
+        self.tx_pool_capacity
+    }
+
+    /// Returns the policy applied when the memory pool is full.
+    pub fn tx_pool_eviction_policy(&self) -> TxPoolEvictionPolicy {
+        self.tx_pool_eviction_policy
+    }
+
     /// Returns public (consensus and service) keys of known validators.
     pub fn validators(&self) -> &[ValidatorKeys] {
         &self.config.validator_keys
@@ -493,7 +586,7 @@ impl State {
             .iter()
             .position(|pk| pk.consensus_key == *self.consensus_public_key())
             .map(|id| ValidatorId(id as u16));
-        self.whitelist.set_validators(
+        self.whitelist.write().set_validators(
             config.validator_keys.iter().map(|x| {
                 x.consensus_key
             }),
@@ -501,7 +594,9 @@ impl State {
         self.renew_validator_id(validator_id);
         trace!("Validator={:#?}", self.validator_state());
 
+        // The strategies may depend on the validators, so they are rebuilt for every change.
         self.timeout_adjuster = make_timeout_adjuster(&config.consensus);
+        self.leader_election = make_leader_election(&config.consensus);
         self.config = config;
     }
 
@@ -511,6 +606,18 @@ impl State {
         self.propose_timeout = timeout;
     }
 
+    /// Chooses the leaders of the rounds of the current height (see `LeaderElection` for
+    /// the details).
+    pub fn update_leaders(&mut self, snapshot: &Snapshot) {
+        let height = self.height();
+        let validators_count = self.validators().len();
+        self.leaders = self.leader_election.leaders(
+            height,
+            validators_count,
+            snapshot,
+        );
+    }
+
     /// Returns adjusted (see `TimeoutAdjuster` for the details) value of the propose timeout.
     pub fn propose_timeout(&self) -> Milliseconds {
         self.propose_timeout
@@ -549,9 +656,10 @@ impl State {
         &self.consensus_public_key
     }
 
-    /// Returns the consensus secret key of the current node.
-    pub fn consensus_secret_key(&self) -> &SecretKey {
-        &self.consensus_secret_key
+    /// Returns the consensus secret key of the current node. The key is absent if it is
+    /// kept by the remote signer only.
+    pub fn consensus_secret_key(&self) -> Option<&SecretKey> {
+        self.consensus_secret_key.as_ref()
     }
 
     /// Returns the service public key of the current node.
@@ -566,9 +674,15 @@ impl State {
 
     /// Returns the leader id for the specified round and current height.
     pub fn leader(&self, round: Round) -> ValidatorId {
-        let height: u64 = self.height().into();
         let round: u64 = round.into();
-        ValidatorId(((height + round) % (self.validators().len() as u64)) as u16)
+        if self.leaders.is_empty() {
+            // Leaders have not been chosen yet, fall back to the round-robin order.
+            let height: u64 = self.height().into();
+            return ValidatorId(((height + round) % (self.validators().len() as u64)) as u16);
+        }
+        // The first leader is the leader of the first round.
+        let count = self.leaders.len() as u64;
+        self.leaders[((round + count - 1) % count) as usize]
     }
 
     /// Updates known round for a validator and returns
@@ -648,6 +762,17 @@ impl State {
         self.height_start_time
     }
 
+    /// Moves the start time of the current height forward, so that the rounds of the height
+    /// start later by the given delay.
+    pub fn postpone_height_start(&mut self, delay: Duration) {
+        self.height_start_time += delay;
+    }
+
+    /// Returns `true` if any proposes are known at the current height.
+    pub fn has_proposes(&self) -> bool {
+        !self.proposes.is_empty()
+    }
+
     /// Returns the current round.
     pub fn round(&self) -> Round {
         self.round
@@ -720,10 +845,165 @@ impl State {
         self.prevotes.clear();
         self.precommits.clear();
         self.validators_rounds.clear();
+        self.signed_messages.clear();
+        self.reported_evidence.clear();
         if let Some(ref mut validator_state) = self.validator_state {
             validator_state.clear();
         }
-        self.requests.clear(); // FIXME: clear all timeouts (ECR-171)
+        // Requests for the blocks of the next heights stay actual during the block sync.
+        let height = self.height;
+        // FIXME: clear all timeouts (ECR-171)
+        self.requests.retain(|data, _| match *data {
+            RequestData::Block(block_height) => block_height >= height,
+            _ => false,
+        });
+        self.pending_blocks = self.pending_blocks.split_off(&height);
+        self.retain_block_chunks(|msg| msg.block().height() >= height);
+    }
+
+    /// Moves the node to the height following the given block, which has been received
+    /// during the state sync, and finishes the state sync.
+    pub fn jump_to_block(&mut self, block: &Block, height_start_time: SystemTime) {
+        self.height = block.height();
+        self.new_height(&block.hash(), height_start_time);
+        self.state_sync_block = None;
+    }
+
+    /// Returns the block of the state snapshot being downloaded, if the state sync is
+    /// in progress.
+    pub fn state_sync_block(&self) -> Option<&Block> {
+        self.state_sync_block.as_ref()
+    }
+
+    /// Starts the state sync to the state snapshot at the given block.
+    pub fn start_state_sync(&mut self, block: Block) {
+        self.state_sync_block = Some(block);
+    }
+
+    /// Cancels the state sync, so that it can be started anew.
+    pub fn cancel_state_sync(&mut self) {
+        self.state_sync_block = None;
+    }
+
+    /// Records the consensus message of a validator for the current height. Returns
+    /// the evidence if the validator has already signed a conflicting message of the same
+    /// kind in the same round. The evidence is returned only once for each validator and
+    /// round, so the conflicting messages received again are not reported.
+    pub fn add_signed_message(&mut self, msg: &ConsensusMessage) -> Option<Evidence> {
+        let key = (msg.validator(), msg.round(), msg.raw().message_type());
+        let validator_key = self.consensus_public_key_of(msg.validator())?;
+        let evidence = match self.signed_messages.entry(key) {
+            Entry::Occupied(e) => Evidence::from_messages(&validator_key, e.get(), msg),
+            Entry::Vacant(e) => {
+                e.insert(msg.clone());
+                None
+            }
+        }?;
+        let reported = (msg.validator(), msg.height(), msg.round());
+        if self.reported_evidence.insert(reported) {
+            Some(evidence)
+        } else {
+            None
+        }
+    }
+
+    /// Adds the block received ahead of the current height to the queue.
+    pub fn add_pending_block(&mut self, msg: BlockResponse) {
+        self.pending_blocks.insert(msg.block().height(), msg);
+    }
+
+    /// Accumulates the part of the block transactions received in a `BlockResponse`.
+    /// Returns the message with all the transactions of the block once the last part
+    /// is received from the same peer. If the parts do not add up to the block, the
+    /// accumulated ones are dropped and the accumulation starts again from the given part.
+    ///
+    /// The total length of the accumulated parts is bounded by `MAX_BLOCK_CHUNKS_LEN`.
+    /// The parts of the blocks of the next heights are dropped to make room for the block
+    /// of the current height; a part that does not fit anyway is dropped.
+    pub fn add_block_chunk(&mut self, msg: BlockResponse) -> Option<BlockResponse> {
+        let tx_count = msg.block().tx_count() as usize;
+        let len: usize = msg.transactions().iter().map(|tx| tx.len()).sum();
+        if self.block_chunks_len + len > MAX_BLOCK_CHUNKS_LEN && msg.block().height() == self.height
+        {
+            let height = self.height;
+            self.block_chunks.retain(|_, &mut (ref first, _, _)| {
+                first.block().height() == height
+            });
+            self.block_chunks_len = self.block_chunks.values().map(|entry| entry.2).sum();
+        }
+        if self.block_chunks_len + len > MAX_BLOCK_CHUNKS_LEN {
+            warn!(
+                "Too many parts of blocks are accumulated, dropping the part of the block {:?}",
+                msg.block().hash()
+            );
+            return None;
+        }
+
+        let key = (msg.block().hash(), *msg.from());
+        let is_complete = {
+            let entry = self.block_chunks.entry(key).or_insert_with(
+                || (msg.clone(), Vec::new(), 0),
+            );
+            if entry.1.len() + msg.transactions().len() > tx_count {
+                self.block_chunks_len -= entry.2;
+                *entry = (msg.clone(), Vec::new(), 0);
+            }
+            entry.1.extend(msg.transactions());
+            entry.2 += len;
+            self.block_chunks_len += len;
+            entry.1.len() == tx_count
+        };
+        if !is_complete {
+            return None;
+        }
+
+        let (first, transactions, len) = self.block_chunks.remove(&key).unwrap();
+        self.block_chunks_len -= len;
+        // The assembled message is only handled locally, the signatures of its parts
+        // have already been verified.
+        Some(BlockResponse::new_with_signature(
+            first.from(),
+            first.to(),
+            first.block(),
+            first.precommits(),
+            transactions,
+            first.raw().signature(),
+        ))
+    }
+
+    /// Returns `true` if the block of the given height is in the queue.
+    pub fn has_pending_block(&self, height: Height) -> bool {
+        self.pending_blocks.contains_key(&height)
+    }
+
+    /// Removes the block of the given height from the queue and returns it.
+    pub fn take_pending_block(&mut self, height: Height) -> Option<BlockResponse> {
+        self.pending_blocks.remove(&height)
+    }
+
+    /// Records that the peer has served an invalid block. The queued blocks received
+    /// from the peer are dropped.
+    pub fn add_invalid_block(&mut self, peer: &PublicKey) {
+        *self.invalid_blocks.entry(*peer).or_insert(0) += 1;
+        let blocks = ::std::mem::replace(&mut self.pending_blocks, BTreeMap::new());
+        self.pending_blocks = blocks
+            .into_iter()
+            .filter(|&(_, ref msg)| msg.from() != peer)
+            .collect();
+        self.retain_block_chunks(|msg| msg.from() != peer);
+    }
+
+    fn retain_block_chunks<F: Fn(&BlockResponse) -> bool>(&mut self, f: F) {
+        self.block_chunks.retain(|_, &mut (ref msg, _, _)| f(msg));
+        self.block_chunks_len = self.block_chunks.values().map(|&(_, _, len)| len).sum();
+    }
+
+    /// Returns `true` if the peer has served too many invalid blocks and should not be
+    /// asked for blocks anymore.
+    pub fn is_block_peer_banned(&self, peer: &PublicKey) -> bool {
+        self.invalid_blocks.get(peer).map_or(false, |count| {
+            *count >= MAX_INVALID_BLOCKS
+        })
     }
 
     /// Returns a list of queued consensus messages.
@@ -758,6 +1038,21 @@ impl State {
         full_proposes
     }
 
+    /// Returns `true` if some propose of the current height is waiting for this transaction.
+    pub fn is_tx_awaited(&self, tx_hash: &Hash) -> bool {
+        self.proposes
+            .values()
+            .any(|state| state.unknown_txs.contains(tx_hash))
+    }
+
+    /// Returns `true` if the transaction is referenced by some propose of the current height.
+    /// Such transactions must not be evicted from the pool.
+    pub fn is_tx_proposed(&self, tx_hash: &Hash) -> bool {
+        self.proposes
+            .values()
+            .any(|state| state.message().transactions().contains(tx_hash))
+    }
+
     /// Returns pre-votes for the specified round and propose hash.
     pub fn prevotes(&self, round: Round, propose_hash: Hash) -> &[Prevote] {
         self.prevotes
@@ -787,6 +1082,17 @@ impl State {
         }
     }
 
+    /// Returns `true` if this node has made a propose in the specified round.
+    pub fn have_self_propose(&self, round: Round) -> bool {
+        let validator_id = match self.validator_id() {
+            Some(validator_id) => validator_id,
+            None => return false,
+        };
+        self.proposes.values().any(|state| {
+            state.message().validator() == validator_id && state.message().round() == round
+        })
+    }
+
     /// Adds propose from this node to the proposes list for the current height. Such propose
     /// cannot contain unknown transactions. Returns hash of the propose.
     pub fn add_self_propose(&mut self, msg: Propose) -> Hash {
@@ -1032,7 +1338,8 @@ impl State {
             if let Some(peer) = peer {
                 state.remove(&peer);
             }
-            state.peek()
+            state.sent_to = state.peek();
+            state.sent_to
         };
 
         if next.is_none() {
@@ -1041,6 +1348,25 @@ impl State {
         next
     }
 
+    /// Returns `true` if the data has already been requested.
+    pub fn is_requested(&self, data: &RequestData) -> bool {
+        self.requests.contains_key(data)
+    }
+
+    /// Records that the request for the data has been sent to the peer.
+    pub fn request_sent(&mut self, data: &RequestData, peer: PublicKey) {
+        if let Some(state) = self.requests.get_mut(data) {
+            state.sent_to = Some(peer);
+        }
+    }
+
+    /// Returns `true` if the request for the data has been sent to the peer last.
+    pub fn is_requested_from(&self, data: &RequestData, peer: &PublicKey) -> bool {
+        self.requests.get(data).map_or(false, |state| {
+            state.sent_to.as_ref() == Some(peer)
+        })
+    }
+
     /// Removes the specified request from the pending request list.
     pub fn remove_request(&mut self, data: &RequestData) -> HashSet<PublicKey> {
         let state = self.requests.remove(data);
@@ -1058,6 +1384,16 @@ impl State {
     }
 }
 
+fn make_leader_election(config: &ConsensusConfig) -> Box<LeaderElection> {
+    match config.leader_election {
+        LeaderElectionConfig::RoundRobin => Box::new(RoundRobin::new()),
+        LeaderElectionConfig::ReputationWeighted { window } => {
+            Box::new(ReputationWeighted::new(window))
+        }
+        LeaderElectionConfig::Weighted { ref weights } => Box::new(Weighted::new(weights)),
+    }
+}
+
 fn make_timeout_adjuster(config: &ConsensusConfig) -> Box<TimeoutAdjuster> {
     match config.timeout_adjuster {
         TimeoutAdjusterConfig::Constant { timeout } => Box::new(Constant::new(timeout)),
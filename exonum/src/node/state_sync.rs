@@ -0,0 +1,754 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! State sync: bootstrapping of a fresh node from a state snapshot of its peers.
+//!
+//! Every node keeps a snapshot of the storage taken after the last committed height that is
+//! a multiple of `StateSyncConfig::snapshot_interval`. A fresh node with the enabled state
+//! sync, which lags behind its peers by more than the snapshot interval, requests the block
+//! of the snapshot with `StateSnapshotRequest` and then downloads the contents of the indexes
+//! chunk by chunk with `StateChunkRequest`s from the same peer.
+//!
+//! Only the indexes making up the blockchain state are transferred, see
+//! `Blockchain::is_synced_index`. Each chunk is checked as soon as it arrives: the entries
+//! must follow the requested key in order, and the entries of the indexes keyed by hashes,
+//! such as blocks and transactions, must match their keys. The checked chunks are staged
+//! on disk in a separate table of the node storage, so the memory used by the download
+//! does not depend on the size of the state.
+//!
+//! After the last chunk the whole history and state are verified and rebuilt by
+//! `Blockchain::restore_state` and written to the node storage at once, so the storage
+//! never contains a partially downloaded state: if the node stops during the state sync,
+//! the download is started from scratch after the restart. A peer that sends a malformed
+//! chunk or a state that fails the verification is banned, and the state sync is started
+//! anew with another peer. If the node cannot restore the state by itself, for example,
+//! a service does not support the state sync, the node falls back to the block sync.
+
+use crypto::{self, CryptoHash, PublicKey, Signature};
+use messages::{Message, RawMessage, StateChunkRequest, StateChunkResponse, StateEntry,
+               StateSnapshotRequest, StateSnapshotResponse, HEADER_LENGTH};
+use blockchain::{CommitCertificate, Schema, HASHED_INDEXES};
+use helpers::Height;
+use storage::{index_names, Iter, Iterator as StorageIterator, Snapshot};
+use super::{NodeHandler, PeerBehavior, RequestData, SignedAction};
+
+/// Space reserved in `StateChunkResponse` for the fixed fields, the index names and the keys.
+const CHUNK_RESERVED_LEN: usize = HEADER_LENGTH + 1024;
+/// Space occupied by a `StateEntry` in `StateChunkResponse` besides its key and value.
+const ENTRY_OVERHEAD_LEN: usize = 24;
+/// Table of the node storage the received chunks are staged in. The keys are the names
+/// of the received indexes followed by a zero byte and the keys of the entries.
+const STAGED_STATE: &str = "__STATE_SYNC__";
+
+/// Snapshot of the storage kept by the node for the state sync of its peers.
+pub(crate) struct StateSnapshot {
+    height: Height,
+    snapshot: Box<Snapshot>,
+}
+
+/// State snapshot being downloaded from a peer.
+pub(crate) struct StateDownload {
+    /// Peer the chunks are downloaded from.
+    peer: PublicKey,
+    /// Chunk being requested from the peer.
+    request: RequestData,
+}
+
+/// Received indexes staged in the node storage, presented under their original names.
+struct StagedState {
+    snapshot: Box<Snapshot>,
+}
+
+/// Iterator over the staged entries of a single index.
+struct StagedIter<'a> {
+    iter: Iter<'a>,
+    prefix: Vec<u8>,
+}
+
+/// Entries of a single index transferred in one `StateChunkResponse`.
+#[derive(Debug, PartialEq)]
+struct StateChunk {
+    entries: Vec<StateEntry>,
+    next_index_name: String,
+    next_key: Vec<u8>,
+}
+
+impl NodeHandler {
+    /// Takes a state snapshot if the last committed height is a multiple of the snapshot
+    /// interval.
+    pub(crate) fn update_state_snapshot(&mut self) {
+        let interval = self.state_sync.snapshot_interval;
+        let height = self.blockchain.last_block().height();
+        if interval == 0 || height == Height::zero() || height.0 % interval != 0 {
+            return;
+        }
+        trace!("Take state snapshot at height {}", height);
+        self.state_snapshot = Some(StateSnapshot {
+            height,
+            snapshot: self.blockchain.snapshot(),
+        });
+    }
+
+    /// Returns `true` if the node should download a state snapshot instead of requesting
+    /// blocks from a peer with the given height.
+    pub(crate) fn need_state_sync(&self, peer_height: Height) -> bool {
+        // Only a fresh node, which has nothing but the genesis block, uses the state sync.
+        self.state_sync.enabled && self.state.height() == Height(1) &&
+            peer_height.0 > self.state.height().0 + self.state_sync.snapshot_interval
+    }
+
+    /// Handles `StateSnapshotRequest` message. For details see the message documentation.
+    pub fn handle_request_state_snapshot(&mut self, msg: &StateSnapshotRequest) {
+        trace!("HANDLE STATE SNAPSHOT REQUEST");
+        let response = match self.state_snapshot {
+            Some(ref state_snapshot) => {
+                let schema = Schema::new(&state_snapshot.snapshot);
+                let block_hash = schema
+                    .block_hash_by_height(state_snapshot.height)
+                    .expect("Block of the state snapshot is absent");
+                let block = schema.blocks().get(&block_hash).unwrap();
+                let precommits = schema.precommits(&block_hash);
+                StateSnapshotResponse::new_with_signature(
+                    self.state.consensus_public_key(),
+                    msg.from(),
+                    block,
+                    precommits.iter().collect(),
+                    &Signature::zero(),
+                )
+            }
+            None => return,
+        };
+        if let Some(response) = self.sign(&response, SignedAction::Send(*msg.from())) {
+            self.send_to_peer(*msg.from(), response.raw());
+        }
+    }
+
+    /// Handles `StateChunkRequest` message. For details see the message documentation.
+    pub fn handle_request_state_chunk(&mut self, msg: &StateChunkRequest) {
+        trace!(
+            "HANDLE STATE CHUNK REQUEST: index={}, height={}",
+            msg.index_name(),
+            msg.height()
+        );
+        let max_len = self.state.consensus_config().max_message_len as usize;
+        let max_entries = self.state_sync.max_chunk_entries as usize;
+        let chunk = match self.state_snapshot {
+            Some(ref state_snapshot) if state_snapshot.height == msg.height() => {
+                let blockchain = &self.blockchain;
+                state_chunk(
+                    &*state_snapshot.snapshot,
+                    msg.index_name(),
+                    msg.from_key(),
+                    max_entries,
+                    max_len.saturating_sub(CHUNK_RESERVED_LEN),
+                    &|name| blockchain.is_synced_index(name),
+                )
+            }
+            _ => return,
+        };
+        let chunk = match chunk {
+            Some(chunk) => chunk,
+            None => {
+                error!(
+                    "Entry of index {} does not fit into a state chunk",
+                    msg.index_name()
+                );
+                return;
+            }
+        };
+
+        let response = StateChunkResponse::new_with_signature(
+            self.state.consensus_public_key(),
+            msg.from(),
+            msg.height(),
+            msg.index_name(),
+            msg.from_key(),
+            chunk.entries,
+            &chunk.next_index_name,
+            &chunk.next_key,
+            &Signature::zero(),
+        );
+        if let Some(response) = self.sign(&response, SignedAction::Send(*msg.from())) {
+            self.send_to_peer(*msg.from(), response.raw());
+        }
+    }
+
+    /// Handles `StateSnapshotResponse` message and starts downloading the state snapshot.
+    pub fn handle_state_snapshot(&mut self, msg: &StateSnapshotResponse) {
+        if !self.verify_state_sync_message(msg.to(), msg.from(), msg.raw()) {
+            return;
+        }
+        let peers = self.remove_request(&RequestData::StateSnapshot);
+        if peers.is_empty() || self.state_download.is_some() {
+            return;
+        }
+
+        let block = msg.block();
+        if block.height() < self.state.height() {
+            return;
+        }
+        // The signatures of the precommits are verified against the configuration actual
+        // at the height of the block, which is a part of the downloaded state.
+        let is_consistent = CommitCertificate::from_precommits(&msg.precommits())
+            .ok()
+            .map_or(false, |certificate| {
+                certificate.block_hash() == &block.hash() && certificate.height() == block.height()
+            });
+        if !is_consistent {
+            error!("Precommits do not match the block, state snapshot={:?}", msg);
+            self.update_reputation(msg.from(), PeerBehavior::InvalidBlock);
+            return;
+        }
+
+        info!(
+            "Start state sync to height {}, block={}",
+            block.height(),
+            block.hash().to_hex()
+        );
+        let request = RequestData::StateChunk(block.height(), String::new(), Vec::new());
+        // Chunks left by a state sync interrupted by the restart of the node are dropped.
+        self.clear_staged_state();
+        self.state.start_state_sync(block);
+        self.state_download = Some(StateDownload {
+            peer: *msg.from(),
+            request: request.clone(),
+        });
+        self.request_state_chunk(request);
+    }
+
+    /// Handles `StateChunkResponse` message. The checked entries are staged until the whole
+    /// state is downloaded and the next chunk is requested. The state sync is finished after
+    /// the last chunk.
+    pub fn handle_state_chunk(&mut self, msg: &StateChunkResponse) {
+        if !self.verify_state_sync_message(msg.to(), msg.from(), msg.raw()) {
+            return;
+        }
+        let data = RequestData::StateChunk(
+            msg.height(),
+            msg.index_name().to_owned(),
+            msg.from_key().to_vec(),
+        );
+        // Chunks are accepted only from the peer the state is downloaded from, so that
+        // the peer can be blamed for an invalid state.
+        match self.state_download {
+            Some(ref download) if download.peer == *msg.from() && download.request == data => {}
+            _ => return,
+        }
+        self.remove_request(&data);
+
+        trace!(
+            "Handle state chunk: index={}, entries={}",
+            msg.index_name(),
+            msg.entries().len()
+        );
+        let checked = {
+            let blockchain = &self.blockchain;
+            check_state_chunk(msg, &|name| blockchain.is_synced_index(name))
+        };
+        if let Err(reason) = checked {
+            error!(
+                "Invalid state chunk from peer {:?}: {}, index={}",
+                msg.from(),
+                reason,
+                msg.index_name()
+            );
+            self.abort_state_sync();
+            self.ban_peer(msg.from());
+            return;
+        }
+
+        let next = if msg.next_index_name().is_empty() {
+            None
+        } else {
+            Some(RequestData::StateChunk(
+                msg.height(),
+                msg.next_index_name().to_owned(),
+                msg.next_key().to_vec(),
+            ))
+        };
+        let mut fork = self.blockchain.fork();
+        for entry in msg.entries() {
+            fork.put(
+                STAGED_STATE,
+                staged_key(msg.index_name(), entry.key()),
+                entry.value().to_vec(),
+            );
+        }
+        self.blockchain.merge(fork.into_patch()).expect(
+            "Unable to save the state chunk",
+        );
+        if let Some(ref next) = next {
+            self.state_download.as_mut().unwrap().request = next.clone();
+        }
+
+        match next {
+            Some(next) => self.request_state_chunk(next),
+            None => self.finish_state_sync(),
+        }
+    }
+
+    /// Handles the timeout of `StateChunkRequest`. If the peer the state is downloaded from
+    /// does not respond, the state sync is started anew.
+    pub(crate) fn handle_state_chunk_timeout(&mut self, data: &RequestData) {
+        let is_requested = self.state_download.as_ref().map_or(false, |download| {
+            download.request == *data
+        });
+        if is_requested {
+            warn!("Peer does not respond during the state sync, {:?}", data);
+            self.abort_state_sync();
+        }
+    }
+
+    /// Verifies the downloaded state, writes it to the storage and moves the node
+    /// to the height following the snapshot block.
+    fn finish_state_sync(&mut self) {
+        let download = self.state_download.take().expect(
+            "State sync is not in progress",
+        );
+        let block = self.state.state_sync_block().cloned().expect(
+            "State sync is not in progress",
+        );
+        let restored = {
+            let staged = StagedState { snapshot: self.blockchain.snapshot() };
+            self.blockchain.restore_state(&staged, &block)
+        };
+        let patch = match restored {
+            Ok(patch) => patch,
+            Err(ref e) if !e.is_peer_fault() => {
+                warn!("Falling back to the block sync: {}", e);
+                self.state_sync.enabled = false;
+                self.clear_staged_state();
+                self.state.cancel_state_sync();
+                self.request_next_block();
+                return;
+            }
+            Err(e) => {
+                error!(
+                    "Invalid state received from peer {:?}: {}, block={:?}",
+                    download.peer,
+                    e,
+                    block
+                );
+                self.clear_staged_state();
+                self.state.cancel_state_sync();
+                self.ban_peer(&download.peer);
+                return;
+            }
+        };
+        // The staged chunks are dropped together with saving the restored state.
+        let mut fork = self.blockchain.fork();
+        fork.merge(patch);
+        fork.remove_by_prefix(STAGED_STATE, None);
+        self.blockchain.merge_sync(fork.into_patch()).expect(
+            "Unable to save the state",
+        );
+        info!(
+            "State sync finished at height {}, block={}",
+            block.height(),
+            block.hash().to_hex()
+        );
+
+        self.state.update_config(
+            Schema::new(&self.blockchain.snapshot()).actual_configuration(),
+        );
+        self.state.jump_to_block(
+            &block,
+            self.system_state.current_time(),
+        );
+        let snapshot = self.blockchain.snapshot();
+        self.state.adjust_timeout(&*snapshot);
+        self.state.update_leaders(&*snapshot);
+        self.broadcast_status();
+        self.add_status_timeout();
+        self.add_round_timeout();
+        self.request_next_block();
+    }
+
+    /// Drops the downloaded chunks, so that the state sync is started anew after
+    /// the next `Status` message of a peer.
+    fn abort_state_sync(&mut self) {
+        if let Some(download) = self.state_download.take() {
+            self.remove_request(&download.request);
+        }
+        self.clear_staged_state();
+        self.state.cancel_state_sync();
+    }
+
+    /// Removes the staged chunks from the storage.
+    fn clear_staged_state(&mut self) {
+        let mut fork = self.blockchain.fork();
+        fork.remove_by_prefix(STAGED_STATE, None);
+        self.blockchain.merge(fork.into_patch()).expect(
+            "Unable to clear the staged state",
+        );
+    }
+
+    /// Sends a chunk request to the peer the state is downloaded from.
+    fn request_state_chunk(&mut self, data: RequestData) {
+        let peer = self.state_download.as_ref().map(|download| download.peer).expect(
+            "State sync is not in progress",
+        );
+        let message = match data {
+            RequestData::StateChunk(height, ref index_name, ref from_key) => {
+                StateChunkRequest::new_with_signature(
+                    self.state.consensus_public_key(),
+                    &peer,
+                    height,
+                    index_name,
+                    from_key,
+                    &Signature::zero(),
+                )
+            }
+            _ => unreachable!("Not a state chunk request: {:?}", data),
+        };
+        self.state.request(data.clone(), peer);
+        self.add_request_timeout(data, Some(peer));
+        if let Some(message) = self.sign(&message, SignedAction::Send(peer)) {
+            self.send_to_peer(peer, message.raw());
+        }
+    }
+
+    fn verify_state_sync_message(
+        &mut self,
+        to: &PublicKey,
+        from: &PublicKey,
+        raw: &RawMessage,
+    ) -> bool {
+        if to != self.state.consensus_public_key() {
+            return false;
+        }
+        if !self.state.whitelist().allow(from) {
+            error!(
+                "Received state sync message from peer = {} which not in whitelist.",
+                from.to_hex()
+            );
+            return false;
+        }
+        if !raw.verify_signature(from) {
+            error!("Received state sync message with incorrect signature, msg={:?}", raw);
+            self.penalize_message_source(PeerBehavior::InvalidSignature);
+            return false;
+        }
+        true
+    }
+}
+
+impl Snapshot for StagedState {
+    fn get(&self, name: &str, key: &[u8]) -> Option<Vec<u8>> {
+        self.snapshot.get(STAGED_STATE, &staged_key(name, key))
+    }
+
+    fn iter<'a>(&'a self, name: &str, from: &[u8]) -> Iter<'a> {
+        Box::new(StagedIter {
+            iter: self.snapshot.iter(STAGED_STATE, &staged_key(name, from)),
+            prefix: staged_key(name, &[]),
+        })
+    }
+}
+
+impl<'a> StorageIterator for StagedIter<'a> {
+    fn next(&mut self) -> Option<(&[u8], &[u8])> {
+        let prefix = &self.prefix;
+        self.iter.next().and_then(|(key, value)| {
+            if key.starts_with(prefix) {
+                Some((&key[prefix.len()..], value))
+            } else {
+                None
+            }
+        })
+    }
+
+    fn peek(&mut self) -> Option<(&[u8], &[u8])> {
+        let prefix = &self.prefix;
+        self.iter.peek().and_then(|(key, value)| {
+            if key.starts_with(prefix) {
+                Some((&key[prefix.len()..], value))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+/// Returns the key of the entry of the `index_name` index in the staging table.
+fn staged_key(index_name: &str, key: &[u8]) -> Vec<u8> {
+    let mut staged_key = Vec::with_capacity(index_name.len() + 1 + key.len());
+    staged_key.extend_from_slice(index_name.as_bytes());
+    staged_key.push(0);
+    staged_key.extend_from_slice(key);
+    staged_key
+}
+
+/// Collects the entries of the `index_name` index starting from `from_key`. Returns `None`
+/// if the first entry does not fit into `max_len` bytes. Only the indexes for which
+/// `is_synced` returns `true` are transferred.
+fn state_chunk(
+    snapshot: &Snapshot,
+    index_name: &str,
+    from_key: &[u8],
+    max_entries: usize,
+    max_len: usize,
+    is_synced: &Fn(&str) -> bool,
+) -> Option<StateChunk> {
+    let mut entries = Vec::new();
+    let mut len = 0;
+    let mut next_key = None;
+
+    if !index_name.is_empty() && is_synced(index_name) {
+        let mut iter = snapshot.iter(index_name, from_key);
+        while let Some((key, value)) = iter.peek().map(|(k, v)| (k.to_vec(), v.to_vec())) {
+            let entry_len = key.len() + value.len() + ENTRY_OVERHEAD_LEN;
+            if entries.len() >= max_entries || len + entry_len > max_len {
+                if entries.is_empty() {
+                    return None;
+                }
+                next_key = Some(key);
+                break;
+            }
+            len += entry_len;
+            entries.push(StateEntry::new(&key, &value));
+            iter.next();
+        }
+    }
+
+    let (next_index_name, next_key) = match next_key {
+        Some(key) => (index_name.to_owned(), key),
+        None => {
+            let next_index_name = index_names(snapshot)
+                .into_iter()
+                .find(|name| name.as_str() > index_name && is_synced(name))
+                .unwrap_or_default();
+            (next_index_name, Vec::new())
+        }
+    };
+    Some(StateChunk {
+        entries,
+        next_index_name,
+        next_key,
+    })
+}
+
+/// Checks that the chunk contains the entries of a transferred index in the ascending order
+/// of keys starting from the requested key and that it moves the download forward.
+fn check_state_chunk(
+    msg: &StateChunkResponse,
+    is_synced: &Fn(&str) -> bool,
+) -> Result<(), &'static str> {
+    let index_name = msg.index_name();
+    let entries = msg.entries();
+    if index_name.is_empty() {
+        if !entries.is_empty() {
+            return Err("entries are sent without an index");
+        }
+    } else if !is_synced(index_name) {
+        return Err("index is not transferred during the state sync");
+    }
+
+    let mut last_key = None;
+    for entry in &entries {
+        let key = entry.key();
+        let is_ordered = match last_key {
+            Some(last_key) => key > last_key,
+            None => key >= msg.from_key(),
+        };
+        if !is_ordered {
+            return Err("entries are not in the ascending order of keys");
+        }
+        last_key = Some(key);
+    }
+    if HASHED_INDEXES.iter().any(|name| *name == index_name) {
+        let is_hashed = entries.iter().all(|entry| {
+            crypto::hash(entry.value()).as_ref() == entry.key()
+        });
+        if !is_hashed {
+            return Err("entries do not match their hashes");
+        }
+    }
+
+    let next_index_name = msg.next_index_name();
+    let next_key = msg.next_key();
+    if next_index_name.is_empty() {
+        Ok(())
+    } else if next_index_name == index_name {
+        match last_key {
+            Some(last_key) if next_key > last_key => Ok(()),
+            _ => Err("next key does not follow the entries"),
+        }
+    } else if next_index_name > index_name && next_key.is_empty() && is_synced(next_index_name) {
+        Ok(())
+    } else {
+        Err("next index is not transferred or does not follow the index")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{check_state_chunk, staged_key, state_chunk, StagedState, StateChunk,
+                STAGED_STATE};
+    use crypto::{gen_keypair, hash};
+    use helpers::Height;
+    use messages::{StateChunkResponse, StateEntry};
+    use storage::{Database, Entry, KeySetIndex, ListIndex, MapIndex, MemoryDB, Snapshot};
+
+    fn create_database() -> MemoryDB {
+        let db = MemoryDB::new();
+        let mut fork = db.fork();
+        {
+            let mut list: ListIndex<_, u64> = ListIndex::new("a.list", &mut fork);
+            list.extend(vec![1, 2, 3]);
+            let mut map: MapIndex<_, u64, u64> = MapIndex::new("b.map", &mut fork);
+            map.put(&1, 10);
+            let mut pool: KeySetIndex<_, u64> =
+                KeySetIndex::new("core.transactions_pool", &mut fork);
+            pool.insert(1);
+            let mut entry: Entry<_, u64> = Entry::new("d.entry", &mut fork);
+            entry.set(5);
+        }
+        db.merge(fork.into_patch()).unwrap();
+        db
+    }
+
+    fn is_synced(name: &str) -> bool {
+        name != "__INDEXES_METADATA__" && name != "core.transactions_pool"
+    }
+
+    fn chunk_response(
+        index_name: &str,
+        from_key: &[u8],
+        keys: &[&[u8]],
+        next_index_name: &str,
+        next_key: &[u8],
+    ) -> StateChunkResponse {
+        let (public_key, secret_key) = gen_keypair();
+        let entries = keys.iter().map(|key| StateEntry::new(key, &[1])).collect();
+        StateChunkResponse::new(
+            &public_key,
+            &public_key,
+            Height(10),
+            index_name,
+            from_key,
+            entries,
+            next_index_name,
+            next_key,
+            &secret_key,
+        )
+    }
+
+    #[test]
+    fn test_state_chunks_cover_all_indexes() {
+        let db = create_database();
+        let snapshot = db.snapshot();
+
+        let mut cursor = (String::new(), Vec::new());
+        let mut chunks = Vec::new();
+        loop {
+            let chunk = state_chunk(&*snapshot, &cursor.0, &cursor.1, 2, 1024, &is_synced)
+                .unwrap();
+            let next = (chunk.next_index_name.clone(), chunk.next_key.clone());
+            chunks.push((cursor.0.clone(), chunk.entries.len()));
+            if next.0.is_empty() {
+                break;
+            }
+            cursor = next;
+        }
+
+        assert_eq!(
+            chunks,
+            vec![
+                ("".to_owned(), 0),
+                ("a.list".to_owned(), 2),
+                ("a.list".to_owned(), 2),
+                ("b.map".to_owned(), 1),
+                ("d.entry".to_owned(), 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_state_chunk_size_limit() {
+        let db = create_database();
+        let snapshot = db.snapshot();
+
+        let chunk = state_chunk(&*snapshot, "b.map", &[], 10, 10, &is_synced);
+        assert_eq!(chunk, None);
+
+        let chunk = state_chunk(&*snapshot, "core.transactions_pool", &[], 10, 1024, &is_synced)
+            .unwrap();
+        assert_eq!(
+            chunk,
+            StateChunk {
+                entries: Vec::<StateEntry>::new(),
+                next_index_name: "d.entry".to_owned(),
+                next_key: Vec::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn test_check_state_chunk() {
+        let value_hash = hash(&[1]);
+        let valid = vec![
+            chunk_response("", &[], &[], "a.list", &[]),
+            chunk_response("a.list", &[], &[&[0], &[1]], "a.list", &[2]),
+            chunk_response("a.list", &[2], &[&[2], &[3]], "b.map", &[]),
+            chunk_response("core.blocks", &[], &[value_hash.as_ref()], "d.entry", &[]),
+            chunk_response("d.entry", &[], &[&[]], "", &[]),
+        ];
+        for msg in &valid {
+            assert_eq!(check_state_chunk(msg, &is_synced), Ok(()));
+        }
+
+        let invalid = vec![
+            // Entries of a local index.
+            chunk_response("core.transactions_pool", &[], &[&[1]], "d.entry", &[]),
+            // Next index is local.
+            chunk_response("a.list", &[], &[&[0]], "core.transactions_pool", &[]),
+            // Entries before the requested key.
+            chunk_response("a.list", &[2], &[&[1], &[2]], "b.map", &[]),
+            // Entries out of order.
+            chunk_response("a.list", &[], &[&[1], &[0]], "b.map", &[]),
+            // The chunk does not move forward.
+            chunk_response("a.list", &[2], &[&[2]], "a.list", &[2]),
+            chunk_response("b.map", &[], &[&[1]], "a.list", &[]),
+            // Entries without an index.
+            chunk_response("", &[], &[&[1]], "a.list", &[]),
+            // Entries do not match their hashes.
+            chunk_response("core.blocks", &[], &[&[1]], "d.entry", &[]),
+        ];
+        for msg in &invalid {
+            assert!(check_state_chunk(msg, &is_synced).is_err());
+        }
+    }
+
+    #[test]
+    fn test_staged_state() {
+        let db = MemoryDB::new();
+        let mut fork = db.fork();
+        fork.put(STAGED_STATE, staged_key("a", &[1]), vec![10]);
+        fork.put(STAGED_STATE, staged_key("a", &[2]), vec![20]);
+        fork.put(STAGED_STATE, staged_key("ab", &[0]), vec![30]);
+        db.merge(fork.into_patch()).unwrap();
+
+        let staged = StagedState { snapshot: db.snapshot() };
+        assert_eq!(staged.get("a", &[1]), Some(vec![10]));
+        assert_eq!(staged.get("ab", &[1]), None);
+
+        let mut entries = Vec::new();
+        let mut iter = staged.iter("a", &[]);
+        while let Some((key, value)) = iter.next() {
+            entries.push((key.to_vec(), value.to_vec()));
+        }
+        assert_eq!(entries, vec![(vec![1], vec![10]), (vec![2], vec![20])]);
+    }
+}
@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use std::collections::BTreeSet;
+use std::sync::{Arc, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 use crypto::PublicKey;
 
@@ -43,6 +44,19 @@ impl Whitelist {
         self.whitelisted_peers.insert(peer);
     }
 
+    /// Removes peer from the whitelist. Returns `true` if the peer was in the whitelist.
+    /// Validators are allowed to connect regardless of the whitelisted peers.
+    pub fn remove(&mut self, peer: &PublicKey) -> bool {
+        self.whitelisted_peers.remove(peer)
+    }
+
+    /// Replaces the whitelisted peers and the enabled flag with the ones of the given
+    /// whitelist. The list of validators is kept.
+    pub fn replace_peers(&mut self, other: &Whitelist) {
+        self.whitelist_enabled = other.whitelist_enabled;
+        self.whitelisted_peers = other.whitelisted_peers.clone();
+    }
+
     /// Returns list of whitelisted peers.
     pub fn collect_allowed(&self) -> Vec<&PublicKey> {
         self.whitelisted_peers
@@ -65,6 +79,34 @@ impl Whitelist {
     }
 }
 
+/// `Whitelist` shared between the node and its network part, which rejects peers
+/// that are not in the whitelist during the encrypted handshake.
+#[derive(Debug, Clone, Default)]
+pub struct SharedWhitelist {
+    inner: Arc<RwLock<Whitelist>>,
+}
+
+impl SharedWhitelist {
+    /// Creates a shared whitelist from the given one.
+    pub fn new(whitelist: Whitelist) -> SharedWhitelist {
+        SharedWhitelist { inner: Arc::new(RwLock::new(whitelist)) }
+    }
+
+    /// Returns `true` if a peer with the given public key can connect.
+    pub fn allow(&self, peer: &PublicKey) -> bool {
+        self.read().allow(peer)
+    }
+
+    /// Locks the whitelist for reading.
+    pub fn read(&self) -> RwLockReadGuard<Whitelist> {
+        self.inner.read().expect("Whitelist lock is poisoned")
+    }
+
+    /// Locks the whitelist for writing.
+    pub fn write(&self) -> RwLockWriteGuard<Whitelist> {
+        self.inner.write().expect("Whitelist lock is poisoned")
+    }
+}
 
 #[cfg(test)]
 mod test {
@@ -113,6 +155,24 @@ mod test {
         assert_eq!(whitelist.collect_allowed().len(), 2);
     }
 
+    #[test]
+    fn test_remove_from_whitelist() {
+        let regular = make_keys(REGULAR_PEERS, 4);
+        let validators = make_keys(VALIDATORS[0], 2);
+
+        let mut whitelist = Whitelist::default();
+        whitelist.whitelist_enabled = true;
+        whitelist.set_validators(validators.clone());
+        whitelist.add(regular[0]);
+        whitelist.add(regular[1]);
+        assert!(whitelist.remove(&regular[0]));
+        assert!(!whitelist.remove(&regular[0]));
+        check_in_whitelist(&whitelist, &regular, &[1], &[0, 2, 3]);
+
+        assert!(!whitelist.remove(&validators[0]));
+        check_in_whitelist(&whitelist, &validators, &[0, 1], &[]);
+    }
+
     #[test]
     fn test_wildcard() {
         let regular = make_keys(REGULAR_PEERS, 4);
@@ -16,21 +16,27 @@
 
 use std::time::Duration;
 use std::collections::BTreeMap;
+use std::net::SocketAddr;
 
 use rand::{thread_rng, Rng};
 use bit_vec::BitVec;
 
 use messages::{RawMessage, Message, Propose, Prevote, Precommit, ProposeRequest, PrevotesRequest,
-               TransactionsRequest, CONSENSUS, Connect, PeersRequest};
-use crypto::{CryptoHash, Hash, Seed, gen_keypair, gen_keypair_from_seed};
-use blockchain::{Blockchain, Schema};
-use node;
+               TransactionsRequest, CONSENSUS, Connect, PeersRequest, Status, BlocksRequest,
+               BlockResponse, ConsensusMessage, Disconnect, ReportEvidence, TransactionsBatch,
+               TransactionsResponse, SUPPORTED_PROTOCOL_VERSIONS, TEST_NETWORK_ID};
+use crypto::{CryptoHash, Hash, Seed, gen_keypair, gen_keypair_from_seed, hash};
+use blockchain::{Blockchain, ConsensusLock, Evidence, LeaderElectionConfig, Schema};
+use node::{self, PeerBehavior};
+use node::signer::{sign_raw, LocalSigner};
 use node::state::{PREVOTES_REQUEST_TIMEOUT, PROPOSE_REQUEST_TIMEOUT, TRANSACTIONS_REQUEST_TIMEOUT};
-use helpers::{Height, Round, user_agent};
-use super::timestamping::{TimestampTx, TimestampingTxGenerator, TIMESTAMPING_SERVICE};
-use super::sandbox::{timestamping_sandbox, sandbox_with_services_uninitialized};
+use helpers::{Height, Round, ValidatorId, user_agent};
+use super::timestamping::{TimestampTx, TimestampingService, TimestampingTxGenerator,
+                          TIMESTAMPING_SERVICE};
+use super::sandbox::{timestamping_sandbox, sandbox_consensus_config, sandbox_with_consensus,
+                     sandbox_with_services_uninitialized, Sandbox};
 use super::sandbox_tests_helper::*;
-use super::config_updater::TxConfig;
+use super::config_updater::{ConfigUpdateService, TxConfig};
 
 // HANDLE CONSENSUS BASIC
 
@@ -44,6 +50,7 @@ fn test_queue_message_from_future_round() {
         ROUND_TWO,
         &sandbox.last_hash(),
         &[],
+        sandbox.time().into(),
         sandbox.s(VALIDATOR_3),
     );
 
@@ -118,6 +125,7 @@ fn test_reach_actual_round() {
         ROUND_FOUR,
         &block_at_first_height.clone().hash(),
         &[], // there are no transactions in future propose
+        sandbox.time().into(),
         sandbox.s(VALIDATOR_3),
     );
 
@@ -332,6 +340,63 @@ fn should_not_send_propose_and_prevote_after_node_restart() {
     // if there any sent messages
 }
 
+/// Idea:
+/// - The leader sends `Propose` and `Prevote`.
+/// - The leader restarts.
+/// - The leader restores its propose from the consensus messages cache, does not propose again
+///   and precommits for the restored propose once it gets a majority of prevotes.
+#[test]
+fn test_restarted_leader_restores_own_propose() {
+    let sandbox = timestamping_sandbox();
+
+    sandbox.add_time(Duration::from_millis(sandbox.round_timeout()));
+    sandbox.add_time(Duration::from_millis(
+        sandbox.round_timeout() + sandbox.propose_timeout(),
+    ));
+    assert!(sandbox.is_leader());
+    sandbox.assert_state(HEIGHT_ONE, ROUND_THREE);
+
+    let propose = ProposeBuilder::new(&sandbox)
+        .with_duration_since_sandbox_time(sandbox.propose_timeout())
+        .build();
+    let block = BlockBuilder::new(&sandbox)
+        .with_duration_since_sandbox_time(sandbox.propose_timeout())
+        .build();
+    let prevote = make_prevote_from_propose(&sandbox, &propose);
+    sandbox.broadcast(&propose);
+    sandbox.broadcast(&prevote);
+
+    let sandbox = sandbox.restart();
+    sandbox.broadcast(&prevote);
+    sandbox.assert_state(HEIGHT_ONE, ROUND_THREE);
+    {
+        let state = sandbox.node_state();
+        assert!(state.propose(&propose.hash()).is_some());
+        assert!(state.have_self_propose(ROUND_THREE));
+    }
+
+    for &validator in &[VALIDATOR_1, VALIDATOR_2] {
+        sandbox.recv(&Prevote::new(
+            validator,
+            HEIGHT_ONE,
+            ROUND_THREE,
+            &propose.hash(),
+            LOCK_ZERO,
+            sandbox.s(validator),
+        ));
+    }
+    sandbox.assert_lock(ROUND_THREE, Some(propose.hash()));
+    sandbox.broadcast(&Precommit::new(
+        VALIDATOR_0,
+        HEIGHT_ONE,
+        ROUND_THREE,
+        &propose.hash(),
+        &block.hash(),
+        sandbox.time().into(),
+        sandbox.s(VALIDATOR_0),
+    ));
+}
+
 /// Idea:
 /// - Node gets locked on some Propose from certain validator.
 /// - Node restarts.
@@ -623,18 +688,10 @@ fn test_recover_consensus_messages_in_other_round() {
 
     sandbox_new.assert_lock(LOCK_TWO, Some(second_propose.hash()));
     sandbox_new.assert_state(HEIGHT_ONE, ROUND_TWO);
+    // Votes are restored from the consensus write-ahead log rather than signed again,
+    // so the precommit keeps its original time.
     sandbox_new.broadcast(&first_prevote);
-
-    let first_precommit_new_time = Precommit::new(
-        first_precommit.validator(),
-        first_precommit.height(),
-        first_precommit.round(),
-        first_precommit.propose_hash(),
-        first_precommit.block_hash(),
-        sandbox_new.time().into(),
-        sandbox_new.s(VALIDATOR_0),
-    );
-    sandbox_new.broadcast(&first_precommit_new_time);
+    sandbox_new.broadcast(&first_precommit);
     sandbox_new.broadcast(&first_updated_prevote);
     sandbox_new.broadcast(&second_precommit);
 }
@@ -655,8 +712,26 @@ fn should_restore_peers_after_restart() {
     let (p1, s1, a1) = (sandbox.p(v1), sandbox.s(v1).clone(), sandbox.a(v1));
 
     let time = sandbox.time();
-    let connect_from_0 = Connect::new(&p0, a0, time.into(), &user_agent::get(), &s0);
-    let connect_from_1 = Connect::new(&p1, a1, time.into(), &user_agent::get(), &s1);
+    let connect_from_0 = Connect::new(
+        &p0,
+        a0,
+        time.into(),
+        &user_agent::get(),
+        SUPPORTED_PROTOCOL_VERSIONS,
+        &sandbox.network_id(),
+        false,
+        &s0,
+    );
+    let connect_from_1 = Connect::new(
+        &p1,
+        a1,
+        time.into(),
+        &user_agent::get(),
+        SUPPORTED_PROTOCOL_VERSIONS,
+        &sandbox.network_id(),
+        false,
+        &s1,
+    );
     let peers_request = PeersRequest::new(&p1, &p0, &s1);
 
     // check that peers are absent
@@ -779,6 +854,7 @@ fn test_queue_propose_message_from_next_height() {
                                       ROUND_TWO,
                                       &block_at_first_height.clone().hash(),
                                       &[], // there are no transactions in future propose
+                                      sandbox.time().into(),
                                       sandbox.s(VALIDATOR_0));
 
     sandbox.recv(&future_propose);
@@ -870,6 +946,7 @@ fn test_ignore_message_with_incorrect_validator_id() {
         ROUND_ONE,
         &sandbox.last_hash(),
         &[],
+        sandbox.time().into(),
         sandbox.s(VALIDATOR_1),
     );
 
@@ -886,6 +963,7 @@ fn test_ignore_message_with_incorrect_signature() {
         ROUND_ONE,
         &sandbox.last_hash(),
         &[],
+        sandbox.time().into(),
         sandbox.s(VALIDATOR_1),
     );
 
@@ -1043,7 +1121,15 @@ fn response_to_request_txs() {
         sandbox.s(VALIDATOR_1),
     ));
 
-    sandbox.send(sandbox.a(VALIDATOR_1), &tx);
+    sandbox.send(
+        sandbox.a(VALIDATOR_1),
+        &TransactionsResponse::new(
+            &sandbox.p(VALIDATOR_0),
+            &sandbox.p(VALIDATOR_1),
+            vec![tx.raw().clone()],
+            sandbox.s(VALIDATOR_0),
+        ),
+    );
 }
 
 /// idea of the test is to
@@ -1181,7 +1267,15 @@ fn respond_to_request_tx_propose_prevotes_precommits() {
             sandbox.s(VALIDATOR_1),
         ));
 
-        sandbox.send(sandbox.a(VALIDATOR_1), &tx);
+        sandbox.send(
+            sandbox.a(VALIDATOR_1),
+            &TransactionsResponse::new(
+                &sandbox.p(VALIDATOR_0),
+                &sandbox.p(VALIDATOR_1),
+                vec![tx.raw().clone()],
+                sandbox.s(VALIDATOR_0),
+            ),
+        );
     }
 
     {
@@ -3378,3 +3472,977 @@ fn test_schema_config_changes() {
 //     - add timeout             //for RequestTransaction is covered in handle_tx_has_full_propose()
 
 // todo add scenario for single node network
+
+/// A node behind the other nodes requests all the missing blocks with a single
+/// `BlocksRequest` message.
+#[test]
+fn test_request_blocks_on_status_from_future_height() {
+    let sandbox = timestamping_sandbox();
+
+    sandbox.recv(&Status::new(
+        &sandbox.p(VALIDATOR_1),
+        HEIGHT_THREE,
+        &empty_hash(),
+        sandbox.s(VALIDATOR_1),
+    ));
+    sandbox.send(
+        sandbox.a(VALIDATOR_1),
+        &BlocksRequest::new(
+            &sandbox.p(VALIDATOR_0),
+            &sandbox.p(VALIDATOR_1),
+            HEIGHT_ONE,
+            2,
+            sandbox.s(VALIDATOR_0),
+        ),
+    );
+}
+
+/// A node responds to `BlocksRequest` with a `BlockResponse` for each of the known
+/// requested blocks.
+#[test]
+fn test_handle_blocks_request() {
+    let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
+
+    add_one_height_with_transactions(&sandbox, &sandbox_state, &[]);
+    add_one_height_with_transactions(&sandbox, &sandbox_state, &[]);
+
+    sandbox.recv(&BlocksRequest::new(
+        &sandbox.p(VALIDATOR_1),
+        &sandbox.p(VALIDATOR_0),
+        HEIGHT_ONE,
+        5,
+        sandbox.s(VALIDATOR_1),
+    ));
+    for height in &[HEIGHT_ONE, HEIGHT_TWO] {
+        let proof = sandbox.block_and_precommits(*height).unwrap();
+        sandbox.send(
+            sandbox.a(VALIDATOR_1),
+            &BlockResponse::new(
+                &sandbox.p(VALIDATOR_0),
+                &sandbox.p(VALIDATOR_1),
+                proof.block,
+                proof.precommits,
+                vec![],
+                sandbox.s(VALIDATOR_0),
+            ),
+        );
+    }
+}
+
+/// A block which transactions are split between several `BlockResponse` messages
+/// is committed once all the parts are received from the peer the block is requested from.
+#[test]
+fn test_handle_block_in_chunks() {
+    let sandbox = timestamping_sandbox();
+
+    let txs = [gen_timestamping_tx(), gen_timestamping_tx()];
+    let tx_hashes = txs.iter().map(|tx| tx.hash()).collect::<Vec<_>>();
+    let state_hash = sandbox.compute_state_hash(txs.iter().map(|tx| tx.raw()));
+    let propose = ProposeBuilder::new(&sandbox)
+        .with_tx_hashes(&tx_hashes)
+        .build();
+    let block = BlockBuilder::new(&sandbox)
+        .with_txs_hashes(&tx_hashes)
+        .with_state_hash(&state_hash)
+        .build();
+    let precommits = [VALIDATOR_1, VALIDATOR_2, VALIDATOR_3]
+        .iter()
+        .map(|validator| {
+            Precommit::new(
+                *validator,
+                HEIGHT_ONE,
+                ROUND_ONE,
+                &propose.hash(),
+                &block.hash(),
+                sandbox.time().into(),
+                sandbox.s(*validator),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    sandbox.recv(&Status::new(
+        &sandbox.p(VALIDATOR_1),
+        HEIGHT_TWO,
+        &block.hash(),
+        sandbox.s(VALIDATOR_1),
+    ));
+    sandbox.send(
+        sandbox.a(VALIDATOR_1),
+        &BlocksRequest::new(
+            &sandbox.p(VALIDATOR_0),
+            &sandbox.p(VALIDATOR_1),
+            HEIGHT_ONE,
+            1,
+            sandbox.s(VALIDATOR_0),
+        ),
+    );
+
+    // The parts from the peer the block is not requested from are ignored.
+    for tx in &txs {
+        sandbox.recv(&BlockResponse::new(
+            &sandbox.p(VALIDATOR_2),
+            &sandbox.p(VALIDATOR_0),
+            block.clone(),
+            precommits.clone(),
+            vec![tx.raw().clone()],
+            sandbox.s(VALIDATOR_2),
+        ));
+    }
+
+    for tx in &txs {
+        sandbox.assert_state(HEIGHT_ONE, ROUND_ONE);
+        sandbox.recv(&BlockResponse::new(
+            &sandbox.p(VALIDATOR_1),
+            &sandbox.p(VALIDATOR_0),
+            block.clone(),
+            precommits.clone(),
+            vec![tx.raw().clone()],
+            sandbox.s(VALIDATOR_1),
+        ));
+    }
+    sandbox.assert_state(HEIGHT_TWO, ROUND_ONE);
+    sandbox.check_broadcast_status(HEIGHT_TWO, &block.hash());
+}
+
+/// A replica ignores the consensus messages and the transactions from its peers, forwards
+/// the transactions from the api to the upstream and commits the blocks received
+/// through the block sync.
+#[test]
+fn test_replica_follows_committed_blocks() {
+    let sandbox = timestamping_sandbox();
+    let upstream = sandbox.a(VALIDATOR_1);
+    let sandbox = sandbox.restart_as_replica(upstream);
+
+    let propose = ProposeBuilder::new(&sandbox).build();
+    sandbox.recv(&propose);
+    sandbox.add_time(Duration::from_millis(sandbox.round_timeout() * 2));
+    sandbox.assert_state(HEIGHT_ONE, ROUND_ONE);
+
+    let tx = gen_timestamping_tx();
+    sandbox.recv(&tx);
+    assert!(sandbox.transactions_hashes().is_empty());
+
+    sandbox
+        .node_handler_mut()
+        .channel
+        .api_requests
+        .send(node::ExternalMessage::Transaction(Box::new(tx.clone())))
+        .unwrap();
+    sandbox.process_events();
+    sandbox.send(upstream, &tx);
+    assert!(sandbox.transactions_hashes().is_empty());
+
+    let tx_hashes = [tx.hash()];
+    let state_hash = sandbox.compute_state_hash(&[tx.raw().clone()]);
+    let propose = ProposeBuilder::new(&sandbox)
+        .with_tx_hashes(&tx_hashes)
+        .build();
+    let block = BlockBuilder::new(&sandbox)
+        .with_txs_hashes(&tx_hashes)
+        .with_state_hash(&state_hash)
+        .build();
+    let precommits = [VALIDATOR_1, VALIDATOR_2, VALIDATOR_3]
+        .iter()
+        .map(|validator| {
+            Precommit::new(
+                *validator,
+                HEIGHT_ONE,
+                ROUND_ONE,
+                &propose.hash(),
+                &block.hash(),
+                sandbox.time().into(),
+                sandbox.s(*validator),
+            )
+        })
+        .collect::<Vec<_>>();
+    sandbox.recv(&BlockResponse::new(
+        &sandbox.p(VALIDATOR_1),
+        &sandbox.p(VALIDATOR_0),
+        block.clone(),
+        precommits,
+        vec![tx.raw().clone()],
+        sandbox.s(VALIDATOR_1),
+    ));
+    sandbox.assert_state(HEIGHT_TWO, ROUND_ONE);
+    sandbox.check_broadcast_status(HEIGHT_TWO, &block.hash());
+}
+
+/// Emulates the connection of a replica to the sandbox node.
+fn connect_replica(sandbox: &Sandbox) {
+    let (public_key, secret_key) = gen_keypair();
+    let connect = Connect::new(
+        &public_key,
+        SocketAddr::from(([127, 0, 0, 1], 12_040)),
+        sandbox.time().into(),
+        &user_agent::get(),
+        SUPPORTED_PROTOCOL_VERSIONS,
+        &sandbox.network_id(),
+        true,
+        &secret_key,
+    );
+    sandbox.connect_peer(&connect);
+    let our_connect = sandbox.node_state().our_connect_message().clone();
+    sandbox.send(connect.addr(), &our_connect);
+}
+
+/// A validator adds the transactions forwarded by a replica to the pool and broadcasts them
+/// like the transactions received from the api.
+#[test]
+fn test_handle_tx_forwarded_by_replica() {
+    let sandbox = timestamping_sandbox();
+    connect_replica(&sandbox);
+
+    let tx = gen_timestamping_tx();
+    sandbox.recv(&tx);
+    assert!(sandbox.transactions_hashes().contains(&tx.hash()));
+    sandbox.add_time(Duration::from_millis(sandbox.tx_batch_timeout()));
+    sandbox.broadcast(&tx);
+}
+
+/// A replica passes the transactions forwarded by its own replicas to the upstream.
+#[test]
+fn test_replica_forwards_tx_of_replica() {
+    let sandbox = timestamping_sandbox();
+    let upstream = sandbox.a(VALIDATOR_1);
+    let sandbox = sandbox.restart_as_replica(upstream);
+    connect_replica(&sandbox);
+
+    let tx = gen_timestamping_tx();
+    sandbox.recv(&tx);
+    sandbox.send(upstream, &tx);
+    assert!(sandbox.transactions_hashes().is_empty());
+}
+
+/// Messages stamped with the identifier of another network are ignored, even if they are
+/// signed by a validator.
+#[test]
+fn test_ignore_messages_of_other_network() {
+    let sandbox = timestamping_sandbox();
+
+    let status = Status::new(
+        &sandbox.p(VALIDATOR_1),
+        HEIGHT_THREE,
+        &empty_hash(),
+        sandbox.s(VALIDATOR_1),
+    );
+    let signer = LocalSigner::new(sandbox.p(VALIDATOR_1), sandbox.s(VALIDATOR_1).clone());
+    let status = sign_raw(&signer, &status.raw().with_network_id(TEST_NETWORK_ID + 1)).unwrap();
+    assert!(status.verify_signature(&sandbox.p(VALIDATOR_1)));
+    sandbox.recv(&status);
+}
+
+/// Transactions larger than `max_block_size` are not added to the pool, since they
+/// cannot be included into any block.
+#[test]
+fn test_reject_tx_exceeding_max_block_size() {
+    let mut consensus = sandbox_consensus_config();
+    consensus.max_block_size = Some(512);
+    let sandbox = sandbox_with_consensus(vec![Box::new(TimestampingService::new())], consensus);
+
+    let large_tx = TimestampingTxGenerator::new(1024).next().unwrap();
+    assert!(large_tx.raw().len() > 512);
+    sandbox.recv(&large_tx);
+    assert!(sandbox.transactions_hashes().is_empty());
+
+    let tx = gen_timestamping_tx();
+    sandbox.recv(&tx);
+    assert_eq!(sandbox.transactions_hashes(), vec![tx.hash()]);
+}
+
+/// A node that receives conflicting prevotes of a validator broadcasts the evidence.
+#[test]
+fn test_report_conflicting_prevotes() {
+    let sandbox = timestamping_sandbox();
+
+    let first = Prevote::new(
+        VALIDATOR_1,
+        HEIGHT_ONE,
+        ROUND_ONE,
+        &hash(&[1]),
+        LOCK_ZERO,
+        sandbox.s(VALIDATOR_1),
+    );
+    let second = Prevote::new(
+        VALIDATOR_1,
+        HEIGHT_ONE,
+        ROUND_ONE,
+        &hash(&[2]),
+        LOCK_ZERO,
+        sandbox.s(VALIDATOR_1),
+    );
+    sandbox.recv(&first);
+    sandbox.recv(&first);
+    sandbox.recv(&second);
+    sandbox.add_time(Duration::from_millis(sandbox.tx_batch_timeout()));
+
+    let evidence = Evidence::from_messages(
+        &sandbox.p(VALIDATOR_1),
+        &ConsensusMessage::Prevote(first),
+        &ConsensusMessage::Prevote(second),
+    ).unwrap();
+    sandbox.broadcast(&ReportEvidence::new(
+        &sandbox.p(VALIDATOR_0),
+        evidence,
+        sandbox.s(VALIDATOR_0),
+    ));
+}
+
+/// A transaction submitted to the node again is not gossiped again.
+#[test]
+fn test_duplicate_tx_is_not_gossiped() {
+    let sandbox = timestamping_sandbox();
+
+    let tx = gen_timestamping_tx();
+    for _ in 0..2 {
+        sandbox
+            .node_handler_mut()
+            .channel
+            .api_requests
+            .send(node::ExternalMessage::Transaction(Box::new(tx.clone())))
+            .unwrap();
+        sandbox.process_events();
+    }
+    sandbox.add_time(Duration::from_millis(sandbox.tx_batch_timeout()));
+    sandbox.broadcast(&tx);
+    assert_eq!(sandbox.transactions_hashes(), vec![tx.hash()]);
+}
+
+/// A node reports a validator only once per round, even if it receives more conflicting
+/// messages of the validator.
+#[test]
+fn test_report_conflicting_messages_once() {
+    let sandbox = timestamping_sandbox();
+
+    let make_prevote = |propose: u8| {
+        Prevote::new(
+            VALIDATOR_1,
+            HEIGHT_ONE,
+            ROUND_ONE,
+            &hash(&[propose]),
+            LOCK_ZERO,
+            sandbox.s(VALIDATOR_1),
+        )
+    };
+    let (first, second, third) = (make_prevote(1), make_prevote(2), make_prevote(3));
+    sandbox.recv(&first);
+    sandbox.recv(&second);
+    sandbox.add_time(Duration::from_millis(sandbox.tx_batch_timeout()));
+
+    let evidence = Evidence::from_messages(
+        &sandbox.p(VALIDATOR_1),
+        &ConsensusMessage::Prevote(first),
+        &ConsensusMessage::Prevote(second.clone()),
+    ).unwrap();
+    sandbox.broadcast(&ReportEvidence::new(
+        &sandbox.p(VALIDATOR_0),
+        evidence,
+        sandbox.s(VALIDATOR_0),
+    ));
+
+    // The same and other conflicting messages are not reported again.
+    sandbox.recv(&second);
+    sandbox.recv(&third);
+    sandbox.add_time(Duration::from_millis(sandbox.tx_batch_timeout()));
+}
+
+/// A peer that sends invalid blocks is banned and disconnected.
+#[test]
+fn test_ban_peer_sending_invalid_blocks() {
+    let sandbox = timestamping_sandbox();
+
+    let prev_hash = hash(&[1]);
+    let block = BlockBuilder::new(&sandbox).with_prev_hash(&prev_hash).build();
+    let response = BlockResponse::new(
+        &sandbox.p(VALIDATOR_1),
+        &sandbox.p(VALIDATOR_0),
+        block,
+        vec![],
+        vec![],
+        sandbox.s(VALIDATOR_1),
+    );
+
+    sandbox.recv(&response);
+    {
+        let state = sandbox.node_state();
+        let reputation = state.reputation();
+        assert_eq!(
+            reputation.get(&sandbox.p(VALIDATOR_1)).score(),
+            PeerBehavior::InvalidBlock.score()
+        );
+        assert!(!reputation.is_banned(&sandbox.p(VALIDATOR_1), sandbox.time()));
+    }
+
+    sandbox.recv(&response);
+    let state = sandbox.node_state();
+    assert!(state.reputation().is_banned(
+        &sandbox.p(VALIDATOR_1),
+        sandbox.time(),
+    ));
+    assert!(!state.peers().contains_key(&sandbox.p(VALIDATOR_1)));
+}
+
+/// A peer is disconnected on the request from the private api.
+#[test]
+fn test_disconnect_peer_on_request() {
+    let sandbox = timestamping_sandbox();
+    assert!(sandbox.node_state().peers().contains_key(
+        &sandbox.p(VALIDATOR_1),
+    ));
+
+    let message = node::ExternalMessage::PeerDisconnect(sandbox.p(VALIDATOR_1));
+    sandbox
+        .node_handler_mut()
+        .channel
+        .api_requests
+        .send(message)
+        .unwrap();
+    sandbox.process_events();
+
+    assert!(!sandbox.node_state().peers().contains_key(
+        &sandbox.p(VALIDATOR_1),
+    ));
+}
+
+/// Peers are added to and removed from the whitelist on the requests from the private api.
+#[test]
+fn test_whitelist_update_on_request() {
+    let sandbox = timestamping_sandbox();
+    let (peer, _) = gen_keypair();
+
+    let message = node::ExternalMessage::WhitelistAdd(peer);
+    sandbox
+        .node_handler_mut()
+        .channel
+        .api_requests
+        .send(message)
+        .unwrap();
+    sandbox.process_events();
+    assert!(
+        sandbox
+            .node_state()
+            .whitelist()
+            .read()
+            .collect_allowed()
+            .contains(&&peer)
+    );
+
+    let message = node::ExternalMessage::WhitelistRemove(peer);
+    sandbox
+        .node_handler_mut()
+        .channel
+        .api_requests
+        .send(message)
+        .unwrap();
+    sandbox.process_events();
+    assert!(
+        !sandbox
+            .node_state()
+            .whitelist()
+            .read()
+            .collect_allowed()
+            .contains(&&peer)
+    );
+}
+
+/// On shutdown the node sends `Disconnect` to its peers and stops once all the connections
+/// are closed. Transactions received from the clients during the shutdown are ignored.
+#[test]
+fn test_graceful_shutdown() {
+    let sandbox = timestamping_sandbox();
+
+    sandbox
+        .node_handler_mut()
+        .channel
+        .api_requests
+        .send(node::ExternalMessage::Shutdown)
+        .unwrap();
+    sandbox.process_events();
+    sandbox.broadcast(&Disconnect::new(
+        &sandbox.p(VALIDATOR_0),
+        sandbox.time().into(),
+        sandbox.s(VALIDATOR_0),
+    ));
+    assert!(sandbox.node_state().peers().is_empty());
+
+    let tx = gen_timestamping_tx();
+    sandbox
+        .node_handler_mut()
+        .channel
+        .api_requests
+        .send(node::ExternalMessage::Transaction(Box::new(tx)))
+        .unwrap();
+    sandbox.process_events();
+    sandbox.add_time(Duration::from_millis(sandbox.tx_batch_timeout()));
+
+    for id in 1..sandbox.n_validators() {
+        assert!(!sandbox.is_stopped());
+        sandbox.disconnect(sandbox.a(ValidatorId(id as u16)));
+    }
+    assert!(sandbox.is_stopped());
+}
+
+/// The node stops after `shutdown_timeout` even if some connections are not closed.
+#[test]
+fn test_shutdown_timeout() {
+    let sandbox = timestamping_sandbox();
+
+    sandbox
+        .node_handler_mut()
+        .channel
+        .api_requests
+        .send(node::ExternalMessage::Shutdown)
+        .unwrap();
+    sandbox.process_events();
+    sandbox.broadcast(&Disconnect::new(
+        &sandbox.p(VALIDATOR_0),
+        sandbox.time().into(),
+        sandbox.s(VALIDATOR_0),
+    ));
+
+    sandbox.disconnect(sandbox.a(VALIDATOR_1));
+    sandbox.add_time(Duration::from_millis(node::DEFAULT_SHUTDOWN_TIMEOUT - 1));
+    assert!(!sandbox.is_stopped());
+    sandbox.add_time(Duration::from_millis(1));
+    assert!(sandbox.is_stopped());
+}
+
+/// The node disconnects from a peer which has notified about its shutdown, unless
+/// the notice is older than the connection.
+#[test]
+fn test_handle_disconnect_notice() {
+    let sandbox = timestamping_sandbox();
+    let connect_time = sandbox.time();
+
+    sandbox.recv(&Disconnect::new(
+        &sandbox.p(VALIDATOR_1),
+        (connect_time - Duration::from_secs(1)).into(),
+        sandbox.s(VALIDATOR_1),
+    ));
+    assert!(sandbox.node_state().peers().contains_key(
+        &sandbox.p(VALIDATOR_1),
+    ));
+
+    sandbox.recv(&Disconnect::new(
+        &sandbox.p(VALIDATOR_1),
+        connect_time.into(),
+        sandbox.s(VALIDATOR_1),
+    ));
+    assert!(!sandbox.node_state().peers().contains_key(
+        &sandbox.p(VALIDATOR_1),
+    ));
+    assert!(sandbox.node_state().peers().contains_key(
+        &sandbox.p(VALIDATOR_2),
+    ));
+}
+
+/// Transactions received from the clients are broadcast in a single batch.
+#[test]
+fn test_broadcast_txs_batch() {
+    let sandbox = timestamping_sandbox();
+    let txs = [gen_timestamping_tx(), gen_timestamping_tx()];
+
+    for tx in &txs {
+        let message = node::ExternalMessage::Transaction(Box::new(tx.clone()));
+        sandbox
+            .node_handler_mut()
+            .channel
+            .api_requests
+            .send(message)
+            .unwrap();
+    }
+    sandbox.process_events();
+
+    sandbox.add_time(Duration::from_millis(sandbox.tx_batch_timeout() - 1));
+    sandbox.add_time(Duration::from_millis(1));
+    sandbox.broadcast(&TransactionsBatch::new(
+        &sandbox.p(VALIDATOR_0),
+        txs.iter().map(|tx| tx.raw().clone()).collect(),
+        sandbox.s(VALIDATOR_0),
+    ));
+}
+
+/// Transactions of a received batch are added to the pool.
+#[test]
+fn test_handle_txs_batch() {
+    let sandbox = timestamping_sandbox();
+    let txs = [gen_timestamping_tx(), gen_timestamping_tx()];
+
+    sandbox.recv(&TransactionsBatch::new(
+        &sandbox.p(VALIDATOR_1),
+        txs.iter().map(|tx| tx.raw().clone()).collect(),
+        sandbox.s(VALIDATOR_1),
+    ));
+
+    let pool = sandbox.transactions_hashes();
+    for tx in &txs {
+        assert!(pool.contains(&tx.hash()));
+    }
+}
+
+/// Requested transactions are sent in a single response.
+#[test]
+fn response_to_request_txs_with_several_txs() {
+    let sandbox = timestamping_sandbox();
+    let txs = [gen_timestamping_tx(), gen_timestamping_tx()];
+    for tx in &txs {
+        sandbox.recv(tx);
+    }
+
+    sandbox.recv(&TransactionsRequest::new(
+        &sandbox.p(VALIDATOR_1),
+        &sandbox.p(VALIDATOR_0),
+        &[txs[0].hash(), txs[1].hash()],
+        sandbox.s(VALIDATOR_1),
+    ));
+
+    sandbox.send(
+        sandbox.a(VALIDATOR_1),
+        &TransactionsResponse::new(
+            &sandbox.p(VALIDATOR_0),
+            &sandbox.p(VALIDATOR_1),
+            txs.iter().map(|tx| tx.raw().clone()).collect(),
+            sandbox.s(VALIDATOR_0),
+        ),
+    );
+}
+
+/// Transactions of a response are added to the pool, unless the response is intended
+/// for another node.
+#[test]
+fn test_handle_txs_response() {
+    let sandbox = timestamping_sandbox();
+    let txs = [gen_timestamping_tx(), gen_timestamping_tx()];
+
+    sandbox.recv(&TransactionsResponse::new(
+        &sandbox.p(VALIDATOR_1),
+        &sandbox.p(VALIDATOR_2),
+        vec![txs[0].raw().clone()],
+        sandbox.s(VALIDATOR_1),
+    ));
+    sandbox.recv(&TransactionsResponse::new(
+        &sandbox.p(VALIDATOR_1),
+        &sandbox.p(VALIDATOR_0),
+        vec![txs[1].raw().clone()],
+        sandbox.s(VALIDATOR_1),
+    ));
+
+    let pool = sandbox.transactions_hashes();
+    assert!(!pool.contains(&txs[0].hash()));
+    assert!(pool.contains(&txs[1].hash()));
+}
+
+fn sandbox_with_leader_election(leader_election: LeaderElectionConfig) -> Sandbox {
+    let mut consensus = sandbox_consensus_config();
+    consensus.leader_election = leader_election;
+    sandbox_with_consensus(
+        vec![
+            Box::new(TimestampingService::new()),
+            Box::new(ConfigUpdateService::new()),
+        ],
+        consensus,
+    )
+}
+
+/// Validators that have not proposed any of the recent blocks lead the rounds after
+/// the other validators, except for the probation heights.
+#[test]
+fn test_reputation_leader_election() {
+    let sandbox =
+        sandbox_with_leader_election(LeaderElectionConfig::ReputationWeighted { window: 8 });
+    let sandbox_state = SandboxState::new();
+
+    // All the validators lead the first rounds in turn during the probation.
+    for height in 1..5 {
+        assert_eq!(sandbox.current_height(), Height(height));
+        assert_eq!(sandbox.leader(ROUND_ONE), ValidatorId(((height + 1) % 4) as u16));
+        add_one_height(&sandbox, &sandbox_state);
+    }
+
+    // Only our node has proposed blocks, so it leads the first rounds.
+    for height in 5..9 {
+        assert_eq!(sandbox.current_height(), Height(height));
+        assert!(sandbox.is_leader());
+        assert_eq!(sandbox.leader(ROUND_ONE), VALIDATOR_0);
+        assert_ne!(sandbox.leader(ROUND_TWO), VALIDATOR_0);
+        add_one_height(&sandbox, &sandbox_state);
+    }
+
+    // The next probation starts.
+    assert_eq!(sandbox.current_height(), Height(9));
+    assert_eq!(sandbox.leader(ROUND_ONE), VALIDATOR_2);
+    assert_eq!(sandbox.leader(ROUND_TWO), VALIDATOR_3);
+}
+
+/// Validators lead the first rounds proportionally to their weights.
+#[test]
+fn test_weighted_leader_election() {
+    let sandbox = sandbox_with_leader_election(LeaderElectionConfig::Weighted {
+        weights: vec![2, 1, 1, 0],
+    });
+    let sandbox_state = SandboxState::new();
+
+    // The sequence of the first round leaders is `[0, 1, 2, 0]`.
+    assert_eq!(sandbox.leader(ROUND_ONE), VALIDATOR_2);
+    assert_eq!(sandbox.leader(ROUND_TWO), VALIDATOR_3);
+    assert_eq!(sandbox.leader(ROUND_THREE), VALIDATOR_0);
+    add_one_height(&sandbox, &sandbox_state);
+
+    for _ in 0..2 {
+        assert!(sandbox.is_leader());
+        assert_eq!(sandbox.leader(ROUND_TWO), VALIDATOR_1);
+        add_one_height(&sandbox, &sandbox_state);
+    }
+
+    assert_eq!(sandbox.current_height(), Height(4));
+    assert_eq!(sandbox.leader(ROUND_ONE), VALIDATOR_1);
+    assert_eq!(sandbox.leader(ROUND_FOUR), VALIDATOR_0);
+}
+
+/// Idea: the node is restarted after every step of the consensus at a height.
+/// - After each restart the node broadcasts the votes it has signed before the restart
+///   (taken from the consensus write-ahead log) and keeps its lock.
+/// - The node commits the block once it receives enough precommits.
+/// - Votes are not broadcast again after a restart at the next height.
+#[test]
+fn test_consensus_log_restart_at_every_step() {
+    let sandbox = timestamping_sandbox();
+
+    let propose = ProposeBuilder::new(&sandbox)
+        .with_duration_since_sandbox_time(sandbox.propose_timeout())
+        .build();
+    let block = BlockBuilder::new(&sandbox)
+        .with_duration_since_sandbox_time(sandbox.propose_timeout())
+        .build();
+    let prevote = make_prevote_from_propose(&sandbox, &propose);
+    let precommit = Precommit::new(
+        VALIDATOR_0,
+        HEIGHT_ONE,
+        ROUND_ONE,
+        &propose.hash(),
+        &block.hash(),
+        sandbox.time().into(),
+        sandbox.s(VALIDATOR_0),
+    );
+    let (prevotes, precommits) = {
+        let make_prevote = |validator| {
+            Prevote::new(
+                validator,
+                HEIGHT_ONE,
+                ROUND_ONE,
+                &propose.hash(),
+                LOCK_ZERO,
+                sandbox.s(validator),
+            )
+        };
+        let make_precommit = |validator| {
+            Precommit::new(
+                validator,
+                HEIGHT_ONE,
+                ROUND_ONE,
+                &propose.hash(),
+                &block.hash(),
+                sandbox.time().into(),
+                sandbox.s(validator),
+            )
+        };
+        (
+            [make_prevote(VALIDATOR_1), make_prevote(VALIDATOR_2)],
+            [make_precommit(VALIDATOR_1), make_precommit(VALIDATOR_2)],
+        )
+    };
+
+    // Nothing has been signed yet.
+    let sandbox = sandbox.restart();
+    sandbox.assert_state(HEIGHT_ONE, ROUND_ONE);
+    sandbox.assert_lock(LOCK_ZERO, None);
+
+    // Prevote.
+    sandbox.recv(&propose);
+    sandbox.broadcast(&prevote);
+    let sandbox = sandbox.restart();
+    sandbox.broadcast(&prevote);
+    sandbox.assert_state(HEIGHT_ONE, ROUND_ONE);
+    sandbox.assert_lock(LOCK_ZERO, None);
+
+    // Not enough prevotes to lock.
+    sandbox.recv(&prevotes[0]);
+    sandbox.assert_lock(LOCK_ZERO, None);
+    let sandbox = sandbox.restart();
+    sandbox.broadcast(&prevote);
+    sandbox.assert_lock(LOCK_ZERO, None);
+
+    // Lock and precommit.
+    for msg in &prevotes {
+        sandbox.recv(msg);
+    }
+    sandbox.assert_lock(LOCK_ONE, Some(propose.hash()));
+    sandbox.broadcast(&precommit);
+    let sandbox = sandbox.restart();
+    sandbox.broadcast(&prevote);
+    sandbox.broadcast(&precommit);
+    sandbox.assert_state(HEIGHT_ONE, ROUND_ONE);
+    sandbox.assert_lock(LOCK_ONE, Some(propose.hash()));
+
+    // Not enough precommits to commit.
+    sandbox.recv(&precommits[0]);
+    sandbox.assert_state(HEIGHT_ONE, ROUND_ONE);
+    let sandbox = sandbox.restart();
+    sandbox.broadcast(&prevote);
+    sandbox.broadcast(&precommit);
+    sandbox.assert_lock(LOCK_ONE, Some(propose.hash()));
+
+    // Commit.
+    for msg in &precommits {
+        sandbox.recv(msg);
+    }
+    sandbox.assert_state(HEIGHT_TWO, ROUND_ONE);
+    sandbox.check_broadcast_status(HEIGHT_TWO, &block.hash());
+    let sandbox = sandbox.restart();
+    sandbox.assert_state(HEIGHT_TWO, ROUND_ONE);
+    sandbox.assert_lock(LOCK_ZERO, None);
+}
+
+/// Idea: the node crashes after its lock has been written to the consensus write-ahead log,
+/// but before it has signed a precommit.
+/// - The node makes the lock again after the restart using the cached prevotes.
+/// - The node signs the precommit for the restored lock.
+#[test]
+fn test_consensus_log_restores_lock_without_precommit() {
+    let sandbox = timestamping_sandbox();
+
+    let propose = ProposeBuilder::new(&sandbox)
+        .with_duration_since_sandbox_time(sandbox.propose_timeout())
+        .build();
+    let block = BlockBuilder::new(&sandbox)
+        .with_duration_since_sandbox_time(sandbox.propose_timeout())
+        .build();
+    let prevote = make_prevote_from_propose(&sandbox, &propose);
+    let prevotes = [VALIDATOR_1, VALIDATOR_2]
+        .iter()
+        .map(|validator| {
+            Prevote::new(
+                *validator,
+                HEIGHT_ONE,
+                ROUND_ONE,
+                &propose.hash(),
+                LOCK_ZERO,
+                sandbox.s(*validator),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    sandbox.recv(&propose);
+    sandbox.broadcast(&prevote);
+    // The lock is saved along with the propose and the prevotes it is based on.
+    let messages = vec![propose.raw().clone(), prevote.raw().clone()]
+        .into_iter()
+        .chain(prevotes.iter().map(|msg| msg.raw().clone()));
+    sandbox.blockchain_mut().save_lock(
+        ConsensusLock::new(HEIGHT_ONE, ROUND_ONE, &propose.hash()),
+        messages,
+    );
+
+    let sandbox = sandbox.restart();
+    sandbox.broadcast(&prevote);
+    sandbox.broadcast(&Precommit::new(
+        VALIDATOR_0,
+        HEIGHT_ONE,
+        ROUND_ONE,
+        &propose.hash(),
+        &block.hash(),
+        sandbox.time().into(),
+        sandbox.s(VALIDATOR_0),
+    ));
+    sandbox.assert_state(HEIGHT_ONE, ROUND_ONE);
+    sandbox.assert_lock(LOCK_ONE, Some(propose.hash()));
+}
+
+/// Idea: the node ignores a `Propose` with the time too far ahead of its own time or
+/// earlier than the time of the last block, and votes for a `Propose` with a valid time.
+#[test]
+fn test_ignore_propose_with_invalid_time() {
+    let sandbox = timestamping_sandbox();
+    let sandbox_state = SandboxState::new();
+
+    let max_drift = sandbox.cfg().consensus.max_propose_time_drift;
+    let future_time = sandbox.time() + Duration::from_millis(max_drift + 1);
+    let propose = ProposeBuilder::new(&sandbox)
+        .with_time(future_time.into())
+        .build();
+    sandbox.recv(&propose);
+
+    add_one_height(&sandbox, &sandbox_state);
+    sandbox.assert_state(HEIGHT_TWO, ROUND_ONE);
+    assert!(!sandbox.is_leader());
+
+    let last_block_time = sandbox.last_block().time();
+    let propose = ProposeBuilder::new(&sandbox)
+        .with_time(last_block_time - ::chrono::Duration::milliseconds(1))
+        .build();
+    sandbox.recv(&propose);
+
+    let propose = ProposeBuilder::new(&sandbox)
+        .with_time(last_block_time)
+        .build();
+    sandbox.recv(&propose);
+    sandbox.broadcast(&make_prevote_from_propose(&sandbox, &propose));
+}
+
+fn sandbox_with_max_idle_interval(max_idle_interval: u64) -> Sandbox {
+    let mut consensus = sandbox_consensus_config();
+    consensus.max_idle_interval = Some(max_idle_interval);
+    sandbox_with_consensus(
+        vec![
+            Box::new(TimestampingService::new()),
+            Box::new(ConfigUpdateService::new()),
+        ],
+        consensus,
+    )
+}
+
+/// Idea: with `max_idle_interval` set, the node neither proposes nor moves to the next
+/// round while the transactions pool is empty, and the leader proposes once a
+/// transaction arrives.
+#[test]
+fn test_skip_empty_blocks() {
+    let sandbox = sandbox_with_max_idle_interval(60_000);
+    let sandbox_state = SandboxState::new();
+
+    add_one_height(&sandbox, &sandbox_state);
+    add_one_height(&sandbox, &sandbox_state);
+    sandbox.assert_state(HEIGHT_THREE, ROUND_ONE);
+    assert!(sandbox.is_leader());
+
+    sandbox.add_time(Duration::from_millis(3 * sandbox.round_timeout()));
+    sandbox.assert_state(HEIGHT_THREE, ROUND_ONE);
+
+    let tx = gen_timestamping_tx();
+    sandbox.recv(&tx);
+    sandbox.add_time(Duration::from_millis(sandbox.propose_timeout()));
+
+    let propose = ProposeBuilder::new(&sandbox)
+        .with_tx_hashes(&[tx.hash()])
+        .build();
+    sandbox.broadcast(&propose);
+    sandbox.broadcast(&make_prevote_from_propose(&sandbox, &propose));
+    sandbox.assert_state(HEIGHT_THREE, ROUND_ONE);
+}
+
+/// Idea: with `max_idle_interval` set, the rounds resume once the interval has passed
+/// since the last block, even if the transactions pool is still empty.
+#[test]
+fn test_rounds_resume_after_max_idle_interval() {
+    let sandbox = sandbox_with_max_idle_interval(2_500);
+    let sandbox_state = SandboxState::new();
+
+    add_one_height(&sandbox, &sandbox_state);
+    add_one_height(&sandbox, &sandbox_state);
+    sandbox.assert_state(HEIGHT_THREE, ROUND_ONE);
+
+    sandbox.add_time(Duration::from_millis(2 * sandbox.round_timeout()));
+    sandbox.assert_state(HEIGHT_THREE, ROUND_ONE);
+
+    sandbox.add_time(Duration::from_millis(sandbox.round_timeout()));
+    sandbox.assert_state(HEIGHT_THREE, ROUND_TWO);
+}
@@ -43,6 +43,7 @@ fn test_send_propose_and_prevote() {
         ROUND_THREE,
         &sandbox.last_hash(),
         &[tx.hash()],
+        sandbox.time().into(),
         sandbox.s(VALIDATOR_0),
     );
 
@@ -67,6 +68,7 @@ fn test_send_prevote() {
         ROUND_ONE,
         &sandbox.last_hash(),
         &[],
+        sandbox.time().into(),
         sandbox.s(VALIDATOR_2),
     );
 
@@ -91,6 +93,7 @@ fn test_get_lock_and_send_precommit() {
         ROUND_ONE,
         &sandbox.last_hash(),
         &[],
+        sandbox.time().into(),
         sandbox.s(VALIDATOR_2),
     );
 
@@ -98,6 +101,7 @@ fn test_get_lock_and_send_precommit() {
         SCHEMA_MAJOR_VERSION,
         VALIDATOR_2,
         HEIGHT_ONE,
+        propose.time(),
         0,
         &sandbox.last_hash(),
         &Hash::zero(),
@@ -152,6 +156,7 @@ fn test_commit() {
         ROUND_ONE,
         &sandbox.last_hash(),
         &[],
+        sandbox.time().into(),
         sandbox.s(VALIDATOR_2),
     );
 
@@ -159,6 +164,7 @@ fn test_commit() {
         SCHEMA_MAJOR_VERSION,
         VALIDATOR_2,
         HEIGHT_ONE,
+        propose.time(),
         0,
         &sandbox.last_hash(),
         &Hash::zero(),
@@ -229,7 +235,8 @@ fn received_unexpected_propose() {
     let sandbox = timestamping_sandbox();
 
     let propose = Propose::new(VALIDATOR_1, Height::zero(), ROUND_ONE,
-                               &sandbox.last_hash(), &[], sandbox.s(VALIDATOR_1));
+                               &sandbox.last_hash(), &[], sandbox.time().into(),
+                               sandbox.s(VALIDATOR_1));
 
     sandbox.recv(&propose);
     sandbox.broadcast(&Prevote::new(VALIDATOR_0, Height::zero(), ROUND_ONE, &propose.hash(),
@@ -26,13 +26,14 @@ use std::iter::FromIterator;
 use futures::{self, Async, Future, Stream};
 use futures::Sink;
 use futures::sync::mpsc;
-use node::{Configuration, ExternalMessage, ListenerConfig, NodeHandler, NodeSender, ServiceConfig,
-           State, SystemStateProvider, ApiSender};
-use blockchain::{Block, BlockProof, Blockchain, ConsensusConfig, GenesisConfig, Schema, Service,
-                 SharedNodeState, StoredConfiguration, TimeoutAdjusterConfig, Transaction,
-                 ValidatorKeys};
+use node::{Configuration, ExternalMessage, ListenerConfig, MemoryPoolConfig, NodeHandler,
+           NodeSender, ReplicaConfig, ServiceConfig, State, SystemStateProvider, ApiSender};
+use blockchain::{Block, BlockProof, Blockchain, ConsensusConfig, GenesisConfig,
+                 LeaderElectionConfig, Schema, Service, SharedNodeState, StoredConfiguration,
+                 TimeoutAdjusterConfig, Transaction, ValidatorKeys};
 use storage::{MapProof, MemoryDB};
-use messages::{Any, Connect, Message, RawMessage, RawTransaction, Status};
+use messages::{Any, Connect, Message, RawMessage, RawTransaction, Status,
+               SUPPORTED_PROTOCOL_VERSIONS, TEST_NETWORK_ID};
 use crypto::{Hash, PublicKey, SecretKey, Seed, gen_keypair, gen_keypair_from_seed};
 use helpers::{Height, Milliseconds, Round, ValidatorId, user_agent};
 use events::{Event, InternalEvent, EventHandler, NetworkEvent, NetworkRequest, TimeoutRequest,
@@ -73,6 +74,7 @@ pub struct SandboxInner {
     pub network_requests_rx: mpsc::Receiver<NetworkRequest>,
     pub internal_requests_rx: mpsc::Receiver<InternalRequest>,
     pub api_requests_rx: mpsc::Receiver<ExternalMessage>,
+    pub is_stopped: bool,
 }
 
 impl SandboxInner {
@@ -94,6 +96,8 @@ impl SandboxInner {
                 match network {
                     NetworkRequest::SendMessage(peer, msg) => self.sent.push_back((peer, msg)),
                     NetworkRequest::DisconnectWithPeer(_) |
+                    NetworkRequest::BanPeer(..) |
+                    NetworkRequest::UpdateConfiguration(..) |
                     NetworkRequest::Shutdown => {}
                 }
             }
@@ -112,7 +116,10 @@ impl SandboxInner {
                             InternalEvent::JumpToRound(height, round).into(),
                         )
                     }
-                    InternalRequest::Shutdown => unimplemented!(),
+                    InternalRequest::Shutdown => self.is_stopped = true,
+                    InternalRequest::Sign(request) => {
+                        panic!("Sandbox does not use the remote signer: {:?}", request)
+                    }
                 }
 
             }
@@ -152,6 +159,9 @@ impl Sandbox {
             self.a(VALIDATOR_0),
             connect_message_time.into(),
             &user_agent::get(),
+            SUPPORTED_PROTOCOL_VERSIONS,
+            &self.network_id(),
+            false,
             self.s(VALIDATOR_0),
         );
 
@@ -162,6 +172,9 @@ impl Sandbox {
                 self.a(validator),
                 self.time().into(),
                 &user_agent::get(),
+                SUPPORTED_PROTOCOL_VERSIONS,
+                &self.network_id(),
+                false,
                 self.s(validator),
             ));
             self.send(self.a(validator), &connect);
@@ -223,6 +236,10 @@ impl Sandbox {
         Ref::map(self.inner.borrow(), |inner| inner.handler.state())
     }
 
+    pub fn network_id(&self) -> Hash {
+        *self.node_state().our_connect_message().network_id()
+    }
+
     pub fn blockchain_ref(&self) -> Ref<Blockchain> {
         Ref::map(self.inner.borrow(), |inner| &inner.handler.blockchain)
     }
@@ -247,10 +264,25 @@ impl Sandbox {
         self.inner.borrow_mut().handle_event(event);
     }
 
+    /// Emulates the connection of the peer from the address the messages are received from.
+    pub fn connect_peer(&self, connect: &Connect) {
+        self.check_unexpected_message();
+        let dummy_addr = SocketAddr::from(([127, 0, 0, 1], 12_039));
+        let event = NetworkEvent::PeerConnected(dummy_addr, connect.clone());
+        self.inner.borrow_mut().handle_event(event);
+    }
+
     pub fn process_events(&self) {
         self.inner.borrow_mut().process_events();
     }
 
+    /// Emulates the closure of the connection with the given address.
+    pub fn disconnect(&self, addr: SocketAddr) {
+        self.check_unexpected_message();
+        let event = NetworkEvent::PeerDisconnected(addr);
+        self.inner.borrow_mut().handle_event(event);
+    }
+
     pub fn send<T: Message>(&self, addr: SocketAddr, msg: &T) {
         self.process_events();
         let any_expected_msg = Any::from_raw(msg.raw().clone()).unwrap();
@@ -375,6 +407,11 @@ impl Sandbox {
         }
     }
 
+    /// Returns `true` if the node has requested to stop its event loop.
+    pub fn is_stopped(&self) -> bool {
+        self.inner.borrow().is_stopped
+    }
+
     pub fn is_leader(&self) -> bool {
         self.node_state().is_leader()
     }
@@ -425,6 +462,7 @@ impl Sandbox {
         I: IntoIterator<Item = &'a RawTransaction>,
     {
         let height = self.current_height();
+        let time = self.time();
         let mut blockchain = self.blockchain_mut();
         let (hashes, recover, patch) = {
             let mut hashes = Vec::new();
@@ -448,7 +486,7 @@ impl Sandbox {
 
         let fork = {
             let mut fork = blockchain.fork();
-            let (_, patch) = blockchain.create_patch(ValidatorId(0), height, &hashes);
+            let (_, patch) = blockchain.create_patch(ValidatorId(0), height, time.into(), &hashes);
             fork.merge(patch);
             fork
         };
@@ -504,6 +542,10 @@ impl Sandbox {
         self.cfg().consensus.round_timeout
     }
 
+    pub fn tx_batch_timeout(&self) -> Milliseconds {
+        MemoryPoolConfig::default().tx_batch_timeout
+    }
+
     pub fn transactions_hashes(&self) -> Vec<Hash> {
         let schema = Schema::new(self.blockchain_ref().snapshot());
         let idx = schema.transactions_pool();
@@ -525,6 +567,11 @@ impl Sandbox {
         self.node_state().height()
     }
 
+    pub fn current_round_start_time(&self) -> SystemTime {
+        let round = self.current_round();
+        self.inner.borrow().handler.round_start_time(round)
+    }
+
     pub fn current_leader(&self) -> ValidatorId {
         self.node_state().leader(self.current_round())
     }
@@ -554,16 +601,31 @@ impl Sandbox {
 
     /// Creates new sandbox with "restarted" node initialized by the given time.
     pub fn restart_with_time(self, time: SystemTime) -> Self {
+        self.restart_with(time, None)
+    }
+
+    /// Creates new sandbox with the node "restarted" as a read-only replica
+    /// with the given upstream.
+    pub fn restart_as_replica(self, upstream: SocketAddr) -> Self {
+        let time = UNIX_EPOCH + Duration::new(INITIAL_TIME_IN_SECS, 0);
+        self.restart_with(time, Some(ReplicaConfig { upstream }))
+    }
+
+    fn restart_with(self, time: SystemTime, replica: Option<ReplicaConfig>) -> Self {
+        let is_replica = replica.is_some();
         let connect = self.connect().map(|c| {
             Connect::new(
                 c.pub_key(),
                 c.addr(),
                 time.into(),
                 c.user_agent(),
+                c.protocol_versions(),
+                c.network_id(),
+                is_replica,
                 self.s(VALIDATOR_0),
             )
         });
-        let sandbox = self.restart_uninitialized_with_time(time);
+        let sandbox = self.restart_uninitialized_with(time, replica);
         if let Some(connect) = connect {
             sandbox.broadcast(&connect);
         }
@@ -580,11 +642,20 @@ impl Sandbox {
     /// Constructs a new uninitialized instance of a `Sandbox` preserving database and
     /// configuration.
     pub fn restart_uninitialized_with_time(self, time: SystemTime) -> Sandbox {
+        self.restart_uninitialized_with(time, None)
+    }
+
+    fn restart_uninitialized_with(
+        self,
+        time: SystemTime,
+        replica: Option<ReplicaConfig>,
+    ) -> Sandbox {
         let network_channel = mpsc::channel(100);
         let internal_channel = mpsc::channel(100);
         let api_channel = mpsc::channel(100);
 
         let address = self.a(VALIDATOR_0);
+        let secret_key = self.node_secret_key();
         let inner = self.inner.borrow();
 
         let blockchain = inner.handler.blockchain.clone_with_api_sender(
@@ -603,7 +674,7 @@ impl Sandbox {
             listener: ListenerConfig {
                 address,
                 consensus_public_key: *inner.handler.state.consensus_public_key(),
-                consensus_secret_key: inner.handler.state.consensus_secret_key().clone(),
+                consensus_secret_key: Some(secret_key),
                 whitelist: Default::default(),
             },
             service: ServiceConfig {
@@ -613,6 +684,11 @@ impl Sandbox {
             network: NetworkConfiguration::default(),
             peer_discovery: Vec::new(),
             mempool: Default::default(),
+            state_sync: Default::default(),
+            signer: Default::default(),
+            reputation: Default::default(),
+            replica,
+            network_id: TEST_NETWORK_ID,
         };
 
         let system_state = SandboxSystemStateProvider {
@@ -639,6 +715,7 @@ impl Sandbox {
             api_requests_rx: api_channel.1,
             handler,
             time: Arc::clone(&inner.time),
+            is_stopped: false,
         };
         let sandbox = Sandbox {
             inner: RefCell::new(inner),
@@ -656,7 +733,7 @@ impl Sandbox {
     }
 
     fn node_secret_key(&self) -> SecretKey {
-        self.node_state().consensus_secret_key().clone()
+        self.validators_map[&self.node_public_key()].clone()
     }
 }
 
@@ -675,7 +752,13 @@ fn gen_primitive_socket_addr(idx: u8) -> SocketAddr {
 
 /// Constructs an instance of a `Sandbox` and initializes connections.
 pub fn sandbox_with_services(services: Vec<Box<Service>>) -> Sandbox {
-    let mut sandbox = sandbox_with_services_uninitialized(services);
+    sandbox_with_consensus(services, sandbox_consensus_config())
+}
+
+/// Constructs an instance of a `Sandbox` with the given consensus configuration and
+/// initializes connections.
+pub fn sandbox_with_consensus(services: Vec<Box<Service>>, consensus: ConsensusConfig) -> Sandbox {
+    let mut sandbox = sandbox_uninitialized(services, consensus);
     let time = sandbox.time();
     let validators_count = sandbox.validators_map.len();
     sandbox.initialize(time, 1, validators_count);
@@ -684,6 +767,26 @@ pub fn sandbox_with_services(services: Vec<Box<Service>>) -> Sandbox {
 
 /// Constructs an uninitialized instance of a `Sandbox`.
 pub fn sandbox_with_services_uninitialized(services: Vec<Box<Service>>) -> Sandbox {
+    sandbox_uninitialized(services, sandbox_consensus_config())
+}
+
+/// Returns the consensus configuration of the sandbox.
+pub fn sandbox_consensus_config() -> ConsensusConfig {
+    ConsensusConfig {
+        round_timeout: 1000,
+        status_timeout: 600_000,
+        peers_timeout: 600_000,
+        txs_block_limit: 1000,
+        max_message_len: 1024 * 1024,
+        timeout_adjuster: TimeoutAdjusterConfig::Constant { timeout: 200 },
+        leader_election: LeaderElectionConfig::RoundRobin,
+        max_idle_interval: None,
+        max_block_size: None,
+        max_propose_time_drift: 10_000,
+    }
+}
+
+fn sandbox_uninitialized(services: Vec<Box<Service>>, consensus: ConsensusConfig) -> Sandbox {
     let validators = vec![
         gen_keypair_from_seed(&Seed::new([12; 32])),
         gen_keypair_from_seed(&Seed::new([13; 32])),
@@ -709,14 +812,6 @@ pub fn sandbox_with_services_uninitialized(services: Vec<Box<Service>>) -> Sandb
         ApiSender::new(api_channel.0.clone()),
     );
 
-    let consensus = ConsensusConfig {
-        round_timeout: 1000,
-        status_timeout: 600_000,
-        peers_timeout: 600_000,
-        txs_block_limit: 1000,
-        max_message_len: 1024 * 1024,
-        timeout_adjuster: TimeoutAdjusterConfig::Constant { timeout: 200 },
-    };
     let genesis = GenesisConfig::new_with_consensus(
         consensus,
         validators.iter().zip(service_keys.iter()).map(|x| {
@@ -732,7 +827,7 @@ pub fn sandbox_with_services_uninitialized(services: Vec<Box<Service>>) -> Sandb
         listener: ListenerConfig {
             address: addresses[0],
             consensus_public_key: validators[0].0,
-            consensus_secret_key: validators[0].1.clone(),
+            consensus_secret_key: Some(validators[0].1.clone()),
             whitelist: Default::default(),
         },
         service: ServiceConfig {
@@ -742,6 +837,11 @@ pub fn sandbox_with_services_uninitialized(services: Vec<Box<Service>>) -> Sandb
         network: NetworkConfiguration::default(),
         peer_discovery: Vec::new(),
         mempool: Default::default(),
+        state_sync: Default::default(),
+        signer: Default::default(),
+        reputation: Default::default(),
+        replica: None,
+        network_id: TEST_NETWORK_ID,
     };
 
     // TODO use factory or other solution like set_handler or run
@@ -780,6 +880,7 @@ pub fn sandbox_with_services_uninitialized(services: Vec<Box<Service>>) -> Sandb
         internal_requests_rx: internal_channel.1,
         handler,
         time: shared_time,
+        is_stopped: false,
     };
     let sandbox = Sandbox {
         inner: RefCell::new(inner),
@@ -882,6 +983,9 @@ mod tests {
             s.a(VALIDATOR_2),
             s.time().into(),
             &user_agent::get(),
+            SUPPORTED_PROTOCOL_VERSIONS,
+            &s.network_id(),
+            false,
             &secret,
         ));
         s.send(
@@ -891,6 +995,9 @@ mod tests {
                 s.a(VALIDATOR_0),
                 s.time().into(),
                 &user_agent::get(),
+                SUPPORTED_PROTOCOL_VERSIONS,
+                &s.network_id(),
+                false,
                 s.s(VALIDATOR_0),
             ),
         );
@@ -918,6 +1025,9 @@ mod tests {
                 s.a(VALIDATOR_0),
                 s.time().into(),
                 &user_agent::get(),
+                SUPPORTED_PROTOCOL_VERSIONS,
+                &s.network_id(),
+                false,
                 s.s(VALIDATOR_0),
             ),
         );
@@ -933,6 +1043,9 @@ mod tests {
             s.a(VALIDATOR_2),
             s.time().into(),
             &user_agent::get(),
+            SUPPORTED_PROTOCOL_VERSIONS,
+            &s.network_id(),
+            false,
             &secret,
         ));
         s.send(
@@ -942,6 +1055,9 @@ mod tests {
                 s.a(VALIDATOR_0),
                 s.time().into(),
                 &user_agent::get(),
+                SUPPORTED_PROTOCOL_VERSIONS,
+                &s.network_id(),
+                false,
                 s.s(VALIDATOR_0),
             ),
         );
@@ -957,6 +1073,9 @@ mod tests {
             s.a(VALIDATOR_2),
             s.time().into(),
             &user_agent::get(),
+            SUPPORTED_PROTOCOL_VERSIONS,
+            &s.network_id(),
+            false,
             &secret,
         ));
     }
@@ -971,6 +1090,9 @@ mod tests {
             s.a(VALIDATOR_2),
             s.time().into(),
             &user_agent::get(),
+            SUPPORTED_PROTOCOL_VERSIONS,
+            &s.network_id(),
+            false,
             &secret,
         ));
         s.recv(&Connect::new(
@@ -978,6 +1100,9 @@ mod tests {
             s.a(VALIDATOR_3),
             s.time().into(),
             &user_agent::get(),
+            SUPPORTED_PROTOCOL_VERSIONS,
+            &s.network_id(),
+            false,
             &secret,
         ));
         panic!("Oops! We don't catch unexpected message");
@@ -993,6 +1118,9 @@ mod tests {
             s.a(VALIDATOR_2),
             s.time().into(),
             &user_agent::get(),
+            SUPPORTED_PROTOCOL_VERSIONS,
+            &s.network_id(),
+            false,
             &secret,
         ));
         s.add_time(Duration::from_millis(1000));
@@ -1007,6 +1135,7 @@ mod tests {
         ]);
         let state = SandboxState::new();
         add_one_height(&sandbox, &state);
+        sandbox.add_time(Duration::from_millis(sandbox.tx_batch_timeout()));
         let tx = TxAfterCommit::new_with_height(Height(1));
         sandbox.broadcast(&tx);
     }
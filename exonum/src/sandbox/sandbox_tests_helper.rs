@@ -19,6 +19,7 @@ use std::cell::RefCell;
 use std::collections::BTreeMap;
 
 use bit_vec::BitVec;
+use chrono::{DateTime, Utc};
 use messages::{RawTransaction, Message, Propose, Prevote, Precommit, ProposeRequest,
                PrevotesRequest};
 use blockchain::{Block, SCHEMA_MAJOR_VERSION};
@@ -55,6 +56,7 @@ pub struct BlockBuilder<'a> {
     proposer_id: Option<ValidatorId>,
     height: Option<Height>,
     duration_since_sandbox_time: Option<Milliseconds>,
+    time: Option<DateTime<Utc>>,
     prev_hash: Option<Hash>,
     tx_hash: Option<Hash>,
     state_hash: Option<Hash>,
@@ -69,6 +71,7 @@ impl<'a> BlockBuilder<'a> {
             proposer_id: None,
             height: None,
             duration_since_sandbox_time: None,
+            time: None,
             prev_hash: None,
             tx_hash: None,
             state_hash: None,
@@ -96,6 +99,11 @@ impl<'a> BlockBuilder<'a> {
         self
     }
 
+    pub fn with_time(mut self, time: DateTime<Utc>) -> Self {
+        self.time = Some(time);
+        self
+    }
+
     pub fn with_prev_hash(mut self, prev_hash: &'a Hash) -> Self {
         self.prev_hash = Some(*prev_hash);
         self
@@ -127,6 +135,9 @@ impl<'a> BlockBuilder<'a> {
                 || self.sandbox.current_leader(),
             ),
             self.height.unwrap_or_else(|| self.sandbox.current_height()),
+            self.time.unwrap_or_else(|| {
+                block_time(self.sandbox, self.duration_since_sandbox_time)
+            }),
             self.tx_count.unwrap_or(0),
             &self.prev_hash.unwrap_or_else(|| self.sandbox.last_hash()),
             &self.tx_hash.unwrap_or_else(Hash::zero),
@@ -144,6 +155,7 @@ pub struct ProposeBuilder<'a> {
     height: Option<Height>,
     round: Option<Round>,
     duration_since_sandbox_time: Option<Milliseconds>,
+    time: Option<DateTime<Utc>>,
     prev_hash: Option<&'a Hash>,
     tx_hashes: Option<&'a [Hash]>,
 
@@ -157,6 +169,7 @@ impl<'a> ProposeBuilder<'a> {
             height: None,
             round: None,
             duration_since_sandbox_time: None,
+            time: None,
             prev_hash: None,
             tx_hashes: None,
             sandbox,
@@ -186,6 +199,11 @@ impl<'a> ProposeBuilder<'a> {
         self
     }
 
+    pub fn with_time(mut self, time: DateTime<Utc>) -> Self {
+        self.time = Some(time);
+        self
+    }
+
     pub fn with_prev_hash(mut self, prev_hash: &'a Hash) -> Self {
         self.prev_hash = Some(prev_hash);
         self
@@ -205,6 +223,9 @@ impl<'a> ProposeBuilder<'a> {
             self.round.unwrap_or_else(|| self.sandbox.current_round()),
             self.prev_hash.unwrap_or(&self.sandbox.last_hash()),
             self.tx_hashes.unwrap_or(&[]),
+            self.time.unwrap_or_else(|| {
+                block_time(self.sandbox, self.duration_since_sandbox_time)
+            }),
             self.sandbox.s(self.validator_id.unwrap_or_else(
                 || self.sandbox.current_leader(),
             )),
@@ -212,6 +233,17 @@ impl<'a> ProposeBuilder<'a> {
     }
 }
 
+// Time of the block proposed `duration_since_sandbox_time` milliseconds after the start
+// of the current round, or at the current sandbox time.
+fn block_time(sandbox: &TimestampingSandbox, duration: Option<Milliseconds>) -> DateTime<Utc> {
+    match duration {
+        Some(duration) => {
+            (sandbox.current_round_start_time() + Duration::from_millis(duration)).into()
+        }
+        None => sandbox.time().into(),
+    }
+}
+
 pub struct SandboxState {
     pub accepted_propose_hash: RefCell<Hash>,
     pub accepted_block_hash: RefCell<Hash>,
@@ -400,6 +432,7 @@ where
             let state_hash = sandbox.compute_state_hash(&raw_txs);
             let block = BlockBuilder::new(sandbox)
                 .with_txs_hashes(&hashes)
+                .with_time(propose.time())
                 .with_state_hash(&state_hash)
                 .build();
 
@@ -513,6 +546,7 @@ pub fn add_one_height_with_transactions_from_other_validator(
             let state_hash = sandbox.compute_state_hash(&raw_txs);
             let block = BlockBuilder::new(sandbox)
                 .with_txs_hashes(&hashes)
+                .with_time(propose.time())
                 .with_state_hash(&state_hash)
                 .build();
             trace!("new_block: {:?}", block);
@@ -565,6 +599,7 @@ fn get_propose_with_transactions_for_validator(
         sandbox.current_round(),
         &sandbox.last_hash(),
         transactions,
+        sandbox.time().into(),
         sandbox.s(validator),
     )
 }
@@ -640,6 +675,7 @@ pub fn receive_valid_propose_with_transactions(
         sandbox.current_round(),
         &sandbox.last_hash(),
         transactions,
+        sandbox.time().into(),
         sandbox.s(sandbox.current_leader()),
     );
     sandbox.recv(&propose);
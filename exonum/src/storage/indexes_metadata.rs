@@ -159,6 +159,16 @@ pub fn assert_index_type(name: &str, index_type: IndexType, is_family: bool, vie
     }
 }
 
+/// Returns names of all indexes created in the storage in lexicographic order,
+/// including the internal table with the indexes metadata.
+pub fn index_names(view: &Snapshot) -> Vec<String> {
+    let metadata = BaseIndex::indexes_metadata(view);
+    let mut names = vec![INDEXES_METADATA_TABLE_NAME.to_owned()];
+    names.extend(metadata.iter::<_, String, IndexMetadata>(&()).map(|(name, _)| name));
+    names.sort();
+    names
+}
+
 pub fn set_index_type(name: &str, index_type: IndexType, is_family: bool, view: &mut Fork) {
     if name == INDEXES_METADATA_TABLE_NAME {
         panic!("Attempt to access an internal storage infrastructure");
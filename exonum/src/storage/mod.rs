@@ -132,6 +132,8 @@ pub use self::proof_list_index::{ProofListIndex, ListProof};
 #[doc(no_inline)]
 pub use self::proof_map_index::{ProofMapIndex, MapProof, HashedKey};
 pub use self::hash::UniqueHash;
+pub use self::indexes_metadata::index_names;
+pub(crate) use self::indexes_metadata::INDEXES_METADATA_TABLE_NAME;
 
 /// A specialized `Result` type for I/O operations with storage.
 pub type Result<T> = ::std::result::Result<T, Error>;
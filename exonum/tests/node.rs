@@ -15,6 +15,7 @@
 // This is a regression test for exonum node.
 extern crate exonum;
 extern crate futures;
+extern crate hyper;
 extern crate serde_json;
 extern crate tokio_timer;
 
@@ -24,6 +25,7 @@ use std::sync::{Arc, Mutex};
 
 use futures::Future;
 use futures::sync::oneshot;
+use hyper::status::StatusCode;
 use tokio_timer::Timer;
 use serde_json::Value;
 
@@ -178,3 +180,34 @@ fn test_node_restart_regression() {
     start_node(node_cfg, db, Arc::clone(&init_times));
     assert_eq!(*init_times.lock().unwrap(), 1);
 }
+
+/// The api servers keep listening after the node has stopped, but reject all the requests.
+#[test]
+fn test_api_rejects_requests_after_stop() {
+    let mut node_cfg = helpers::generate_testnet_config(1, 16_320)[0].clone();
+    let public_api_address = "127.0.0.1:16321".parse().unwrap();
+    node_cfg.api.public_api_address = Some(public_api_address);
+    let url = format!("http://{}/api/system/v1/healthcheck", public_api_address);
+
+    let node = Node::new(MemoryDB::new(), vec![], node_cfg);
+    let api_tx = node.channel();
+    let node_thread = thread::spawn(move || { node.run().unwrap(); });
+
+    let client = hyper::Client::new();
+    let response = loop {
+        // Wait for the api server to start.
+        if let Ok(response) = client.get(&url).send() {
+            break response;
+        }
+        thread::sleep(Duration::from_millis(100));
+    };
+    assert_eq!(response.status, StatusCode::Ok);
+
+    api_tx
+        .send_external_message(ExternalMessage::Shutdown)
+        .unwrap();
+    node_thread.join().unwrap();
+
+    let response = client.get(&url).send().unwrap();
+    assert_eq!(response.status, StatusCode::ServiceUnavailable);
+}
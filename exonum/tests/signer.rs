@@ -0,0 +1,208 @@
+// Copyright 2018 The Exonum Team
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//   http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Tests for the external signer of the consensus messages.
+extern crate exonum;
+extern crate tempdir;
+
+use tempdir::TempDir;
+
+use std::net::TcpListener;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use exonum::blockchain::ApiContext;
+use exonum::crypto::{gen_keypair, hash, PublicKey, SecretKey, Signature};
+use exonum::helpers::{self, Height, Round, ValidatorId};
+use exonum::messages::{Message, Prevote, Status};
+use exonum::node::signer::{sign_message, DoubleSignGuard, LocalSigner, RemoteSigner,
+                           SignerServer};
+use exonum::node::{Node, Signer, SignerConfig, SignerError};
+use exonum::storage::MemoryDB;
+
+fn prevote(height: u64, round: u32, propose: &[u8]) -> Prevote {
+    Prevote::new_with_signature(
+        ValidatorId(0),
+        Height(height),
+        Round(round),
+        &hash(propose),
+        Round::zero(),
+        &Signature::zero(),
+    )
+}
+
+fn run_tcp_server(keys: (PublicKey, SecretKey), state: &Path) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let guard = DoubleSignGuard::open(state).unwrap();
+    let mut server = SignerServer::new(LocalSigner::new(keys.0, keys.1), guard);
+    thread::spawn(move || server.run_tcp(&listener).unwrap());
+    address
+}
+
+fn remote_signer(address: &str, public_key: PublicKey) -> RemoteSigner {
+    RemoteSigner::new(address, public_key, Duration::from_secs(5))
+}
+
+#[test]
+fn test_remote_signer_signs_messages() {
+    let dir = TempDir::new("exonum_signer").unwrap();
+    let (public_key, secret_key) = gen_keypair();
+    let address = run_tcp_server((public_key, secret_key.clone()), &dir.path().join("state"));
+    let signer = remote_signer(&address, public_key);
+
+    let prevote = sign_message(&signer, &prevote(1, 1, b"propose")).unwrap();
+    assert!(prevote.verify_signature(&public_key));
+    let expected = Prevote::new(
+        ValidatorId(0),
+        Height(1),
+        Round(1),
+        &hash(b"propose"),
+        Round::zero(),
+        &secret_key,
+    );
+    assert_eq!(prevote, expected);
+
+    // Non-consensus messages are signed without restrictions.
+    let status = Status::new_with_signature(
+        &public_key,
+        Height(1),
+        &hash(b"block"),
+        &Signature::zero(),
+    );
+    let status = sign_message(&signer, &status).unwrap();
+    assert!(status.verify_signature(&public_key));
+}
+
+#[test]
+fn test_remote_signer_with_wrong_key() {
+    let dir = TempDir::new("exonum_signer").unwrap();
+    let address = run_tcp_server(gen_keypair(), &dir.path().join("state"));
+    let signer = remote_signer(&address, gen_keypair().0);
+
+    match sign_message(&signer, &prevote(1, 1, b"propose")) {
+        Err(SignerError::Protocol(_)) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_remote_signer_refuses_double_signing() {
+    let dir = TempDir::new("exonum_signer").unwrap();
+    let (public_key, secret_key) = gen_keypair();
+    let address = run_tcp_server((public_key, secret_key), &dir.path().join("state"));
+    let signer = remote_signer(&address, public_key);
+
+    sign_message(&signer, &prevote(2, 1, b"first")).unwrap();
+    // The same message may be signed again.
+    sign_message(&signer, &prevote(2, 1, b"first")).unwrap();
+    // Messages for other rounds do not conflict.
+    sign_message(&signer, &prevote(2, 2, b"second")).unwrap();
+
+    match sign_message(&signer, &prevote(2, 1, b"second")) {
+        Err(SignerError::Refused(_)) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+
+    sign_message(&signer, &prevote(3, 1, b"third")).unwrap();
+    match sign_message(&signer, &prevote(2, 3, b"third")) {
+        Err(SignerError::Refused(_)) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+}
+
+#[test]
+fn test_double_sign_guard_persists_state() {
+    let dir = TempDir::new("exonum_signer").unwrap();
+    let path = dir.path().join("state");
+    let (public_key, secret_key) = gen_keypair();
+
+    {
+        let mut guard = DoubleSignGuard::open(&path).unwrap();
+        guard.check(prevote(5, 1, b"first").raw().body()).unwrap();
+    }
+
+    let guard = DoubleSignGuard::open(&path).unwrap();
+    assert_eq!(guard.height(), Height(5));
+
+    let address = run_tcp_server((public_key, secret_key), &path);
+    let signer = remote_signer(&address, public_key);
+    match sign_message(&signer, &prevote(5, 1, b"second")) {
+        Err(SignerError::Refused(_)) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    match sign_message(&signer, &prevote(4, 1, b"first")) {
+        Err(SignerError::Refused(_)) => {}
+        other => panic!("Unexpected result: {:?}", other),
+    }
+    sign_message(&signer, &prevote(5, 1, b"first")).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_remote_signer_over_unix_socket() {
+    use std::os::unix::net::UnixListener;
+
+    let dir = TempDir::new("exonum_signer").unwrap();
+    let socket = dir.path().join("signer.sock");
+    let (public_key, secret_key) = gen_keypair();
+
+    let listener = UnixListener::bind(&socket).unwrap();
+    let mut server = SignerServer::new(
+        LocalSigner::new(public_key, secret_key),
+        DoubleSignGuard::new(),
+    );
+    thread::spawn(move || server.run_unix(&listener).unwrap());
+
+    let address = format!("unix:{}", socket.display());
+    let signer = remote_signer(&address, public_key);
+    assert_eq!(signer.public_key(), &public_key);
+    let prevote = sign_message(&signer, &prevote(1, 1, b"propose")).unwrap();
+    assert!(prevote.verify_signature(&public_key));
+}
+
+#[test]
+fn test_remote_service_signer() {
+    let dir = TempDir::new("exonum_signer").unwrap();
+    let mut node_cfg = helpers::generate_testnet_config(1, 16_330)[0].clone();
+    let service_keys = (node_cfg.service_public_key, node_cfg.service_secret_key.clone());
+    let address = run_tcp_server(service_keys, &dir.path().join("state"));
+    node_cfg.service_signer = SignerConfig::Remote {
+        address,
+        timeout: 5_000,
+    };
+    let service_public_key = node_cfg.service_public_key;
+
+    let node = Node::new(MemoryDB::new(), vec![], node_cfg);
+    let context = ApiContext::new(&node);
+    assert_eq!(context.signer().public_key(), &service_public_key);
+    let status = Status::new_with_signature(
+        &service_public_key,
+        Height(1),
+        &hash(b"block"),
+        &Signature::zero(),
+    );
+    let status = sign_message(&**context.signer(), &status).unwrap();
+    assert!(status.verify_signature(&service_public_key));
+}
+
+#[test]
+fn test_node_without_consensus_key_and_remote_signer() {
+    let mut node_cfg = helpers::generate_testnet_config(1, 16_340)[0].clone();
+    node_cfg.consensus_secret_key = None;
+
+    let node = Node::new(MemoryDB::new(), vec![], node_cfg);
+    assert!(node.run_handler().is_err());
+}
@@ -12,13 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::sync::Arc;
+
 use bodyparser;
 use exonum::api::{Api, ApiError};
-use exonum::crypto::{CryptoHash, PublicKey, SecretKey, Hash};
+use exonum::crypto::{CryptoHash, PublicKey, Hash, Signature};
 use exonum::blockchain::{ApiContext, Blockchain, StoredConfiguration, Schema as CoreSchema};
 use exonum::encoding::serialize::json::reexport as serde_json;
 use exonum::helpers::Height;
-use exonum::node::{ApiSender, TransactionSend};
+use exonum::node::{ApiSender, Signer, TransactionSend};
+use exonum::node::signer::sign_message;
 use exonum::storage::StorageValue;
 use iron::prelude::*;
 use router::Router;
@@ -60,8 +63,10 @@ pub struct VoteResponse {
 
 #[derive(Clone)]
 pub struct PrivateApi {
+    blockchain: Blockchain,
     channel: ApiSender,
-    service_keys: (PublicKey, SecretKey),
+    public_key: PublicKey,
+    signer: Arc<Signer>,
 }
 
 #[derive(Clone)]
@@ -264,11 +269,28 @@ impl PublicApi {
 impl PrivateApi {
     pub fn new(context: &ApiContext) -> Self {
         PrivateApi {
+            blockchain: context.blockchain().clone(),
             channel: context.node_channel().clone(),
-            service_keys: (*context.public_key(), context.secret_key().clone()),
+            public_key: *context.public_key(),
+            signer: Arc::clone(context.signer()),
         }
     }
 
+    /// Signs the transaction with the service key of the node.
+    fn sign<M: ::exonum::messages::Message>(&self, message: &M) -> Result<M, ApiError> {
+        sign_message(&*self.signer, message).map_err(|e| {
+            ApiError::InternalError(e.to_string().into())
+        })
+    }
+
+    /// Checks that the services of the node can migrate their data to the schema versions
+    /// listed in the configuration once it becomes actual.
+    fn check_service_versions(&self, config: &StoredConfiguration) -> Result<(), ApiError> {
+        self.blockchain
+            .check_service_versions(&config.service_versions)
+            .map_err(|e| ApiError::BadRequest(e.to_string()))
+    }
+
     fn handle_propose(self, router: &mut Router) {
         let post_propose = move |req: &mut Request| -> IronResult<Response> {
             let config = match req.get::<bodyparser::Struct<StoredConfiguration>>() {
@@ -278,13 +300,14 @@ impl PrivateApi {
             };
 
             config.consensus.validate_configuration();
+            self.check_service_versions(&config)?;
 
             let cfg_hash = config.hash();
-            let propose = Propose::new(
-                &self.service_keys.0,
+            let propose = self.sign(&Propose::new_with_signature(
+                &self.public_key,
                 ::std::str::from_utf8(config.into_bytes().as_slice()).unwrap(),
-                &self.service_keys.1,
-            );
+                &Signature::zero(),
+            ))?;
             let tx_hash = propose.hash();
 
             self.channel.send(propose.into()).map_err(ApiError::from)?;
@@ -299,8 +322,18 @@ impl PrivateApi {
     fn handle_vote(self, router: &mut Router) {
         let post_vote = move |req: &mut Request| -> IronResult<Response> {
             let cfg_hash: Hash = self.url_fragment(req, "hash")?;
+            let propose = Schema::new(self.blockchain.snapshot()).propose(&cfg_hash);
+            if let Some(propose) = propose {
+                let config = StoredConfiguration::try_deserialize(propose.cfg().as_bytes())
+                    .map_err(|e| ApiError::BadRequest(e.to_string()))?;
+                self.check_service_versions(&config)?;
+            }
 
-            let vote = Vote::new(&self.service_keys.0, &cfg_hash, &self.service_keys.1);
+            let vote = self.sign(&Vote::new_with_signature(
+                &self.public_key,
+                &cfg_hash,
+                &Signature::zero(),
+            ))?;
             let tx_hash = vote.hash();
 
             self.channel.send(vote.into()).map_err(ApiError::from)?;
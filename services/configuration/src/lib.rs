@@ -83,7 +83,7 @@ use exonum::crypto::Hash;
 use exonum::encoding::Error as EncodingError;
 use exonum::helpers::fabric::{self, Context};
 use exonum::messages::RawTransaction;
-use exonum::storage::Snapshot;
+use exonum::storage::{Fork, Snapshot};
 use iron::Handler;
 use router::Router;
 
@@ -123,6 +123,10 @@ impl blockchain::Service for Service {
         transactions::tx_from_raw(raw)
     }
 
+    fn restore_state(&self, received: &Snapshot, fork: &mut Fork) -> Result<(), failure::Error> {
+        Schema::new(fork).restore(received)
+    }
+
     fn public_api_handler(&self, ctx: &ApiContext) -> Option<Box<Handler>> {
         let mut router = Router::new();
         let api = api::PublicApi::new(ctx);
@@ -17,6 +17,7 @@
 use exonum::crypto::{self, CryptoHash, Hash, PublicKey, Signature};
 use exonum::encoding::Field;
 use exonum::storage::{Fork, ProofListIndex, ProofMapIndex, Snapshot, StorageValue};
+use failure;
 
 use std::borrow::Cow;
 
@@ -203,6 +204,32 @@ impl<'a> Schema<&'a mut Fork> {
     ) -> ProofListIndex<&mut Fork, MaybeVote> {
         ProofListIndex::new_in_family(VOTES, config_hash, &mut self.view)
     }
+
+    /// Restores the indexes received during the state sync. The votes for every proposal
+    /// must match the `votes_history_hash` of the proposal.
+    pub(crate) fn restore(&mut self, received: &Snapshot) -> Result<(), failure::Error> {
+        let received = Schema::new(received);
+        for (cfg_hash, propose_data) in received.propose_data_by_config_hash().iter() {
+            {
+                let mut votes = self.votes_by_config_hash_mut(&cfg_hash);
+                votes.extend(received.votes_by_config_hash(&cfg_hash).iter());
+                if votes.merkle_root() != *propose_data.votes_history_hash() {
+                    bail!(
+                        "Votes for the configuration {:?} do not match the proposal",
+                        cfg_hash
+                    );
+                }
+            }
+            self.propose_data_by_config_hash_mut().put(
+                &cfg_hash,
+                propose_data,
+            );
+        }
+        self.config_hash_by_ordinal_mut().extend(
+            received.config_hash_by_ordinal().iter(),
+        );
+        Ok(())
+    }
 }
 
 #[cfg(test)]
@@ -23,6 +23,8 @@
 
 #[macro_use]
 extern crate failure;
+#[macro_use]
+extern crate log;
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
@@ -47,10 +49,11 @@ use exonum::blockchain::{Blockchain, Service, ServiceContext, Schema, ApiContext
 use exonum::messages::{RawTransaction, Message};
 use exonum::encoding::serialize::json::reexport::Value;
 use exonum::storage::{Fork, Snapshot, ProofMapIndex, Entry};
-use exonum::crypto::{Hash, PublicKey};
+use exonum::crypto::{Hash, PublicKey, Signature};
 use exonum::encoding;
 use exonum::helpers::fabric::{ServiceFactory, Context};
 use exonum::api::Api;
+use exonum::node::signer::sign_message;
 
 /// Time service id.
 const SERVICE_ID: u16 = 4;
@@ -464,19 +467,33 @@ impl Service for TimeService {
         Value::Null
     }
 
+    fn restore_state(&self, received: &Snapshot, fork: &mut Fork) -> Result<(), failure::Error> {
+        let received = TimeSchema::new(received);
+        let mut schema = TimeSchema::new(fork);
+        for (validator, time) in received.validators_times().iter() {
+            schema.validators_times_mut().put(&validator, time);
+        }
+        if let Some(time) = received.time().get() {
+            schema.time_mut().set(time);
+        }
+        Ok(())
+    }
+
     /// Creates transaction after commit of the block.
     fn handle_commit(&self, context: &ServiceContext) {
         // The transaction must be created by the validator.
         if context.validator_id().is_none() {
             return;
         }
-        let (pub_key, sec_key) = (*context.public_key(), context.secret_key().clone());
-        context
-            .transaction_sender()
-            .send(Box::new(
-                TxTime::new(self.time.current_time(), &pub_key, &sec_key),
-            ))
-            .unwrap();
+        let tx = TxTime::new_with_signature(
+            self.time.current_time(),
+            context.public_key(),
+            &Signature::zero(),
+        );
+        match sign_message(context.signer(), &tx) {
+            Ok(tx) => context.transaction_sender().send(Box::new(tx)).unwrap(),
+            Err(e) => error!("Unable to sign the time transaction: {}", e),
+        }
     }
 
     fn private_api_handler(&self, ctx: &ApiContext) -> Option<Box<Handler>> {
@@ -502,14 +502,17 @@ impl TestKit {
         let last_hash = self.last_block_hash();
 
         let config_patch = self.update_configuration(new_block_height);
-        let (block_hash, patch) = {
-            let validator_id = self.leader().validator_id().unwrap();
-            self.blockchain.create_patch(
-                validator_id,
-                new_block_height,
-                tx_hashes,
-            )
-        };
+        let propose = self.leader().create_propose(
+            new_block_height,
+            &last_hash,
+            tx_hashes,
+        );
+        let (block_hash, patch) = self.blockchain.create_patch(
+            propose.validator(),
+            new_block_height,
+            propose.time(),
+            tx_hashes,
+        );
 
         let patch = if let Some(config_patch) = config_patch {
             let mut fork = self.blockchain.fork();
@@ -520,11 +523,6 @@ impl TestKit {
             patch
         };
 
-        let propose = self.leader().create_propose(
-            new_block_height,
-            &last_hash,
-            tx_hashes,
-        );
         let precommits: Vec<_> = self.network()
             .validators()
             .iter()
@@ -161,13 +161,16 @@ impl TestNode {
         }
     }
 
-    /// Creates a `Propose` message signed by this validator.
+    /// Creates a `Propose` message signed by this validator. The time of the proposed block
+    /// is the current system time.
     pub fn create_propose(
         &self,
         height: Height,
         last_hash: &crypto::Hash,
         tx_hashes: &[crypto::Hash],
     ) -> Propose {
+        use std::time::SystemTime;
+
         Propose::new(
             self.validator_id.expect(
                 "An attempt to create propose from a non-validator node.",
@@ -176,6 +179,7 @@ impl TestNode {
             Round::first(),
             last_hash,
             tx_hashes,
+            SystemTime::now().into(),
             &self.consensus_secret_key,
         )
     }